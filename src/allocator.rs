@@ -0,0 +1,106 @@
+use crate::{
+    execution::ExecutionProfile,
+    scheduler::random_fraction,
+    structs::{AccountSummary, Contender, Profile},
+};
+
+// What the account already has on, for allocators that size new orders around it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Exposure {
+    // Option contracts held in the profile's ticker, long and short alike.
+    pub(crate) held_contracts: f64,
+    // Orders the bot placed that are still working.
+    pub(crate) working_orders: usize,
+}
+
+// Decides how much of the account each scan puts to work. Implement it and return it from
+// allocator_for to size orders another way without touching the scan or order code.
+pub(crate) trait Allocator {
+    // Function that returns how many contenders a scan should rank and confirm at most, 0 when
+    // the account can't take a trade.
+    fn max_contenders(&self, account: &AccountSummary, exposure: &Exposure) -> usize;
+
+    // Function that returns the fills to place for each ranked contender, best first. Missing
+    // entries and 0 skip a contender.
+    fn allocate(
+        &self,
+        contenders: &[Contender],
+        account: &AccountSummary,
+        exposure: &Exposure,
+    ) -> Vec<i32>;
+}
+
+// Sizes by the profile's EXECUTION_PROFILE: orders and fills per order grow with equity with
+// loan in $800 units.
+pub(crate) struct ExecutionAllocator {
+    pub(crate) execution: ExecutionProfile,
+}
+
+impl Allocator for ExecutionAllocator {
+    fn max_contenders(&self, account: &AccountSummary, _exposure: &Exposure) -> usize {
+        self.execution.sizes(account.equity_with_loan).0.max(0) as usize
+    }
+
+    fn allocate(
+        &self,
+        contenders: &[Contender],
+        account: &AccountSummary,
+        exposure: &Exposure,
+    ) -> Vec<i32> {
+        let num_fills: i32 = self.execution.sizes(account.equity_with_loan).1;
+        vec![num_fills; contenders.len().min(self.max_contenders(account, exposure))]
+    }
+}
+
+// Randomizes another allocator's quantities within a band, so the bot doesn't show the same
+// size every cycle to market makers reading its flow.
+pub(crate) struct JitteredAllocator {
+    pub(crate) inner: Box<dyn Allocator>,
+    pub(crate) jitter_pct: f64,
+}
+
+impl Allocator for JitteredAllocator {
+    fn max_contenders(&self, account: &AccountSummary, exposure: &Exposure) -> usize {
+        self.inner.max_contenders(account, exposure)
+    }
+
+    fn allocate(
+        &self,
+        contenders: &[Contender],
+        account: &AccountSummary,
+        exposure: &Exposure,
+    ) -> Vec<i32> {
+        self.inner
+            .allocate(contenders, account, exposure)
+            .into_iter()
+            .map(|quantity| jittered_quantity(quantity, self.jitter_pct, random_fraction()))
+            .collect()
+    }
+}
+
+// Function that scales a quantity down by up to jitter_pct, with fraction in [0, 1) picking
+// where in the band it lands, so jitter never sizes above what the equity allows. Skipped
+// contenders stay skipped and the rest keep at least one fill.
+pub(crate) fn jittered_quantity(quantity: i32, jitter_pct: f64, fraction: f64) -> i32 {
+    if quantity <= 0 {
+        return quantity;
+    }
+    let scale: f64 = 1.0 - jitter_pct * (1.0 - fraction);
+    ((quantity as f64 * scale).round() as i32).clamp(1, quantity)
+}
+
+// Function that returns the allocator a profile sizes its orders with, randomized by
+// SIZE_JITTER_PCT when set.
+pub(crate) fn allocator_for(profile: &Profile, size_jitter_pct: f64) -> Box<dyn Allocator> {
+    let allocator: Box<dyn Allocator> = Box::new(ExecutionAllocator {
+        execution: profile.execution.clone(),
+    });
+    if size_jitter_pct > 0.0 {
+        Box::new(JitteredAllocator {
+            inner: allocator,
+            jitter_pct: size_jitter_pct,
+        })
+    } else {
+        allocator
+    }
+}
@@ -0,0 +1,306 @@
+use chrono::{DateTime, Utc};
+
+use crate::{
+    encryption::read_lines,
+    filters::{leg_filter_checks, LegFilter},
+    helpers::{
+        boxspread_risk_free_profit, butterfly_risk_free_profit, calc_time_difference,
+        calendar_spread_risk_free_profit, format_strike,
+    },
+    journal::{find_decision, JournalEntry, LegQuote},
+    stress::CalendarPosition,
+    structs::ProfitGate,
+};
+
+// One of the scanner's checks replayed on a recorded decision, with the numbers it compared.
+#[derive(Debug, PartialEq)]
+pub(crate) struct FilterCheck {
+    pub(crate) name: &'static str,
+    pub(crate) passed: bool,
+    pub(crate) detail: String,
+    // How far a failed threshold check fell short, the amount its threshold would have to move.
+    pub(crate) shortfall: Option<f64>,
+}
+
+impl FilterCheck {
+    pub(crate) fn new(name: &'static str, passed: bool, detail: String) -> Self {
+        FilterCheck {
+            name,
+            passed,
+            detail,
+            shortfall: None,
+        }
+    }
+
+    // Function that creates a threshold check, recording its shortfall when it failed.
+    pub(crate) fn threshold(
+        name: &'static str,
+        passed: bool,
+        detail: String,
+        shortfall: f64,
+    ) -> Self {
+        FilterCheck {
+            shortfall: (!passed).then_some(shortfall),
+            ..FilterCheck::new(name, passed, detail)
+        }
+    }
+}
+
+// The scan inputs a candidate's checks depend on besides its legs' quotes.
+pub(crate) struct ScanContext {
+    pub(crate) scan_date: String,
+    pub(crate) arb_threshold: f64,
+    pub(crate) strike_dif: f64,
+    pub(crate) mean_strike: f64,
+    pub(crate) underlying_price: Option<f64>,
+    // Implied volatility at the money, None when it can't be implied.
+    pub(crate) atm_vol: Option<f64>,
+    pub(crate) profit_gate: ProfitGate,
+    // When the candidate was scanned, for quote age, None when unknown.
+    pub(crate) scanned_at: Option<DateTime<Utc>>,
+    pub(crate) leg_filters: Vec<LegFilter>,
+}
+
+// Function that checks two strikes are the configured distance apart.
+fn spacing_check(name: &'static str, low: f64, high: f64, strike_dif: f64) -> FilterCheck {
+    let spacing: f64 = ((high - low) * 10.0).round() / 10.0;
+    FilterCheck::new(
+        name,
+        spacing == strike_dif,
+        format!("{} - {} = {} == {}", high, low, spacing, strike_dif),
+    )
+}
+
+// Function that checks a worst case profit clears the strategy's minimum.
+fn profit_check(worst_case: f64, min_profit: f64) -> FilterCheck {
+    FilterCheck::threshold(
+        "worst case profit",
+        worst_case > min_profit,
+        format!("{:.2} > {:.2}", worst_case, min_profit),
+        min_profit - worst_case,
+    )
+}
+
+// Function that replays the scanner's checks on a recorded decision, in the scanner's order.
+// Returns None for other entries or when the recorded legs don't match the spread type.
+pub(crate) fn replay_decision(entry: &JournalEntry) -> Option<Vec<FilterCheck>> {
+    let JournalEntry::Decision {
+        type_spread,
+        scan_date,
+        arb_threshold,
+        strike_dif,
+        mean_strike,
+        underlying_price,
+        atm_vol,
+        profit_gate,
+        leg_filters,
+        quotes,
+        time,
+        ..
+    } = entry
+    else {
+        return None;
+    };
+
+    scanner_checks(
+        type_spread,
+        quotes,
+        &ScanContext {
+            scan_date: scan_date.clone(),
+            arb_threshold: *arb_threshold,
+            strike_dif: *strike_dif,
+            mean_strike: *mean_strike,
+            underlying_price: *underlying_price,
+            atm_vol: *atm_vol,
+            profit_gate: *profit_gate,
+            scanned_at: DateTime::parse_from_rfc3339(time)
+                .ok()
+                .map(|time| time.with_timezone(&Utc)),
+            leg_filters: leg_filters.clone(),
+        },
+    )
+}
+
+// Function that runs the scanner's checks on a candidate's leg quotes, in the scanner's order:
+// the arb value, the leg filter pipeline, then the spread's own checks. Returns None when the
+// legs don't match the spread type.
+pub(crate) fn scanner_checks(
+    type_spread: &str,
+    quotes: &[LegQuote],
+    context: &ScanContext,
+) -> Option<Vec<FilterCheck>> {
+    let (arb_threshold, strike_dif): (f64, f64) = (context.arb_threshold, context.strike_dif);
+    let (scan_date, underlying_price, profit_gate) = (
+        &context.scan_date,
+        &context.underlying_price,
+        &context.profit_gate,
+    );
+
+    let mut checks: Vec<FilterCheck> = match (type_spread, quotes) {
+        ("Calendar", [front, back]) => {
+            let arb_val: f64 = front.mkt - back.mkt;
+            let days_apart: i64 = calc_time_difference(&front.date, &back.date);
+            let worst_case: f64 = calendar_spread_risk_free_profit(
+                &CalendarPosition {
+                    strike: front.strike,
+                    right: front.right.clone(),
+                    front_days: calc_time_difference(scan_date, &front.date),
+                    back_days: calc_time_difference(scan_date, &back.date),
+                    credit: arb_val,
+                    back_price: back.mkt,
+                    exit_cost: (back.ask - back.bid) / 2.0,
+                },
+                *underlying_price,
+                profit_gate,
+            );
+            vec![
+                FilterCheck::threshold(
+                    "arb value",
+                    arb_val >= arb_threshold,
+                    format!(
+                        "{:.2} - {:.2} = {:.2} >= {:.2}",
+                        front.mkt, back.mkt, arb_val, arb_threshold
+                    ),
+                    arb_threshold - arb_val,
+                ),
+                FilterCheck::new(
+                    "consecutive expiries",
+                    days_apart == 1,
+                    format!(
+                        "{} to {} is {} day(s) == 1",
+                        front.date, back.date, days_apart
+                    ),
+                ),
+                profit_check(worst_case, profit_gate.calendar_min_profit),
+            ]
+        }
+        ("Butterfly", [left, center, right]) => {
+            let arb_val: f64 = (2.0 * center.mkt) - (left.mkt + right.mkt);
+            vec![
+                FilterCheck::threshold(
+                    "arb value",
+                    arb_val >= arb_threshold,
+                    format!(
+                        "2 * {:.2} - ({:.2} + {:.2}) = {:.2} >= {:.2}",
+                        center.mkt, left.mkt, right.mkt, arb_val, arb_threshold
+                    ),
+                    arb_threshold - arb_val,
+                ),
+                spacing_check("left wing spacing", left.strike, center.strike, strike_dif),
+                spacing_check(
+                    "right wing spacing",
+                    center.strike,
+                    right.strike,
+                    strike_dif,
+                ),
+                profit_check(
+                    butterfly_risk_free_profit(arb_val, profit_gate),
+                    profit_gate.butterfly_min_profit,
+                ),
+            ]
+        }
+        ("Boxspread", [current_p, current_c, right_c, right_p]) => {
+            let arb_val: f64 = (current_p.mkt + right_c.mkt) - (current_c.mkt + right_p.mkt);
+            let box_threshold: f64 = -5.0 - arb_threshold;
+            vec![
+                FilterCheck::threshold(
+                    "arb value",
+                    arb_val <= box_threshold,
+                    format!(
+                        "({:.2} + {:.2}) - ({:.2} + {:.2}) = {:.2} <= {:.2}",
+                        current_p.mkt,
+                        right_c.mkt,
+                        current_c.mkt,
+                        right_p.mkt,
+                        arb_val,
+                        box_threshold
+                    ),
+                    arb_val - box_threshold,
+                ),
+                spacing_check(
+                    "call strike spacing",
+                    current_c.strike,
+                    right_c.strike,
+                    strike_dif,
+                ),
+                spacing_check(
+                    "put strike spacing",
+                    current_p.strike,
+                    right_p.strike,
+                    strike_dif,
+                ),
+                profit_check(
+                    boxspread_risk_free_profit(
+                        -arb_val,
+                        right_c.strike - current_c.strike,
+                        calc_time_difference(scan_date, &current_p.date),
+                        profit_gate,
+                    ),
+                    profit_gate.boxspread_min_profit,
+                ),
+            ]
+        }
+        _ => return None,
+    };
+    checks.splice(1..1, leg_filter_checks(type_spread, quotes, context));
+    Some(checks)
+}
+
+// Function that formats the replay of an order's decision from the journal file.
+pub(crate) fn audit_order(path: &str, order_id: &str) -> Result<String, String> {
+    let lines = read_lines(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let entry: JournalEntry = find_decision(lines, order_id)
+        .ok_or(format!("No decision recorded for order {}", order_id))?;
+    let checks: Vec<FilterCheck> = replay_decision(&entry)
+        .ok_or(format!("Decision for order {} can't be replayed", order_id))?;
+
+    let mut report: String = match &entry {
+        JournalEntry::Decision {
+            time,
+            ticker,
+            type_spread,
+            exp_date,
+            regime_value,
+            underlying_price,
+            rank_value,
+            ..
+        } => format!(
+            "Order {}: {} {} {} scanned at {}\nUnderlying {}, VIX {:.2}, rank {:.2}\n",
+            order_id,
+            ticker,
+            type_spread,
+            exp_date,
+            time,
+            underlying_price
+                .map(|price| format!("{:.2}", price))
+                .unwrap_or("unknown".to_string()),
+            regime_value,
+            rank_value
+        ),
+        _ => String::new(),
+    };
+    if let JournalEntry::Decision { quotes, .. } = &entry {
+        for quote in quotes {
+            report.push_str(&format!(
+                "  Quote {}{} {}: {:.2}/{:.2} from {:?} at {}\n",
+                format_strike(quote.strike),
+                quote.right,
+                quote.date,
+                quote.bid,
+                quote.ask,
+                quote.source,
+                quote.fetched_at.as_deref().unwrap_or("unknown")
+            ));
+        }
+    }
+    for check in &checks {
+        report.push_str(&format!(
+            "  {} {}: {}\n",
+            if check.passed { "PASS" } else { "FAIL" },
+            check.name,
+            check.detail
+        ));
+    }
+
+    Ok(report)
+}
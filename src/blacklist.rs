@@ -0,0 +1,139 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Below this score an entry has decayed away and is forgotten.
+const FORGET_SCORE: f64 = 0.05;
+
+// A contract kept out of scans, as reported in health.json.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct BlacklistedQuote {
+    pub(crate) conid: String,
+    pub(crate) contract: String,
+    pub(crate) score: f64,
+}
+
+struct Entry {
+    contract: String,
+    score: f64,
+    updated_at: DateTime<Utc>,
+    // A fill shows the quotes are real, so the conid isn't scored again for a while.
+    filled_at: Option<DateTime<Utc>>,
+}
+
+// Conids whose arbs keep ending cancelled unfilled or rejected, a sign of a stale quote on that
+// strike. Each such order adds one to a conid's score, which halves every half-life. A conid is
+// left out of scans while its score is at least the threshold, so a strike that stops failing
+// comes back on its own.
+pub(crate) struct QuoteBlacklist {
+    threshold: f64,
+    half_life_minutes: f64,
+    entries: HashMap<String, Entry>,
+}
+
+impl QuoteBlacklist {
+    pub(crate) fn new(threshold: u64, half_life_minutes: f64) -> Self {
+        QuoteBlacklist {
+            threshold: threshold as f64,
+            half_life_minutes,
+            entries: HashMap::new(),
+        }
+    }
+
+    // Function that checks whether the blacklist is turned on (a threshold of 0 disables it).
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.threshold > 0.0 && self.half_life_minutes > 0.0
+    }
+
+    // Function that returns a score decayed from when it was last updated until now.
+    fn decayed(&self, score: f64, updated_at: DateTime<Utc>, now: DateTime<Utc>) -> f64 {
+        let minutes: f64 = (now - updated_at).num_milliseconds().max(0) as f64 / 60_000.0;
+        score * 0.5_f64.powf(minutes / self.half_life_minutes)
+    }
+
+    // Function that records how an order with a conid among its legs ended, described by its
+    // contract for the report. Returns true when the failure put the conid on the blacklist.
+    pub(crate) fn record(
+        &mut self,
+        conid: &str,
+        contract: &str,
+        filled: bool,
+        now: DateTime<Utc>,
+    ) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+        let was_listed: bool = self.is_blacklisted(conid, now);
+        let score: f64 = match self.entries.get(conid) {
+            Some(entry) => self.decayed(entry.score, entry.updated_at, now),
+            None => 0.0,
+        };
+        let half_life: chrono::Duration =
+            chrono::Duration::milliseconds((self.half_life_minutes * 60_000.0) as i64);
+        let entry: &mut Entry = self.entries.entry(conid.to_string()).or_insert(Entry {
+            contract: contract.to_string(),
+            score: 0.0,
+            updated_at: now,
+            filled_at: None,
+        });
+        entry.updated_at = now;
+        if filled {
+            entry.score = 0.0;
+            entry.filled_at = Some(now);
+            return false;
+        }
+        if entry
+            .filled_at
+            .is_some_and(|filled_at| now - filled_at < half_life)
+        {
+            entry.score = score;
+            return false;
+        }
+        entry.score = score + 1.0;
+        !was_listed && entry.score >= self.threshold
+    }
+
+    // Function that checks whether a conid is kept out of scans.
+    pub(crate) fn is_blacklisted(&self, conid: &str, now: DateTime<Utc>) -> bool {
+        self.is_enabled()
+            && self.entries.get(conid).is_some_and(|entry| {
+                self.decayed(entry.score, entry.updated_at, now) >= self.threshold
+            })
+    }
+
+    // Function that returns the conids kept out of scans with their decayed scores, highest first,
+    // forgetting entries that have decayed away.
+    pub(crate) fn report(&mut self, now: DateTime<Utc>) -> Vec<BlacklistedQuote> {
+        let half_life: chrono::Duration =
+            chrono::Duration::milliseconds((self.half_life_minutes * 60_000.0) as i64);
+        let scores: HashMap<String, f64> = self
+            .entries
+            .iter()
+            .map(|(conid, entry)| {
+                (
+                    conid.clone(),
+                    self.decayed(entry.score, entry.updated_at, now),
+                )
+            })
+            .collect();
+        self.entries.retain(|conid, entry| {
+            scores[conid] >= FORGET_SCORE
+                || entry
+                    .filled_at
+                    .is_some_and(|filled_at| now - filled_at < half_life)
+        });
+
+        let mut listed: Vec<BlacklistedQuote> = self
+            .entries
+            .iter()
+            .filter(|(conid, _)| scores[*conid] >= self.threshold)
+            .map(|(conid, entry)| BlacklistedQuote {
+                conid: conid.clone(),
+                contract: entry.contract.clone(),
+                score: (scores[conid] * 100.0).round() / 100.0,
+            })
+            .collect();
+        listed.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.conid.cmp(&b.conid)));
+        listed
+    }
+}
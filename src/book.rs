@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, io};
+
+use crate::{
+    encryption::{encryption_enabled, open_line, seal_line},
+    helpers::value_to_f64,
+    structs::PositionResponse,
+};
+
+// Account positions as last seen by the bot, compared against the account at the next startup.
+pub(crate) const BOOK_PATH: &str = "book.json";
+
+// Shares delivered per equity option contract.
+const SHARES_PER_CONTRACT: f64 = 100.0;
+
+// Snapshot of the account's positions.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Book {
+    pub(crate) time: String,
+    pub(crate) positions: Vec<PositionResponse>,
+}
+
+// How an option leg left the book between two snapshots.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SettlementKind {
+    // A short leg was assigned, moving shares against it.
+    Assigned,
+    // A long leg was exercised, moving shares with it.
+    Exercised,
+    // The leg expired without shares moving, worthless or cash settled.
+    Expired,
+}
+
+// An option leg that left the book without the bot trading it.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Settlement {
+    pub(crate) kind: SettlementKind,
+    pub(crate) conid: i64,
+    pub(crate) und_conid: i64,
+    pub(crate) right: String,
+    pub(crate) strike: f64,
+    pub(crate) expiry: String,
+    // Contracts that left the book, negative for a short leg.
+    pub(crate) contracts: f64,
+    // Shares of the underlying the leg turned into, zero when it expired.
+    pub(crate) shares: f64,
+}
+
+// Function that loads the last book, None when there isn't a readable one.
+pub(crate) fn load_book(path: &str) -> Option<Book> {
+    serde_json::from_str(&open_line(fs::read_to_string(path).ok()?)?).ok()
+}
+
+// Function that saves the book, as one encrypted line when encryption is on.
+pub(crate) fn save_book(path: &str, book: &Book) -> io::Result<()> {
+    if cfg!(test) {
+        return Ok(());
+    }
+    let contents: String = if encryption_enabled() {
+        seal_line(&serde_json::to_string(book)?)?
+    } else {
+        serde_json::to_string_pretty(book)?
+    };
+    fs::write(path, contents)
+}
+
+// Function that returns the quantity held of a conid.
+fn held(positions: &[PositionResponse], conid: i64) -> f64 {
+    positions
+        .iter()
+        .filter(|position| position.conid == conid)
+        .map(|position| position.position)
+        .sum()
+}
+
+// Function that finds the option legs that shrank between two books without being traded away:
+// ones whose underlying's shares moved the way exercise or assignment would move them, and
+// ones past their expiry (YYYYMMDD) by today. Shrinking legs that match neither were closed by
+// trades and are left out.
+pub(crate) fn reconcile_book(
+    previous: &[PositionResponse],
+    current: &[PositionResponse],
+    today: &str,
+) -> Vec<Settlement> {
+    let mut share_moves: Vec<(i64, f64)> = Vec::new();
+    let mut settlements: Vec<Settlement> = Vec::new();
+
+    for leg in previous {
+        let (und_conid, right, strike, expiry): (i64, &str, f64, &str) = match (
+            leg.und_conid,
+            leg.put_or_call.as_deref(),
+            leg.strike.as_ref().and_then(value_to_f64),
+            leg.expiry.as_deref(),
+        ) {
+            (Some(und_conid), Some(right), Some(strike), Some(expiry)) => {
+                (und_conid, &right[..1], strike, expiry)
+            }
+            _ => continue,
+        };
+
+        // Contracts that left the book, with the sign of the leg.
+        let now_held: f64 = held(current, leg.conid);
+        let contracts: f64 = if leg.position > 0.0 {
+            leg.position - now_held.clamp(0.0, leg.position)
+        } else {
+            leg.position - now_held.clamp(leg.position, 0.0)
+        };
+        if contracts == 0.0 {
+            continue;
+        }
+
+        // Long calls and short puts deliver shares, long puts and short calls take them.
+        let shares: f64 = if right == "C" { contracts } else { -contracts } * SHARES_PER_CONTRACT;
+        let moves: usize = match share_moves
+            .iter()
+            .position(|(conid, _)| *conid == und_conid)
+        {
+            Some(index) => index,
+            None => {
+                let moved: f64 = held(current, und_conid) - held(previous, und_conid);
+                share_moves.push((und_conid, moved));
+                share_moves.len() - 1
+            }
+        };
+        let unexplained: f64 = share_moves[moves].1;
+
+        let (kind, shares): (SettlementKind, f64) =
+            if unexplained * shares > 0.0 && unexplained.abs() >= shares.abs() {
+                share_moves[moves].1 -= shares;
+                if contracts < 0.0 {
+                    (SettlementKind::Assigned, shares)
+                } else {
+                    (SettlementKind::Exercised, shares)
+                }
+            } else if expiry < today {
+                (SettlementKind::Expired, 0.0)
+            } else {
+                continue;
+            };
+
+        settlements.push(Settlement {
+            kind,
+            conid: leg.conid,
+            und_conid,
+            right: right.to_string(),
+            strike,
+            expiry: expiry.to_string(),
+            contracts,
+            shares,
+        });
+    }
+
+    settlements
+}
@@ -0,0 +1,408 @@
+use ordered_float::OrderedFloat;
+use std::collections::HashMap;
+
+use crate::{
+    combos::ComboIndex,
+    helpers::calc_time_difference,
+    market::median_spread,
+    quote_diff::QuoteDiff,
+    structs::{ConidsMap, Opt, SecDefInfoResponse, StrikeSlice},
+    validation::{validate_quote, QuoteRejection},
+};
+
+// Columns of the chain export, see ChainView::to_csv.
+const CHAIN_CSV_HEADER: &str = "expiry,right,strike,bid,mid,ask,ask_size,conid";
+
+// Conids per market data snapshot request.
+const SNAPSHOT_BATCH: usize = 300;
+
+// Function that splits a secdef section's months field ("JAN24;FEB24;MAR24") into the listed
+// option months, nearest first, dropping blanks.
+pub(crate) fn parse_option_months(months: &str) -> Vec<String> {
+    months
+        .split(';')
+        .map(str::trim)
+        .filter(|month| !month.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// Function that builds the chain's expiries, sorted strikes and conids from secdef contracts,
+// keeping the first num_days expiries at least num_days_offset days out from the current date
+// (YYMMDD). Also returns the conids batched for snapshot requests.
+pub(crate) fn chain_from_contracts(
+    contracts: &[SecDefInfoResponse],
+    current_date: &str,
+    num_days: i64,
+    num_days_offset: i64,
+) -> (Vec<String>, Vec<String>, StrikeSlice, ConidsMap) {
+    let mut dates: Vec<String> = Vec::new();
+    let mut strikes: StrikeSlice = HashMap::new();
+    let mut conids: ConidsMap = HashMap::new();
+    let mut batch: Vec<String> = Vec::new();
+    let mut batches: Vec<String> = Vec::new();
+
+    let mut contracts: Vec<&SecDefInfoResponse> = contracts.iter().collect();
+    contracts.sort_by(|a, b| a.maturity_date.cmp(&b.maturity_date));
+
+    for contract in contracts {
+        let exp_date: &str = contract
+            .maturity_date
+            .get(2..)
+            .unwrap_or(&contract.maturity_date);
+        if calc_time_difference(current_date, exp_date) < num_days_offset {
+            continue;
+        }
+        if !dates.iter().any(|date| date == exp_date) {
+            if dates.len() as i64 >= num_days {
+                break;
+            }
+            dates.push(exp_date.to_string());
+        }
+
+        let conid: String = contract.conid.to_string();
+        let rights: &mut HashMap<OrderedFloat<f64>, String> = conids
+            .entry(exp_date.to_string())
+            .or_default()
+            .entry(contract.right.clone())
+            .or_default();
+        if rights.contains_key(&OrderedFloat(contract.strike)) {
+            continue;
+        }
+        rights.insert(OrderedFloat(contract.strike), conid.clone());
+        strikes
+            .entry(exp_date.to_string())
+            .or_default()
+            .entry(contract.right.clone())
+            .or_default()
+            .push(contract.strike);
+
+        batch.push(conid);
+        if batch.len() == SNAPSHOT_BATCH {
+            batches.push(batch.join(","));
+            batch.clear();
+        }
+    }
+    if !batch.is_empty() {
+        batches.push(batch.join(","));
+    }
+
+    for date in &dates {
+        for right in ["C", "P"] {
+            strikes
+                .entry(date.clone())
+                .or_default()
+                .entry(right.to_string())
+                .or_default()
+                .sort_by(f64::total_cmp);
+            conids
+                .entry(date.clone())
+                .or_default()
+                .entry(right.to_string())
+                .or_default();
+        }
+    }
+
+    (batches, dates, strikes, conids)
+}
+
+// A contract's position in a chain's interned conids.
+pub(crate) type ContractId = u32;
+
+// The contracts of one expiry and right: strikes ascending with their contract ids alongside.
+#[derive(Default)]
+struct Series {
+    strikes: Vec<f64>,
+    ids: Vec<Option<ContractId>>,
+}
+
+// Function that returns the position of a right ("C" or "P") in a chain's series.
+fn right_index(right: &str) -> Option<usize> {
+    match right {
+        "C" => Some(0),
+        "P" => Some(1),
+        _ => None,
+    }
+}
+
+// Option chain for one underlying: expiries, sorted strikes per right, conids and the latest quotes.
+// Contracts are addressed by expiry index, right and strike index, and their conids are interned
+// once so quotes are looked up by contract id rather than by string through nested maps.
+pub(crate) struct ChainView {
+    dates: Vec<String>,
+    series: Vec<[Series; 2]>,
+    conids: Vec<String>,
+    contract_ids: HashMap<String, ContractId>,
+    quotes: Vec<Option<Opt>>,
+    previous_quotes: Vec<Option<Opt>>,
+    underlying_price: Option<f64>,
+    combos: ComboIndex,
+}
+
+impl ChainView {
+    pub(crate) fn new(dates: Vec<String>, strikes: StrikeSlice, conids: ConidsMap) -> Self {
+        let mut interned: Vec<String> = Vec::new();
+        let mut contract_ids: HashMap<String, ContractId> = HashMap::new();
+        let series: Vec<[Series; 2]> = dates
+            .iter()
+            .map(|date| {
+                ["C", "P"].map(|right| {
+                    let mut contracts: Vec<(f64, Option<ContractId>)> = strikes
+                        .get(date)
+                        .and_then(|rights| rights.get(right))
+                        .map(|strikes| strikes.as_slice())
+                        .unwrap_or(&[])
+                        .iter()
+                        .map(|strike| {
+                            let id: Option<ContractId> = conids
+                                .get(date)
+                                .and_then(|rights| rights.get(right))
+                                .and_then(|strikes| strikes.get(&OrderedFloat(*strike)))
+                                .map(|conid| {
+                                    *contract_ids.entry(conid.clone()).or_insert_with(|| {
+                                        interned.push(conid.clone());
+                                        (interned.len() - 1) as ContractId
+                                    })
+                                });
+                            (*strike, id)
+                        })
+                        .collect();
+                    contracts.sort_by(|a, b| a.0.total_cmp(&b.0));
+                    let (strikes, ids): (Vec<f64>, Vec<Option<ContractId>>) =
+                        contracts.into_iter().unzip();
+                    Series { strikes, ids }
+                })
+            })
+            .collect();
+
+        let mut chain: ChainView = ChainView {
+            dates,
+            series,
+            conids: interned,
+            contract_ids,
+            quotes: Vec::new(),
+            previous_quotes: Vec::new(),
+            underlying_price: None,
+            combos: ComboIndex::default(),
+        };
+        chain.combos = ComboIndex::build(&chain);
+        chain
+    }
+
+    // Function that returns the index of the chain's spread combinations.
+    pub(crate) fn combos(&self) -> &ComboIndex {
+        &self.combos
+    }
+
+    // Function that returns how many contracts the chain holds.
+    pub(crate) fn contract_count(&self) -> usize {
+        self.conids.len()
+    }
+
+    // Function that replaces the quotes with the latest snapshot, keyed by conid, keeping the
+    // replaced ones as the previous snapshot. Quotes for conids outside the chain are dropped.
+    pub(crate) fn set_quotes(&mut self, quotes: HashMap<String, Opt>) {
+        let mut snapshot: Vec<Option<Opt>> = Vec::with_capacity(self.conids.len());
+        snapshot.resize_with(self.conids.len(), || None);
+        for (conid, opt) in quotes {
+            if let Some(id) = self.contract_ids.get(&conid) {
+                snapshot[*id as usize] = Some(opt);
+            }
+        }
+        self.previous_quotes = std::mem::replace(&mut self.quotes, snapshot);
+    }
+
+    // Function that records the underlying's price at the time of the snapshot, if known.
+    pub(crate) fn set_underlying_price(&mut self, underlying_price: Option<f64>) {
+        self.underlying_price = underlying_price;
+    }
+
+    // Function that returns the underlying's price at the time of the snapshot, if known.
+    pub(crate) fn underlying_price(&self) -> Option<f64> {
+        self.underlying_price
+    }
+
+    // Function that returns the median bid/ask spread of the chain as a fraction of mid.
+    pub(crate) fn median_spread(&self) -> Option<f64> {
+        let quotes: Vec<(f64, f64)> = self
+            .quotes
+            .iter()
+            .flatten()
+            .map(|opt| (opt.bid, opt.ask))
+            .collect();
+        median_spread(&quotes)
+    }
+
+    // Function that drops quotes failing validation, so bad gateway data never reaches the arb
+    // math, and returns the rejections found.
+    pub(crate) fn validate_quotes(
+        &mut self,
+        underlying_price: Option<f64>,
+        max_size: f64,
+    ) -> Vec<QuoteRejection> {
+        let mut rejections: Vec<QuoteRejection> = Vec::new();
+
+        for rights in &self.series {
+            for (right, series) in ["C", "P"].iter().zip(rights) {
+                for (strike, id) in series.strikes.iter().zip(&series.ids) {
+                    let quote: &mut Option<Opt> =
+                        match id.and_then(|id| self.quotes.get_mut(id as usize)) {
+                            Some(quote) => quote,
+                            None => continue,
+                        };
+                    let checked: Option<Result<(), QuoteRejection>> = quote
+                        .as_ref()
+                        .map(|opt| validate_quote(opt, *strike, right, underlying_price, max_size));
+                    if let Some(Err(rejection)) = checked {
+                        *quote = None;
+                        rejections.push(rejection);
+                    }
+                }
+            }
+        }
+
+        rejections
+    }
+
+    // Function that counts the contracts whose latest quote has both a bid and an ask.
+    pub(crate) fn quoted_count(&self) -> usize {
+        self.quotes
+            .iter()
+            .flatten()
+            .filter(|opt| opt.bid > 0.0 && opt.ask > 0.0)
+            .count()
+    }
+
+    // Function that returns the expiries in ascending order.
+    pub(crate) fn dates(&self) -> &[String] {
+        &self.dates
+    }
+
+    // Function that returns the mean call strike of the nearest expiry, 0.0 without one.
+    pub(crate) fn mean_strike(&self) -> f64 {
+        match self.dates.first() {
+            Some(date) => {
+                let call_strikes: &[f64] = self.strikes(date, "C");
+                call_strikes.iter().sum::<f64>() / call_strikes.len() as f64
+            }
+            None => 0.0,
+        }
+    }
+
+    // Function that returns the expiry after the given one, if any.
+    pub(crate) fn next_date(&self, date: &str) -> Option<&String> {
+        let index: usize = self.dates.iter().position(|d| d == date)?;
+        self.dates.get(index + 1)
+    }
+
+    // Function that returns the expiries at most the given number of days from the current date.
+    #[allow(dead_code)]
+    pub(crate) fn expiries_within(&self, current_date: &str, days: i64) -> Vec<&String> {
+        self.dates
+            .iter()
+            .filter(|date| calc_time_difference(current_date, date) <= days)
+            .collect()
+    }
+
+    // Function that returns the contracts of an expiry and right, if listed.
+    fn series(&self, date: &str, right: &str) -> Option<&Series> {
+        let date_index: usize = self.dates.iter().position(|d| d == date)?;
+        Some(&self.series[date_index][right_index(right)?])
+    }
+
+    // Function that returns the sorted strikes for an expiry and right ("C" or "P").
+    pub(crate) fn strikes(&self, date: &str, right: &str) -> &[f64] {
+        self.series(date, right)
+            .map(|series| series.strikes.as_slice())
+            .unwrap_or(&[])
+    }
+
+    // Function that returns the neighbouring strikes below and above a strike.
+    pub(crate) fn adjacent_strikes(
+        &self,
+        date: &str,
+        right: &str,
+        strike: f64,
+    ) -> (Option<f64>, Option<f64>) {
+        let strikes: &[f64] = self.strikes(date, right);
+        match strikes.iter().position(|s| *s == strike) {
+            Some(index) => (
+                index.checked_sub(1).map(|i| strikes[i]),
+                strikes.get(index + 1).copied(),
+            ),
+            None => (None, None),
+        }
+    }
+
+    // Function that returns the interned id of a contract.
+    pub(crate) fn contract_id(&self, date: &str, right: &str, strike: f64) -> Option<ContractId> {
+        let series: &Series = self.series(date, right)?;
+        let index: usize = series
+            .strikes
+            .binary_search_by(|s| s.total_cmp(&strike))
+            .ok()?;
+        series.ids[index]
+    }
+
+    // Function that returns the conid of a contract.
+    pub(crate) fn conid(&self, date: &str, right: &str, strike: f64) -> Option<&String> {
+        self.contract_id(date, right, strike)
+            .map(|id| &self.conids[id as usize])
+    }
+
+    // Function that returns the latest quote of a contract.
+    pub(crate) fn quote(&self, date: &str, right: &str, strike: f64) -> Option<&Opt> {
+        self.contract_id(date, right, strike)
+            .and_then(|id| self.quotes.get(id as usize))
+            .and_then(Option::as_ref)
+    }
+
+    // Function that formats every contract in the chain as CSV with a header, one row per
+    // expiry, right and strike, leaving the quote columns blank for contracts without a quote.
+    pub(crate) fn to_csv(&self) -> String {
+        let mut csv: String = CHAIN_CSV_HEADER.to_string();
+        csv.push('\n');
+        for date in &self.dates {
+            for right in ["C", "P"] {
+                for strike in self.strikes(date, right) {
+                    let conid: &str = self
+                        .conid(date, right, *strike)
+                        .map(String::as_str)
+                        .unwrap_or("");
+                    let quote: String = match self.quote(date, right, *strike) {
+                        Some(opt) => format!(
+                            "{:.2},{:.2},{:.2},{}",
+                            opt.bid,
+                            (opt.bid + opt.ask) / 2.0,
+                            opt.ask,
+                            opt.asz
+                        ),
+                        None => ",,,".to_string(),
+                    };
+                    csv.push_str(&format!(
+                        "{},{},{},{},{}\n",
+                        date, right, strike, quote, conid
+                    ));
+                }
+            }
+        }
+        csv
+    }
+
+    // Function that returns the contracts whose quote changed since the previous snapshot, all of
+    // them when there is none.
+    pub(crate) fn quote_diff(&self) -> QuoteDiff {
+        if self.previous_quotes.is_empty() {
+            QuoteDiff::full()
+        } else {
+            QuoteDiff::between(&self.previous_quotes, &self.quotes)
+        }
+    }
+
+    // Function that returns a contract's quote in the previous snapshot.
+    pub(crate) fn previous_quote(&self, date: &str, right: &str, strike: f64) -> Option<&Opt> {
+        self.contract_id(date, right, strike)
+            .and_then(|id| self.previous_quotes.get(id as usize))
+            .and_then(Option::as_ref)
+    }
+}
@@ -0,0 +1,34 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use chrono_tz::America::New_York;
+
+// Source of the current time for scans and market hours checks, so replays and tests can run
+// them at a synthetic time.
+pub(crate) trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    // Function that returns the calendar date in New York, where the options list and expire, so
+    // days to expiry don't depend on the timezone of the machine the bot runs on.
+    fn today(&self) -> NaiveDate {
+        self.now().with_timezone(&New_York).date_naive()
+    }
+
+    // Function that returns the New York date as YYMMDD, the format expiries are compared in.
+    fn scan_date(&self) -> String {
+        let today: NaiveDate = self.today();
+        format!(
+            "{:02}{:02}{:02}",
+            today.year() % 100,
+            today.month(),
+            today.day()
+        )
+    }
+}
+
+// The wall clock.
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
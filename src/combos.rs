@@ -0,0 +1,181 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    chain::{ChainView, ContractId},
+    quote_diff::QuoteDiff,
+};
+
+// Most multiples of the strike difference a butterfly's wings are scanned at.
+pub(crate) const MAX_BUTTERFLY_WIDTHS: usize = 3;
+
+// A leg of a spread combination.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ComboLeg {
+    pub(crate) strike: f64,
+    pub(crate) right: &'static str,
+    pub(crate) date: String,
+}
+
+// A spread combination the scanners evaluate, its legs in the order the spread is ordered.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Combo {
+    pub(crate) type_spread: &'static str,
+    pub(crate) legs: Vec<ComboLeg>,
+}
+
+impl Combo {
+    // Function that returns the legs as the (strike, right, expiry) tuples the leg filters take.
+    pub(crate) fn leg_refs(&self) -> Vec<(f64, &str, &String)> {
+        self.legs
+            .iter()
+            .map(|leg| (leg.strike, leg.right, &leg.date))
+            .collect()
+    }
+
+    // Function that checks whether every leg has a conid, a strike the chain lists may lack one
+    // when the secdef lookups didn't agree.
+    pub(crate) fn has_conids(&self, chain: &ChainView) -> bool {
+        self.legs.iter().all(|leg| {
+            chain
+                .contract_id(&leg.date, leg.right, leg.strike)
+                .is_some()
+        })
+    }
+}
+
+// Every spread combination of a chain, indexed by the contract ids of its legs so a quote update leads
+// straight to the combinations it re-prices. Built once with the chain: calendar pairs of the
+// same strike in consecutive expiries, butterfly triplets with wings up to MAX_BUTTERFLY_WIDTHS
+// strikes out and box quadruplets of call and put strikes paired by index.
+#[derive(Default)]
+pub(crate) struct ComboIndex {
+    combos: Vec<Combo>,
+    // Combination ids per contract id.
+    by_contract: Vec<Vec<usize>>,
+}
+
+impl ComboIndex {
+    pub(crate) fn build(chain: &ChainView) -> Self {
+        let mut index: ComboIndex = ComboIndex {
+            combos: Vec::new(),
+            by_contract: vec![Vec::new(); chain.contract_count()],
+        };
+
+        for date in chain.dates() {
+            let next_date: &String = match chain.next_date(date) {
+                Some(next_date) => next_date,
+                None => break,
+            };
+            for right in ["C", "P"] {
+                for strike in chain.strikes(date, right) {
+                    if chain.conid(next_date, right, *strike).is_some() {
+                        index.push(
+                            chain,
+                            "Calendar",
+                            vec![(*strike, right, date), (*strike, right, next_date)],
+                        );
+                    }
+                }
+            }
+        }
+
+        for date in chain.dates() {
+            for right in ["C", "P"] {
+                let strikes: &[f64] = chain.strikes(date, right);
+                for (i, strike) in strikes.iter().enumerate() {
+                    for wing in (1..=MAX_BUTTERFLY_WIDTHS)
+                        .take_while(|wing| *wing <= i && i + wing < strikes.len())
+                    {
+                        index.push(
+                            chain,
+                            "Butterfly",
+                            vec![
+                                (strikes[i - wing], right, date),
+                                (*strike, right, date),
+                                (strikes[i + wing], right, date),
+                            ],
+                        );
+                    }
+                }
+            }
+        }
+
+        for date in chain.dates() {
+            let cs: &[f64] = chain.strikes(date, "C");
+            let ps: &[f64] = chain.strikes(date, "P");
+            if cs.len() > 1 && ps.len() > 1 {
+                // Put strikes are paired with call strikes by index.
+                for i in 0..(cs.len() - 1).min(ps.len() - 1) {
+                    index.push(
+                        chain,
+                        "Boxspread",
+                        vec![
+                            (ps[i], "P", date),
+                            (cs[i], "C", date),
+                            (cs[i + 1], "C", date),
+                            (ps[i + 1], "P", date),
+                        ],
+                    );
+                }
+            }
+        }
+
+        index
+    }
+
+    // Function that adds a combination and indexes it under each of its legs' contract ids.
+    fn push(
+        &mut self,
+        chain: &ChainView,
+        type_spread: &'static str,
+        legs: Vec<(f64, &'static str, &String)>,
+    ) {
+        let id: usize = self.combos.len();
+        for (strike, right, date) in &legs {
+            if let Some(contract) = chain.contract_id(date, right, *strike) {
+                let ids: &mut Vec<usize> = &mut self.by_contract[contract as usize];
+                if ids.last() != Some(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+        self.combos.push(Combo {
+            type_spread,
+            legs: legs
+                .into_iter()
+                .map(|(strike, right, date)| ComboLeg {
+                    strike,
+                    right,
+                    date: date.clone(),
+                })
+                .collect(),
+        });
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.combos.len()
+    }
+
+    // Function that queues a spread type's combinations a diff touches for evaluation, all of them
+    // for a full diff. The queue keeps chain order (expiry, right, strike), so an incremental scan
+    // lists its contenders in the same order as a full one.
+    pub(crate) fn queue(&self, type_spread: &str, diff: &QuoteDiff) -> Vec<&Combo> {
+        match diff.changed() {
+            None => self
+                .combos
+                .iter()
+                .filter(|combo| combo.type_spread == type_spread)
+                .collect(),
+            Some(changed) => changed
+                .iter()
+                .filter_map(|contract: &ContractId| self.by_contract.get(*contract as usize))
+                .flatten()
+                .copied()
+                .collect::<BTreeSet<usize>>()
+                .into_iter()
+                .map(|id| &self.combos[id])
+                .filter(|combo| combo.type_spread == type_spread)
+                .collect(),
+        }
+    }
+}
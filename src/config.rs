@@ -0,0 +1,1303 @@
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use dotenv::dotenv;
+use reqwest::blocking::{Client, ClientBuilder};
+use serde::{
+    de::{
+        self,
+        value::{Error as ValueError, MapDeserializer, SeqDeserializer},
+        IntoDeserializer, Visitor,
+    },
+    forward_to_deserialize_any, Deserialize, Deserializer,
+};
+use std::{
+    collections::HashMap, env, error::Error, fs, io::stdin, net::IpAddr, str::FromStr,
+    time::Duration,
+};
+
+use crate::{
+    combos::MAX_BUTTERFLY_WIDTHS,
+    execution::{fill_type_preset, parse_execution_profile, ExecutionProfile},
+    expiry::{is_physically_settled, ExpiryAction},
+    exposure::ExpiryCaps,
+    fill_model::{load_fill_rates, FillModel},
+    filters::{default_leg_filters, parse_leg_filters, LegFilter},
+    headers::{auth_header, build_default_headers, parse_headers, DEFAULT_USER_AGENT},
+    helpers::{
+        gateway_base_url, parse_blackout_windows, parse_list, parse_market_holidays, JOURNAL_PATH,
+    },
+    logging::LogLevel,
+    positions::PositionRules,
+    replies::ReplyPolicy,
+    resolver::{apply_resolver, parse_static_hosts, IpVersion, ResolverConfig},
+    scheduler::{
+        parse_scan_schedule, parse_time_windows, ScanWindow, StrategySchedule, StrategySchedules,
+    },
+    structs::{
+        ButterflyWings, ExpiryRule, HedgeRule, OrderTags, PriceBounds, PriceLimits, Profile,
+        ProfitGate, RollRule, TickRule,
+    },
+};
+
+// Settings file read at startup, values set in the environment or .env take precedence.
+pub(crate) const CONFIG_PATH: &str = "config.toml";
+
+// Function that gets input and returns result.
+pub(crate) fn get_user_input(prompt: &str) -> String {
+    let mut input: String = String::new();
+    println!("{}", prompt);
+    stdin().read_line(&mut input).expect("Failed to read line");
+    input.trim().to_string()
+}
+
+// A raw setting, parsed into whatever type the field it lands in asks for.
+struct Setting {
+    key: String,
+    raw: String,
+}
+
+impl Setting {
+    // Function that builds the error for a value that doesn't parse as the field's type.
+    fn invalid(&self, expected: &str) -> ValueError {
+        de::Error::custom(format!(
+            "{} must be {}, got \"{}\"",
+            self.key.to_uppercase(),
+            expected,
+            self.raw
+        ))
+    }
+
+    // Function that parses the trimmed raw value.
+    fn parse<T: FromStr>(&self, expected: &str) -> Result<T, ValueError> {
+        self.raw
+            .trim()
+            .parse::<T>()
+            .map_err(|_| self.invalid(expected))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, ValueError> for Setting {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for Setting {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_string(self.raw)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        match self.raw.trim().to_lowercase().as_str() {
+            "true" | "yes" | "y" | "1" => visitor.visit_bool(true),
+            "false" | "no" | "n" | "0" => visitor.visit_bool(false),
+            _ => Err(self.invalid("true or false")),
+        }
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_i64(self.parse("an integer")?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_u64(self.parse("a positive integer")?)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_f64(self.parse("a number")?)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        if self.raw.trim().is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    // Lists are comma separated, e.g. VIX_DISABLED_STRATEGIES=2,3.
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        let key: String = self.key;
+        visitor.visit_seq(SeqDeserializer::new(parse_list(&self.raw).into_iter().map(
+            |raw| Setting {
+                key: key.clone(),
+                raw,
+            },
+        )))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        let variant: String = self.raw.trim().to_lowercase();
+        visitor
+            .visit_enum(variant.into_deserializer())
+            .map_err(|_: ValueError| self.invalid("one of the listed values"))
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i128 u8 u16 u32 u128 f32 char str string bytes byte_buf unit unit_struct
+        newtype_struct tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+// Raw settings keyed by lowercase name: the config file's values, overlaid by the environment.
+// A table in the file such as [spx] becomes spx_ prefixed keys, like SPX_ARB_VALUE in .env.
+pub(crate) struct ConfigSource {
+    values: HashMap<String, String>,
+}
+
+impl ConfigSource {
+    // Function that reads the config file, CONFIG_PATH or CONFIG_FILE if set, then the
+    // environment and .env on top of it.
+    pub(crate) fn load() -> Result<Self, String> {
+        dotenv().ok(); // Load the .env file if present.
+        let path: String = env::var("CONFIG_FILE").unwrap_or(CONFIG_PATH.to_string());
+        let mut source: ConfigSource = match fs::read_to_string(&path) {
+            Ok(text) => ConfigSource::from_toml(&text).map_err(|e| format!("{}: {}", path, e))?,
+            Err(_) => ConfigSource::from_pairs(Vec::<(String, String)>::new()),
+        };
+        source
+            .values
+            .extend(env::vars().map(|(key, val)| (key.to_lowercase(), val)));
+        Ok(source)
+    }
+
+    // Function that builds a source from key value pairs, keys as in .env.
+    pub(crate) fn from_pairs<K: AsRef<str>, V: Into<String>>(
+        pairs: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        ConfigSource {
+            values: pairs
+                .into_iter()
+                .map(|(key, val)| (key.as_ref().to_lowercase(), val.into()))
+                .collect(),
+        }
+    }
+
+    // Function that builds a source from the text of a TOML config file.
+    pub(crate) fn from_toml(text: &str) -> Result<Self, String> {
+        let table: toml::value::Table = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut values: HashMap<String, String> = HashMap::new();
+        flatten_toml("", &table, &mut values);
+        Ok(ConfigSource { values })
+    }
+
+    // Function that deserializes settings into a config struct.
+    fn deserialize<'de, T: Deserialize<'de>>(
+        values: &HashMap<String, String>,
+    ) -> Result<T, String> {
+        T::deserialize(MapDeserializer::<_, ValueError>::new(values.iter().map(
+            |(key, raw)| {
+                (
+                    key.clone(),
+                    Setting {
+                        key: key.clone(),
+                        raw: raw.clone(),
+                    },
+                )
+            },
+        )))
+        .map_err(|e| e.to_string())
+    }
+
+    // Function that returns the settings for a ticker, its prefixed keys over the shared ones.
+    fn profile_values(&self, ticker: &str) -> HashMap<String, String> {
+        let prefix: String = format!("{}_", ticker.to_lowercase());
+        let mut values: HashMap<String, String> = self.values.clone();
+        for (key, val) in &self.values {
+            if let Some(key) = key.strip_prefix(&prefix) {
+                values.insert(key.to_string(), val.clone());
+            }
+        }
+        values
+    }
+}
+
+// Function that flattens TOML tables into prefixed keys, joining arrays with commas.
+fn flatten_toml(prefix: &str, table: &toml::value::Table, values: &mut HashMap<String, String>) {
+    for (key, val) in table {
+        let key: String = if prefix.is_empty() {
+            key.to_lowercase()
+        } else {
+            format!("{}_{}", prefix, key.to_lowercase())
+        };
+        match val {
+            toml::Value::Table(table) => flatten_toml(&key, table, values),
+            toml::Value::String(val) => {
+                values.insert(key, val.clone());
+            }
+            toml::Value::Array(items) => {
+                let items: Vec<String> = items
+                    .iter()
+                    .map(|item| match item {
+                        toml::Value::String(item) => item.clone(),
+                        item => item.to_string(),
+                    })
+                    .collect();
+                values.insert(key, items.join(","));
+            }
+            val => {
+                values.insert(key, val.to_string());
+            }
+        }
+    }
+}
+
+// Fill probability model chosen in FILL_MODEL.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FillModelKind {
+    #[default]
+    Off,
+    Size,
+    Historical,
+}
+
+// How quotes missing a bid, ask or ask size are treated, chosen in MISSING_QUOTES: skipped,
+// so combinations with such a leg aren't scanned, or priced at zero as real quotes.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum MissingQuotes {
+    #[default]
+    Skip,
+    Zero,
+}
+
+// Order confirmation policy chosen in REPLY_POLICY.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ReplyPolicyKind {
+    AutoConfirmAll,
+    #[default]
+    Allowlist,
+    Never,
+}
+
+// Settings for one ticker, each overridable per ticker with a prefix such as SPX_ARB_VALUE.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(default)]
+pub(crate) struct ProfileConfig {
+    pub(crate) option: Option<String>,
+    pub(crate) execution_profile: Option<String>,
+    // The 1/2/3 codes EXECUTION_PROFILE replaced, still read as the preset they name.
+    pub(crate) fill_type: Option<String>,
+    pub(crate) arb_value: f64,
+    pub(crate) strike_dif_value: f64,
+    pub(crate) butterfly_widths: usize,
+    pub(crate) butterfly_arb_values: Vec<f64>,
+    pub(crate) discount_value: Option<f64>,
+    pub(crate) tick_size: f64,
+    pub(crate) tick_threshold: f64,
+    pub(crate) tick_size_above: Option<f64>,
+    pub(crate) calendar_min_profit: f64,
+    pub(crate) butterfly_min_profit: f64,
+    pub(crate) boxspread_min_profit: f64,
+    pub(crate) fee_per_contract: f64,
+    pub(crate) risk_free_rate: f64,
+    pub(crate) hedge_delta_threshold: f64,
+    pub(crate) hedge_conid: Option<String>,
+    pub(crate) hedge_delta_per_unit: f64,
+    pub(crate) expiry_action: ExpiryAction,
+    #[serde(deserialize_with = "deserialize_expiry_cutoff")]
+    pub(crate) expiry_cutoff: NaiveTime,
+    pub(crate) physical_settlement: Option<bool>,
+    pub(crate) roll_dte: Option<i64>,
+    pub(crate) roll_profit_pct: f64,
+    pub(crate) order_referrer: String,
+    pub(crate) calendar_order_tag: Option<String>,
+    pub(crate) butterfly_order_tag: Option<String>,
+    pub(crate) boxspread_order_tag: Option<String>,
+    pub(crate) calendar_min_price: Option<f64>,
+    pub(crate) calendar_max_price: Option<f64>,
+    pub(crate) butterfly_min_price: Option<f64>,
+    pub(crate) butterfly_max_price: Option<f64>,
+    pub(crate) boxspread_min_price: Option<f64>,
+    pub(crate) boxspread_max_price: Option<f64>,
+    pub(crate) calendar_enabled: Option<bool>,
+    pub(crate) butterfly_enabled: Option<bool>,
+    pub(crate) boxspread_enabled: Option<bool>,
+    pub(crate) calendar_scan_interval: u64,
+    pub(crate) butterfly_scan_interval: u64,
+    pub(crate) boxspread_scan_interval: u64,
+    #[serde(deserialize_with = "deserialize_scan_windows")]
+    pub(crate) calendar_scan_windows: Vec<(NaiveTime, NaiveTime)>,
+    #[serde(deserialize_with = "deserialize_scan_windows")]
+    pub(crate) butterfly_scan_windows: Vec<(NaiveTime, NaiveTime)>,
+    #[serde(deserialize_with = "deserialize_scan_windows")]
+    pub(crate) boxspread_scan_windows: Vec<(NaiveTime, NaiveTime)>,
+    pub(crate) max_spreads_per_expiry: u64,
+    pub(crate) max_loss_per_expiry: f64,
+    pub(crate) max_short_per_strike: f64,
+    pub(crate) prefer_reducing_exposure: bool,
+}
+
+impl Default for ProfileConfig {
+    fn default() -> Self {
+        let tick_rule: TickRule = TickRule::default();
+        let profit_gate: ProfitGate = ProfitGate::default();
+        let hedge_rule: HedgeRule = HedgeRule::default();
+        let expiry_rule: ExpiryRule = ExpiryRule::default();
+        let order_tags: OrderTags = OrderTags::default();
+        ProfileConfig {
+            option: None,
+            execution_profile: None,
+            fill_type: None,
+            arb_value: 0.10,
+            strike_dif_value: 5.0,
+            butterfly_widths: 1,
+            butterfly_arb_values: Vec::new(),
+            discount_value: None,
+            tick_size: tick_rule.tick,
+            tick_threshold: tick_rule.threshold,
+            tick_size_above: None,
+            calendar_min_profit: profit_gate.calendar_min_profit,
+            butterfly_min_profit: profit_gate.butterfly_min_profit,
+            boxspread_min_profit: profit_gate.boxspread_min_profit,
+            fee_per_contract: profit_gate.fee_per_contract,
+            risk_free_rate: profit_gate.risk_free_rate,
+            hedge_delta_threshold: hedge_rule.threshold,
+            hedge_conid: hedge_rule.conid,
+            hedge_delta_per_unit: hedge_rule.delta_per_unit,
+            expiry_action: expiry_rule.action,
+            expiry_cutoff: expiry_rule.cutoff,
+            physical_settlement: None,
+            roll_dte: None,
+            roll_profit_pct: 0.0,
+            order_referrer: order_tags.referrer,
+            calendar_order_tag: order_tags.calendar,
+            butterfly_order_tag: order_tags.butterfly,
+            boxspread_order_tag: order_tags.boxspread,
+            calendar_min_price: None,
+            calendar_max_price: None,
+            butterfly_min_price: None,
+            butterfly_max_price: None,
+            boxspread_min_price: None,
+            boxspread_max_price: None,
+            calendar_enabled: None,
+            butterfly_enabled: None,
+            boxspread_enabled: None,
+            calendar_scan_interval: 0,
+            butterfly_scan_interval: 0,
+            boxspread_scan_interval: 0,
+            calendar_scan_windows: Vec::new(),
+            butterfly_scan_windows: Vec::new(),
+            boxspread_scan_windows: Vec::new(),
+            max_spreads_per_expiry: 0,
+            max_loss_per_expiry: 0.0,
+            max_short_per_strike: 0.0,
+            prefer_reducing_exposure: false,
+        }
+    }
+}
+
+impl ProfileConfig {
+    // Function that resets out of range values to their defaults.
+    fn validate(&mut self) {
+        let default: ProfileConfig = ProfileConfig::default();
+        if self.execution_profile.as_ref().is_some_and(|spec| {
+            parse_execution_profile(spec, &ExecutionProfile::default()).is_none()
+        }) {
+            println!("Not a valid Execution Profile, setting to balanced");
+            self.execution_profile = Some("balanced".to_string());
+        }
+        if self.arb_value < 0.10 {
+            println!("Not a valid Arb Value, setting to 0.10");
+            self.arb_value = default.arb_value;
+        }
+        if self.strike_dif_value < 0.0 {
+            println!("Not a valid Strike Dif Value, setting to 5.0");
+            self.strike_dif_value = default.strike_dif_value;
+        }
+        if !(1..=MAX_BUTTERFLY_WIDTHS).contains(&self.butterfly_widths) {
+            println!("Not a valid Butterfly Widths, setting to 1");
+            self.butterfly_widths = default.butterfly_widths;
+        }
+        if self.butterfly_arb_values.iter().any(|val| *val < 0.10) {
+            println!("Not a valid Butterfly Arb Values, scaling the Arb Value by width");
+            self.butterfly_arb_values = default.butterfly_arb_values;
+        }
+        if self
+            .discount_value
+            .is_some_and(|val| !(-0.15..=0.15).contains(&val))
+        {
+            println!("Not a valid Discount Value, setting to 0.0");
+            self.discount_value = Some(0.0);
+        }
+        if self.tick_size < 0.0 {
+            println!("Not a valid Tick Size, setting to {}", default.tick_size);
+            self.tick_size = default.tick_size;
+        }
+        if self.tick_threshold < 0.0 {
+            println!(
+                "Not a valid Tick Threshold, setting to {}",
+                default.tick_threshold
+            );
+            self.tick_threshold = default.tick_threshold;
+        }
+        if self.tick_size_above.is_some_and(|val| val < 0.0) {
+            println!("Not a valid Tick Size Above, setting to {}", self.tick_size);
+            self.tick_size_above = None;
+        }
+        self.fee_per_contract = self.fee_per_contract.max(0.0);
+        if self.max_loss_per_expiry < 0.0 {
+            println!("Not a valid Max Loss Per Expiry, setting to 0.0 (disabled)");
+            self.max_loss_per_expiry = default.max_loss_per_expiry;
+        }
+        if self.max_short_per_strike < 0.0 {
+            println!("Not a valid Max Short Per Strike, setting to 0.0 (disabled)");
+            self.max_short_per_strike = default.max_short_per_strike;
+        }
+        if self.hedge_delta_threshold < 0.0 {
+            println!("Not a valid Hedge Delta Threshold, setting to 0.0 (disabled)");
+            self.hedge_delta_threshold = default.hedge_delta_threshold;
+        }
+        if self
+            .hedge_conid
+            .as_ref()
+            .is_some_and(|conid| conid.trim().is_empty())
+        {
+            self.hedge_conid = None;
+        }
+        if self.hedge_delta_threshold > 0.0 && self.hedge_conid.is_none() {
+            println!(
+                "Hedging needs a Hedge Conid, setting Hedge Delta Threshold to 0.0 (disabled)"
+            );
+            self.hedge_delta_threshold = default.hedge_delta_threshold;
+        }
+        if self.hedge_delta_per_unit <= 0.0 {
+            println!("Not a valid Hedge Delta Per Unit, setting to 1.0");
+            self.hedge_delta_per_unit = default.hedge_delta_per_unit;
+        }
+        if self.roll_dte.is_some_and(|val| val < 0) {
+            println!("Not a valid Roll DTE, disabling DTE rolls");
+            self.roll_dte = None;
+        }
+        if !(0.0..=1.0).contains(&self.roll_profit_pct) {
+            println!("Not a valid Roll Profit Pct, disabling profit rolls");
+            self.roll_profit_pct = default.roll_profit_pct;
+        }
+        if self.order_referrer.trim().is_empty() {
+            println!("Not a valid Order Referrer, setting to NO_REFERRER_PROVIDED");
+            self.order_referrer = default.order_referrer;
+        }
+        for (val, name) in [
+            (&mut self.calendar_order_tag, "Calendar Order Tag"),
+            (&mut self.butterfly_order_tag, "Butterfly Order Tag"),
+            (&mut self.boxspread_order_tag, "Boxspread Order Tag"),
+        ] {
+            if val.as_deref().is_some_and(|tag| !is_valid_order_tag(tag)) {
+                println!("Not a valid {}, leaving its orders untagged", name);
+                *val = None;
+            }
+        }
+        for (min, max, name) in [
+            (
+                &mut self.calendar_min_price,
+                &mut self.calendar_max_price,
+                "Calendar",
+            ),
+            (
+                &mut self.butterfly_min_price,
+                &mut self.butterfly_max_price,
+                "Butterfly",
+            ),
+            (
+                &mut self.boxspread_min_price,
+                &mut self.boxspread_max_price,
+                "Boxspread",
+            ),
+        ] {
+            if let (Some(low), Some(high)) = (*min, *max) {
+                if low > high {
+                    println!(
+                        "Not a valid {} Min Price and Max Price, leaving its prices unbounded",
+                        name
+                    );
+                    *min = None;
+                    *max = None;
+                }
+            }
+        }
+    }
+
+    // Function that prompts for the strategy, execution profile and discount when they aren't
+    // set.
+    fn prompt_missing(&mut self, ticker: &str) {
+        let strategies_set: bool = self.calendar_enabled.is_some()
+            || self.butterfly_enabled.is_some()
+            || self.boxspread_enabled.is_some();
+        if self.option.is_none() && !strategies_set {
+            let prompt: &str = "\
+1 for Calendar
+2 for Butterfly
+3 for Boxspread
+DEFAULT for Calendar + Butterfly + Boxspread
+";
+            self.option = Some(get_user_input(&format!(
+                "{}\nEnter which strategy the bot should use for {}:",
+                prompt, ticker
+            )));
+        }
+        if self.execution_profile.is_none() && self.fill_type.is_none() {
+            let prompt: &str = "\
+conservative for single order, single fill
+concentrated for single order, multiple fills
+spread-across-orders for multiple orders, single fill
+balanced for multiple orders, multiple fills
+";
+            let input: String = get_user_input(&format!(
+                "{}\nEnter which execution profile the bot should use for {}:",
+                prompt, ticker
+            ));
+            self.execution_profile = Some(
+                match parse_execution_profile(&input, &ExecutionProfile::default()) {
+                    Some(_) => input,
+                    None => {
+                        println!("Not a valid Execution Profile, setting to balanced");
+                        "balanced".to_string()
+                    }
+                },
+            );
+        }
+        if self.discount_value.is_none() {
+            let input: String = get_user_input("Enter a Discount Value between -0.15 and 0.15:");
+            self.discount_value = Some(match input.parse::<f64>() {
+                Ok(val) if (-0.15..=0.15).contains(&val) => val,
+                _ => {
+                    println!("Not a valid Discount Value, setting to 0.0");
+                    0.0
+                }
+            });
+        }
+    }
+
+    // Function that returns when each strategy is scanned. OPTION picks the strategies a
+    // *_ENABLED setting doesn't turn on or off itself.
+    fn strategies(&self) -> StrategySchedules {
+        let option: &str = self.option.as_deref().unwrap_or_default();
+        let schedule = |code: &str,
+                        enabled: Option<bool>,
+                        interval: u64,
+                        windows: &[(NaiveTime, NaiveTime)]|
+         -> StrategySchedule {
+            StrategySchedule {
+                enabled: enabled.unwrap_or(!matches!(option, "1" | "2" | "3") || option == code),
+                interval,
+                windows: windows.to_vec(),
+            }
+        };
+        StrategySchedules {
+            calendar: schedule(
+                "1",
+                self.calendar_enabled,
+                self.calendar_scan_interval,
+                &self.calendar_scan_windows,
+            ),
+            butterfly: schedule(
+                "2",
+                self.butterfly_enabled,
+                self.butterfly_scan_interval,
+                &self.butterfly_scan_windows,
+            ),
+            boxspread: schedule(
+                "3",
+                self.boxspread_enabled,
+                self.boxspread_scan_interval,
+                &self.boxspread_scan_windows,
+            ),
+        }
+    }
+
+    // Function that builds the trading profile for a ticker, its execution profile filled in
+    // from base where the spec doesn't say.
+    pub(crate) fn profile(&self, ticker: &str, base: &ExecutionProfile) -> Profile {
+        let spec: &str = match (&self.execution_profile, &self.fill_type) {
+            (Some(spec), _) => spec,
+            (None, Some(fill_type)) => fill_type_preset(fill_type),
+            (None, None) => "balanced",
+        };
+        Profile {
+            ticker: ticker.to_string(),
+            execution: parse_execution_profile(spec, base).unwrap_or_else(|| base.clone()),
+            arb_val: self.arb_value,
+            strike_dif_value: self.strike_dif_value,
+            butterfly_wings: ButterflyWings {
+                widths: self.butterfly_widths,
+                arb_values: self.butterfly_arb_values.clone(),
+            },
+            discount_value: self.discount_value.unwrap_or(0.0),
+            tick_rule: TickRule {
+                tick: self.tick_size,
+                threshold: self.tick_threshold,
+                tick_above: self.tick_size_above.unwrap_or(self.tick_size),
+            },
+            profit_gate: ProfitGate {
+                calendar_min_profit: self.calendar_min_profit,
+                butterfly_min_profit: self.butterfly_min_profit,
+                boxspread_min_profit: self.boxspread_min_profit,
+                fee_per_contract: self.fee_per_contract,
+                risk_free_rate: self.risk_free_rate,
+            },
+            hedge_rule: HedgeRule {
+                threshold: self.hedge_delta_threshold,
+                conid: self.hedge_conid.clone(),
+                delta_per_unit: self.hedge_delta_per_unit,
+            },
+            expiry_rule: ExpiryRule {
+                action: self.expiry_action,
+                cutoff: self.expiry_cutoff,
+                physical_settlement: self
+                    .physical_settlement
+                    .unwrap_or_else(|| is_physically_settled(ticker)),
+            },
+            roll_rule: RollRule {
+                dte: self.roll_dte,
+                profit_pct: self.roll_profit_pct,
+            },
+            order_tags: OrderTags {
+                referrer: self.order_referrer.clone(),
+                calendar: self.calendar_order_tag.clone(),
+                butterfly: self.butterfly_order_tag.clone(),
+                boxspread: self.boxspread_order_tag.clone(),
+            },
+            strategies: self.strategies(),
+            expiry_caps: ExpiryCaps {
+                max_spreads: self.max_spreads_per_expiry as f64,
+                max_loss: self.max_loss_per_expiry,
+            },
+            position_rules: PositionRules {
+                max_short_per_strike: self.max_short_per_strike,
+                prefer_reducing: self.prefer_reducing_exposure,
+            },
+            price_limits: PriceLimits {
+                calendar: PriceBounds {
+                    min: self.calendar_min_price,
+                    max: self.calendar_max_price,
+                },
+                butterfly: PriceBounds {
+                    min: self.butterfly_min_price,
+                    max: self.butterfly_max_price,
+                },
+                boxspread: PriceBounds {
+                    min: self.boxspread_min_price,
+                    max: self.boxspread_max_price,
+                },
+            },
+        }
+    }
+}
+
+// Function that checks an order tag fits in a client order id: up to 16 letters, digits or
+// underscores.
+pub(crate) fn is_valid_order_tag(tag: &str) -> bool {
+    !tag.is_empty() && tag.len() <= 16 && tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Function that parses a strategy's scan windows, warning about windows that don't parse.
+fn deserialize_scan_windows<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(NaiveTime, NaiveTime)>, D::Error> {
+    let val: String = String::deserialize(deserializer)?;
+    let windows: Vec<(NaiveTime, NaiveTime)> = parse_time_windows(&val);
+    if windows.len() != val.split(';').filter(|w| !w.trim().is_empty()).count() {
+        println!("Some Scan Windows are not valid and were ignored");
+    }
+    Ok(windows)
+}
+
+// Function that parses SCAN_SCHEDULE, warning about windows that don't parse.
+fn deserialize_scan_schedule<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<ScanWindow>, D::Error> {
+    let val: String = String::deserialize(deserializer)?;
+    let schedule: Vec<ScanWindow> = parse_scan_schedule(&val);
+    if schedule.len() != val.split(';').filter(|w| !w.trim().is_empty()).count() {
+        println!("Some Scan Schedule windows are not valid and were ignored");
+    }
+    Ok(schedule)
+}
+
+// Function that parses EXPIRY_CUTOFF as HH:MM, warning and keeping the default when it doesn't.
+fn deserialize_expiry_cutoff<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<NaiveTime, D::Error> {
+    let val: String = String::deserialize(deserializer)?;
+    let default: NaiveTime = ExpiryRule::default().cutoff;
+    Ok(
+        NaiveTime::parse_from_str(val.trim(), "%H:%M").unwrap_or_else(|_| {
+            println!(
+                "Not a valid Expiry Cutoff, setting to {}",
+                default.format("%H:%M")
+            );
+            default
+        }),
+    )
+}
+
+// Function that parses MARKET_HOLIDAYS, warning about dates that don't parse.
+fn deserialize_market_holidays<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<NaiveDate>, D::Error> {
+    let val: String = String::deserialize(deserializer)?;
+    let holidays: Vec<NaiveDate> = parse_market_holidays(&val);
+    if holidays.len() != val.split(';').filter(|d| !d.trim().is_empty()).count() {
+        println!("Some Market Holidays are not valid and were ignored");
+    }
+    Ok(holidays)
+}
+
+// Function that parses BLACKOUT_WINDOWS, warning about windows that don't parse.
+fn deserialize_blackout_windows<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(NaiveDateTime, NaiveDateTime)>, D::Error> {
+    let val: String = String::deserialize(deserializer)?;
+    let windows: Vec<(NaiveDateTime, NaiveDateTime)> = parse_blackout_windows(&val);
+    if windows.len() != val.split(';').filter(|w| !w.trim().is_empty()).count() {
+        println!("Some Blackout Windows are not valid and were ignored");
+    }
+    Ok(windows)
+}
+
+// Function that parses LEG_FILTERS, warning about steps that don't parse.
+fn deserialize_leg_filters<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<LegFilter>, D::Error> {
+    let val: String = String::deserialize(deserializer)?;
+    let filters: Vec<LegFilter> = parse_leg_filters(&val);
+    if filters.len() != val.split(';').filter(|f| !f.trim().is_empty()).count() {
+        println!("Some Leg Filters are not valid and were ignored");
+    }
+    Ok(filters)
+}
+
+// Function that parses HTTP_HEADERS, "Name: value" pairs separated by semicolons.
+fn deserialize_headers<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(String, String)>, D::Error> {
+    Ok(parse_headers(&String::deserialize(deserializer)?))
+}
+
+// Function that parses GATEWAY_RESOLVE, "host=ip,ip" mappings separated by semicolons.
+fn deserialize_static_hosts<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(String, Vec<IpAddr>)>, D::Error> {
+    Ok(parse_static_hosts(&String::deserialize(deserializer)?))
+}
+
+// Every setting the bot reads, named after its .env key in lowercase. Values left unset in the
+// file and the environment take the defaults below, except the ones prompted for at startup.
+#[derive(Deserialize)]
+#[serde(default)]
+pub(crate) struct Config {
+    #[serde(rename = "profiles")]
+    pub(crate) tickers: Vec<String>,
+    pub(crate) ticker: Option<String>,
+    #[serde(skip)]
+    pub(crate) profiles: Vec<Profile>,
+    pub(crate) test_mode: Option<bool>,
+    pub(crate) max_clock_drift: f64,
+    pub(crate) seconds_to_sleep: Option<u64>,
+    #[serde(deserialize_with = "deserialize_scan_schedule")]
+    pub(crate) scan_schedule: Vec<ScanWindow>,
+    pub(crate) scan_jitter_pct: f64,
+    pub(crate) size_jitter_pct: f64,
+    pub(crate) num_days: Option<i64>,
+    pub(crate) num_days_offset: Option<i64>,
+    pub(crate) max_underlying_move: f64,
+    pub(crate) max_underlying_move_pct: f64,
+    pub(crate) fast_market_spread_multiple: f64,
+    pub(crate) fast_market_window: usize,
+    pub(crate) open_warmup_minutes: i64,
+    pub(crate) wind_down_minutes: i64,
+    pub(crate) daemon: bool,
+    #[serde(deserialize_with = "deserialize_market_holidays")]
+    pub(crate) market_holidays: Vec<NaiveDate>,
+    #[serde(deserialize_with = "deserialize_blackout_windows")]
+    pub(crate) blackout_windows: Vec<(NaiveDateTime, NaiveDateTime)>,
+    pub(crate) cancel_on_blackout: bool,
+    pub(crate) strategy_window: usize,
+    pub(crate) strategy_min_fill_rate: f64,
+    pub(crate) strategy_max_slippage: f64,
+    pub(crate) order_lifetime: u64,
+    pub(crate) order_max_drift: f64,
+    pub(crate) order_join_best: bool,
+    pub(crate) order_escalate_after: u64,
+    pub(crate) order_escalate_step: f64,
+    pub(crate) order_submit_delay_ms: u64,
+    pub(crate) ladder_step: f64,
+    pub(crate) ladder_rungs: i32,
+    pub(crate) ladder_cancel_on_fill: bool,
+    pub(crate) net_orders: bool,
+    pub(crate) combo_quotes: bool,
+    pub(crate) max_quote_size: f64,
+    pub(crate) missing_quotes: MissingQuotes,
+    pub(crate) skew_max_residual: f64,
+    pub(crate) realized_move_multiple: f64,
+    pub(crate) realized_vol_days: usize,
+    pub(crate) near_miss_margin: f64,
+    pub(crate) max_notional_per_minute: f64,
+    pub(crate) max_notional_per_hour: f64,
+    #[serde(deserialize_with = "deserialize_leg_filters")]
+    pub(crate) leg_filters: Vec<LegFilter>,
+    pub(crate) incremental_scan: bool,
+    pub(crate) dedupe_contenders: bool,
+    pub(crate) blacklist_after: u64,
+    pub(crate) blacklist_half_life: f64,
+    pub(crate) fill_model: FillModelKind,
+    pub(crate) fill_size_half: f64,
+    pub(crate) vix_threshold: f64,
+    pub(crate) vix_arb_scale: f64,
+    pub(crate) vix_disabled_strategies: Vec<String>,
+    pub(crate) reply_policy: ReplyPolicyKind,
+    pub(crate) reply_allowlist: Vec<String>,
+    pub(crate) log_level: LogLevel,
+    pub(crate) mask_account_ids: bool,
+    pub(crate) account_alias: String,
+    pub(crate) mask_balances: bool,
+    pub(crate) http_trace: bool,
+    pub(crate) http_timeout_ms: u64,
+    pub(crate) http_user_agent: String,
+    #[serde(deserialize_with = "deserialize_headers")]
+    pub(crate) http_headers: Vec<(String, String)>,
+    pub(crate) domain: Option<String>,
+    pub(crate) port: Option<String>,
+    pub(crate) gateway_scheme: String,
+    pub(crate) gateway_base_path: String,
+    pub(crate) gateway_ip_version: IpVersion,
+    #[serde(deserialize_with = "deserialize_static_hosts")]
+    pub(crate) gateway_resolve: Vec<(String, Vec<IpAddr>)>,
+    pub(crate) gateway_bearer_token: Option<String>,
+    pub(crate) gateway_basic_auth: Option<String>,
+    pub(crate) secondary_domain: Option<String>,
+    pub(crate) secondary_port: Option<String>,
+    pub(crate) failover_after: u64,
+    pub(crate) failback_after: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            tickers: Vec::new(),
+            ticker: None,
+            profiles: Vec::new(),
+            test_mode: None,
+            max_clock_drift: 5.0,
+            seconds_to_sleep: None,
+            scan_schedule: Vec::new(),
+            scan_jitter_pct: 0.0,
+            size_jitter_pct: 0.0,
+            num_days: None,
+            num_days_offset: None,
+            max_underlying_move: 0.0,
+            max_underlying_move_pct: 0.0,
+            fast_market_spread_multiple: 0.0,
+            fast_market_window: 20,
+            open_warmup_minutes: 5,
+            wind_down_minutes: 5,
+            daemon: false,
+            market_holidays: Vec::new(),
+            blackout_windows: Vec::new(),
+            cancel_on_blackout: false,
+            strategy_window: 20,
+            strategy_min_fill_rate: 0.0,
+            strategy_max_slippage: 0.0,
+            order_lifetime: 0,
+            order_max_drift: 0.0,
+            order_join_best: false,
+            order_escalate_after: 0,
+            order_escalate_step: 0.05,
+            order_submit_delay_ms: 0,
+            ladder_step: 0.0,
+            ladder_rungs: 3,
+            ladder_cancel_on_fill: false,
+            net_orders: false,
+            combo_quotes: false,
+            max_quote_size: 10000.0,
+            missing_quotes: MissingQuotes::Skip,
+            skew_max_residual: 0.0,
+            realized_move_multiple: 0.0,
+            realized_vol_days: 20,
+            near_miss_margin: 0.0,
+            max_notional_per_minute: 0.0,
+            max_notional_per_hour: 0.0,
+            leg_filters: default_leg_filters(),
+            incremental_scan: true,
+            dedupe_contenders: true,
+            blacklist_after: 0,
+            blacklist_half_life: 30.0,
+            fill_model: FillModelKind::Off,
+            fill_size_half: 10.0,
+            vix_threshold: 0.0,
+            vix_arb_scale: 1.0,
+            vix_disabled_strategies: Vec::new(),
+            reply_policy: ReplyPolicyKind::Allowlist,
+            reply_allowlist: Vec::new(),
+            log_level: LogLevel::Info,
+            mask_account_ids: true,
+            account_alias: "[ACCOUNT]".to_string(),
+            mask_balances: false,
+            http_trace: false,
+            http_timeout_ms: 30000,
+            http_user_agent: DEFAULT_USER_AGENT.to_string(),
+            http_headers: Vec::new(),
+            domain: None,
+            port: None,
+            gateway_scheme: "https".to_string(),
+            gateway_base_path: String::new(),
+            gateway_ip_version: IpVersion::Any,
+            gateway_resolve: Vec::new(),
+            gateway_bearer_token: None,
+            gateway_basic_auth: None,
+            secondary_domain: None,
+            secondary_port: None,
+            failover_after: 3,
+            failback_after: 5,
+        }
+    }
+}
+
+// The settings the one-shot commands that print and exit need, read without validating or
+// prompting for the rest of the config so nothing else is printed.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub(crate) struct ConsoleSettings {
+    pub(crate) mask_balances: bool,
+}
+
+impl ConsoleSettings {
+    // Function that loads the console settings, the defaults when the config can't be read.
+    pub(crate) fn load() -> ConsoleSettings {
+        ConfigSource::load()
+            .and_then(|source| ConfigSource::deserialize(&source.values))
+            .unwrap_or_default()
+    }
+}
+
+impl Config {
+    // Function that loads the config from the config file, the environment and .env, prompting
+    // for whatever required setting is still missing.
+    pub(crate) fn load() -> Result<Config, String> {
+        Config::from_source(&ConfigSource::load()?, true)
+    }
+
+    // Function that deserializes and validates the shared settings, then one profile per ticker
+    // listed in PROFILES, or a single profile for TICKER when PROFILES is not set.
+    pub(crate) fn from_source(source: &ConfigSource, prompt: bool) -> Result<Config, String> {
+        let mut config: Config = ConfigSource::deserialize(&source.values)?;
+        config.validate();
+        if config.tickers.is_empty() {
+            config.tickers.extend(config.ticker.clone());
+        }
+        if prompt {
+            config.prompt_missing();
+        }
+
+        for ticker in &config.tickers {
+            let mut profile: ProfileConfig =
+                ConfigSource::deserialize(&source.profile_values(ticker))
+                    .map_err(|e| format!("{}: {}", ticker, e))?;
+            profile.validate();
+            if prompt {
+                profile.prompt_missing(ticker);
+            }
+            let built: Profile = profile.profile(ticker, &config.execution_base());
+            config.profiles.push(built);
+        }
+
+        Ok(config)
+    }
+
+    // Function that returns the execution settings a profile starts from before its preset and
+    // overrides: the shared LADDER_STEP, LADDER_RUNGS and ORDER_LIFETIME.
+    fn execution_base(&self) -> ExecutionProfile {
+        ExecutionProfile {
+            ladder_step: self.ladder_step,
+            ladder_rungs: self.ladder_rungs,
+            order_lifetime: self.order_lifetime,
+            ..ExecutionProfile::default()
+        }
+    }
+
+    // Function that resets out of range values to their defaults.
+    fn validate(&mut self) {
+        let default: Config = Config::default();
+        if self.seconds_to_sleep.is_some_and(|val| val < 5) {
+            println!("Not a valid positive integer greater than or equal to 5, setting to 60");
+            self.seconds_to_sleep = Some(60);
+        }
+        if self.num_days.is_some_and(|val| !(1..10).contains(&val)) {
+            println!("Not a valid number in the range 1-9, setting to 5");
+            self.num_days = Some(5);
+        }
+        if self
+            .num_days_offset
+            .is_some_and(|val| !(0..=21).contains(&val))
+        {
+            println!("Not a valid number in the range 0-21, setting to 0");
+            self.num_days_offset = Some(0);
+        }
+        for (val, name) in [
+            (&mut self.max_underlying_move, "Max Underlying Move"),
+            (&mut self.max_underlying_move_pct, "Max Underlying Move Pct"),
+            (
+                &mut self.fast_market_spread_multiple,
+                "Fast Market Spread Multiple",
+            ),
+            (&mut self.order_max_drift, "Order Max Drift"),
+            (&mut self.skew_max_residual, "Skew Max Residual"),
+            (&mut self.realized_move_multiple, "Realized Move Multiple"),
+            (&mut self.strategy_max_slippage, "Strategy Max Slippage"),
+            (&mut self.vix_threshold, "VIX Threshold"),
+            (&mut self.max_clock_drift, "Max Clock Drift"),
+            (&mut self.near_miss_margin, "Near Miss Margin"),
+            (&mut self.max_notional_per_minute, "Max Notional Per Minute"),
+            (&mut self.max_notional_per_hour, "Max Notional Per Hour"),
+            (&mut self.ladder_step, "Ladder Step"),
+        ] {
+            if *val < 0.0 {
+                println!("Not a valid {}, setting to 0.0 (disabled)", name);
+                *val = 0.0;
+            }
+        }
+        if !(0..=360).contains(&self.wind_down_minutes) {
+            println!("Not a valid Wind Down Minutes, setting to 5");
+            self.wind_down_minutes = default.wind_down_minutes;
+        }
+        if !(0.0..=0.5).contains(&self.scan_jitter_pct) {
+            println!("Not a valid Scan Jitter Pct, setting to 0.0 (disabled)");
+            self.scan_jitter_pct = 0.0;
+        }
+        if !(0.0..=0.5).contains(&self.size_jitter_pct) {
+            println!("Not a valid Size Jitter Pct, setting to 0.0 (disabled)");
+            self.size_jitter_pct = 0.0;
+        }
+        if !(0.0..=1.0).contains(&self.strategy_min_fill_rate) {
+            println!("Not a valid Strategy Min Fill Rate, setting to 0.0 (disabled)");
+            self.strategy_min_fill_rate = 0.0;
+        }
+        if self.strategy_window == 0 {
+            println!("Not a valid Strategy Window, setting to 20");
+            self.strategy_window = default.strategy_window;
+        }
+        if self.realized_vol_days < 2 {
+            println!("Not a valid Realized Vol Days, setting to 20");
+            self.realized_vol_days = default.realized_vol_days;
+        }
+        if self.blacklist_half_life <= 0.0 || self.blacklist_half_life.is_nan() {
+            println!("Not a valid Blacklist Half Life, setting to 30");
+            self.blacklist_half_life = default.blacklist_half_life;
+        }
+        if self.failover_after == 0 {
+            println!("Not a valid Failover After, setting to 3");
+            self.failover_after = default.failover_after;
+        }
+        if self.failback_after == 0 {
+            println!("Not a valid Failback After, setting to 5");
+            self.failback_after = default.failback_after;
+        }
+        if self.ladder_rungs < 1 {
+            println!("Not a valid Ladder Rungs, setting to 3");
+            self.ladder_rungs = default.ladder_rungs;
+        }
+        if self.order_escalate_step < 0.0 {
+            println!("Not a valid Order Escalate Step, setting to 0.05");
+            self.order_escalate_step = default.order_escalate_step;
+        }
+        if self.max_quote_size < 0.0 {
+            println!("Not a valid Max Quote Size, setting to 10000");
+            self.max_quote_size = default.max_quote_size;
+        }
+        if self.fill_size_half <= 0.0 {
+            println!("Not a valid Fill Size Half, setting to 10");
+            self.fill_size_half = default.fill_size_half;
+        }
+        if self.vix_arb_scale < 1.0 {
+            println!("Not a valid VIX Arb Scale, setting to 1.0");
+            self.vix_arb_scale = default.vix_arb_scale;
+        }
+        self.gateway_scheme = self.gateway_scheme.trim().to_lowercase();
+        if self.gateway_scheme != "http" && self.gateway_scheme != "https" {
+            println!("Not a valid Gateway Scheme, setting to https");
+            self.gateway_scheme = default.gateway_scheme;
+        }
+        if self.http_user_agent.trim().is_empty() {
+            self.http_user_agent = default.http_user_agent;
+        }
+        if self.account_alias.trim().is_empty() {
+            self.account_alias = default.account_alias;
+        }
+    }
+
+    // Function that prompts for the required settings that aren't set.
+    fn prompt_missing(&mut self) {
+        if self.tickers.is_empty() {
+            self.tickers
+                .push(get_user_input("Enter which ticker the bot should use:"));
+        }
+        if self.test_mode.is_none() {
+            let input: String =
+                get_user_input("Would you like to run the bot in testing mode? (Y / N):");
+            self.test_mode = Some(input.to_lowercase() == "yes" || input.to_lowercase() == "y");
+        }
+        if self.seconds_to_sleep.is_none() {
+            let input: String = get_user_input("Enter number of seconds to sleep:");
+            self.seconds_to_sleep = Some(match input.parse::<u64>() {
+                Ok(val) if val >= 5 => val,
+                _ => {
+                    println!(
+                        "Not a valid positive integer greater than or equal to 5, setting to 60"
+                    );
+                    60
+                }
+            });
+        }
+        if self.num_days.is_none() {
+            let input: String = get_user_input("Enter number of days worth of data to receive:");
+            self.num_days = Some(match input.parse::<i64>() {
+                Ok(val) if val > 0 && val < 10 => val,
+                _ => {
+                    println!("Not a valid number in the range 1-9, setting to 5");
+                    5
+                }
+            });
+        }
+        if self.num_days_offset.is_none() {
+            let input: String = get_user_input("Enter number of days offset:");
+            self.num_days_offset = Some(match input.parse::<i64>() {
+                Ok(val) if (0..=21).contains(&val) => val,
+                _ => {
+                    println!("Not a valid number in the range 0-21, setting to 0");
+                    0
+                }
+            });
+        }
+    }
+
+    // Function that returns whether orders are only logged, not submitted.
+    pub(crate) fn test_mode(&self) -> bool {
+        self.test_mode.unwrap_or(false)
+    }
+
+    // Function that returns the default seconds to sleep between scans.
+    pub(crate) fn seconds_to_sleep(&self) -> u64 {
+        self.seconds_to_sleep.unwrap_or(60)
+    }
+
+    // Function that returns the number of days of expiries to scan.
+    pub(crate) fn num_days(&self) -> i64 {
+        self.num_days.unwrap_or(5)
+    }
+
+    // Function that returns the number of days to offset from the current day.
+    pub(crate) fn num_days_offset(&self) -> i64 {
+        self.num_days_offset.unwrap_or(0)
+    }
+
+    // Function that fills the gateway address and credentials left unset from a secret store.
+    pub(crate) fn fill_secrets(&mut self, lookup: impl Fn(&str) -> Option<String>) {
+        for (val, key) in [
+            (&mut self.domain, "DOMAIN"),
+            (&mut self.port, "PORT"),
+            (&mut self.gateway_bearer_token, "GATEWAY_BEARER_TOKEN"),
+            (&mut self.gateway_basic_auth, "GATEWAY_BASIC_AUTH"),
+        ] {
+            if val.is_none() {
+                *val = lookup(key);
+            }
+        }
+    }
+
+    // Function that builds the gateway's base URL, localhost:5000 unless configured.
+    pub(crate) fn base_url(&self) -> String {
+        gateway_base_url(
+            &self.gateway_scheme,
+            self.domain.as_deref().unwrap_or("localhost"),
+            self.port.as_deref().unwrap_or("5000"),
+            &self.gateway_base_path,
+        )
+    }
+
+    // Function that builds the backup gateway's base URL, None unless SECONDARY_DOMAIN or
+    // SECONDARY_PORT is set. It shares the primary's scheme, base path and credentials.
+    pub(crate) fn secondary_base_url(&self) -> Option<String> {
+        if self.secondary_domain.is_none() && self.secondary_port.is_none() {
+            return None;
+        }
+        Some(gateway_base_url(
+            &self.gateway_scheme,
+            self.secondary_domain
+                .as_deref()
+                .or(self.domain.as_deref())
+                .unwrap_or("localhost"),
+            self.secondary_port
+                .as_deref()
+                .or(self.port.as_deref())
+                .unwrap_or("5000"),
+            &self.gateway_base_path,
+        ))
+    }
+
+    // Function that returns the extra headers sent on every request, auth included.
+    pub(crate) fn extra_headers(&self) -> Vec<(String, String)> {
+        let mut headers: Vec<(String, String)> = self.http_headers.clone();
+        headers.extend(auth_header(
+            self.gateway_bearer_token.as_deref(),
+            self.gateway_basic_auth.as_deref(),
+        ));
+        headers
+    }
+
+    // Function that returns how the gateway's hostname is resolved.
+    pub(crate) fn resolver(&self) -> ResolverConfig {
+        ResolverConfig {
+            ip_version: self.gateway_ip_version,
+            static_hosts: self.gateway_resolve.clone(),
+        }
+    }
+
+    // Function that builds the HTTP client every gateway request is sent with, giving up on a
+    // request after HTTP_TIMEOUT_MS so a hung gateway can't stall a cycle (0 waits forever).
+    pub(crate) fn client(&self) -> Result<Client, Box<dyn Error>> {
+        let mut builder: ClientBuilder = ClientBuilder::new()
+            .danger_accept_invalid_certs(true)
+            .default_headers(build_default_headers(
+                &self.http_user_agent,
+                &self.extra_headers(),
+            )?);
+        if self.http_timeout_ms > 0 {
+            builder = builder.timeout(Duration::from_millis(self.http_timeout_ms));
+        }
+        Ok(apply_resolver(builder, &self.resolver()).build()?)
+    }
+
+    // Function that builds the fill probability model used in ranking.
+    pub(crate) fn fill_model(&self) -> FillModel {
+        match self.fill_model {
+            FillModelKind::Off => FillModel::Off,
+            FillModelKind::Size => FillModel::Size {
+                half_size: self.fill_size_half,
+            },
+            FillModelKind::Historical => FillModel::Historical {
+                rates: load_fill_rates(JOURNAL_PATH),
+            },
+        }
+    }
+
+    // Function that builds how order confirmation prompts are answered.
+    pub(crate) fn reply_policy(&self) -> ReplyPolicy {
+        match self.reply_policy {
+            ReplyPolicyKind::AutoConfirmAll => ReplyPolicy::AutoConfirmAll,
+            ReplyPolicyKind::Allowlist => {
+                ReplyPolicy::ConfirmAllowlist(self.reply_allowlist.clone())
+            }
+            ReplyPolicyKind::Never => ReplyPolicy::NeverConfirm,
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use std::{fs, path::Path};
+
+use crate::logging::LogLevel;
+
+// Order submission is paused while this file exists, so trading can be stopped and resumed on
+// a running bot with touch and rm without losing its session.
+pub(crate) const PAUSE_PATH: &str = "pause";
+
+// Function that checks whether order submission is paused.
+pub(crate) fn is_paused() -> bool {
+    Path::new(PAUSE_PATH).exists()
+}
+
+// The log level written to this file overrides LOG_LEVEL until the file is removed, so a running
+// bot can be made chattier with e.g. echo debug > log_level.
+pub(crate) const LOG_LEVEL_PATH: &str = "log_level";
+
+// Function that returns the level set through the control file, None without a valid one.
+pub(crate) fn log_level_override() -> Option<LogLevel> {
+    LogLevel::parse(&fs::read_to_string(LOG_LEVEL_PATH).ok()?)
+}
+
+// Touching this file makes the bot write the chain it just scanned to chain-<ticker>.csv, for
+// research in pandas or Polars. The file is removed once the request is picked up.
+pub(crate) const DUMP_CHAIN_PATH: &str = "dump_chain";
+
+// Function that checks for a chain export request, removing it so each touch exports once.
+pub(crate) fn take_chain_dump_request() -> bool {
+    fs::remove_file(DUMP_CHAIN_PATH).is_ok()
+}
+
+// Function that returns the file a ticker's chain is exported to.
+pub(crate) fn chain_dump_path(ticker: &str) -> String {
+    format!("chain-{}.csv", ticker)
+}
@@ -0,0 +1,161 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use openssl::{
+    hash::MessageDigest,
+    pkcs5::pbkdf2_hmac,
+    rand::rand_bytes,
+    symm::{decrypt_aead, encrypt_aead, Cipher},
+};
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, ErrorKind},
+    sync::OnceLock,
+};
+
+use crate::metrics::increment;
+
+// Marks an encrypted line, lines without it are read as plain text.
+const ENCRYPTED_PREFIX: &str = "enc:";
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+// Random salt the key is derived with, created with the first key and needed to decrypt.
+pub(crate) const SALT_PATH: &str = "encryption.salt";
+const SALT_LEN: usize = 16;
+// PBKDF2 rounds, so guessing a passphrase costs as much per guess as the bot pays once a run.
+const KDF_ITERATIONS: usize = 210_000;
+
+// The key the journal, P&L file and book are encrypted with, unset when encryption is off.
+static CIPHER: OnceLock<LineCipher> = OnceLock::new();
+
+// Encrypts lines of the bot's files one at a time with AES-256-GCM, so they can still be
+// appended to. Each line is "enc:" and the base64 of its random nonce, ciphertext and tag.
+pub(crate) struct LineCipher {
+    key: [u8; 32],
+}
+
+impl LineCipher {
+    // Function that derives the key from a passphrase and salt with PBKDF2-HMAC-SHA256.
+    pub(crate) fn new(passphrase: &str, salt: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut key: [u8; 32] = [0; 32];
+        pbkdf2_hmac(
+            passphrase.as_bytes(),
+            salt,
+            KDF_ITERATIONS,
+            MessageDigest::sha256(),
+            &mut key,
+        )?;
+        Ok(LineCipher { key })
+    }
+
+    // Function that encrypts a line.
+    pub(crate) fn encrypt(&self, line: &str) -> Result<String, Box<dyn Error>> {
+        let mut nonce: [u8; NONCE_LEN] = [0; NONCE_LEN];
+        rand_bytes(&mut nonce)?;
+        let mut tag: [u8; TAG_LEN] = [0; TAG_LEN];
+        let ciphertext: Vec<u8> = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(&nonce),
+            &[],
+            line.as_bytes(),
+            &mut tag,
+        )?;
+        let sealed: Vec<u8> = [&nonce[..], &ciphertext, &tag].concat();
+        Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(sealed)))
+    }
+
+    // Function that decrypts a line, passing plain text lines through.
+    pub(crate) fn decrypt(&self, line: &str) -> Result<String, Box<dyn Error>> {
+        let encoded: &str = match line.strip_prefix(ENCRYPTED_PREFIX) {
+            Some(encoded) => encoded,
+            None => return Ok(line.to_string()),
+        };
+        let sealed: Vec<u8> = STANDARD.decode(encoded.trim())?;
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            return Err("Encrypted line is too short".into());
+        }
+        let (nonce, rest) = sealed.split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+        let plaintext: Vec<u8> = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(nonce),
+            &[],
+            ciphertext,
+            tag,
+        )?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+// Function that reads the salt file, creating it with a random salt when there isn't one yet.
+pub(crate) fn load_or_create_salt(path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+        Ok(encoded) => Ok(STANDARD.decode(encoded.trim())?),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let mut salt: [u8; SALT_LEN] = [0; SALT_LEN];
+            rand_bytes(&mut salt)?;
+            fs::write(path, STANDARD.encode(salt))?;
+            Ok(salt.to_vec())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Function that turns on encryption of the journal, P&L file and book with a passphrase.
+pub(crate) fn set_encryption_key(passphrase: &str) -> Result<(), Box<dyn Error>> {
+    let salt: Vec<u8> = load_or_create_salt(SALT_PATH)?;
+    let _ = CIPHER.set(LineCipher::new(passphrase, &salt)?);
+    Ok(())
+}
+
+// Function that checks whether encryption is on.
+pub(crate) fn encryption_enabled() -> bool {
+    CIPHER.get().is_some()
+}
+
+// Function that returns a line as it should be written, encrypted when encryption is on.
+pub(crate) fn seal_line(line: &str) -> io::Result<String> {
+    match CIPHER.get() {
+        Some(cipher) => cipher
+            .encrypt(line)
+            .map_err(|e| io::Error::other(e.to_string())),
+        None => Ok(line.to_string()),
+    }
+}
+
+// Function that returns a line as read back, None for an encrypted line that can't be decrypted
+// (no key or the wrong one), which is counted and skipped.
+pub(crate) fn open_line(line: String) -> Option<String> {
+    if !line.starts_with(ENCRYPTED_PREFIX) {
+        return Some(line);
+    }
+    match CIPHER.get().map(|cipher| cipher.decrypt(&line)) {
+        Some(Ok(line)) => Some(line),
+        _ => {
+            increment("lines_undecryptable", 1);
+            None
+        }
+    }
+}
+
+// Function that reads a file's lines, decrypting the encrypted ones.
+pub(crate) fn read_lines(path: &str) -> io::Result<impl Iterator<Item = String>> {
+    Ok(BufReader::new(File::open(path)?)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(open_line))
+}
+
+// Function that runs the `decrypt FILE` subcommand, printing the file's lines decrypted.
+pub(crate) fn run_decrypt_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let path: &String = args.first().ok_or("Usage: trading_bot_rust decrypt FILE")?;
+    let cipher: &LineCipher = CIPHER
+        .get()
+        .ok_or("No ENCRYPTION_KEY set in the environment, .env or OS keyring")?;
+    for line in BufReader::new(File::open(path)?).lines() {
+        println!("{}", cipher.decrypt(&line?)?);
+    }
+    Ok(())
+}
@@ -0,0 +1,160 @@
+// Equity with loan per unit of sizing, an account trades once it has one.
+const SIZING_UNIT: f64 = 800.0;
+
+// How a profile's orders and fills per order grow with the account, in $800 units of equity
+// with loan.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Sizing {
+    // One order of one fill.
+    Single,
+    // One order, a fill per unit.
+    Concentrated,
+    // An order of one fill per unit.
+    Spread,
+    // Orders and fills both grow with the square root of the units.
+    Balanced,
+}
+
+// How a profile works its orders: how many it places per scan and their fills, how the fills
+// are laddered, and how long an order may work before it is cancelled.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ExecutionProfile {
+    pub(crate) name: String,
+    pub(crate) sizing: Sizing,
+    // Orders per scan at most, 0 for no cap.
+    pub(crate) max_orders: i32,
+    pub(crate) max_fills: i32,
+    pub(crate) ladder_step: f64,
+    pub(crate) ladder_rungs: i32,
+    // Seconds an order may work, 0 to leave it to the end of the cycle.
+    pub(crate) order_lifetime: u64,
+}
+
+impl Default for ExecutionProfile {
+    fn default() -> Self {
+        ExecutionProfile {
+            name: "balanced".to_string(),
+            sizing: Sizing::Balanced,
+            max_orders: 0,
+            max_fills: 9,
+            ladder_step: 0.0,
+            ladder_rungs: 3,
+            order_lifetime: 0,
+        }
+    }
+}
+
+impl ExecutionProfile {
+    // Function that returns the number of orders and fills per order for an account's equity
+    // with loan, none below one unit.
+    pub(crate) fn sizes(&self, equity_with_loan: f64) -> (i32, i32) {
+        let (orders, fills): (i32, i32) =
+            calc_final_num_orders(self.sizing, self.max_fills.max(1), equity_with_loan);
+        if self.max_orders > 0 {
+            (orders.min(self.max_orders), fills)
+        } else {
+            (orders, fills)
+        }
+    }
+}
+
+// Function that calcs the number of orders and fills for a sizing.
+fn calc_final_num_orders(sizing: Sizing, max_fills: i32, port_val: f64) -> (i32, i32) {
+    let num_times: i32 = (port_val / SIZING_UNIT).floor() as i32;
+
+    if num_times < 1 {
+        return (0, 0);
+    }
+
+    match sizing {
+        Sizing::Single => (1, 1),
+        Sizing::Concentrated => (1, num_times.min(max_fills)),
+        Sizing::Spread => (num_times, 1),
+        Sizing::Balanced => get_optimal_num_orders(port_val, max_fills),
+    }
+}
+
+// Function that gets the ideal number of orders and fills.
+fn get_optimal_num_orders(portfolio_value: f64, max_fills: i32) -> (i32, i32) {
+    let num: i32 = (portfolio_value / SIZING_UNIT).sqrt() as i32;
+    if num > max_fills {
+        (
+            (portfolio_value / SIZING_UNIT / max_fills as f64).floor() as i32,
+            max_fills,
+        )
+    } else {
+        (num, num)
+    }
+}
+
+// Function that returns the sizing of a preset by name, or by the FILL_TYPE code it replaces.
+fn preset(name: &str) -> Option<(&'static str, Sizing)> {
+    match name.trim().to_lowercase().as_str() {
+        "conservative" | "1" => Some(("conservative", Sizing::Single)),
+        "concentrated" | "2" => Some(("concentrated", Sizing::Concentrated)),
+        "spread-across-orders" | "3" => Some(("spread-across-orders", Sizing::Spread)),
+        "balanced" | "all" | "default" => Some(("balanced", Sizing::Balanced)),
+        _ => None,
+    }
+}
+
+// Function that maps a legacy FILL_TYPE code to its preset, anything but 1, 2 or 3 being the
+// balanced default as before.
+pub(crate) fn fill_type_preset(fill_type: &str) -> &'static str {
+    match preset(fill_type) {
+        Some((name, _)) => name,
+        None => "balanced",
+    }
+}
+
+// Function that parses EXECUTION_PROFILE, a preset optionally followed by "key=value" overrides
+// separated by semicolons, e.g. "spread-across-orders;max_orders=4;order_lifetime=30".
+// Whatever it doesn't set comes from base. Returns None when the preset is unknown or an
+// override doesn't parse.
+pub(crate) fn parse_execution_profile(
+    value: &str,
+    base: &ExecutionProfile,
+) -> Option<ExecutionProfile> {
+    let mut profile: ExecutionProfile = base.clone();
+    let mut custom: bool = false;
+
+    for (i, step) in value.split(';').map(str::trim).enumerate() {
+        match step.split_once('=') {
+            None if i == 0 && !step.is_empty() => {
+                let (name, sizing): (&str, Sizing) = preset(step)?;
+                profile.name = name.to_string();
+                profile.sizing = sizing;
+            }
+            None if step.is_empty() => continue,
+            None => return None,
+            Some((key, val)) => {
+                let val: &str = val.trim();
+                match key.trim().to_lowercase().as_str() {
+                    "max_orders" => {
+                        profile.max_orders = val.parse().ok().filter(|val: &i32| *val >= 0)?;
+                    }
+                    "max_fills" => {
+                        profile.max_fills = val.parse().ok().filter(|val: &i32| *val >= 1)?;
+                    }
+                    "ladder_step" => {
+                        profile.ladder_step = val
+                            .parse()
+                            .ok()
+                            .filter(|val: &f64| val.is_finite() && *val >= 0.0)?;
+                    }
+                    "ladder_rungs" => {
+                        profile.ladder_rungs = val.parse().ok().filter(|val: &i32| *val >= 1)?;
+                    }
+                    "order_lifetime" => profile.order_lifetime = val.parse().ok()?,
+                    _ => return None,
+                }
+                custom = true;
+            }
+        }
+    }
+
+    if custom {
+        profile.name = format!("{} (custom)", profile.name);
+    }
+    Some(profile)
+}
@@ -0,0 +1,61 @@
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::{greeks::intrinsic_value, helpers::value_to_f64, structs::PositionResponse};
+
+// Index options that settle in cash, options on anything else settle into shares.
+const CASH_SETTLED: &[&str] = &[
+    "SPX", "SPXW", "XSP", "NDX", "NDXP", "RUT", "VIX", "DJX", "OEX",
+];
+
+// What is done with an option position on its expiration day once the cutoff passes.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ExpiryAction {
+    // Only warn about in the money short legs.
+    #[default]
+    Off,
+    // Close the expiring leg at market.
+    Close,
+    // Close the expiring leg and reopen it in the next expiry as one combo order.
+    Roll,
+}
+
+// Function that checks if options on a ticker settle into shares of the underlying.
+pub(crate) fn is_physically_settled(ticker: &str) -> bool {
+    !CASH_SETTLED.contains(&ticker.to_uppercase().as_str())
+}
+
+// Function that checks if an option position expires on the given day.
+pub(crate) fn expires_on(position: &PositionResponse, day: NaiveDate) -> bool {
+    position.position != 0.0
+        && position
+            .expiry
+            .as_ref()
+            .and_then(|expiry| NaiveDate::parse_from_str(expiry, "%Y%m%d").ok())
+            == Some(day)
+}
+
+// Function that checks if an option position is short and in the money.
+pub(crate) fn is_itm_short(position: &PositionResponse, spot: f64) -> bool {
+    let strike: Option<f64> = position.strike.as_ref().and_then(value_to_f64);
+    match (strike, position.put_or_call.as_deref()) {
+        (Some(strike), Some(right)) if position.position < 0.0 => {
+            intrinsic_value(spot, strike, &right[..1]) > 0.0
+        }
+        _ => false,
+    }
+}
+
+// Function that returns the conidex and quantity of the combo buying back or selling out an
+// expiring leg and reopening the same position in a later contract.
+pub(crate) fn roll_conidex(expiring_conid: &str, next_conid: &str, position: f64) -> (String, i32) {
+    let (close, open): (i32, i32) = if position < 0.0 { (1, -1) } else { (-1, 1) };
+    (
+        format!(
+            "28812380;;;{}/{},{}/{}",
+            expiring_conid, close, next_conid, open
+        ),
+        position.abs().round() as i32,
+    )
+}
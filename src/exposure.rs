@@ -0,0 +1,279 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    encryption::read_lines,
+    journal::{JournalEntry, JournalLeg},
+};
+
+// Shares per option contract, to turn per share prices into dollars.
+const CONTRACT_MULTIPLIER: f64 = 100.0;
+
+// What the bot has on in one of a ticker's expirations, as reported in health.json: its filled
+// and working spreads, the net debit they cost (negative for a net credit) and the most they
+// can lose at expiry, in dollars.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct ExpiryExposure {
+    pub(crate) ticker: String,
+    pub(crate) expiry: String,
+    pub(crate) spreads: f64,
+    pub(crate) net_debit: f64,
+    pub(crate) worst_case_loss: f64,
+}
+
+// Caps on a profile's exposure to any one expiration, 0 disables a cap.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct ExpiryCaps {
+    pub(crate) max_spreads: f64,
+    pub(crate) max_loss: f64,
+}
+
+// A spread order the bot placed, at its limit price while working and its fill price once
+// filled.
+#[derive(Clone, Debug)]
+struct OpenSpread {
+    ticker: String,
+    type_spread: String,
+    expiry: String,
+    quantity: f64,
+    price: f64,
+    legs: Vec<JournalLeg>,
+}
+
+impl OpenSpread {
+    // Function that returns how many spreads the order is, butterflies and boxes being ordered
+    // as two combos a fill.
+    fn spreads(&self) -> f64 {
+        match self.type_spread.as_str() {
+            "Butterfly" | "Boxspread" => self.quantity / 2.0,
+            _ => self.quantity,
+        }
+    }
+}
+
+// A roll the bot submitted, moving calendars of a ticker from one expiration to another once it
+// fills.
+struct PendingRoll {
+    ticker: String,
+    from_date: String,
+    to_date: String,
+    quantity: f64,
+}
+
+// The bot's open spreads by expiration, built by replaying its journal: submitted orders count
+// until they end unfilled, filled calendar rolls move calendars to the new expiration, and
+// expirations past drop out of the report. Positions closed by hand are not seen. A netted order
+// holds the spreads of each order it consolidates.
+#[derive(Default)]
+pub(crate) struct ExposureBook {
+    spreads: HashMap<String, Vec<OpenSpread>>,
+    rolls: HashMap<String, PendingRoll>,
+}
+
+impl ExposureBook {
+    // Function that updates the book with a journal entry.
+    pub(crate) fn apply(&mut self, entry: &JournalEntry) {
+        match entry {
+            JournalEntry::Submitted {
+                order_id,
+                ticker,
+                type_spread,
+                exp_date,
+                limit_price,
+                quantity,
+                legs,
+                ..
+            } => {
+                self.spreads
+                    .entry(order_id.clone())
+                    .or_default()
+                    .push(OpenSpread {
+                        ticker: ticker.clone(),
+                        type_spread: type_spread.clone(),
+                        expiry: exp_date.clone(),
+                        quantity: *quantity as f64,
+                        price: *limit_price,
+                        legs: legs.clone(),
+                    });
+            }
+            JournalEntry::Roll {
+                order_id,
+                ticker,
+                from_date,
+                to_date,
+                quantity,
+                ..
+            } => {
+                self.rolls.insert(
+                    order_id.clone(),
+                    PendingRoll {
+                        ticker: ticker.clone(),
+                        from_date: from_date.clone(),
+                        to_date: to_date.clone(),
+                        quantity: *quantity as f64,
+                    },
+                );
+            }
+            JournalEntry::Status {
+                order_id,
+                status,
+                avg_price,
+                ..
+            } => {
+                let filled: bool = status == "filled";
+                if let Some(roll) = self.rolls.remove(order_id) {
+                    if filled {
+                        self.move_calendars(order_id, &roll);
+                    }
+                } else if filled {
+                    // A netted order's fill price is the consolidated combo's, not a spread's.
+                    if let (Some([spread]), Some(avg_price)) = (
+                        self.spreads.get_mut(order_id).map(Vec::as_mut_slice),
+                        avg_price,
+                    ) {
+                        spread.price = *avg_price;
+                    }
+                } else {
+                    self.spreads.remove(order_id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Function that moves a filled roll's calendars to the expiration it rolled them to,
+    // splitting an order when only part of it was rolled.
+    fn move_calendars(&mut self, roll_id: &str, roll: &PendingRoll) {
+        let rolls = |spread: &OpenSpread| -> bool {
+            spread.ticker == roll.ticker
+                && spread.type_spread == "Calendar"
+                && spread.expiry == roll.from_date
+        };
+        let mut remaining: f64 = roll.quantity;
+        let mut order_ids: Vec<String> = self
+            .spreads
+            .iter()
+            .filter(|(_, spreads)| spreads.iter().any(rolls))
+            .map(|(order_id, _)| order_id.clone())
+            .collect();
+        order_ids.sort();
+
+        for order_id in order_ids {
+            let mut split: Vec<OpenSpread> = Vec::new();
+            for spread in self.spreads.get_mut(&order_id).into_iter().flatten() {
+                if remaining <= 0.0 {
+                    break;
+                }
+                if !rolls(spread) {
+                    continue;
+                }
+                if spread.quantity <= remaining {
+                    remaining -= spread.quantity;
+                    spread.expiry = roll.to_date.clone();
+                } else {
+                    let mut rolled: OpenSpread = spread.clone();
+                    spread.quantity -= remaining;
+                    rolled.quantity = remaining;
+                    rolled.expiry = roll.to_date.clone();
+                    remaining = 0.0;
+                    split.push(rolled);
+                }
+            }
+            if !split.is_empty() {
+                self.spreads
+                    .insert(format!("{}-{}", order_id, roll_id), split);
+            }
+        }
+    }
+
+    // Function that returns the exposure of every expiration from today (YYMMDD) on, by ticker
+    // and expiration.
+    pub(crate) fn report(&self, today: &str) -> Vec<ExpiryExposure> {
+        let mut exposures: BTreeMap<(String, String), ExpiryExposure> = BTreeMap::new();
+        for spread in self.spreads.values().flatten() {
+            if spread.expiry.as_str() < today {
+                continue;
+            }
+            let exposure: &mut ExpiryExposure = exposures
+                .entry((spread.ticker.clone(), spread.expiry.clone()))
+                .or_insert_with(|| ExpiryExposure {
+                    ticker: spread.ticker.clone(),
+                    expiry: spread.expiry.clone(),
+                    ..ExpiryExposure::default()
+                });
+            exposure.spreads += spread.spreads();
+            exposure.net_debit += spread.price * spread.quantity * CONTRACT_MULTIPLIER;
+            exposure.worst_case_loss +=
+                worst_case_loss(&spread.type_spread, &spread.legs, spread.price)
+                    * spread.quantity
+                    * CONTRACT_MULTIPLIER;
+        }
+        exposures
+            .into_values()
+            .map(|mut exposure| {
+                exposure.net_debit = (exposure.net_debit * 100.0).round() / 100.0;
+                exposure.worst_case_loss = (exposure.worst_case_loss * 100.0).round() / 100.0;
+                exposure
+            })
+            .collect()
+    }
+
+    // Function that returns a ticker's exposure to one expiration.
+    pub(crate) fn exposure(&self, ticker: &str, expiry: &str) -> ExpiryExposure {
+        self.report(expiry)
+            .into_iter()
+            .find(|exposure| exposure.ticker == ticker && exposure.expiry == expiry)
+            .unwrap_or_default()
+    }
+}
+
+// Function that returns the most a combo bought at a price can lose per share at expiry. A
+// calendar can lose at most its debit. Butterfly and box combos are balanced, so their payoff
+// is flat past the outer strikes and the worst case is at zero or a strike.
+pub(crate) fn worst_case_loss(type_spread: &str, legs: &[JournalLeg], price: f64) -> f64 {
+    if type_spread == "Calendar" {
+        return price.max(0.0);
+    }
+    let payoff = |underlying: f64| -> f64 {
+        legs.iter()
+            .map(|leg| {
+                let intrinsic: f64 = if leg.right == "C" {
+                    (underlying - leg.strike).max(0.0)
+                } else {
+                    (leg.strike - underlying).max(0.0)
+                };
+                leg.ratio as f64 * intrinsic
+            })
+            .sum()
+    };
+    legs.iter()
+        .map(|leg| leg.strike)
+        .chain(std::iter::once(0.0))
+        .map(|underlying| price - payoff(underlying))
+        .fold(0.0, f64::max)
+}
+
+// Function that builds the book from journal lines, keeping a ticker's entries.
+pub(crate) fn exposure_book<I: Iterator<Item = String>>(lines: I, ticker: &str) -> ExposureBook {
+    let mut book: ExposureBook = ExposureBook::default();
+    for entry in lines.filter_map(|line| serde_json::from_str::<JournalEntry>(&line).ok()) {
+        let of_ticker: bool = match &entry {
+            JournalEntry::Submitted { ticker: t, .. } | JournalEntry::Roll { ticker: t, .. } => {
+                t == ticker
+            }
+            _ => true,
+        };
+        if of_ticker {
+            book.apply(&entry);
+        }
+    }
+    book
+}
+
+// Function that loads a ticker's book from the journal file, empty without a journal.
+pub(crate) fn load_exposure_book(path: &str, ticker: &str) -> ExposureBook {
+    match read_lines(path) {
+        Ok(lines) => exposure_book(lines, ticker),
+        Err(_) => ExposureBook::default(),
+    }
+}
@@ -0,0 +1,97 @@
+// The gateway requests are routed to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum GatewayRole {
+    Primary,
+    Secondary,
+}
+
+impl GatewayRole {
+    // Function that returns the name the gateway is reported under in health.json and the log.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            GatewayRole::Primary => "primary",
+            GatewayRole::Secondary => "secondary",
+        }
+    }
+}
+
+// Routes requests to the primary gateway while it is healthy, to the secondary after the primary
+// failed FAILOVER_AFTER health checks in a row, and back once it passed FAILBACK_AFTER in a row.
+// Both gateways have to be logged in to the same account for orders to carry over.
+pub(crate) struct Failover {
+    primary: String,
+    secondary: String,
+    active: GatewayRole,
+    fail_after: u64,
+    recover_after: u64,
+    failures: u64,
+    successes: u64,
+}
+
+impl Failover {
+    pub(crate) fn new(
+        primary: String,
+        secondary: String,
+        fail_after: u64,
+        recover_after: u64,
+    ) -> Self {
+        Failover {
+            primary,
+            secondary,
+            active: GatewayRole::Primary,
+            fail_after,
+            recover_after,
+            failures: 0,
+            successes: 0,
+        }
+    }
+
+    pub(crate) fn primary_url(&self) -> &str {
+        &self.primary
+    }
+
+    pub(crate) fn secondary_url(&self) -> &str {
+        &self.secondary
+    }
+
+    pub(crate) fn active(&self) -> GatewayRole {
+        self.active
+    }
+
+    // Function that returns the base URL of the gateway requests should go to.
+    pub(crate) fn active_url(&self) -> &str {
+        match self.active {
+            GatewayRole::Primary => &self.primary,
+            GatewayRole::Secondary => &self.secondary,
+        }
+    }
+
+    // Function that records a health check of the primary, returning the gateway to switch to
+    // when the check tips it over. Failing over also needs the secondary to pass a check, so a
+    // bot with both gateways down stays where it is.
+    pub(crate) fn record_primary(
+        &mut self,
+        healthy: bool,
+        secondary_healthy: impl FnOnce() -> bool,
+    ) -> Option<GatewayRole> {
+        if healthy {
+            self.failures = 0;
+            self.successes = self.successes.saturating_add(1);
+        } else {
+            self.successes = 0;
+            self.failures = self.failures.saturating_add(1);
+        }
+
+        match self.active {
+            GatewayRole::Primary if self.failures >= self.fail_after && secondary_healthy() => {
+                self.active = GatewayRole::Secondary;
+                Some(GatewayRole::Secondary)
+            }
+            GatewayRole::Secondary if self.successes >= self.recover_after => {
+                self.active = GatewayRole::Primary;
+                Some(GatewayRole::Primary)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::{encryption::read_lines, structs::Contender};
+
+// Model that estimates the probability of a contender filling at its limit price.
+pub(crate) enum FillModel {
+    Off,
+    // Probability grows with the thinnest leg's ask size, reaching 50% at half_size contracts.
+    Size { half_size: f64 },
+    // Probability is the smoothed fill rate per spread type, learned from the journal.
+    Historical { rates: HashMap<String, f64> },
+}
+
+impl FillModel {
+    // Function that returns the estimated fill probability for a contender, between 0 and 1.
+    pub(crate) fn probability(&self, contender: &Contender) -> f64 {
+        match self {
+            FillModel::Off => 1.0,
+            FillModel::Size { half_size } => {
+                if contender.avg_ask <= 0.0 {
+                    0.0
+                } else {
+                    contender.avg_ask / (contender.avg_ask + half_size)
+                }
+            }
+            FillModel::Historical { rates } => *rates.get(&contender.type_spread).unwrap_or(&0.5),
+        }
+    }
+}
+
+// Function that learns fill rates per spread type from journal lines. Each line is a JSON object
+// with a "type_spread" and a final "status"; rates use add-one smoothing so a handful of orders
+// can't pin a strategy to 0% or 100%.
+pub(crate) fn parse_fill_rates<I: Iterator<Item = String>>(lines: I) -> HashMap<String, f64> {
+    let mut counts: HashMap<String, (u64, u64)> = HashMap::new();
+
+    for line in lines {
+        let record: Value = match serde_json::from_str(&line) {
+            Ok(record) => record,
+            Err(_) => continue,
+        };
+        let (type_spread, status): (&str, &str) =
+            match (record["type_spread"].as_str(), record["status"].as_str()) {
+                (Some(type_spread), Some(status)) => (type_spread, status),
+                _ => continue,
+            };
+
+        let entry: &mut (u64, u64) = counts.entry(type_spread.to_string()).or_insert((0, 0));
+        match status {
+            "filled" => {
+                entry.0 += 1;
+                entry.1 += 1;
+            }
+            "cancelled" | "expired" | "rejected" => entry.1 += 1,
+            _ => {}
+        }
+    }
+
+    counts
+        .into_iter()
+        .map(|(type_spread, (filled, total))| {
+            (type_spread, (filled as f64 + 1.0) / (total as f64 + 2.0))
+        })
+        .collect()
+}
+
+// Function that loads fill rates from the journal file, empty if there is no journal yet.
+pub(crate) fn load_fill_rates(path: &str) -> HashMap<String, f64> {
+    match read_lines(path) {
+        Ok(lines) => parse_fill_rates(lines),
+        Err(_) => HashMap::new(),
+    }
+}
@@ -0,0 +1,361 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{FilterCheck, ScanContext},
+    helpers::{calc_time_difference, expected_move},
+    journal::LegQuote,
+};
+
+// The leg filters a scan runs when LEG_FILTERS is not set, the scanners' original checks with
+// calendars kept within 10% of spot.
+pub(crate) const DEFAULT_LEG_FILTERS: &str = "min_bid=1;min_size=0;calendar:strike_pct=10";
+
+// The pipeline before LEG_FILTERS was configurable, calendars kept within 500 points of the
+// mean strike.
+const LEGACY_LEG_FILTERS: &str = "min_bid=1;min_size=0;calendar:strike_window=500";
+
+// A check run on every leg of a candidate, named after its key in LEG_FILTERS.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LegFilterKind {
+    // Every bid above the value.
+    MinBid,
+    // Every ask size above the value.
+    MinSize,
+    // Every bid/ask spread at most the value.
+    MaxSpread,
+    // Every strike at most the value away from the mean strike of the nearest expiry.
+    StrikeWindow,
+    // Every strike at most the value in percent away from the underlying's price, or from the
+    // mean strike when the price is unknown.
+    StrikePct,
+    // Every strike at most the value in standard deviations of the underlying's expected move to
+    // the leg's expiry away from its price, the move taken from the implied volatility at the
+    // money. Legs pass when the price or volatility is unknown.
+    StrikeMove,
+    // Every quote fetched at most the value in seconds before the scan, legs of unknown age pass.
+    MaxAge,
+    // Every open interest at least the value, legs without a reported open interest pass.
+    MinOpenInterest,
+}
+
+impl LegFilterKind {
+    // Function that parses a filter's LEG_FILTERS key.
+    fn parse(key: &str) -> Option<Self> {
+        match key.trim().to_lowercase().as_str() {
+            "min_bid" => Some(LegFilterKind::MinBid),
+            "min_size" => Some(LegFilterKind::MinSize),
+            "max_spread" => Some(LegFilterKind::MaxSpread),
+            "strike_window" => Some(LegFilterKind::StrikeWindow),
+            "strike_pct" => Some(LegFilterKind::StrikePct),
+            "strike_move" => Some(LegFilterKind::StrikeMove),
+            "max_age" => Some(LegFilterKind::MaxAge),
+            "min_open_interest" => Some(LegFilterKind::MinOpenInterest),
+            _ => None,
+        }
+    }
+
+    // Function that returns the name the filter is reported under in audits and near misses.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            LegFilterKind::MinBid => "leg bids",
+            LegFilterKind::MinSize => "leg ask sizes",
+            LegFilterKind::MaxSpread => "leg spreads",
+            LegFilterKind::StrikeWindow => "near the money",
+            LegFilterKind::StrikePct => "near the spot",
+            LegFilterKind::StrikeMove => "within the expected move",
+            LegFilterKind::MaxAge => "quote age",
+            LegFilterKind::MinOpenInterest => "open interest",
+        }
+    }
+
+    // Function that returns the metrics counter of the candidates the filter rejected.
+    pub(crate) fn counter(&self) -> &'static str {
+        match self {
+            LegFilterKind::MinBid => "filter_rejected_min_bid",
+            LegFilterKind::MinSize => "filter_rejected_min_size",
+            LegFilterKind::MaxSpread => "filter_rejected_max_spread",
+            LegFilterKind::StrikeWindow => "filter_rejected_strike_window",
+            LegFilterKind::StrikePct => "filter_rejected_strike_pct",
+            LegFilterKind::StrikeMove => "filter_rejected_strike_move",
+            LegFilterKind::MaxAge => "filter_rejected_max_age",
+            LegFilterKind::MinOpenInterest => "filter_rejected_min_open_interest",
+        }
+    }
+}
+
+// One step of the leg filter pipeline, optionally limited to one spread type.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct LegFilter {
+    pub(crate) kind: LegFilterKind,
+    pub(crate) value: f64,
+    pub(crate) spread: Option<String>,
+}
+
+impl LegFilter {
+    // Function that checks whether the filter runs on a spread type.
+    pub(crate) fn applies_to(&self, type_spread: &str) -> bool {
+        self.spread
+            .as_ref()
+            .is_none_or(|spread| spread.eq_ignore_ascii_case(type_spread))
+    }
+
+    // Function that runs the filter on a candidate's leg quotes, recording the numbers compared.
+    pub(crate) fn check(&self, quotes: &[LegQuote], context: &ScanContext) -> FilterCheck {
+        let value: f64 = self.value;
+        let name: &'static str = self.kind.name();
+        let join = |vals: Vec<String>| -> String { vals.join(", ") };
+        match self.kind {
+            LegFilterKind::MinBid => {
+                let lowest: f64 = quotes.iter().map(|q| q.bid).fold(f64::INFINITY, f64::min);
+                FilterCheck::threshold(
+                    name,
+                    quotes.iter().all(|q| q.bid > value),
+                    format!(
+                        "[{}] > {:.2}",
+                        join(quotes.iter().map(|q| format!("{:.2}", q.bid)).collect()),
+                        value
+                    ),
+                    value - lowest,
+                )
+            }
+            LegFilterKind::MinSize => FilterCheck::new(
+                name,
+                quotes.iter().all(|q| q.asz > value),
+                format!(
+                    "[{}] > {}",
+                    join(quotes.iter().map(|q| format!("{}", q.asz)).collect()),
+                    value
+                ),
+            ),
+            LegFilterKind::MaxSpread => {
+                let widest: f64 = quotes
+                    .iter()
+                    .map(|q| q.ask - q.bid)
+                    .fold(f64::NEG_INFINITY, f64::max);
+                FilterCheck::threshold(
+                    name,
+                    quotes.iter().all(|q| q.ask - q.bid <= value),
+                    format!(
+                        "[{}] <= {:.2}",
+                        join(
+                            quotes
+                                .iter()
+                                .map(|q| format!("{:.2}", q.ask - q.bid))
+                                .collect()
+                        ),
+                        value
+                    ),
+                    widest - value,
+                )
+            }
+            LegFilterKind::StrikeWindow => {
+                let distances: Vec<f64> = quotes
+                    .iter()
+                    .map(|q| (q.strike - context.mean_strike).abs())
+                    .collect();
+                let farthest: f64 = distances.iter().copied().fold(0.0, f64::max);
+                FilterCheck::threshold(
+                    name,
+                    farthest <= value,
+                    format!(
+                        "|[{}] - {:.1}| <= {}",
+                        join(quotes.iter().map(|q| format!("{}", q.strike)).collect()),
+                        context.mean_strike,
+                        value
+                    ),
+                    farthest - value,
+                )
+            }
+            LegFilterKind::StrikePct => {
+                let center: f64 = context.underlying_price.unwrap_or(context.mean_strike);
+                let bound: f64 = center * value / 100.0;
+                let farthest: f64 = quotes
+                    .iter()
+                    .map(|q| (q.strike - center).abs())
+                    .fold(0.0, f64::max);
+                FilterCheck::threshold(
+                    name,
+                    farthest <= bound,
+                    format!(
+                        "|[{}] - {:.1}| <= {:.1}",
+                        join(quotes.iter().map(|q| format!("{}", q.strike)).collect()),
+                        center,
+                        bound
+                    ),
+                    farthest - bound,
+                )
+            }
+            LegFilterKind::StrikeMove => {
+                let (spot, vol): (f64, f64) = match (context.underlying_price, context.atm_vol) {
+                    (Some(spot), Some(vol)) => (spot, vol),
+                    _ => {
+                        return FilterCheck::new(
+                            name,
+                            true,
+                            "spot or volatility unknown".to_string(),
+                        )
+                    }
+                };
+                // Calendar days to each leg's expiry, as the trading days expected_move takes.
+                let bounds: Vec<f64> = quotes
+                    .iter()
+                    .map(|q| {
+                        let days: f64 =
+                            calc_time_difference(&context.scan_date, &q.date).max(1) as f64;
+                        value * expected_move(spot, vol, days * 252.0 / 365.0)
+                    })
+                    .collect();
+                let excess: f64 = quotes
+                    .iter()
+                    .zip(&bounds)
+                    .map(|(q, bound)| (q.strike - spot).abs() - bound)
+                    .fold(f64::NEG_INFINITY, f64::max);
+                FilterCheck::threshold(
+                    name,
+                    excess <= 0.0,
+                    format!(
+                        "[{}]",
+                        join(
+                            quotes
+                                .iter()
+                                .zip(&bounds)
+                                .map(|(q, bound)| {
+                                    format!("|{} - {:.1}| <= {:.1}", q.strike, spot, bound)
+                                })
+                                .collect()
+                        )
+                    ),
+                    excess,
+                )
+            }
+            LegFilterKind::MaxAge => {
+                let ages: Vec<Option<f64>> = quotes
+                    .iter()
+                    .map(|q| quote_age(q, context.scanned_at))
+                    .collect();
+                let oldest: f64 = ages.iter().flatten().copied().fold(0.0, f64::max);
+                FilterCheck::threshold(
+                    name,
+                    oldest <= value,
+                    format!(
+                        "[{}] <= {}s",
+                        join(
+                            ages.iter()
+                                .map(|age| match age {
+                                    Some(age) => format!("{:.1}s", age),
+                                    None => "unknown".to_string(),
+                                })
+                                .collect()
+                        ),
+                        value
+                    ),
+                    oldest - value,
+                )
+            }
+            LegFilterKind::MinOpenInterest => {
+                let lowest: f64 = quotes
+                    .iter()
+                    .filter_map(|q| q.open_interest)
+                    .fold(f64::INFINITY, f64::min);
+                FilterCheck::threshold(
+                    name,
+                    quotes
+                        .iter()
+                        .all(|q| q.open_interest.is_none_or(|oi| oi >= value)),
+                    format!(
+                        "[{}] >= {}",
+                        join(
+                            quotes
+                                .iter()
+                                .map(|q| match q.open_interest {
+                                    Some(oi) => format!("{}", oi),
+                                    None => "unknown".to_string(),
+                                })
+                                .collect()
+                        ),
+                        value
+                    ),
+                    value - lowest,
+                )
+            }
+        }
+    }
+}
+
+// Function that returns how many seconds before the scan a leg's quote was fetched, None when
+// either time is unknown.
+fn quote_age(quote: &LegQuote, scanned_at: Option<DateTime<Utc>>) -> Option<f64> {
+    let fetched_at: DateTime<Utc> = DateTime::parse_from_rfc3339(quote.fetched_at.as_ref()?)
+        .ok()?
+        .with_timezone(&Utc);
+    Some((scanned_at? - fetched_at).num_milliseconds() as f64 / 1000.0)
+}
+
+// Function that parses LEG_FILTERS, "[spread:]filter=value" steps separated by semicolons and run
+// in the order listed. Steps that don't parse are skipped.
+pub(crate) fn parse_leg_filters(value: &str) -> Vec<LegFilter> {
+    value
+        .split(';')
+        .filter_map(|step| {
+            let (scope, filter): (Option<&str>, &str) = match step.split_once(':') {
+                Some((spread, filter)) => (Some(spread.trim()), filter),
+                None => (None, step),
+            };
+            let (key, val): (&str, &str) = filter.split_once('=')?;
+            let value: f64 = val
+                .trim()
+                .parse()
+                .ok()
+                .filter(|val: &f64| val.is_finite())?;
+            Some(LegFilter {
+                kind: LegFilterKind::parse(key)?,
+                value,
+                spread: match scope {
+                    Some("") => return None,
+                    scope => scope.map(|spread| spread.to_string()),
+                },
+            })
+        })
+        .collect()
+}
+
+// Function that returns the pipeline used when LEG_FILTERS is not set.
+pub(crate) fn default_leg_filters() -> Vec<LegFilter> {
+    parse_leg_filters(DEFAULT_LEG_FILTERS)
+}
+
+// Function that returns the pipeline decisions journaled before LEG_FILTERS was configurable
+// were made with.
+pub(crate) fn legacy_leg_filters() -> Vec<LegFilter> {
+    parse_leg_filters(LEGACY_LEG_FILTERS)
+}
+
+// Function that runs a spread type's leg filters on a candidate in pipeline order.
+pub(crate) fn leg_filter_checks(
+    type_spread: &str,
+    quotes: &[LegQuote],
+    context: &ScanContext,
+) -> Vec<FilterCheck> {
+    context
+        .leg_filters
+        .iter()
+        .filter(|filter| filter.applies_to(type_spread))
+        .map(|filter| filter.check(quotes, context))
+        .collect()
+}
+
+// Function that returns the first leg filter a candidate fails, stopping there like the
+// scanners do, None when it passes them all.
+pub(crate) fn first_rejection<'a>(
+    type_spread: &str,
+    quotes: &[LegQuote],
+    context: &'a ScanContext,
+) -> Option<&'a LegFilter> {
+    context
+        .leg_filters
+        .iter()
+        .filter(|filter| filter.applies_to(type_spread))
+        .find(|filter| !filter.check(quotes, context).passed)
+}
@@ -0,0 +1,150 @@
+// Black-Scholes pricing and greeks for European index options, with zero rates and dividends.
+// Expiries this close together make carry negligible next to the bid/ask spread.
+
+// Function that returns the standard normal probability density.
+pub(crate) fn norm_pdf(x: f64) -> f64 {
+    (-0.5 * x * x).exp() / (2.0 * std::f64::consts::PI).sqrt()
+}
+
+// Function that returns the standard normal cumulative distribution (Abramowitz and Stegun 7.1.26).
+pub(crate) fn norm_cdf(x: f64) -> f64 {
+    let t: f64 = 1.0 / (1.0 + 0.3275911 * x.abs() / std::f64::consts::SQRT_2);
+    let poly: f64 = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf: f64 = 1.0 - poly * (-(x * x) / 2.0).exp();
+    if x >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+// Function that returns d1 and d2, or None at expiry or with no volatility.
+fn d1_d2(spot: f64, strike: f64, years: f64, vol: f64) -> Option<(f64, f64)> {
+    if years <= 0.0 || vol <= 0.0 || spot <= 0.0 || strike <= 0.0 {
+        return None;
+    }
+    let vol_sqrt_t: f64 = vol * years.sqrt();
+    let d1: f64 = ((spot / strike).ln() + 0.5 * vol * vol * years) / vol_sqrt_t;
+    Some((d1, d1 - vol_sqrt_t))
+}
+
+// Function that returns the intrinsic value of a call ("C") or put ("P").
+pub(crate) fn intrinsic_value(spot: f64, strike: f64, right: &str) -> f64 {
+    if right == "C" {
+        (spot - strike).max(0.0)
+    } else {
+        (strike - spot).max(0.0)
+    }
+}
+
+// Function that returns the option price.
+pub(crate) fn bs_price(spot: f64, strike: f64, years: f64, vol: f64, right: &str) -> f64 {
+    match d1_d2(spot, strike, years, vol) {
+        Some((d1, d2)) => {
+            if right == "C" {
+                spot * norm_cdf(d1) - strike * norm_cdf(d2)
+            } else {
+                strike * norm_cdf(-d2) - spot * norm_cdf(-d1)
+            }
+        }
+        None => intrinsic_value(spot, strike, right),
+    }
+}
+
+// Function that returns the option delta.
+pub(crate) fn bs_delta(spot: f64, strike: f64, years: f64, vol: f64, right: &str) -> f64 {
+    match d1_d2(spot, strike, years, vol) {
+        Some((d1, _)) => {
+            if right == "C" {
+                norm_cdf(d1)
+            } else {
+                norm_cdf(d1) - 1.0
+            }
+        }
+        None => 0.0,
+    }
+}
+
+// Function that returns the option gamma.
+pub(crate) fn bs_gamma(spot: f64, strike: f64, years: f64, vol: f64) -> f64 {
+    match d1_d2(spot, strike, years, vol) {
+        Some((d1, _)) => norm_pdf(d1) / (spot * vol * years.sqrt()),
+        None => 0.0,
+    }
+}
+
+// Function that returns the option vega, per 1.00 change in volatility.
+pub(crate) fn bs_vega(spot: f64, strike: f64, years: f64, vol: f64) -> f64 {
+    match d1_d2(spot, strike, years, vol) {
+        Some((d1, _)) => spot * norm_pdf(d1) * years.sqrt(),
+        None => 0.0,
+    }
+}
+
+// Function that returns the option theta, per year.
+pub(crate) fn bs_theta(spot: f64, strike: f64, years: f64, vol: f64) -> f64 {
+    match d1_d2(spot, strike, years, vol) {
+        Some((d1, _)) => -spot * norm_pdf(d1) * vol / (2.0 * years.sqrt()),
+        None => 0.0,
+    }
+}
+
+// Function that backs out the volatility implied by a price, by bisection.
+pub(crate) fn implied_vol(
+    price: f64,
+    spot: f64,
+    strike: f64,
+    years: f64,
+    right: &str,
+) -> Option<f64> {
+    if years <= 0.0 || price <= intrinsic_value(spot, strike, right) {
+        return None;
+    }
+
+    let (mut low, mut high): (f64, f64) = (0.001, 5.0);
+    if bs_price(spot, strike, years, high, right) < price {
+        return None;
+    }
+    for _ in 0..100 {
+        let mid: f64 = (low + high) / 2.0;
+        if bs_price(spot, strike, years, mid, right) < price {
+            low = mid;
+        } else {
+            high = mid;
+        }
+        if high - low < 1e-6 {
+            break;
+        }
+    }
+
+    Some((low + high) / 2.0)
+}
+
+// Function that returns the share of a calendar's short front leg premium expected to decay per
+// day, net of the long back leg's decay, from each leg's implied volatility. None when either
+// leg's volatility can't be implied.
+pub(crate) fn calendar_theta_score(
+    spot: f64,
+    strike: f64,
+    right: &str,
+    front_price: f64,
+    back_price: f64,
+    front_days: i64,
+    back_days: i64,
+) -> Option<f64> {
+    // Same-day expiries still decay over the session, so price at least a day out.
+    let front_years: f64 = front_days.max(1) as f64 / 365.0;
+    let back_years: f64 = back_days.max(front_days + 1).max(2) as f64 / 365.0;
+    let front_vol: f64 = implied_vol(front_price, spot, strike, front_years, right)?;
+    let back_vol: f64 = implied_vol(back_price, spot, strike, back_years, right)?;
+
+    let net_theta: f64 = (bs_theta(spot, strike, back_years, back_vol)
+        - bs_theta(spot, strike, front_years, front_vol))
+        / 365.0;
+    if front_price <= 0.0 {
+        return None;
+    }
+    Some((net_theta / front_price).clamp(0.0, 1.0))
+}
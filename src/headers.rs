@@ -0,0 +1,60 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONNECTION, PROXY_AUTHORIZATION, USER_AGENT,
+};
+use std::error::Error;
+
+pub(crate) const DEFAULT_USER_AGENT: &str = "trading_bot_rust/1.0";
+
+// Function that parses extra request headers from "Name: value;Name: value".
+pub(crate) fn parse_headers(val: &str) -> Vec<(String, String)> {
+    val.split(';')
+        .filter_map(|header| {
+            let (name, value) = header.split_once(':')?;
+            let name: &str = name.trim();
+            if name.is_empty() {
+                return None;
+            }
+            Some((name.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+// Function that builds the headers sent on every request to the gateway, extra headers are
+// added last so they can override the defaults.
+pub(crate) fn build_default_headers(
+    user_agent: &str,
+    extra_headers: &[(String, String)],
+) -> Result<HeaderMap, Box<dyn Error>> {
+    let mut headers: HeaderMap = HeaderMap::new();
+    headers.insert(CONNECTION, HeaderValue::from_static("keep-alive"));
+    headers.insert(USER_AGENT, HeaderValue::from_str(user_agent)?);
+
+    for (name, value) in extra_headers {
+        let name: HeaderName = HeaderName::from_bytes(name.as_bytes())?;
+        let mut value: HeaderValue = HeaderValue::from_str(value)?;
+        // Keep credentials out of debug output.
+        if name == AUTHORIZATION || name == PROXY_AUTHORIZATION {
+            value.set_sensitive(true);
+        }
+        headers.insert(name, value);
+    }
+
+    Ok(headers)
+}
+
+// Function that builds the Authorization header for a gateway behind an authenticated reverse
+// proxy, a bearer token takes precedence over basic "user:password" credentials.
+pub(crate) fn auth_header(
+    bearer_token: Option<&str>,
+    basic_auth: Option<&str>,
+) -> Option<(String, String)> {
+    let value: String = match (bearer_token, basic_auth) {
+        (Some(token), _) if !token.trim().is_empty() => format!("Bearer {}", token.trim()),
+        (_, Some(credentials)) if credentials.contains(':') => {
+            format!("Basic {}", STANDARD.encode(credentials.trim()))
+        }
+        _ => return None,
+    };
+    Some(("Authorization".to_string(), value))
+}
@@ -0,0 +1,54 @@
+use chrono::Utc;
+use serde_json::Value;
+use std::{error::Error, fs};
+
+use crate::{
+    metrics::{endpoint_snapshot, snapshot},
+    structs::Health,
+};
+
+// Left in plain text even with ENCRYPTION_KEY set, as monitors poll it without the key. It holds
+// balances but no account ids.
+const HEALTH_FILE: &str = "health.json";
+
+// Function that writes the health snapshot to the health file.
+pub(crate) fn write_health(health: &mut Health) -> Result<(), Box<dyn Error>> {
+    write_health_to(health, HEALTH_FILE)
+}
+
+// Function that writes the health snapshot, via a temp file so readers never see a partial write.
+pub(crate) fn write_health_to(health: &mut Health, path: &str) -> Result<(), Box<dyn Error>> {
+    health.updated_at = Utc::now().to_rfc3339();
+    health.metrics = snapshot();
+    health.endpoints = endpoint_snapshot();
+    let tmp_file: String = format!("{}.tmp", path);
+    fs::write(&tmp_file, serde_json::to_vec_pretty(health)?)?;
+    fs::rename(&tmp_file, path)?;
+    Ok(())
+}
+
+// Function that reads the last health snapshot written by a running bot.
+pub(crate) fn read_health() -> Result<Health, Box<dyn Error>> {
+    read_health_from(HEALTH_FILE)
+}
+
+// Function that reads a health snapshot from the given path.
+pub(crate) fn read_health_from(path: &str) -> Result<Health, Box<dyn Error>> {
+    let contents: String = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+// Function that formats a health snapshot for the console, with the account balances printed as
+// *** when they are masked.
+pub(crate) fn health_report(health: &Health, mask_balances: bool) -> String {
+    if !mask_balances {
+        return serde_json::to_string_pretty(health).unwrap_or_default();
+    }
+    let mut report: Value = serde_json::to_value(health).unwrap_or_default();
+    if let Some(Value::Object(account)) = report.get_mut("account") {
+        for value in account.values_mut() {
+            *value = Value::String("***".to_string());
+        }
+    }
+    serde_json::to_string_pretty(&report).unwrap_or_default()
+}
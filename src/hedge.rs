@@ -0,0 +1,96 @@
+use chrono::NaiveDate;
+
+use crate::{
+    greeks::{bs_delta, implied_vol, intrinsic_value},
+    helpers::value_to_f64,
+    journal::final_status,
+    structs::{HedgeRule, Order, PositionResponse},
+};
+
+// Shares per equity or index option contract.
+const OPTION_MULTIPLIER: f64 = 100.0;
+
+// Function that returns the delta of an option position in units of the underlying, from the
+// implied volatility of its mark. Expired legs and marks below intrinsic count as fully in or
+// out of the money.
+pub(crate) fn option_position_delta(
+    position: &PositionResponse,
+    spot: f64,
+    today: NaiveDate,
+) -> Option<f64> {
+    let strike: f64 = value_to_f64(position.strike.as_ref()?)?;
+    let right: &str = match position.put_or_call.as_deref()? {
+        "C" | "CALL" => "C",
+        "P" | "PUT" => "P",
+        _ => return None,
+    };
+    let expiry: NaiveDate = NaiveDate::parse_from_str(position.expiry.as_ref()?, "%Y%m%d").ok()?;
+    let years: f64 = (expiry - today).num_days() as f64 / 365.0;
+
+    let delta: f64 = match implied_vol(position.mkt_price, spot, strike, years, right) {
+        Some(vol) => bs_delta(spot, strike, years, vol, right),
+        None if intrinsic_value(spot, strike, right) > 0.0 => {
+            if right == "C" {
+                1.0
+            } else {
+                -1.0
+            }
+        }
+        None => 0.0,
+    };
+    Some(delta * position.position * OPTION_MULTIPLIER)
+}
+
+// Function that checks whether an instrument type can hedge, which an index can't as it isn't
+// traded.
+pub(crate) fn is_tradable_hedge(instrument_type: &str) -> bool {
+    !instrument_type.is_empty() && instrument_type != "IND"
+}
+
+// Function that returns the net delta in units of the underlying of the options held on it and
+// the position held in the hedge instrument.
+pub(crate) fn net_delta(
+    positions: &[PositionResponse],
+    spot: f64,
+    today: NaiveDate,
+    underlying_conid: &str,
+    rule: &HedgeRule,
+    hedge_conid: &str,
+) -> f64 {
+    positions
+        .iter()
+        .map(|position| {
+            if position.conid.to_string() == hedge_conid {
+                position.position * rule.delta_per_unit
+            } else if position.und_conid.map(|conid| conid.to_string()).as_deref()
+                == Some(underlying_conid)
+            {
+                option_position_delta(position, spot, today).unwrap_or(0.0)
+            } else {
+                0.0
+            }
+        })
+        .sum()
+}
+
+// Function that returns the signed hedge quantity bringing the net delta back toward flat, None
+// while it is within the threshold or the hedge would round to nothing.
+pub(crate) fn hedge_quantity(net_delta: f64, rule: &HedgeRule) -> Option<i32> {
+    if rule.threshold <= 0.0 || rule.delta_per_unit <= 0.0 || net_delta.abs() <= rule.threshold {
+        return None;
+    }
+    let quantity: i32 = (-net_delta / rule.delta_per_unit).round() as i32;
+    if quantity == 0 {
+        None
+    } else {
+        Some(quantity)
+    }
+}
+
+// Function that checks whether an order is still working, from the account's orders. An order
+// the gateway no longer lists has ended.
+pub(crate) fn is_working(orders: &[Order], order_id: &str) -> bool {
+    orders
+        .iter()
+        .any(|order| order.order_id == order_id && final_status(&order.status).is_none())
+}
@@ -1,279 +1,119 @@
-use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc, Weekday};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
 use chrono_tz::America::New_York;
 use dotenv::dotenv;
-use std::{env, error::Error, io::stdin};
-
-// Function that gets input and returns result.
-fn get_user_input(prompt: &str) -> String {
-    let mut input: String = String::new();
-    println!("{}", prompt);
-    stdin().read_line(&mut input).expect("Failed to read line");
-    input.trim().to_string()
-}
+use ordered_float::OrderedFloat;
+use std::{cmp::Ordering, collections::HashMap, env, error::Error};
+
+// Order journal written by the bot, one JSON object per line.
+pub(crate) const JOURNAL_PATH: &str = "journal.jsonl";
+
+use crate::{
+    stress::{simulate_calendar, CalendarPosition, StressConfig},
+    structs::{Bar, Contender, MarketDataResponse, ProfitGate, Quote},
+};
 
 // Function that uses dotenv to load/read the .env file and return the value of the key.
 pub(crate) fn get_dotenv_variable(key: &str) -> Result<String, Box<dyn Error>> {
-    dotenv()?; // Load the .env file.
+    dotenv().ok(); // Load the .env file if present.
     match env::var(key) {
         Ok(value) => Ok(value),
         Err(e) => Err(Box::new(e)),
     }
 }
 
-// Function that gets option for contracts to look for.
-pub(crate) fn get_option() -> String {
-    match get_dotenv_variable("OPTION") {
-        Ok(val) => val,
-        Err(_) => {
-            let prompt: &str = "\
-1 for Calendar
-2 for Butterfly
-3 for Boxspread
-DEFAULT for Calendar + Butterfly + Boxspread
-";
-            get_user_input(&format!(
-                "{}\nEnter which strategy the bot should use:",
-                prompt
-            ))
-        }
+// Function that builds the gateway's base URL, an empty port uses the scheme's default.
+pub(crate) fn gateway_base_url(scheme: &str, domain: &str, port: &str, base_path: &str) -> String {
+    let port: String = if port.trim().is_empty() {
+        String::new()
+    } else {
+        format!(":{}", port.trim())
+    };
+    let base_path: &str = base_path.trim().trim_matches('/');
+    if base_path.is_empty() {
+        format!("{}://{}{}", scheme, domain, port)
+    } else {
+        format!("{}://{}{}/{}", scheme, domain, port, base_path)
     }
 }
 
-// Function that gets fill type.
-pub(crate) fn get_fill_type() -> String {
-    match get_dotenv_variable("FILL_TYPE") {
-        Ok(val) => val,
-        Err(_) => {
-            let prompt: &str = "\
-1 for single order, single fill
-2 for single order, multiple fills
-3 for multiple orders, single fill
-DEFAULT for multiple orders, multiple fills
-";
-            get_user_input(&format!(
-                "{}\nEnter which fill type the bot should use:",
-                prompt
-            ))
-        }
-    }
+// Function that splits a comma separated config value into trimmed, non-empty items.
+pub(crate) fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
 }
 
-// Function that gets ticker.
-pub(crate) fn get_ticker() -> String {
-    match get_dotenv_variable("TICKER") {
-        Ok(val) => val,
-        Err(_) => get_user_input("Enter which ticker the bot should use:"),
-    }
-}
+// Function that parses "YYYY-MM-DD HH:MM-HH:MM" windows in New York time separated by semicolons.
+pub(crate) fn parse_blackout_windows(value: &str) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let mut windows: Vec<(NaiveDateTime, NaiveDateTime)> = Vec::new();
 
-// Function that gets arb value.
-pub(crate) fn get_arb_value() -> f64 {
-    match get_dotenv_variable("ARB_VALUE") {
-        Ok(val) => match val.parse::<f64>() {
-            Ok(val) => {
-                if val >= 0.10 {
-                    val
-                } else {
-                    println!("Not a valid Arb Value, setting to 0.10");
-                    0.10
-                }
-            }
-            Err(_) => {
-                println!("Not a valid Arb Value, setting to 0.10");
-                0.10
-            }
-        },
-        Err(_) => {
-            println!("Arb Value not found, setting to 0.10");
-            0.10
-        }
-    }
-}
+    for window in value.split(';').map(|w| w.trim()).filter(|w| !w.is_empty()) {
+        let (date, times) = match window.split_once(' ') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let (start, end) = match times.split_once('-') {
+            Some(parts) => parts,
+            None => continue,
+        };
 
-// Function that gets strike dif value.
-pub(crate) fn get_strike_dif_value() -> f64 {
-    match get_dotenv_variable("STRIKE_DIF_VALUE") {
-        Ok(val) => match val.parse::<f64>() {
-            Ok(val) => {
-                if val >= 0.0 {
-                    val
-                } else {
-                    println!("Not a valid Strike Dif Value, setting to 5.0");
-                    5.0
-                }
+        if let (Ok(date), Ok(start), Ok(end)) = (
+            NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d"),
+            NaiveTime::parse_from_str(start.trim(), "%H:%M"),
+            NaiveTime::parse_from_str(end.trim(), "%H:%M"),
+        ) {
+            if start < end {
+                windows.push((date.and_time(start), date.and_time(end)));
             }
-            Err(_) => {
-                println!("Not a valid Strike Dif Value, setting to 5.0");
-                5.0
-            }
-        },
-        Err(_) => {
-            println!("Strike Dif Value not found, setting to 5.0");
-            5.0
         }
     }
-}
 
-// Function that gets mode.
-pub(crate) fn get_mode() -> bool {
-    match get_dotenv_variable("TEST_MODE") {
-        Ok(val) => val.to_lowercase() != "yes" && val.to_lowercase() != "y",
-        Err(_) => {
-            let input: String =
-                get_user_input("Would you like to run the bot in testing mode? (Y / N):");
-            input.to_lowercase() != "yes" && input.to_lowercase() != "y"
-        }
-    }
+    windows
 }
 
-// Function that gets seconds to wait before cancelling and trying again.
-pub(crate) fn get_seconds_to_sleep() -> u64 {
-    match get_dotenv_variable("SECONDS_TO_SLEEP") {
-        Ok(val) => {
-            match val.parse::<u64>() {
-                Ok(parsed_val) => {
-                    if parsed_val >= 5 {
-                        parsed_val
-                    } else {
-                        println!("Not a valid positive integer greater than or equal to 5, setting to 60");
-                        60
-                    }
-                }
-                Err(_) => {
-                    println!("Not a valid integer, setting to 60");
-                    60
-                }
-            }
-        }
-        Err(_) => {
-            let input: String = get_user_input("Enter number of seconds to sleep:");
-            match input.parse::<u64>() {
-                Ok(parsed_input) => {
-                    if parsed_input >= 5 {
-                        parsed_input
-                    } else {
-                        println!("Not a valid positive integer greater than or equal to 5, setting to 60");
-                        60
-                    }
-                }
-                Err(_) => {
-                    println!("Not a valid integer, setting to 60");
-                    60
-                }
-            }
-        }
-    }
+// Function that checks if the current time falls inside any blackout window.
+pub(crate) fn is_in_blackout(
+    current_time: DateTime<Utc>,
+    windows: &[(NaiveDateTime, NaiveDateTime)],
+) -> bool {
+    let ny_time: NaiveDateTime = current_time.with_timezone(&New_York).naive_local();
+    windows
+        .iter()
+        .any(|(start, end)| ny_time >= *start && ny_time < *end)
 }
 
-// Function that gets discount value.
-pub(crate) fn get_discount_value() -> f64 {
-    match get_dotenv_variable("DISCOUNT_VALUE") {
-        Ok(val) => match val.parse::<f64>() {
-            Ok(val) => {
-                if val >= -0.15 && val <= 0.15 {
-                    val
-                } else {
-                    println!("Not a valid Discount Value, setting to 0.0");
-                    0.0
-                }
-            }
-            Err(_) => {
-                println!("Not a valid Discount Value, setting to 0.0");
-                0.0
-            }
-        },
-        Err(_) => {
-            let input: String = get_user_input("Enter a Discount Value between -0.15 and 0.15:");
-            match input.parse::<f64>() {
-                Ok(val) => {
-                    if val >= -0.15 && val <= 0.15 {
-                        val
-                    } else {
-                        println!("Not a valid Discount Value, setting to 0.0");
-                        0.0
-                    }
-                }
-                Err(_) => {
-                    println!("Not a valid Discount Value, setting to 0.0");
-                    0.0
-                }
-            }
-        }
-    }
-}
+// Function that checks if the underlying moved further than allowed since the scan started.
+// A limit of 0.0 disables that check.
+pub(crate) fn underlying_moved_too_far(
+    start_price: f64,
+    current_price: f64,
+    max_points: f64,
+    max_pct: f64,
+) -> bool {
+    let moved: f64 = (current_price - start_price).abs();
 
-// Function that gets number of days.
-pub(crate) fn get_num_days() -> i64 {
-    match get_dotenv_variable("NUM_DAYS") {
-        Ok(val) => match val.parse::<i64>() {
-            Ok(parsed_val) => {
-                if parsed_val > 0 && parsed_val < 10 {
-                    parsed_val
-                } else {
-                    println!("Not a valid number in the range 1-9, setting to 5");
-                    5
-                }
-            }
-            Err(_) => {
-                println!("Not a valid integer, setting to 5");
-                5
-            }
-        },
-        Err(_) => {
-            let input: String = get_user_input("Enter number of days worth of data to receive:");
-            match input.parse::<i64>() {
-                Ok(parsed_input) => {
-                    if parsed_input > 0 && parsed_input < 10 {
-                        parsed_input
-                    } else {
-                        println!("Not a valid number in the range 1-9, setting to 5");
-                        5
-                    }
-                }
-                Err(_) => {
-                    println!("Not a valid integer, setting to 5");
-                    5
-                }
-            }
-        }
+    if max_points > 0.0 && moved > max_points {
+        return true;
     }
+
+    max_pct > 0.0 && start_price > 0.0 && (moved / start_price * 100.0) > max_pct
 }
 
-// Function that gets number of days to offset from the current day.
-pub(crate) fn get_num_days_offset() -> i64 {
-    match get_dotenv_variable("NUM_DAYS_OFFSET") {
-        Ok(val) => match val.parse::<i64>() {
-            Ok(parsed_val) => {
-                if parsed_val >= 0 && parsed_val <= 21 {
-                    parsed_val
-                } else {
-                    println!("Not a valid number in the range 0-21, setting to 0");
-                    0
-                }
-            }
-            Err(_) => {
-                println!("Not a valid integer, setting to 0");
-                0
-            }
-        },
-        Err(_) => {
-            let input: String = get_user_input("Enter number of days offset:");
-            match input.parse::<i64>() {
-                Ok(parsed_input) => {
-                    if parsed_input >= 0 && parsed_input <= 21 {
-                        parsed_input
-                    } else {
-                        println!("Not a valid number in the range 0-21, setting to 0");
-                        0
-                    }
-                }
-                Err(_) => {
-                    println!("Not a valid integer, setting to 0");
-                    0
-                }
-            }
-        }
-    }
+// Function that returns how many seconds the local clock is behind the gateway's, negative
+// when ahead, from an HTTP Date header and the local times the request was sent and answered.
+// The server stamped the header somewhere in between, so it is compared with the midpoint.
+pub(crate) fn clock_drift(
+    date_header: &str,
+    sent: DateTime<Utc>,
+    received: DateTime<Utc>,
+) -> Option<f64> {
+    let server: DateTime<Utc> = DateTime::parse_from_rfc2822(date_header.trim())
+        .ok()?
+        .with_timezone(&Utc);
+    let midpoint: DateTime<Utc> = sent + (received - sent) / 2;
+    Some((server - midpoint).num_milliseconds() as f64 / 1000.0)
 }
 
 // Function that checks if the stock market is currently open.
@@ -306,30 +146,61 @@ pub(crate) fn is_us_stock_market_open(current_time: chrono::DateTime<Utc>) -> bo
     ny_time >= market_open && ny_time <= market_close
 }
 
-// Function that calcs the number of orders and fills for every fill type.
-pub(crate) fn calc_final_num_orders(fill: &str, port_val: f64) -> (i32, i32) {
-    let num_times: i32 = (port_val / 800.0).floor() as i32;
+// Function that parses MARKET_HOLIDAYS, dates like "2024-12-25" separated by semicolons, skipping
+// the ones that don't parse.
+pub(crate) fn parse_market_holidays(value: &str) -> Vec<NaiveDate> {
+    value
+        .split(';')
+        .filter_map(|date| NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok())
+        .collect()
+}
+
+// Function that checks if it is a market holiday in New York.
+pub(crate) fn is_market_holiday(current_time: DateTime<Utc>, holidays: &[NaiveDate]) -> bool {
+    holidays.contains(&current_time.with_timezone(&New_York).date_naive())
+}
 
-    if num_times < 1 {
-        return (0, 0);
+// Function that returns when the market next opens, 9:30 New York time on the next weekday that
+// isn't a holiday, or the current time when it is open now.
+pub(crate) fn next_market_open(
+    current_time: DateTime<Utc>,
+    holidays: &[NaiveDate],
+) -> DateTime<Utc> {
+    if is_us_stock_market_open(current_time) && !is_market_holiday(current_time, holidays) {
+        return current_time;
     }
 
-    match fill {
-        "1" => (1, 1),
-        "2" => (1, if num_times > 9 { 9 } else { num_times }),
-        "3" => (num_times, 1),
-        _ => get_optimal_num_orders(port_val),
+    let mut day: NaiveDate = current_time.with_timezone(&New_York).date_naive();
+    loop {
+        if day.weekday() != Weekday::Sat
+            && day.weekday() != Weekday::Sun
+            && !holidays.contains(&day)
+        {
+            if let Some(open) = New_York
+                .with_ymd_and_hms(day.year(), day.month(), day.day(), 9, 30, 0)
+                .single()
+            {
+                let open: DateTime<Utc> = open.with_timezone(&Utc);
+                if open > current_time {
+                    return open;
+                }
+            }
+        }
+        day = day.succ_opt().unwrap_or(day);
     }
 }
 
-// Function that gets the ideal number of orders and fills.
-fn get_optimal_num_orders(portfolio_value: f64) -> (i32, i32) {
-    let num: i32 = (portfolio_value / 800.0).sqrt() as i32;
-    if num > 9 {
-        ((portfolio_value / 800.0 / 9.0).floor() as i32, 9)
-    } else {
-        (num, num)
-    }
+// Function that checks if the market is open and within `minutes` of its 15:30 New York close.
+pub(crate) fn is_winding_down(current_time: DateTime<Utc>, minutes: i64) -> bool {
+    let ny_time: DateTime<chrono_tz::Tz> = current_time.with_timezone(&New_York);
+    let close: DateTime<chrono_tz::Tz> = match New_York
+        .with_ymd_and_hms(ny_time.year(), ny_time.month(), ny_time.day(), 15, 30, 0)
+        .single()
+    {
+        Some(time) => time,
+        None => return false,
+    };
+    is_us_stock_market_open(current_time) && ny_time >= close - chrono::Duration::minutes(minutes)
 }
 
 // Function that returns the number of days between 2 dates.
@@ -337,7 +208,7 @@ pub(crate) fn calc_time_difference(current_date: &str, date: &str) -> i64 {
     let current_time: NaiveDate = NaiveDate::parse_from_str(current_date, "%y%m%d").unwrap();
     let future_time: NaiveDate = NaiveDate::parse_from_str(date, "%y%m%d").unwrap();
 
-    ((current_time - future_time).num_hours() as f64 / 24.0 * -1.0) as i64
+    -((current_time - future_time).num_hours() as f64 / 24.0) as i64
 }
 
 // Function that returns the rank value for a contract.
@@ -346,10 +217,176 @@ pub(crate) fn calc_rank_value(avg_ask: f64, arb_val: f64, current_date: &str, da
     (avg_ask * arb_val) / (difference as f64)
 }
 
-// Function that predicts max callie loss.
-pub(crate) fn calendar_spread_risk_free_profit(strike: &f64, arb_val: f64) -> f64 {
-    let max_loss: f64 = (strike / 200.0) * 0.03;
-    arb_val - max_loss
+// Function that returns the rank value for a calendar, weighting by how quickly its edge is
+// expected to realize from theta when known, by days to front expiry otherwise.
+pub(crate) fn calc_calendar_rank_value(
+    avg_ask: f64,
+    arb_val: f64,
+    theta_score: Option<f64>,
+    current_date: &str,
+    date: &str,
+) -> f64 {
+    match theta_score {
+        Some(theta_score) => avg_ask * arb_val * theta_score,
+        None => calc_rank_value(avg_ask, arb_val, current_date, date),
+    }
+}
+
+// Function that reads the bars of a marketdata/history response, oldest first, skipping bars
+// without a time or a close.
+pub(crate) fn parse_history(history: &serde_json::Value) -> Vec<Bar> {
+    let mut bars: Vec<Bar> = history["data"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|bar| {
+            let close: f64 = value_to_f64(&bar["c"])?;
+            Some(Bar {
+                time: bar["t"].as_i64()?,
+                open: value_to_f64(&bar["o"]).unwrap_or(close),
+                high: value_to_f64(&bar["h"]).unwrap_or(close),
+                low: value_to_f64(&bar["l"]).unwrap_or(close),
+                close,
+                volume: value_to_f64(&bar["v"]).unwrap_or(0.0),
+            })
+        })
+        .collect();
+    bars.sort_by_key(|bar| bar.time);
+    bars
+}
+
+// Function that returns the annualized volatility of daily closes, the standard deviation of
+// their log returns scaled by the square root of 252 trading days. None with fewer than two
+// returns or a close that isn't positive.
+pub(crate) fn realized_volatility(closes: &[f64]) -> Option<f64> {
+    if closes.len() < 3 || closes.iter().any(|close| *close <= 0.0) {
+        return None;
+    }
+    let returns: Vec<f64> = closes
+        .windows(2)
+        .map(|pair| (pair[1] / pair[0]).ln())
+        .collect();
+    let mean: f64 = returns.iter().sum::<f64>() / returns.len() as f64;
+    let variance: f64 =
+        returns.iter().map(|ret| (ret - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+    Some((variance * 252.0).sqrt())
+}
+
+// Function that returns the one standard deviation move of the underlying over a number of
+// trading days at an annualized volatility.
+pub(crate) fn expected_move(spot: f64, volatility: f64, days: f64) -> f64 {
+    spot * volatility * (days / 252.0).sqrt()
+}
+
+// Function that returns a rank value comparable across strategies: edge per dollar of margin
+// per day to expiry, weighted by liquidity like the per strategy formulas.
+pub(crate) fn calc_normalized_rank_value(contender: &Contender, current_date: &str) -> f64 {
+    let difference: i64 = calc_time_difference(current_date, &contender.exp_date) + 1;
+    let margin: f64 = contender.margin().max(0.01);
+    contender.avg_ask * contender.modeled_edge() / margin / difference as f64
+}
+
+// Function that orders contenders best first with a total, reproducible ordering:
+// rank value desc (NaN last), arb value desc, expiry asc, strategy name, then leg strikes asc.
+pub(crate) fn compare_contenders(a: &Contender, b: &Contender) -> Ordering {
+    let rank = |c: &Contender| -> f64 {
+        if c.rank_value.is_nan() {
+            f64::NEG_INFINITY
+        } else {
+            c.rank_value
+        }
+    };
+
+    rank(b)
+        .total_cmp(&rank(a))
+        .then_with(|| b.arb_val.total_cmp(&a.arb_val))
+        .then_with(|| a.exp_date.cmp(&b.exp_date))
+        .then_with(|| a.type_spread.cmp(&b.type_spread))
+        .then_with(|| {
+            a.contracts
+                .iter()
+                .map(|leg| leg.strike)
+                .zip(b.contracts.iter().map(|leg| leg.strike))
+                .map(|(x, y)| x.total_cmp(&y))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        })
+        .then_with(|| {
+            a.contracts
+                .iter()
+                .map(|leg| leg.type_contract.as_str())
+                .cmp(b.contracts.iter().map(|leg| leg.type_contract.as_str()))
+        })
+}
+
+// Function that drops contenders sharing a leg with a better ranked contender of another
+// strategy, since a vertical mispriced by one bad quote shows up in both a butterfly and a box.
+// Expects contenders sorted best first and returns how many were dropped.
+pub(crate) fn dedupe_contenders(contenders: &mut Vec<Contender>) -> usize {
+    let mut leg_owners: HashMap<(String, String, OrderedFloat<f64>), String> = HashMap::new();
+    let before: usize = contenders.len();
+    contenders.retain(|contender| {
+        let legs: Vec<(String, String, OrderedFloat<f64>)> = contender
+            .contracts
+            .iter()
+            .map(|leg| {
+                (
+                    leg.date.clone(),
+                    leg.type_contract.clone(),
+                    OrderedFloat(leg.strike),
+                )
+            })
+            .collect();
+        let overlaps: bool = legs.iter().any(|leg| {
+            leg_owners
+                .get(leg)
+                .is_some_and(|owner| *owner != contender.type_spread)
+        });
+        if !overlaps {
+            for leg in legs {
+                leg_owners
+                    .entry(leg)
+                    .or_insert_with(|| contender.type_spread.clone());
+            }
+        }
+        !overlaps
+    });
+    before - contenders.len()
+}
+
+// Function that predicts the worst case (1st percentile) calendar profit per share after fees by
+// stress testing the spread through front expiry. Without an underlying price or a usable back
+// leg implied volatility, falls back to a flat loss estimate proportional to the strike.
+pub(crate) fn calendar_spread_risk_free_profit(
+    position: &CalendarPosition,
+    underlying_price: Option<f64>,
+    gate: &ProfitGate,
+) -> f64 {
+    let worst_case: f64 = match underlying_price
+        .and_then(|spot| simulate_calendar(position, spot, &StressConfig::default()))
+    {
+        Some(result) => result.p1,
+        None => position.credit - (position.strike / 200.0) * 0.03,
+    };
+    worst_case - gate.fees_per_share(2.0)
+}
+
+// Function that returns the riskless butterfly profit per share after fees: the wings cost less
+// than the two bodies sell for and the payoff at expiry is never negative.
+pub(crate) fn butterfly_risk_free_profit(credit: f64, gate: &ProfitGate) -> f64 {
+    credit - gate.fees_per_share(4.0)
+}
+
+// Function that returns the riskless short box profit per share after fees: the cash received
+// carried to expiry, less the strike width owed then.
+pub(crate) fn boxspread_risk_free_profit(
+    credit: f64,
+    width: f64,
+    days_to_expiry: i64,
+    gate: &ProfitGate,
+) -> f64 {
+    let carry: f64 = (gate.risk_free_rate * days_to_expiry.max(0) as f64 / 365.0).exp();
+    credit * carry - width - gate.fees_per_share(4.0)
 }
 
 // Function to format the strike price and trim trailing zeros.
@@ -363,3 +400,97 @@ pub(crate) fn format_strike(price: f64) -> String {
     }
     formatted
 }
+
+// Function that checks whether a snapshot batch has finished warming up.
+pub(crate) fn is_snapshot_warm(responses: &[MarketDataResponse]) -> bool {
+    // The farm status (6509) arrives first, then the quotes fill in on later requests.
+    let connected: bool = !responses.is_empty()
+        && responses.iter().all(|response| {
+            response
+                .field_6509
+                .as_ref()
+                .is_some_and(|status| !status.is_empty())
+        });
+    let quoted: bool = responses
+        .iter()
+        .any(|response| response.field_84.is_some() && response.field_86.is_some());
+
+    connected && quoted
+}
+
+// Function that parses a snapshot into a quote, None without a conid to key it by.
+pub(crate) fn parse_quote(response: &MarketDataResponse) -> Option<Quote> {
+    let field = |val: &Option<String>| -> Option<f64> { parse_price(val.as_ref()?) };
+    Some(Quote {
+        conid: response
+            .conid_ex
+            .clone()
+            .or_else(|| response.conid.clone())?,
+        bid: field(&response.field_84),
+        ask: field(&response.field_86),
+        ask_size: field(&response.field_85),
+        last: field(&response.field_31),
+        prior_close: field(&response.field_7741),
+        open_interest: field(&response.field_7638),
+    })
+}
+
+// Function that reads a number the gateway may send as either a JSON number or a string.
+pub(crate) fn value_to_f64(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::Number(number) => number.as_f64(),
+        serde_json::Value::String(number) => parse_price(number),
+        _ => None,
+    }
+}
+
+// Function that parses a price or size field from the gateway, None when there's no value.
+// Handles the "C" (prior close) and "H" (halted) markers IBKR prepends, "N/A" placeholders,
+// thousands separators, "K" and "M" abbreviated sizes, and a decimal comma as in "1.234,50" or
+// "123,45". A lone comma followed by exactly three digits is read as a thousands separator, as
+// the gateway formats numbers.
+pub(crate) fn parse_price(field: &str) -> Option<f64> {
+    let field: &str = field.trim().trim_start_matches(['C', 'H']).trim();
+    let (field, scale): (&str, f64) = match field.strip_suffix(['K', 'k']) {
+        Some(field) => (field, 1_000.0),
+        None => match field.strip_suffix('M') {
+            Some(field) => (field, 1_000_000.0),
+            None => (field, 1.0),
+        },
+    };
+    let (sign, digits): (f64, &str) = match field.strip_prefix('-') {
+        Some(digits) => (-1.0, digits),
+        None => (1.0, field.strip_prefix('+').unwrap_or(field)),
+    };
+    if digits.is_empty()
+        || !digits.starts_with(|c: char| c.is_ascii_digit() || c == '.' || c == ',')
+        || !digits
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.' || c == ',')
+    {
+        return None;
+    }
+
+    let decimal: Option<char> = match (digits.rfind('.'), digits.rfind(',')) {
+        (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+        (Some(_), None) if digits.matches('.').count() == 1 => Some('.'),
+        (None, Some(comma)) if digits.matches(',').count() == 1 && digits.len() - comma != 4 => {
+            Some(',')
+        }
+        _ => None,
+    };
+    let number: String = digits
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_ascii_digit() => Some(c),
+            c if Some(c) == decimal => Some('.'),
+            _ => None,
+        })
+        .collect();
+
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|val| val.is_finite())
+        .map(|val| sign * val * scale)
+}
@@ -1,61 +1,161 @@
-use chrono::{Datelike, Local};
-use ordered_float::OrderedFloat;
+use chrono::{DateTime, Utc};
+use chrono_tz::{America::New_York, Tz};
 use reqwest::{
-    blocking::{Client, ClientBuilder, Response},
-    header::CONTENT_TYPE,
+    blocking::{Client, Response},
+    header::{CONTENT_TYPE, DATE},
+    StatusCode,
 };
 use serde_json::Value;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     error::Error,
-    io::{self, ErrorKind},
+    io,
     process::exit,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, RwLock},
     thread,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    helpers::{calc_rank_value, calc_time_difference, calendar_spread_risk_free_profit},
-    logging::{log_error, log_message},
-    orders::build_request_data,
+    allocator::Exposure,
+    audit::{scanner_checks, FilterCheck, ScanContext},
+    blacklist::{BlacklistedQuote, QuoteBlacklist},
+    book::{load_book, reconcile_book, save_book, Book, Settlement, SettlementKind, BOOK_PATH},
+    chain::{chain_from_contracts, parse_option_months, ChainView},
+    clock::{Clock, SystemClock},
+    config::{Config, MissingQuotes},
+    expiry::{expires_on, is_itm_short, roll_conidex, ExpiryAction},
+    exposure::{load_exposure_book, ExpiryCaps, ExpiryExposure, ExposureBook},
+    fill_model::FillModel,
+    filters::{default_leg_filters, first_rejection, LegFilter, LegFilterKind},
+    greeks::calendar_theta_score,
+    hedge::{hedge_quantity, is_tradable_hedge, is_working, net_delta},
+    helpers::{
+        boxspread_risk_free_profit, butterfly_risk_free_profit, calc_calendar_rank_value,
+        calc_normalized_rank_value, calc_rank_value, calc_time_difference,
+        calendar_spread_risk_free_profit, clock_drift, compare_contenders, dedupe_contenders,
+        expected_move, format_strike, is_snapshot_warm, parse_history, parse_price, parse_quote,
+        realized_volatility, value_to_f64, JOURNAL_PATH,
+    },
+    journal::{
+        append_journal, candidate_quotes, combo_mid, final_status, journal_legs, leg_quotes,
+        load_roll_history, realized_edge, slippage, JournalEntry, JournalLeg, LegQuote,
+        TrackedOrder,
+    },
+    logging::{log_debug, log_error, log_exit, log_message, log_warn},
+    market::{
+        in_open_warmup, is_halted_field, is_quote_stable, vix_regime, MarketMonitor, MarketState,
+        Regime,
+    },
+    metrics::{increment, ScanStats},
+    netting::{net_orders, netted_price, NettedOrder},
+    orders::{
+        build_market_order, build_request_data, build_roll_order, cap_to_ask, combo_confirms,
+        escalated_price, expired_orders, join_price, ladder_rungs, ladder_siblings, placed_fills,
+        round_to_tick,
+    },
+    positions::{contender_legs, max_fills_within_short_limit, reduced_contracts, PositionRules},
+    progress::Progress,
+    quote_diff::{CachedScan, QuoteDiff, ScanKey},
+    replies::{parse_reply, ReplyOutcome, ReplyPolicy},
+    responses::ReadJson,
+    roll::{roll_due, roll_price, roll_target},
+    scheduler::{random_delay, random_fraction, StrategySchedules},
+    session::Unauthenticated,
+    stress::{held_calendars, CalendarPosition, HeldCalendar},
     structs::{
-        AccountResponse, Confirmation, Contender, Contract, MarketDataResponse, Opt,
-        PortfolioResponse, RequestDataStruct, SecDefInfoResponse, SecDefResponse,
+        AccountResponse, AccountSummary, Bar, ButterflyWings, CancelResponse, Confirmation,
+        ConidsMap, Contender, Contract, ContractInfoResponse, ExpiryRule, HedgeRule, LiveOrder,
+        MarketDataResponse, Opt, OrderBody, OrderTags, OrdersResponse, PortfolioResponse,
+        PositionResponse, PriceBounds, PriceLimits, Profile, ProfitGate, Provenance, Quote,
+        QuoteSource, RequestDataStruct, RollRule, SecDefInfoResponse, SecDefResponse,
+        SecDefStrikesResponse, StrikeSlice, TickRule, TradeResponse,
     },
+    surface::{atm_vol, VolSurface},
+    systemd::ExitCode,
+    trace::TraceSend,
+    validation::{check_availability, QuoteRejection},
 };
 
-enum OptionType {
-    Calendar,
-    Butterfly,
-    BoxSpread,
-    All,
-}
+// How long a fetched account summary is reused before it is refetched.
+const ACCOUNT_SUMMARY_TTL: Duration = Duration::from_secs(5);
 
-impl OptionType {
-    fn from_str(s: &str) -> Option<Self> {
-        match s {
-            "1" => Some(OptionType::Calendar),
-            "2" => Some(OptionType::Butterfly),
-            "3" => Some(OptionType::BoxSpread),
-            _ => Some(OptionType::All),
-        }
-    }
-}
+// Number of snapshot rounds to wait for the market data farm before the first scan.
+const WARMUP_ATTEMPTS: u32 = 10;
 
+// Secdef info requests in flight at once while loading a month's chain.
+const STRIKE_LOOKUPS: usize = 8;
+
+#[allow(clippy::upper_case_acronyms)]
 pub(crate) struct IBKR {
     ticker: Option<String>,
     discount_value: Option<f64>,
     arb_val: Option<f64>,
     strike_dif_value: Option<f64>,
-    base_url: Option<String>,
-    live_orders: Option<Vec<String>>,
+    butterfly_wings: ButterflyWings,
+    // Shared with the order monitor so a gateway failover reroutes both.
+    base_url: Option<Arc<RwLock<String>>>,
+    live_orders: Option<Arc<Mutex<Vec<LiveOrder>>>>,
     client: Option<Client>,
     account_id: Option<String>,
     ticker_id: Option<String>,
     conids_strings: Option<Vec<String>>,
-    dates_slice: Option<Vec<String>>,
-    strike_slice: Option<HashMap<String, HashMap<String, Vec<f64>>>>,
-    conids_map: Option<HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>>,
+    chain: Option<ChainView>,
+    vix_id: Option<String>,
+    vix_threshold: f64,
+    vix_arb_scale: f64,
+    vix_disabled_strategies: Vec<String>,
+    max_quote_size: f64,
+    missing_quotes: MissingQuotes,
+    fill_model: FillModel,
+    skew_max_residual: f64,
+    realized_move_multiple: f64,
+    realized_vol_days: usize,
+    realized_vol: Option<f64>,
+    reply_policy: ReplyPolicy,
+    account_summary: Option<(Instant, AccountSummary)>,
+    journal_orders: Vec<TrackedOrder>,
+    disabled_strategies: Vec<String>,
+    // When each spread type was last scanned, for its scan interval.
+    strategy_scanned_at: HashMap<String, DateTime<Utc>>,
+    join_best: bool,
+    combo_quotes: bool,
+    tick_rule: TickRule,
+    profit_gate: ProfitGate,
+    escalate_after: u64,
+    escalate_step: f64,
+    market_monitor: MarketMonitor,
+    open_warmup_minutes: i64,
+    hedge_rule: HedgeRule,
+    // Symbol of the hedge instrument, which its orders are stamped with.
+    hedge_symbol: Option<String>,
+    // The last hedge order sent, not hedged over again until it is no longer working.
+    working_hedge: Option<String>,
+    expiry_rule: ExpiryRule,
+    expiry_handled: HashSet<i64>,
+    roll_rule: RollRule,
+    rolled: HashSet<i64>,
+    orphans_possible: bool,
+    submit_delay_ms: u64,
+    near_miss_margin: f64,
+    leg_filters: Vec<LegFilter>,
+    incremental_scan: bool,
+    dedupe_contenders: bool,
+    quote_blacklist: QuoteBlacklist,
+    scan_cache: HashMap<String, CachedScan>,
+    order_tags: OrderTags,
+    price_limits: PriceLimits,
+    expiry_caps: ExpiryCaps,
+    position_rules: PositionRules,
+    // Contracts held by conid as of the last scan, for the position rules.
+    held_positions: HashMap<String, f64>,
+    exposure_book: ExposureBook,
+    scan_stats: ScanStats,
+    ladder_step: f64,
+    ladder_rungs: i32,
+    ladder_cancel_on_fill: bool,
+    net_orders: bool,
+    clock: Arc<dyn Clock>,
 }
 
 impl IBKR {
@@ -65,65 +165,144 @@ impl IBKR {
             discount_value: None,
             arb_val: None,
             strike_dif_value: None,
+            butterfly_wings: ButterflyWings::default(),
             base_url: None,
             live_orders: None,
             client: None,
             account_id: None,
             ticker_id: None,
             conids_strings: None,
-            dates_slice: None,
-            strike_slice: None,
-            conids_map: None,
+            chain: None,
+            vix_id: None,
+            vix_threshold: 0.0,
+            vix_arb_scale: 1.0,
+            vix_disabled_strategies: Vec::new(),
+            max_quote_size: 0.0,
+            missing_quotes: MissingQuotes::Skip,
+            fill_model: FillModel::Off,
+            skew_max_residual: 0.0,
+            realized_move_multiple: 0.0,
+            realized_vol_days: 20,
+            realized_vol: None,
+            reply_policy: ReplyPolicy::ConfirmAllowlist(Vec::new()),
+            account_summary: None,
+            journal_orders: Vec::new(),
+            disabled_strategies: Vec::new(),
+            strategy_scanned_at: HashMap::new(),
+            join_best: false,
+            combo_quotes: false,
+            tick_rule: TickRule::default(),
+            profit_gate: ProfitGate::default(),
+            escalate_after: 0,
+            escalate_step: 0.0,
+            market_monitor: MarketMonitor::new(0.0, 0),
+            open_warmup_minutes: 0,
+            hedge_rule: HedgeRule::default(),
+            hedge_symbol: None,
+            working_hedge: None,
+            expiry_rule: ExpiryRule::default(),
+            expiry_handled: HashSet::new(),
+            roll_rule: RollRule::default(),
+            rolled: HashSet::new(),
+            orphans_possible: false,
+            submit_delay_ms: 0,
+            near_miss_margin: 0.0,
+            leg_filters: default_leg_filters(),
+            incremental_scan: false,
+            dedupe_contenders: false,
+            quote_blacklist: QuoteBlacklist::new(0, 0.0),
+            scan_cache: HashMap::new(),
+            order_tags: OrderTags::default(),
+            price_limits: PriceLimits::default(),
+            expiry_caps: ExpiryCaps::default(),
+            position_rules: PositionRules::default(),
+            held_positions: HashMap::new(),
+            exposure_book: ExposureBook::default(),
+            scan_stats: ScanStats::default(),
+            ladder_step: 0.0,
+            ladder_rungs: 1,
+            ladder_cancel_on_fill: false,
+            net_orders: false,
+            clock: Arc::new(SystemClock),
         }
     }
 
+    // Function that sets up the session for a profile: the client, account, conids and every
+    // scan and order setting from the config.
     pub(crate) fn init(
         &mut self,
-        ticker: String,
-        discount_value: f64,
-        arb_val: f64,
-        strike_dif_value: f64,
-        domain: String,
-        port: String,
-        num_days: i64,
-        num_days_offset: i64,
+        profile: &Profile,
+        config: &Config,
     ) -> Result<(), Box<dyn Error>> {
-        let mut current_month: String = String::new();
-        let mut next_month: String = String::new();
-
-        self.ticker = Some(ticker);
-        self.discount_value = Some(discount_value);
-        self.arb_val = Some(arb_val);
-        self.strike_dif_value = Some(strike_dif_value);
-        self.base_url = Some(format!("https://{}:{}", domain, port));
-        self.live_orders = Some(Vec::new());
-        self.client = Some(
-            ClientBuilder::new()
-                .danger_accept_invalid_certs(true)
-                .build()
-                .unwrap(),
+        let mut months: Vec<String> = Vec::new();
+
+        self.ticker = Some(profile.ticker.clone());
+        self.discount_value = Some(profile.discount_value);
+        self.arb_val = Some(profile.arb_val);
+        self.strike_dif_value = Some(profile.strike_dif_value);
+        self.butterfly_wings = profile.butterfly_wings.clone();
+        self.tick_rule = profile.tick_rule;
+        self.profit_gate = profile.profit_gate;
+        self.hedge_rule = profile.hedge_rule.clone();
+        self.expiry_rule = profile.expiry_rule;
+        self.roll_rule = profile.roll_rule;
+        self.order_tags = profile.order_tags.clone();
+        self.price_limits = profile.price_limits;
+        self.expiry_caps = profile.expiry_caps;
+        self.position_rules = profile.position_rules;
+        self.exposure_book = load_exposure_book(JOURNAL_PATH, &profile.ticker);
+        self.combo_quotes = config.combo_quotes;
+        self.join_best = config.order_join_best;
+        self.escalate_after = config.order_escalate_after;
+        self.escalate_step = config.order_escalate_step;
+        self.submit_delay_ms = config.order_submit_delay_ms;
+        self.near_miss_margin = config.near_miss_margin;
+        self.leg_filters = config.leg_filters.clone();
+        self.incremental_scan = config.incremental_scan;
+        self.dedupe_contenders = config.dedupe_contenders;
+        self.quote_blacklist =
+            QuoteBlacklist::new(config.blacklist_after, config.blacklist_half_life);
+        self.ladder_step = profile.execution.ladder_step;
+        self.ladder_rungs = profile.execution.ladder_rungs;
+        self.ladder_cancel_on_fill = config.ladder_cancel_on_fill;
+        self.net_orders = config.net_orders;
+        self.max_quote_size = config.max_quote_size;
+        self.missing_quotes = config.missing_quotes;
+        self.fill_model = config.fill_model();
+        self.skew_max_residual = config.skew_max_residual;
+        self.realized_move_multiple = config.realized_move_multiple;
+        self.realized_vol_days = config.realized_vol_days;
+        self.reply_policy = config.reply_policy();
+        self.market_monitor = MarketMonitor::new(
+            config.fast_market_spread_multiple,
+            config.fast_market_window,
         );
+        self.open_warmup_minutes = config.open_warmup_minutes;
+        self.base_url = Some(Arc::new(RwLock::new(config.base_url())));
+        self.live_orders = Some(Arc::new(Mutex::new(Vec::new())));
+        self.client = Some(config.client()?);
         match self.get_account_id() {
             Ok(account_id) => {
+                log_message(format!("Trading account {}.", account_id));
                 self.account_id = Some(account_id);
             }
+            // A rejected session won't fix itself on a restart, unlike a gateway error.
+            Err(e) if e.is::<Unauthenticated>() => log_exit(e.to_string(), ExitCode::AuthFailure),
             Err(e) => log_error(format!("Failed to get account ID: {}", e)),
         }
         match self.get_ticker_conid() {
-            Ok((ticker_id, month1, month2)) => {
+            Ok((ticker_id, option_months)) => {
                 self.ticker_id = Some(ticker_id);
-                current_month = month1;
-                next_month = month2;
+                months = option_months;
             }
             Err(e) => log_error(format!("Failed to get ticker ID: {}", e)),
         }
+        self.check_hedge_instrument();
 
-        match self.get_conids_map(num_days, num_days_offset, current_month, next_month) {
+        match self.get_conids_map(config.num_days(), config.num_days_offset(), &months) {
             Ok((conids_strings, dates_slice, strike_slice, conids_map)) => {
                 self.conids_strings = Some(conids_strings);
-                self.dates_slice = Some(dates_slice);
-                self.strike_slice = Some(strike_slice);
-                self.conids_map = Some(conids_map);
+                self.set_chain(ChainView::new(dates_slice, strike_slice, conids_map));
             }
             Err(e) => {
                 log_error(format!("Failed to init conid map: {}", e));
@@ -132,78 +311,560 @@ impl IBKR {
         }
 
         self.init_ticker_data()?;
+        if let Err(e) = self.refresh_realized_vol() {
+            log_warn(format!("Failed to get realized volatility: {}.", e));
+        }
 
         Ok(())
     }
 
-    // Function that returns a slice of the top arbs given the number of orders.
-    pub(crate) fn get_contender_contracts(
+    // Function that scores the legs of an order that ended against the bad quote blacklist.
+    fn record_blacklist(&mut self, tracked: &TrackedOrder, filled: bool) {
+        let now: DateTime<Utc> = self.clock.now();
+        let ticker: String = self.ticker.clone().unwrap_or_default();
+        for leg in &tracked.legs {
+            let contract: String = format!(
+                "{} {} {}{}",
+                ticker,
+                leg.date,
+                format_strike(leg.strike),
+                leg.right
+            );
+            if self
+                .quote_blacklist
+                .record(&leg.conid, &contract, filled, now)
+            {
+                increment("quotes_blacklisted", 1);
+                log_warn(format!(
+                    "Blacklisted {} (conid {}), its arbs keep going unfilled.",
+                    contract, leg.conid
+                ));
+            }
+        }
+    }
+
+    // Function that returns the bot's open spreads by expiration, from today on.
+    pub(crate) fn expiry_exposure(&self) -> Vec<ExpiryExposure> {
+        self.exposure_book.report(&self.clock.scan_date())
+    }
+
+    // Function that cuts each contender's fills down to what its expiration's caps have room for,
+    // best ranked first. A fill counts at its margin, the most it can lose, until it is
+    // submitted. Returns the quantities and how many contenders were cut.
+    fn cap_expiry_exposure(
+        &self,
+        contenders: &[Contender],
+        quantities: &[i32],
+    ) -> (Vec<i32>, usize) {
+        let caps: ExpiryCaps = self.expiry_caps;
+        if caps.max_spreads <= 0.0 && caps.max_loss <= 0.0 {
+            return (quantities.to_vec(), 0);
+        }
+        let ticker: &str = self.ticker.as_deref().unwrap_or_default();
+        let mut exposures: HashMap<String, ExpiryExposure> = HashMap::new();
+        let mut capped_quantities: Vec<i32> = Vec::new();
+        let mut capped: usize = 0;
+
+        for (contender, quantity) in contenders.iter().zip(quantities) {
+            let exposure: &mut ExpiryExposure = exposures
+                .entry(contender.exp_date.clone())
+                .or_insert_with(|| self.exposure_book.exposure(ticker, &contender.exp_date));
+            let loss_per_fill: f64 = contender.margin().abs() * 100.0;
+            let mut fills: i32 = (*quantity).max(0);
+            if caps.max_spreads > 0.0 {
+                fills = fills.min((caps.max_spreads - exposure.spreads).max(0.0).floor() as i32);
+            }
+            if caps.max_loss > 0.0 && loss_per_fill > 0.0 {
+                fills = fills.min(
+                    ((caps.max_loss - exposure.worst_case_loss).max(0.0) / loss_per_fill).floor()
+                        as i32,
+                );
+            }
+            if fills < *quantity {
+                capped += 1;
+                log_message(format!(
+                    "{} {} {} cut from {} to {} fills by the exposure caps on its expiry.",
+                    ticker, contender.type_spread, contender.exp_date, quantity, fills
+                ));
+            }
+            exposure.spreads += fills as f64;
+            exposure.worst_case_loss += loss_per_fill * fills as f64;
+            capped_quantities.push(fills);
+        }
+        (capped_quantities, capped)
+    }
+
+    // Function that screens ranked contenders against the positions held: contenders that can't
+    // take a fill without going over the short limit on a strike are dropped and, when
+    // preferred, contenders closing held contracts move ahead of the rest in rank order. The
+    // positions are kept for capping the fills at submission, and left as they were when they
+    // can't be fetched.
+    fn screen_held_positions(&mut self, contenders: &mut Vec<Contender>) {
+        match self.get_positions() {
+            Ok(positions) => {
+                self.held_positions = positions
+                    .iter()
+                    .map(|position| (position.conid.to_string(), position.position))
+                    .collect();
+            }
+            Err(e) => log_warn(format!("Failed to get positions: {}.", e)),
+        }
+        let chain: &ChainView = match self.chain.as_ref() {
+            Some(chain) => chain,
+            None => return,
+        };
+        let rules: PositionRules = self.position_rules;
+        let before: usize = contenders.len();
+        contenders.retain(|contender| {
+            max_fills_within_short_limit(
+                &contender_legs(contender, chain),
+                &self.held_positions,
+                rules.max_short_per_strike,
+            ) != Some(0)
+        });
+        increment(
+            "contenders_position_filtered",
+            (before - contenders.len()) as u64,
+        );
+        if rules.prefer_reducing {
+            contenders.sort_by_key(|contender| {
+                reduced_contracts(&contender_legs(contender, chain), &self.held_positions) <= 0.0
+            });
+        }
+    }
+
+    // Function that cuts each contender's fills down to what keeps every strike it sells within
+    // the short limit, counting the fills of the contenders ranked ahead of it. Returns the
+    // quantities and how many contenders were cut.
+    fn cap_held_positions(
+        &self,
+        contenders: &[Contender],
+        quantities: &[i32],
+    ) -> (Vec<i32>, usize) {
+        let max_short: f64 = self.position_rules.max_short_per_strike;
+        let chain: &ChainView = match self.chain.as_ref() {
+            Some(chain) if max_short > 0.0 => chain,
+            _ => return (quantities.to_vec(), 0),
+        };
+        let ticker: &str = self.ticker.as_deref().unwrap_or_default();
+        let mut positions: HashMap<String, f64> = self.held_positions.clone();
+        let mut capped_quantities: Vec<i32> = Vec::new();
+        let mut capped: usize = 0;
+
+        for (contender, quantity) in contenders.iter().zip(quantities) {
+            let legs: Vec<(String, i64)> = contender_legs(contender, chain);
+            let mut fills: i32 = (*quantity).max(0);
+            if let Some(max_fills) = max_fills_within_short_limit(&legs, &positions, max_short) {
+                fills = fills.min(max_fills);
+            }
+            if fills < *quantity {
+                capped += 1;
+                log_message(format!(
+                    "{} {} {} cut from {} to {} fills by the short limit per strike.",
+                    ticker, contender.type_spread, contender.exp_date, quantity, fills
+                ));
+            }
+            for (conid, contracts) in legs {
+                *positions.entry(conid).or_insert(0.0) += (contracts * fills as i64) as f64;
+            }
+            capped_quantities.push(fills);
+        }
+        (capped_quantities, capped)
+    }
+
+    // Function that returns the contracts kept out of scans.
+    pub(crate) fn blacklist_report(&mut self) -> Vec<BlacklistedQuote> {
+        self.quote_blacklist.report(self.clock.now())
+    }
+
+    // Function that swaps the wall clock for another, so scans run at a synthetic time.
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    // Function that swaps in a freshly loaded chain, along with its index of spread combinations.
+    fn set_chain(&mut self, chain: ChainView) {
+        log_message(format!(
+            "Indexed {} spread combinations for {}.",
+            chain.combos().len(),
+            self.ticker.as_deref().unwrap_or_default()
+        ));
+        self.scan_cache.clear();
+        self.chain = Some(chain);
+    }
+
+    // Function that sets the session up again after the gateway restarted: reauthenticates,
+    // rediscovers the account and reloads and primes the chain. Working orders are kept, they
+    // live with the broker rather than the gateway.
+    pub(crate) fn reinit(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+        self.reauthenticate()?;
+        self.account_id = Some(self.get_account_id()?);
+        self.refresh_chain(config)
+    }
+
+    // Function that reloads the chain for a new trading day, dropping expired dates and listing
+    // new ones, and clears what the last session learned about the market and its own orders.
+    pub(crate) fn refresh_chain(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+        let (ticker_id, months) = self.get_ticker_conid()?;
+        let (conids_strings, dates_slice, strike_slice, conids_map) =
+            self.get_conids_map(config.num_days(), config.num_days_offset(), &months)?;
+        self.ticker_id = Some(ticker_id);
+        self.conids_strings = Some(conids_strings);
+        self.set_chain(ChainView::new(dates_slice, strike_slice, conids_map));
+        self.market_monitor = MarketMonitor::new(
+            config.fast_market_spread_multiple,
+            config.fast_market_window,
+        );
+        self.disabled_strategies.clear();
+        self.strategy_scanned_at.clear();
+        self.expiry_handled.clear();
+        self.rolled.clear();
+        if let Err(e) = self.refresh_realized_vol() {
+            log_warn(format!("Failed to get realized volatility: {}.", e));
+        }
+
+        self.init_ticker_data()
+    }
+
+    // Function that sends a GET request for a contract's price history, e.g. a period of "30d"
+    // in "1d" bars, returning the bars oldest first.
+    pub(crate) fn get_history(
         &self,
-        option: &str,
+        conid: &str,
+        period: &str,
+        bar: &str,
+    ) -> Result<Vec<Bar>, Box<dyn Error>> {
+        let history_url: String = format!("{}/v1/api/iserver/marketdata/history", self.base_url());
+        let params: [(&str, &str); 3] = [("conid", conid), ("period", period), ("bar", bar)];
+
+        let response: Response = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .get(&history_url)
+            .query(&params)
+            .send_traced()?;
+        if !response.status().is_success() {
+            return Err(format!("Failed to get price history: {}", response.status()).into());
+        }
+
+        Ok(parse_history(
+            &response.read_json("/iserver/marketdata/history")?,
+        ))
+    }
+
+    // Function that measures the underlying's realized volatility over the last
+    // REALIZED_VOL_DAYS daily closes, once per session since daily bars don't change intraday.
+    fn refresh_realized_vol(&mut self) -> Result<(), Box<dyn Error>> {
+        self.realized_vol = None;
+        if self.realized_move_multiple <= 0.0 {
+            return Ok(());
+        }
+
+        // Calendar days, with room for weekends and holidays.
+        let period: String = format!("{}d", self.realized_vol_days * 2 + 5);
+        let ticker_id: &String = self.ticker_id.as_ref().ok_or("ticker ID is not set")?;
+        let closes: Vec<f64> = self
+            .get_history(ticker_id, &period, "1d")?
+            .iter()
+            .map(|bar| bar.close)
+            .collect();
+        let recent: &[f64] = &closes[closes.len().saturating_sub(self.realized_vol_days + 1)..];
+        let realized_vol: f64 =
+            realized_volatility(recent).ok_or("not enough daily closes in the price history")?;
+        log_message(format!(
+            "{} realized volatility {:.1}% over {} days.",
+            self.ticker.as_deref().unwrap_or_default(),
+            realized_vol * 100.0,
+            recent.len() - 1
+        ));
+        self.realized_vol = Some(realized_vol);
+        Ok(())
+    }
+
+    // Function that sets up the VIX regime filter, a threshold of 0.0 disables it.
+    pub(crate) fn init_volatility_regime(
+        &mut self,
+        vix_threshold: f64,
+        vix_arb_scale: f64,
+        vix_disabled_strategies: Vec<String>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.vix_threshold = vix_threshold;
+        self.vix_arb_scale = vix_arb_scale;
+        self.vix_disabled_strategies = vix_disabled_strategies;
+
+        if vix_threshold > 0.0 {
+            let vix_id: String = self.get_symbol_conid("VIX")?;
+            // The first snapshot for a conid is always empty, so prime it here.
+            let _ = self.get_last_price(&vix_id);
+            self.vix_id = Some(vix_id);
+        }
+
+        Ok(())
+    }
+
+    // Function that returns the quote counts of the last scan.
+    pub(crate) fn scan_stats(&self) -> ScanStats {
+        self.scan_stats
+    }
+
+    // Function that returns a slice of the top arbs given the number of orders, scanning the
+    // strategies their schedules have due.
+    pub(crate) fn get_contender_contracts(
+        &mut self,
+        strategies: &StrategySchedules,
         num_orders: i32,
     ) -> Result<Vec<Contender>, Box<dyn Error>> {
         let contracts_map: HashMap<String, Opt> = self.get_ticker_data()?;
         let mut contender_contracts_total: Vec<Contender> = Vec::new();
 
-        let dates_slice: &Vec<String> =
-            self.dates_slice.as_ref().ok_or("dates slice is not set")?;
-        let strike_slice: &HashMap<String, HashMap<String, Vec<f64>>> = self
-            .strike_slice
-            .as_ref()
-            .ok_or("strike slice is not set")?;
-        let conids_map: &HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>> =
-            self.conids_map.as_ref().ok_or("conids map is not set")?;
-
-        match OptionType::from_str(option).ok_or("Invalid option type")? {
-            OptionType::Calendar => {
-                contender_contracts_total.extend(self.get_calendar_contenders(
-                    &contracts_map,
-                    dates_slice,
-                    strike_slice,
-                    conids_map,
-                )?);
-            }
-            OptionType::Butterfly => {
-                contender_contracts_total.extend(self.get_butterfly_contenders(
-                    &contracts_map,
-                    dates_slice,
-                    strike_slice,
-                    conids_map,
-                )?);
-            }
-            OptionType::BoxSpread => {
-                contender_contracts_total.extend(self.get_boxspread_contenders(
-                    &contracts_map,
-                    dates_slice,
-                    strike_slice,
-                    conids_map,
-                )?);
-            }
-            OptionType::All => {
-                contender_contracts_total.extend(self.get_calendar_contenders(
-                    &contracts_map,
-                    dates_slice,
-                    strike_slice,
-                    conids_map,
-                )?);
-                contender_contracts_total.extend(self.get_butterfly_contenders(
-                    &contracts_map,
-                    dates_slice,
-                    strike_slice,
-                    conids_map,
-                )?);
-                contender_contracts_total.extend(self.get_boxspread_contenders(
-                    &contracts_map,
-                    dates_slice,
-                    strike_slice,
-                    conids_map,
-                )?);
-            }
-        }
-
-        contender_contracts_total.sort_by(|a, b| b.rank_value.partial_cmp(&a.rank_value).unwrap());
+        let last_field: Option<String> = match &self.ticker_id {
+            Some(ticker_id) => self.get_last_field(ticker_id).ok(),
+            None => None,
+        };
+        let underlying_price: Option<f64> = last_field.as_deref().and_then(parse_price);
+        let halted: bool = last_field.as_deref().is_some_and(is_halted_field);
+        let conids_count: usize = self.conids_count();
+        let max_quote_size: f64 = self.max_quote_size;
+        let chain: &mut ChainView = self.chain.as_mut().ok_or("chain is not set")?;
+        chain.set_quotes(contracts_map);
+        chain.set_underlying_price(underlying_price);
+
+        let rejections: Vec<QuoteRejection> =
+            chain.validate_quotes(underlying_price, max_quote_size);
+        self.scan_stats = ScanStats {
+            quoted: chain.quoted_count(),
+            validated: conids_count,
+            rejected: rejections.len(),
+        };
+        increment("quotes_validated", conids_count as u64);
+        for rejection in &rejections {
+            increment(rejection.counter(), 1);
+        }
+        if !rejections.is_empty() {
+            log_message(format!(
+                "Rejected {} bad quotes before scanning.",
+                rejections.len()
+            ));
+        }
+        let spread: Option<f64> = chain.median_spread();
+        if let Some(state) = self.market_monitor.update(halted, spread) {
+            self.log_market_state(state);
+        }
+        let chain: &ChainView = self.chain.as_ref().ok_or("chain is not set")?;
+
+        // Scale the arb threshold and disable strategies when the VIX is in a high regime. When
+        // the VIX can't be read the regime is unknown, so the scan is skipped rather than run as
+        // if the market were calm.
+        let regime_value: Option<f64> = if self.vix_threshold > 0.0 {
+            match self.get_vix_value() {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    log_warn(format!("Failed to get the VIX: {}.", e));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let regime: Regime = match vix_regime(
+            self.vix_threshold,
+            regime_value,
+            self.arb_val.ok_or("arb value is not set")?,
+            self.vix_arb_scale,
+            &self.vix_disabled_strategies,
+            &["1", "2", "3"],
+        ) {
+            Some(regime) => regime,
+            None => {
+                increment("scans_skipped_vix", 1);
+                // The next scan's quote diff won't cover this cycle's changes, so drop the
+                // contenders carried over and rescan in full.
+                self.scan_cache.clear();
+                return Ok(Vec::new());
+            }
+        };
+        let regime_value: f64 = regime_value.unwrap_or(0.0);
+        let arb_val: f64 = regime.arb_val;
+        let enabled = |code: &str| -> bool {
+            regime.enabled.contains(&code) && !self.disabled_strategies.iter().any(|s| s == code)
+        };
+
+        // In a quiet market most quotes repeat, so only the combinations with a changed leg are
+        // evaluated again and the last scan's contenders on unchanged legs carry over. Quote age
+        // filters move with the clock rather than the quotes, so they always get a full rescan.
+        let diff: QuoteDiff = if self.incremental_scan
+            && !self
+                .leg_filters
+                .iter()
+                .any(|filter| filter.kind == LegFilterKind::MaxAge)
+        {
+            chain.quote_diff()
+        } else {
+            QuoteDiff::full()
+        };
+        if let Some(changed) = diff.changed_count() {
+            increment("quotes_changed", changed as u64);
+        }
+        let full: QuoteDiff = QuoteDiff::full();
+        let scan_date: String = self.clock.scan_date();
+        let now: DateTime<Utc> = self.clock.now();
+
+        let mut strategies_run: usize = 0;
+        for (schedule, code, type_spread) in [
+            (&strategies.calendar, "1", "Calendar"),
+            (&strategies.butterfly, "2", "Butterfly"),
+            (&strategies.boxspread, "3", "Boxspread"),
+        ] {
+            // A strategy skipped this cycle is rescanned in full when it is next due, since the
+            // quote diff only covers changes since the previous cycle.
+            let due: bool =
+                schedule.is_due(now, self.strategy_scanned_at.get(type_spread).copied());
+            if !due || !enabled(code) {
+                self.scan_cache.remove(type_spread);
+                continue;
+            }
+            self.strategy_scanned_at
+                .insert(type_spread.to_string(), now);
+            let key: ScanKey = ScanKey {
+                scan_date: scan_date.clone(),
+                arb_threshold: arb_val,
+                // Only the calendar's profit check looks at the underlying.
+                underlying_price: if code == "1" {
+                    chain.underlying_price()
+                } else {
+                    None
+                },
+            };
+            let carried: Option<Vec<Contender>> = self
+                .scan_cache
+                .remove(type_spread)
+                .and_then(|cached| cached.carry_over(&key, chain, &diff));
+            let scan_diff: &QuoteDiff = if carried.is_some() {
+                increment("scans_incremental", 1);
+                &diff
+            } else {
+                &full
+            };
+            let mut contenders: Vec<Contender> = match code {
+                "1" => self.get_calendar_contenders(chain, &scan_date, arb_val, scan_diff)?,
+                "2" => self.get_butterfly_contenders(chain, &scan_date, arb_val, scan_diff)?,
+                _ => self.get_boxspread_contenders(chain, &scan_date, arb_val, scan_diff)?,
+            };
+            contenders.extend(carried.unwrap_or_default());
+            self.scan_cache.insert(
+                type_spread.to_string(),
+                CachedScan {
+                    key,
+                    contenders: contenders.clone(),
+                },
+            );
+            contender_contracts_total.extend(contenders);
+            strategies_run += 1;
+        }
+
+        // Strikes whose arbs keep going unfilled are likely quoting stale prices.
+        if self.quote_blacklist.is_enabled() {
+            let now: DateTime<Utc> = self.clock.now();
+            let before: usize = contender_contracts_total.len();
+            contender_contracts_total.retain(|contender| {
+                contender.contracts.iter().all(|leg| {
+                    chain
+                        .conid(&leg.date, &leg.type_contract, leg.strike)
+                        .is_none_or(|conid| !self.quote_blacklist.is_blacklisted(conid, now))
+                })
+            });
+            increment(
+                "contenders_blacklisted",
+                (before - contender_contracts_total.len()) as u64,
+            );
+        }
+
+        // Opening rotations cross option quotes into arbs that aren't there, so during the warm-up
+        // only act on contenders whose legs quoted within a tick across the last two refreshes.
+        if in_open_warmup(self.clock.now(), self.open_warmup_minutes) {
+            let before: usize = contender_contracts_total.len();
+            contender_contracts_total.retain(|contender| {
+                contender.contracts.iter().all(|leg| {
+                    match (
+                        chain.previous_quote(&leg.date, &leg.type_contract, leg.strike),
+                        chain.quote(&leg.date, &leg.type_contract, leg.strike),
+                    ) {
+                        (Some(previous), Some(current)) => {
+                            is_quote_stable(previous, current, self.tick_rule.tick_for(current.ask))
+                        }
+                        _ => false,
+                    }
+                })
+            });
+            increment(
+                "contenders_warmup_filtered",
+                (before - contender_contracts_total.len()) as u64,
+            );
+        }
+
+        // Drop contenders whose edge comes from a leg off the fitted volatility surface.
+        if self.skew_max_residual > 0.0 {
+            if let Some(surface) = VolSurface::build(chain, &scan_date) {
+                let before: usize = contender_contracts_total.len();
+                contender_contracts_total
+                    .retain(|contender| !surface.explains_arb(contender, self.skew_max_residual));
+                increment(
+                    "contenders_skew_filtered",
+                    (before - contender_contracts_total.len()) as u64,
+                );
+            }
+        }
+
+        // A calendar struck within a day's move of the underlying can be run over before its front
+        // leg expires, the more so the more the underlying has been moving.
+        if let (Some(realized_vol), Some(spot)) = (self.realized_vol, chain.underlying_price()) {
+            let guard: f64 = self.realized_move_multiple * expected_move(spot, realized_vol, 1.0);
+            let before: usize = contender_contracts_total.len();
+            contender_contracts_total.retain(|contender| {
+                contender.type_spread != "Calendar"
+                    || contender
+                        .contracts
+                        .iter()
+                        .all(|leg| (leg.strike - spot).abs() > guard)
+            });
+            increment(
+                "contenders_realized_move_filtered",
+                (before - contender_contracts_total.len()) as u64,
+            );
+        }
+
+        // Each strategy ranks with its own formula, so contenders competing for the same capital
+        // are only compared on the same footing when several strategies ran.
+        if strategies_run > 1 {
+            for contender in contender_contracts_total.iter_mut() {
+                contender.rank_value = calc_normalized_rank_value(contender, &scan_date);
+            }
+        }
+
+        // Prefer slightly smaller edges that are likely to fill over ones that never do.
+        for contender in contender_contracts_total.iter_mut() {
+            contender.regime_value = regime_value;
+            contender.rank_value *= self.fill_model.probability(contender);
+        }
+
+        contender_contracts_total.sort_by(compare_contenders);
+        if self.dedupe_contenders {
+            increment(
+                "contenders_deduplicated",
+                dedupe_contenders(&mut contender_contracts_total) as u64,
+            );
+        }
+
+        if self.position_rules.is_enabled() {
+            self.screen_held_positions(&mut contender_contracts_total);
+        }
 
         let num_orders_usize: usize = num_orders as usize;
+        if self.combo_quotes {
+            contender_contracts_total =
+                self.confirm_with_combo_quotes(contender_contracts_total, num_orders_usize);
+        }
         if contender_contracts_total.len() > num_orders_usize {
             contender_contracts_total.truncate(num_orders_usize);
         }
@@ -211,13 +872,97 @@ impl IBKR {
         Ok(contender_contracts_total)
     }
 
+    // Function that logs a change in the underlying's trading conditions.
+    fn log_market_state(&self, state: MarketState) {
+        let ticker: &str = self.ticker.as_deref().unwrap_or_default();
+        match state {
+            MarketState::Normal => log_message(format!(
+                "{} trading conditions normalized, resuming order submission.",
+                ticker
+            )),
+            MarketState::Halted => {
+                increment("market_halts", 1);
+                log_message(format!("{} is halted, pausing order submission.", ticker))
+            }
+            MarketState::FastMarket { spread, baseline } => {
+                increment("market_fast_markets", 1);
+                log_message(format!(
+                    "{} fast market, median spread {:.1}% against a {:.1}% baseline, pausing order submission.",
+                    ticker,
+                    spread * 100.0,
+                    baseline * 100.0
+                ))
+            }
+        }
+    }
+
+    // Function that returns the underlying's trading conditions as of the last scan.
+    pub(crate) fn market_state(&self) -> MarketState {
+        self.market_monitor.state()
+    }
+
+    // Function that returns the arb threshold for a scan, scaled when the VIX is in a high regime.
+    fn scan_arb_threshold(&self, regime_value: f64) -> Result<f64, Box<dyn Error>> {
+        let arb_val: f64 = self.arb_val.ok_or("arb value is not set")?;
+        Ok(vix_regime(
+            self.vix_threshold,
+            Some(regime_value),
+            arb_val,
+            self.vix_arb_scale,
+            &[],
+            &[],
+        )
+        .map_or(arb_val, |regime| regime.arb_val))
+    }
+
+    // Function that keeps the best contenders whose orders the combos' own quotes confirm,
+    // checking at most a few times as many as will be ordered to bound the extra requests.
+    fn confirm_with_combo_quotes(
+        &self,
+        contender_contracts: Vec<Contender>,
+        num_orders: usize,
+    ) -> Vec<Contender> {
+        let chain: &ChainView = match self.chain.as_ref() {
+            Some(chain) => chain,
+            None => return contender_contracts,
+        };
+
+        let mut confirmed: Vec<Contender> = Vec::new();
+        for contender in contender_contracts.into_iter().take(num_orders * 3) {
+            if confirmed.len() >= num_orders {
+                break;
+            }
+            let request_data: RequestDataStruct = build_request_data(
+                std::slice::from_ref(&contender),
+                &[vec![(1, 0.0)]],
+                &self.account_id,
+                &self.ticker,
+                chain,
+                self.discount_value,
+                &self.order_tags,
+            );
+            let passes: bool = request_data.orders.iter().all(|order| {
+                let best_bid: Option<f64> = self
+                    .get_combo_quote(&order.con_idex)
+                    .ok()
+                    .and_then(|(bid, _)| bid);
+                combo_confirms(order.price, best_bid)
+            });
+
+            if passes {
+                confirmed.push(contender);
+            } else {
+                increment("contenders_combo_rejected", 1);
+            }
+        }
+
+        confirmed
+    }
+
     // Function that sends a GET request for ticker data, and then parses the response.
     fn get_ticker_data(&self) -> Result<HashMap<String, Opt>, Box<dyn Error>> {
         let mut contracts_map: HashMap<String, Opt> = HashMap::new();
-        let chain_url: String = format!(
-            "{}/v1/api/iserver/marketdata/snapshot",
-            self.base_url.as_ref().unwrap()
-        );
+        let chain_url: String = format!("{}/v1/api/iserver/marketdata/snapshot", self.base_url());
         let conids_arr: &Vec<String> = self.conids_strings.as_ref().unwrap();
 
         let client: Arc<Client> = Arc::new(
@@ -227,262 +972,300 @@ impl IBKR {
                 .clone(),
         );
         let chain_url: Arc<String> = Arc::new(chain_url);
-        let response_arr: Arc<Mutex<Vec<Response>>> = Arc::new(Mutex::new(Vec::new()));
 
-        let mut handles: Vec<thread::JoinHandle<()>> = Vec::new();
+        let mut handles: Vec<thread::JoinHandle<Result<Response, String>>> = Vec::new();
 
         for conid in conids_arr {
             let client: Arc<Client> = Arc::clone(&client);
             let chain_url: Arc<String> = Arc::clone(&chain_url);
-            let response_arr: Arc<Mutex<Vec<Response>>> = Arc::clone(&response_arr);
             let conid: String = conid.clone();
 
-            let handle: thread::JoinHandle<()> = thread::spawn(move || {
-                let params: [(&str, &str); 2] = [("conids", &conid), ("fields", "84,85,86")];
+            handles.push(thread::spawn(move || {
+                let params: [(&str, &str); 2] =
+                    [("conids", &conid), ("fields", "84,85,86,6509,7638")];
 
-                match client
+                let response: Response = client
                     .get(chain_url.as_ref())
-                    .header("Connection", "keep-alive")
-                    .header("User-Agent", "trading_bot_rust/1.0")
                     .query(&params)
-                    .send()
-                {
-                    Ok(response) => {
-                        if response.status().is_success() {
-                            let mut response_arr: std::sync::MutexGuard<'_, Vec<Response>> =
-                                response_arr.lock().unwrap();
-                            response_arr.push(response);
-                        } else {
-                            log_error(format!(
-                                "{}\nBody: {:?}",
-                                response.status(),
-                                response.text().unwrap_or_else(|_| "".to_string())
-                            ));
-                        }
-                    }
-                    Err(e) => log_error(format!("Failed to get ticker data: {}", e)),
+                    .send_traced()
+                    .map_err(|e| format!("Failed to get ticker data: {}", e))?;
+                if !response.status().is_success() {
+                    return Err(format!(
+                        "{}\nBody: {:?}",
+                        response.status(),
+                        response.text().unwrap_or_else(|_| "".to_string())
+                    ));
                 }
-            });
-
-            handles.push(handle);
+                Ok(response)
+            }));
         }
 
+        // Wait for every batch before failing, so no request outlives the cycle that sent it.
+        let mut responses: Vec<Response> = Vec::new();
+        let mut errors: Vec<String> = Vec::new();
         for handle in handles {
-            handle.join().unwrap();
+            match handle.join() {
+                Ok(Ok(response)) => responses.push(response),
+                Ok(Err(e)) => errors.push(e),
+                Err(_) => errors.push("snapshot request panicked".to_string()),
+            }
+        }
+        if let Some(e) = errors.into_iter().next() {
+            return Err(e.into());
         }
 
-        let mut response_vec: std::sync::MutexGuard<'_, Vec<Response>> =
-            response_arr.lock().unwrap();
-
-        for response in response_vec.drain(..) {
-            let generic_responses: Vec<MarketDataResponse> = response.json()?;
+        let provenance: Provenance = Provenance {
+            source: QuoteSource::Ibkr,
+            fetched_at: Some(self.clock.now()),
+        };
+        let mut statuses: Vec<String> = Vec::new();
+        for response in responses {
+            let generic_responses: Vec<MarketDataResponse> =
+                response.read_json("/iserver/marketdata/snapshot")?;
 
             for response in &generic_responses {
-                if let Some(field_84_value) = &response.field_84 {
-                    if let Some(field_85_value) = &response.field_85 {
-                        if let Some(field_86_value) = &response.field_86 {
-                            if field_84_value != "" && field_85_value != "" && field_86_value != ""
-                            {
-                                let conid: &String = &response.conid_ex;
-                                let bid_val: f64 = field_84_value
-                                    .replace(",", "")
-                                    .parse::<f64>()
-                                    .map_err(|_| "Failed to parse bid")?;
-                                let ask_val: f64 = field_86_value
-                                    .replace(",", "")
-                                    .parse::<f64>()
-                                    .map_err(|_| "Failed to parse ask")?;
-                                let asz_val: f64 = field_85_value
-                                    .replace(",", "")
-                                    .parse::<f64>()
-                                    .map_err(|_| "Failed to parse asz")?;
-
-                                let mkt_val: f64 =
-                                    ((bid_val + ask_val) / 2.0 * 100.0).round() / 100.0;
-
-                                contracts_map.insert(
-                                    conid.to_string(),
-                                    Opt {
-                                        asz: asz_val,
-                                        mkt: mkt_val,
-                                        bid: bid_val,
-                                    },
-                                );
-                            } else {
-                                let conid: &String = &response.conid_ex;
-                                contracts_map.insert(
-                                    conid.to_string(),
-                                    Opt {
-                                        asz: 0.0,
-                                        mkt: 0.0,
-                                        bid: 0.0,
-                                    },
-                                );
-                            }
-                        } else {
-                            let conid: &String = &response.conid_ex;
-                            contracts_map.insert(
-                                conid.to_string(),
-                                Opt {
-                                    asz: 0.0,
-                                    mkt: 0.0,
-                                    bid: 0.0,
-                                },
-                            );
-                        }
-                    } else {
-                        let conid: &String = &response.conid_ex;
-                        contracts_map.insert(
-                            conid.to_string(),
-                            Opt {
-                                asz: 0.0,
-                                mkt: 0.0,
-                                bid: 0.0,
-                            },
-                        );
+                if let Some(status) = &response.field_6509 {
+                    statuses.push(status.clone());
+                }
+                let quote: Quote = match parse_quote(response) {
+                    Some(quote) => quote,
+                    None => continue,
+                };
+                let opt: Option<Opt> = match quote.to_opt(provenance) {
+                    Some(opt) => Some(opt),
+                    None if self.missing_quotes == MissingQuotes::Zero => {
+                        Some(Opt::zeroed(quote.open_interest, provenance))
                     }
-                } else {
-                    let conid: &String = &response.conid_ex;
-                    contracts_map.insert(
-                        conid.to_string(),
-                        Opt {
-                            asz: 0.0,
-                            mkt: 0.0,
-                            bid: 0.0,
-                        },
-                    );
+                    None => None,
+                };
+                if let Some(opt) = opt {
+                    contracts_map.insert(quote.conid, opt);
                 }
             }
         }
 
-        return Ok(contracts_map);
+        // Missing subscriptions come back as empty quotes, leaving every leg of the chain missing.
+        check_availability(self.ticker.as_deref().unwrap_or_default(), &statuses)?;
+
+        Ok(contracts_map)
     }
 
-    // Function that sends a GET request for ticker data in order to init the response.
+    // Function that warms up the snapshot endpoint until the first scan would see real quotes.
     fn init_ticker_data(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let chain_url: String = format!(
-            "{}/v1/api/iserver/marketdata/snapshot",
-            self.base_url.as_ref().unwrap()
+        let chain_url: String = format!("{}/v1/api/iserver/marketdata/snapshot", self.base_url());
+
+        // Keep polling each batch until the farm reports its status and real quotes show up.
+        let mut pending: Vec<String> = self.conids_strings.as_ref().unwrap().clone();
+        let mut progress: Progress = Progress::new(
+            format!(
+                "Priming {} market data",
+                self.ticker.as_deref().unwrap_or_default()
+            ),
+            "batches",
+            pending.len(),
         );
+        for attempt in 1..=WARMUP_ATTEMPTS {
+            let mut still_cold: Vec<String> = Vec::new();
+            for conid in pending {
+                let params: [(&str, &str); 2] =
+                    [("conids", &conid), ("fields", "84,85,86,6509,7638")];
 
-        let conids_arr: &Vec<String> = self.conids_strings.as_ref().unwrap();
+                let response: Response = self
+                    .client
+                    .as_ref()
+                    .ok_or("Client is not initialized")?
+                    .get(chain_url.clone())
+                    .query(&params)
+                    .send_traced()?;
 
-        for conid in conids_arr {
-            let params: [(&str, &str); 2] = [("conids", conid), ("fields", "84,85,86")];
+                if !response.status().is_success() {
+                    return Err(
+                        format!("{}\nBody: {:?}", response.status(), response.text()?).into(),
+                    );
+                }
 
-            let response: Response = self
-                .client
-                .as_ref()
-                .ok_or("Client is not initialized")?
-                .get(chain_url.clone())
-                .header("Connection", "keep-alive")
-                .header("User-Agent", "trading_bot_rust/1.0")
-                .query(&params)
-                .send()?;
+                let generic_responses: Vec<MarketDataResponse> =
+                    response.json().unwrap_or_default();
+                if is_snapshot_warm(&generic_responses) {
+                    progress.advance(1, &format!("attempt {}/{}", attempt, WARMUP_ATTEMPTS));
+                } else {
+                    still_cold.push(conid);
+                }
+            }
 
-            if !response.status().is_success() {
-                log_error(format!(
-                    "{}\nBody: {:?}",
-                    response.status(),
-                    response.text()?
-                ));
-                exit(1);
+            pending = still_cold;
+            if pending.is_empty() {
+                break;
+            }
+            if attempt < WARMUP_ATTEMPTS {
+                thread::sleep(Duration::from_secs(1));
+            }
+        }
+
+        if !pending.is_empty() {
+            log_message(format!(
+                "Market data warm-up incomplete, {} conid batches still have no quotes.",
+                pending.len()
+            ));
+        }
+
+        // Prime the underlying's last price as well, so the first price guard read isn't empty.
+        let ticker_id: &String = self.ticker_id.as_ref().ok_or("ticker ID is not set")?;
+        for attempt in 1..=WARMUP_ATTEMPTS {
+            if self.get_last_price(ticker_id).is_ok() {
+                break;
+            }
+            if attempt < WARMUP_ATTEMPTS {
+                thread::sleep(Duration::from_secs(1));
             }
         }
 
         Ok(())
     }
 
+    // Function that sends a GET request for the underlying's last price.
+    pub(crate) fn get_underlying_price(&self) -> Result<f64, Box<dyn Error>> {
+        self.get_last_price(self.ticker_id.as_ref().ok_or("ticker ID is not set")?)
+    }
+
+    // Function that sends a GET request for the VIX's last price, if the regime filter is enabled.
+    pub(crate) fn get_vix_value(&self) -> Result<f64, Box<dyn Error>> {
+        self.get_last_price(self.vix_id.as_ref().ok_or("VIX ID is not set")?)
+    }
+
+    // Function that sends a GET request for the last price of a conid.
+    fn get_last_price(&self, conid: &str) -> Result<f64, Box<dyn Error>> {
+        // IBKR prefixes the last price with "C" when the market is closed, and "H" when halted.
+        Ok(parse_price(&self.get_last_field(conid)?).ok_or("Failed to parse last price")?)
+    }
+
+    // Function that sends a GET request for the last price field of a conid, as sent.
+    fn get_last_field(&self, conid: &str) -> Result<String, Box<dyn Error>> {
+        let chain_url: String = format!("{}/v1/api/iserver/marketdata/snapshot", self.base_url());
+        let params: [(&str, &str); 2] = [("conids", conid), ("fields", "31")];
+
+        let response: Response = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .get(&chain_url)
+            .query(&params)
+            .send_traced()?;
+
+        if !response.status().is_success() {
+            return Err(format!("Failed to get last price: {}", response.status()).into());
+        }
+
+        let generic_responses: Vec<MarketDataResponse> =
+            response.read_json("/iserver/marketdata/snapshot")?;
+        Ok(generic_responses
+            .first()
+            .and_then(|response| response.field_31.clone())
+            .ok_or("Last price is not available yet")?)
+    }
+
     // Function that returns a slice of the top calendar arbs.
     pub(crate) fn get_calendar_contenders(
         &self,
-        contracts_map: &HashMap<String, Opt>,
-        dates_slice: &Vec<String>,
-        strike_slice: &HashMap<String, HashMap<String, Vec<f64>>>,
-        conids_map: &HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>,
+        chain: &ChainView,
+        current_date: &str,
+        arb_threshold: f64,
+        diff: &QuoteDiff,
     ) -> Result<Vec<Contender>, Box<dyn Error>> {
-        let arb_threshold: f64 = 0.0 + self.arb_val.as_ref().unwrap();
         let mut contender_contracts: Vec<Contender> = Vec::new();
-        let now: chrono::DateTime<Local> = Local::now();
-        let current_date: String =
-            format!("{:02}{:02}{:02}", now.year() % 100, now.month(), now.day());
-
-        let date_for_mean: &String = &dates_slice[0];
-        let mut mean_val: f64 = 0.0;
-        if let Some(strike_data) = strike_slice.get(date_for_mean) {
-            let sum: f64 = strike_data["C"].iter().sum();
-            let count: f64 = strike_data["C"].len() as f64;
-            mean_val = sum / count;
-        }
-
-        for date_index in 0..(dates_slice.len() - 1) {
-            let date: &String = &dates_slice[date_index];
-
-            if let Some(strike_data) = strike_slice.get(date) {
-                for (contract_type, strikes) in strike_data.iter() {
-                    for current_strike in strikes {
-                        let current_contract_conid: &String = conids_map
-                            .get(date)
-                            .and_then(|ct| ct.get(contract_type))
-                            .and_then(|ct| ct.get(current_strike.into()))
-                            .ok_or("Error accessing current conid")?;
-                        let current_opt: &Opt = contracts_map
-                            .get(current_contract_conid)
-                            .ok_or("Error accessing current contract")?;
-
-                        let next_date: &String = &dates_slice[date_index + 1];
-                        let next_contract_conid: Option<&String> = conids_map
-                            .get(next_date)
-                            .and_then(|ct| ct.get(contract_type))
-                            .and_then(|ct| ct.get(current_strike.into()));
-
-                        if let Some(next_contract_conid) = next_contract_conid {
-                            let next_opt: &Opt = contracts_map
-                                .get(next_contract_conid)
-                                .ok_or("Error accessing next contract")?;
-
-                            let arb_val: f64 = current_opt.mkt - next_opt.mkt;
-
-                            if arb_val >= arb_threshold
-                                && current_opt.bid > 1.0
-                                && next_opt.bid > 1.0
-                                && current_opt.asz > 0.0
-                                && next_opt.asz > 0.0
-                                && calc_time_difference(date, next_date) == 1
-                                && calendar_spread_risk_free_profit(current_strike, arb_val) > 0.25
-                                && (current_strike - mean_val).abs() <= 500.0
-                            {
-                                let avg_ask: f64 = ((current_opt.asz + next_opt.asz) / 2.0).round();
-                                let rank_value: f64 =
-                                    calc_rank_value(avg_ask, arb_val, &current_date, date);
-
-                                contender_contracts.push(Contender {
-                                    arb_val: (arb_val * 100.0).round() / 100.0,
-                                    avg_ask,
-                                    type_spread: "Calendar".to_string(),
-                                    exp_date: date.clone(),
-                                    rank_value,
-                                    contracts: vec![
-                                        Contract {
-                                            strike: *current_strike,
-                                            mkt_price: current_opt.mkt,
-                                            date: date.clone(),
-                                            type_contract: contract_type.clone(),
-                                        },
-                                        Contract {
-                                            strike: *current_strike,
-                                            mkt_price: next_opt.mkt,
-                                            date: next_date.clone(),
-                                            type_contract: contract_type.clone(),
-                                        },
-                                    ],
-                                });
-                            }
-                        }
-                    }
+
+        let context: ScanContext = self.scan_context(chain, current_date, arb_threshold);
+
+        // Combinations with a leg missing its conid or quote are skipped and counted.
+        let mut missing_conids: u64 = 0;
+        let mut missing: u64 = 0;
+
+        for combo in chain.combos().queue("Calendar", diff) {
+            if !combo.has_conids(chain) {
+                missing_conids += 1;
+                continue;
+            }
+            let legs: Vec<(f64, &str, &String)> = combo.leg_refs();
+            let (current_strike, contract_type, date): (f64, &str, &String) = legs[0];
+            let next_date: &String = legs[1].2;
+
+            let (current_opt, next_opt): (&Opt, &Opt) = match (
+                chain.quote(date, contract_type, current_strike),
+                chain.quote(next_date, contract_type, current_strike),
+            ) {
+                (Some(current_opt), Some(next_opt)) => (current_opt, next_opt),
+                _ => {
+                    missing += 1;
+                    continue;
                 }
+            };
+
+            let arb_val: f64 = current_opt.mkt - next_opt.mkt;
+
+            if arb_val >= arb_threshold
+                && self.passes_leg_filters(chain, "Calendar", &legs, &context)
+                && calc_time_difference(date, next_date) == 1
+                && calendar_spread_risk_free_profit(
+                    &CalendarPosition {
+                        strike: current_strike,
+                        right: contract_type.to_string(),
+                        front_days: calc_time_difference(current_date, date),
+                        back_days: calc_time_difference(current_date, next_date),
+                        credit: arb_val,
+                        back_price: next_opt.mkt,
+                        exit_cost: (next_opt.ask - next_opt.bid) / 2.0,
+                    },
+                    chain.underlying_price(),
+                    &self.profit_gate,
+                ) > self.profit_gate.calendar_min_profit
+            {
+                let avg_ask: f64 = ((current_opt.asz + next_opt.asz) / 2.0).round();
+                let theta_score: Option<f64> = chain.underlying_price().and_then(|spot| {
+                    calendar_theta_score(
+                        spot,
+                        current_strike,
+                        contract_type,
+                        current_opt.mkt,
+                        next_opt.mkt,
+                        calc_time_difference(current_date, date),
+                        calc_time_difference(current_date, next_date),
+                    )
+                });
+                let rank_value: f64 =
+                    calc_calendar_rank_value(avg_ask, arb_val, theta_score, current_date, date);
+
+                contender_contracts.push(Contender {
+                    arb_val: (arb_val * 100.0).round() / 100.0,
+                    avg_ask,
+                    type_spread: "Calendar".to_string(),
+                    exp_date: date.clone(),
+                    rank_value,
+                    regime_value: 0.0,
+                    contracts: vec![
+                        Contract {
+                            strike: current_strike,
+                            mkt_price: current_opt.mkt,
+                            date: date.clone(),
+                            type_contract: contract_type.to_string(),
+                            provenance: current_opt.provenance,
+                        },
+                        Contract {
+                            strike: current_strike,
+                            mkt_price: next_opt.mkt,
+                            date: next_date.clone(),
+                            type_contract: contract_type.to_string(),
+                            provenance: next_opt.provenance,
+                        },
+                    ],
+                });
+            } else if self.near_miss_margin > 0.0
+                && arb_val >= arb_threshold - self.near_miss_margin
+            {
+                self.record_near_miss(chain, "Calendar", &legs, &context);
             }
         }
+        self.count_missing_conids("Calendar", missing_conids);
+        increment("combos_missing_quotes", missing);
 
         Ok(contender_contracts)
     }
@@ -490,110 +1273,117 @@ impl IBKR {
     // Function that returns a slice of the top butterfly arbs.
     pub(crate) fn get_butterfly_contenders(
         &self,
-        contracts_map: &HashMap<String, Opt>,
-        dates_slice: &Vec<String>,
-        strike_slice: &HashMap<String, HashMap<String, Vec<f64>>>,
-        conids_map: &HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>,
+        chain: &ChainView,
+        current_date: &str,
+        arb_threshold: f64,
+        diff: &QuoteDiff,
     ) -> Result<Vec<Contender>, Box<dyn Error>> {
-        let arb_threshold: f64 = 0.0 + self.arb_val.as_ref().unwrap();
         let mut contender_contracts: Vec<Contender> = Vec::new();
-        let now: chrono::DateTime<Local> = Local::now();
-        let current_date: String =
-            format!("{:02}{:02}{:02}", now.year() % 100, now.month(), now.day());
-
-        for date in dates_slice {
-            if let Some(strike_data) = strike_slice.get(date) {
-                for &contract_type in &["C", "P"] {
-                    if let Some(contract_strikes) = strike_data.get(contract_type) {
-                        if contract_strikes.len() > 2 {
-                            for i in 1..(contract_strikes.len() - 1) {
-                                let current_strike: &f64 = &contract_strikes[i];
-                                let current_contract_conid: &String = conids_map
-                                    .get(date)
-                                    .and_then(|ct| ct.get(contract_type))
-                                    .and_then(|ct| ct.get(current_strike.into()))
-                                    .ok_or("Error accessing current conid")?;
-                                let current_contract: &Opt = contracts_map
-                                    .get(current_contract_conid)
-                                    .ok_or("Error accessing current contract")?;
-
-                                let left_strike: &f64 = &contract_strikes[i - 1];
-                                let left_contract_conid: &String = conids_map
-                                    .get(date)
-                                    .and_then(|ct| ct.get(contract_type))
-                                    .and_then(|ct| ct.get(left_strike.into()))
-                                    .ok_or("Error accessing left conid")?;
-                                let left_contract: &Opt = contracts_map
-                                    .get(left_contract_conid)
-                                    .ok_or("Error accessing left contract")?;
-
-                                let right_strike: &f64 = &contract_strikes[i + 1];
-                                let right_contract_conid: &String = conids_map
-                                    .get(date)
-                                    .and_then(|ct| ct.get(contract_type))
-                                    .and_then(|ct| ct.get(right_strike.into()))
-                                    .ok_or("Error accessing right conid")?;
-                                let right_contract: &Opt = contracts_map
-                                    .get(right_contract_conid)
-                                    .ok_or("Error accessing right contract")?;
-
-                                let arb_val: f64 = (2.0 * current_contract.mkt)
-                                    - (left_contract.mkt + right_contract.mkt);
-
-                                if arb_val >= arb_threshold
-                                    && left_contract.bid > 1.0
-                                    && right_contract.bid > 1.0
-                                    && current_contract.bid > 1.0
-                                    && left_contract.asz > 0.0
-                                    && right_contract.asz > 0.0
-                                    && current_contract.asz > 0.0
-                                    && ((current_strike - left_strike) * 10.0).round() / 10.0
-                                        == self.strike_dif_value.unwrap()
-                                    && ((right_strike - current_strike) * 10.0).round() / 10.0
-                                        == self.strike_dif_value.unwrap()
-                                {
-                                    let avg_ask: f64 = ((left_contract.asz
-                                        + right_contract.asz
-                                        + (2.0 * current_contract.asz))
-                                        / 4.0)
-                                        .round();
-                                    let rank_value: f64 =
-                                        calc_rank_value(avg_ask, arb_val, &current_date, date);
-
-                                    contender_contracts.push(Contender {
-                                        arb_val: (arb_val * 100.0).round() / 100.0,
-                                        avg_ask,
-                                        type_spread: "Butterfly".to_string(),
-                                        exp_date: date.clone(),
-                                        rank_value,
-                                        contracts: vec![
-                                            Contract {
-                                                strike: *left_strike,
-                                                mkt_price: left_contract.mkt,
-                                                date: date.clone(),
-                                                type_contract: contract_type.to_string(),
-                                            },
-                                            Contract {
-                                                strike: *current_strike,
-                                                mkt_price: current_contract.mkt,
-                                                date: date.clone(),
-                                                type_contract: contract_type.to_string(),
-                                            },
-                                            Contract {
-                                                strike: *right_strike,
-                                                mkt_price: right_contract.mkt,
-                                                date: date.clone(),
-                                                type_contract: contract_type.to_string(),
-                                            },
-                                        ],
-                                    });
-                                }
-                            }
-                        }
-                    }
+        // Each wing width is scanned with its own arb threshold and strike spacing.
+        let contexts: Vec<ScanContext> =
+            self.butterfly_contexts(chain, current_date, arb_threshold);
+        let strike_dif: f64 = self.strike_dif_value.unwrap();
+
+        // Combinations with a leg missing its conid or quote are skipped and counted.
+        let mut missing_conids: u64 = 0;
+        let mut missing: u64 = 0;
+
+        for combo in chain.combos().queue("Butterfly", diff) {
+            if !combo.has_conids(chain) {
+                missing_conids += 1;
+                continue;
+            }
+            let legs: Vec<(f64, &str, &String)> = combo.leg_refs();
+            let (left_strike, contract_type, date): (f64, &str, &String) = legs[0];
+            let (current_strike, right_strike): (f64, f64) = (legs[1].0, legs[2].0);
+
+            // Wings that aren't a width scanned are checked at the base width when adjacent, and
+            // skipped further out.
+            let multiple: usize = match self
+                .butterfly_wings
+                .multiple(current_strike - left_strike, strike_dif)
+            {
+                Some(multiple) => multiple,
+                None if chain
+                    .adjacent_strikes(date, contract_type, current_strike)
+                    .0
+                    == Some(left_strike) =>
+                {
+                    1
                 }
+                None => continue,
+            };
+            let context: &ScanContext = &contexts[multiple - 1];
+            let arb_threshold: f64 = context.arb_threshold;
+
+            let (current_contract, left_contract, right_contract): (&Opt, &Opt, &Opt) = match (
+                chain.quote(date, contract_type, current_strike),
+                chain.quote(date, contract_type, left_strike),
+                chain.quote(date, contract_type, right_strike),
+            ) {
+                (Some(current_contract), Some(left_contract), Some(right_contract)) => {
+                    (current_contract, left_contract, right_contract)
+                }
+                _ => {
+                    missing += 1;
+                    continue;
+                }
+            };
+
+            let arb_val: f64 =
+                (2.0 * current_contract.mkt) - (left_contract.mkt + right_contract.mkt);
+
+            if arb_val >= arb_threshold
+                && self.passes_leg_filters(chain, "Butterfly", &legs, context)
+                && ((current_strike - left_strike) * 10.0).round() / 10.0 == context.strike_dif
+                && ((right_strike - current_strike) * 10.0).round() / 10.0 == context.strike_dif
+                && butterfly_risk_free_profit(arb_val, &self.profit_gate)
+                    > self.profit_gate.butterfly_min_profit
+            {
+                let avg_ask: f64 =
+                    ((left_contract.asz + right_contract.asz + (2.0 * current_contract.asz)) / 4.0)
+                        .round();
+                let rank_value: f64 = calc_rank_value(avg_ask, arb_val, current_date, date);
+
+                contender_contracts.push(Contender {
+                    arb_val: (arb_val * 100.0).round() / 100.0,
+                    avg_ask,
+                    type_spread: "Butterfly".to_string(),
+                    exp_date: date.clone(),
+                    rank_value,
+                    regime_value: 0.0,
+                    contracts: vec![
+                        Contract {
+                            strike: left_strike,
+                            mkt_price: left_contract.mkt,
+                            date: date.clone(),
+                            type_contract: contract_type.to_string(),
+                            provenance: left_contract.provenance,
+                        },
+                        Contract {
+                            strike: current_strike,
+                            mkt_price: current_contract.mkt,
+                            date: date.clone(),
+                            type_contract: contract_type.to_string(),
+                            provenance: current_contract.provenance,
+                        },
+                        Contract {
+                            strike: right_strike,
+                            mkt_price: right_contract.mkt,
+                            date: date.clone(),
+                            type_contract: contract_type.to_string(),
+                            provenance: right_contract.provenance,
+                        },
+                    ],
+                });
+            } else if self.near_miss_margin > 0.0
+                && arb_val >= arb_threshold - self.near_miss_margin
+            {
+                self.record_near_miss(chain, "Butterfly", &legs, context);
             }
         }
+        self.count_missing_conids("Butterfly", missing_conids);
+        increment("combos_missing_quotes", missing);
 
         Ok(contender_contracts)
     }
@@ -601,172 +1391,382 @@ impl IBKR {
     // Function that returns a slice of the top boxspread arbs.
     pub(crate) fn get_boxspread_contenders(
         &self,
-        contracts_map: &HashMap<String, Opt>,
-        dates_slice: &Vec<String>,
-        strike_slice: &HashMap<String, HashMap<String, Vec<f64>>>,
-        conids_map: &HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>,
+        chain: &ChainView,
+        current_date: &str,
+        arb_threshold: f64,
+        diff: &QuoteDiff,
     ) -> Result<Vec<Contender>, Box<dyn Error>> {
-        let arb_threshold: f64 = -5.0 - self.arb_val.as_ref().unwrap();
+        let box_threshold: f64 = -5.0 - arb_threshold;
         let mut contender_contracts: Vec<Contender> = Vec::new();
-        let now: chrono::DateTime<Local> = Local::now();
-        let current_date: String =
-            format!("{:02}{:02}{:02}", now.year() % 100, now.month(), now.day());
-
-        for date in dates_slice {
-            if let Some(strike_data) = strike_slice.get(date) {
-                if let (Some(cs), Some(ps)) = (strike_data.get("C"), strike_data.get("P")) {
-                    if cs.len() > 1 && ps.len() > 1 {
-                        for i in 0..(cs.len() - 1) {
-                            let current_strike_c: &f64 = &cs[i];
-                            let current_c_conid: &String = conids_map
-                                .get(date)
-                                .and_then(|c| c.get("C"))
-                                .and_then(|c| c.get(current_strike_c.into()))
-                                .ok_or("Error accessing current call conid")?;
-                            let current_c: &Opt = contracts_map
-                                .get(current_c_conid)
-                                .ok_or("Error accessing current call contract")?;
-
-                            let current_strike_p: &f64 = &ps[i];
-                            let current_p_conid: &String = conids_map
-                                .get(date)
-                                .and_then(|p| p.get("P"))
-                                .and_then(|p| p.get(current_strike_p.into()))
-                                .ok_or("Error accessing current put conid")?;
-                            let current_p: &Opt = contracts_map
-                                .get(current_p_conid)
-                                .ok_or("Error accessing current put contract")?;
-
-                            let right_strike_c: &f64 = &cs[i + 1];
-                            let right_c_conid: &String = conids_map
-                                .get(date)
-                                .and_then(|c| c.get("C"))
-                                .and_then(|c| c.get(right_strike_c.into()))
-                                .ok_or("Error accessing right call conid")?;
-                            let right_c: &Opt = contracts_map
-                                .get(right_c_conid)
-                                .ok_or("Error accessing right call contract")?;
-
-                            let right_strike_p: &f64 = &ps[i + 1];
-                            let right_p_conid: &String = conids_map
-                                .get(date)
-                                .and_then(|p| p.get("P"))
-                                .and_then(|p| p.get(right_strike_p.into()))
-                                .ok_or("Error accessing right put conid")?;
-                            let right_p: &Opt = contracts_map
-                                .get(right_p_conid)
-                                .ok_or("Error accessing right put contract")?;
-
-                            let arb_val: f64 =
-                                (current_p.mkt + right_c.mkt) - (current_c.mkt + right_p.mkt);
-
-                            if arb_val <= arb_threshold
-                                && current_c.bid > 1.0
-                                && current_p.bid > 1.0
-                                && right_c.bid > 1.0
-                                && right_p.bid > 1.0
-                                && current_c.asz > 0.0
-                                && current_p.asz > 0.0
-                                && right_c.asz > 0.0
-                                && right_p.asz > 0.0
-                                && ((right_strike_c - current_strike_c) * 10.0).round() / 10.0
-                                    == self.strike_dif_value.unwrap()
-                                && ((right_strike_p - current_strike_p) * 10.0).round() / 10.0
-                                    == self.strike_dif_value.unwrap()
-                            {
-                                let avg_ask: f64 =
-                                    ((current_c.asz + right_c.asz + current_p.asz + right_p.asz)
-                                        / 4.0)
-                                        .round();
-                                let rank_value: f64 = calc_rank_value(
-                                    avg_ask,
-                                    (-1.0 * arb_val) - 5.0,
-                                    &current_date,
-                                    date,
-                                );
-
-                                contender_contracts.push(Contender {
-                                    arb_val: (-1.0 * arb_val * 100.0).round() / 100.0,
-                                    avg_ask,
-                                    type_spread: "Boxspread".to_string(),
-                                    exp_date: date.clone(),
-                                    rank_value,
-                                    contracts: vec![
-                                        Contract {
-                                            strike: *current_strike_p,
-                                            mkt_price: current_p.mkt,
-                                            date: date.clone(),
-                                            type_contract: "P".to_string(),
-                                        },
-                                        Contract {
-                                            strike: *current_strike_c,
-                                            mkt_price: current_c.mkt,
-                                            date: date.clone(),
-                                            type_contract: "C".to_string(),
-                                        },
-                                        Contract {
-                                            strike: *right_strike_c,
-                                            mkt_price: right_c.mkt,
-                                            date: date.clone(),
-                                            type_contract: "C".to_string(),
-                                        },
-                                        Contract {
-                                            strike: *right_strike_p,
-                                            mkt_price: right_p.mkt,
-                                            date: date.clone(),
-                                            type_contract: "P".to_string(),
-                                        },
-                                    ],
-                                });
-                            }
-                        }
-                    }
+        let context: ScanContext = self.scan_context(chain, current_date, arb_threshold);
+
+        // Combinations with a leg missing its conid or quote are skipped and counted.
+        let mut missing_conids: u64 = 0;
+        let mut missing: u64 = 0;
+
+        for combo in chain.combos().queue("Boxspread", diff) {
+            if !combo.has_conids(chain) {
+                missing_conids += 1;
+                continue;
+            }
+            let legs: Vec<(f64, &str, &String)> = combo.leg_refs();
+            let date: &String = legs[0].2;
+            let (current_strike_p, current_strike_c, right_strike_c, right_strike_p): (
+                f64,
+                f64,
+                f64,
+                f64,
+            ) = (legs[0].0, legs[1].0, legs[2].0, legs[3].0);
+
+            let (current_c, current_p, right_c, right_p): (&Opt, &Opt, &Opt, &Opt) = match (
+                chain.quote(date, "C", current_strike_c),
+                chain.quote(date, "P", current_strike_p),
+                chain.quote(date, "C", right_strike_c),
+                chain.quote(date, "P", right_strike_p),
+            ) {
+                (Some(current_c), Some(current_p), Some(right_c), Some(right_p)) => {
+                    (current_c, current_p, right_c, right_p)
+                }
+                _ => {
+                    missing += 1;
+                    continue;
                 }
+            };
+
+            let arb_val: f64 = (current_p.mkt + right_c.mkt) - (current_c.mkt + right_p.mkt);
+
+            if arb_val <= box_threshold
+                && self.passes_leg_filters(chain, "Boxspread", &legs, &context)
+                && ((right_strike_c - current_strike_c) * 10.0).round() / 10.0
+                    == self.strike_dif_value.unwrap()
+                && ((right_strike_p - current_strike_p) * 10.0).round() / 10.0
+                    == self.strike_dif_value.unwrap()
+                && boxspread_risk_free_profit(
+                    -arb_val,
+                    right_strike_c - current_strike_c,
+                    calc_time_difference(current_date, date),
+                    &self.profit_gate,
+                ) > self.profit_gate.boxspread_min_profit
+            {
+                let avg_ask: f64 =
+                    ((current_c.asz + right_c.asz + current_p.asz + right_p.asz) / 4.0).round();
+                let rank_value: f64 = calc_rank_value(avg_ask, -arb_val - 5.0, current_date, date);
+
+                contender_contracts.push(Contender {
+                    arb_val: (-arb_val * 100.0).round() / 100.0,
+                    avg_ask,
+                    type_spread: "Boxspread".to_string(),
+                    exp_date: date.clone(),
+                    rank_value,
+                    regime_value: 0.0,
+                    contracts: vec![
+                        Contract {
+                            strike: current_strike_p,
+                            mkt_price: current_p.mkt,
+                            date: date.clone(),
+                            type_contract: "P".to_string(),
+                            provenance: current_p.provenance,
+                        },
+                        Contract {
+                            strike: current_strike_c,
+                            mkt_price: current_c.mkt,
+                            date: date.clone(),
+                            type_contract: "C".to_string(),
+                            provenance: current_c.provenance,
+                        },
+                        Contract {
+                            strike: right_strike_c,
+                            mkt_price: right_c.mkt,
+                            date: date.clone(),
+                            type_contract: "C".to_string(),
+                            provenance: right_c.provenance,
+                        },
+                        Contract {
+                            strike: right_strike_p,
+                            mkt_price: right_p.mkt,
+                            date: date.clone(),
+                            type_contract: "P".to_string(),
+                            provenance: right_p.provenance,
+                        },
+                    ],
+                });
+            } else if self.near_miss_margin > 0.0
+                && arb_val <= box_threshold + self.near_miss_margin
+            {
+                self.record_near_miss(chain, "Boxspread", &legs, &context);
             }
         }
+        self.count_missing_conids("Boxspread", missing_conids);
+        increment("combos_missing_quotes", missing);
 
         Ok(contender_contracts)
     }
 
-    // Function that sends a GET request for portfolio ID.
-    fn get_account_id(&self) -> Result<String, Box<dyn Error>> {
-        let search_url: String = format!(
-            "{}/v1/api/portfolio/accounts",
-            self.base_url.as_ref().unwrap()
-        );
+    // Function that counts a scan's combinations skipped for a strike missing its conid, warning
+    // about the chain inconsistency.
+    fn count_missing_conids(&self, type_spread: &str, missing_conids: u64) {
+        if missing_conids == 0 {
+            return;
+        }
+        increment("combos_missing_conid", missing_conids);
+        log_warn(format!(
+            "Skipped {} {} {} combinations with a strike missing its conid.",
+            missing_conids,
+            self.ticker.as_deref().unwrap_or_default(),
+            type_spread
+        ));
+    }
 
-        let response: Response = self
-            .client
+    // Function that returns the scan inputs of each butterfly wing width scanned, by multiple of
+    // the strike difference from 1.
+    fn butterfly_contexts(
+        &self,
+        chain: &ChainView,
+        current_date: &str,
+        arb_threshold: f64,
+    ) -> Vec<ScanContext> {
+        (1..=self.butterfly_wings.widths.max(1))
+            .map(|multiple| {
+                let context: ScanContext = self.scan_context(chain, current_date, arb_threshold);
+                ScanContext {
+                    arb_threshold: self.butterfly_wings.arb_threshold(
+                        multiple,
+                        arb_threshold,
+                        self.arb_val.unwrap_or(0.0),
+                    ),
+                    strike_dif: context.strike_dif * multiple as f64,
+                    ..context
+                }
+            })
+            .collect()
+    }
+
+    // Function that returns the arb threshold and strike spacing a contender was scanned with,
+    // a butterfly's scaled to its wing width.
+    fn scanned_thresholds(&self, contender: &Contender) -> (f64, f64) {
+        let arb_threshold: f64 = self
+            .scan_arb_threshold(contender.regime_value)
+            .unwrap_or(0.0);
+        let strike_dif: f64 = self.strike_dif_value.unwrap_or(0.0);
+        let multiple: Option<usize> =
+            match (contender.type_spread.as_str(), &contender.contracts[..]) {
+                ("Butterfly", [left, center, _]) => self
+                    .butterfly_wings
+                    .multiple(center.strike - left.strike, strike_dif),
+                _ => None,
+            };
+        match multiple {
+            Some(multiple) => (
+                self.butterfly_wings.arb_threshold(
+                    multiple,
+                    arb_threshold,
+                    self.arb_val.unwrap_or(0.0),
+                ),
+                strike_dif * multiple as f64,
+            ),
+            None => (arb_threshold, strike_dif),
+        }
+    }
+
+    // Function that returns the scan inputs the scanner's checks compare candidates against.
+    fn scan_context(
+        &self,
+        chain: &ChainView,
+        current_date: &str,
+        arb_threshold: f64,
+    ) -> ScanContext {
+        ScanContext {
+            scan_date: current_date.to_string(),
+            arb_threshold,
+            strike_dif: self.strike_dif_value.unwrap_or(0.0),
+            mean_strike: chain.mean_strike(),
+            underlying_price: chain.underlying_price(),
+            atm_vol: atm_vol(chain, current_date),
+            profit_gate: self.profit_gate,
+            scanned_at: Some(self.clock.now()),
+            leg_filters: self.leg_filters.clone(),
+        }
+    }
+
+    // Function that runs the leg filter pipeline on a candidate, counting the filter that
+    // rejected it.
+    fn passes_leg_filters(
+        &self,
+        chain: &ChainView,
+        type_spread: &str,
+        legs: &[(f64, &str, &String)],
+        context: &ScanContext,
+    ) -> bool {
+        match first_rejection(type_spread, &candidate_quotes(chain, legs), context) {
+            Some(filter) => {
+                increment(filter.kind.counter(), 1);
+                false
+            }
+            None => true,
+        }
+    }
+
+    // Function that journals a candidate the scanner turned down when it failed exactly one of its
+    // checks. Callers only pass candidates within NEAR_MISS_MARGIN of the arb threshold, since
+    // most of the chain misses it by far.
+    fn record_near_miss(
+        &self,
+        chain: &ChainView,
+        type_spread: &str,
+        legs: &[(f64, &str, &String)],
+        context: &ScanContext,
+    ) {
+        let quotes: Vec<LegQuote> = candidate_quotes(chain, legs);
+        let checks: Vec<FilterCheck> = match scanner_checks(type_spread, &quotes, context) {
+            Some(checks) => checks,
+            None => return,
+        };
+        let mut failed = checks.into_iter().filter(|check| !check.passed);
+        let check: FilterCheck = match (failed.next(), failed.next()) {
+            (Some(check), None) => check,
+            _ => return,
+        };
+
+        increment("near_misses", 1);
+        if let Err(e) = append_journal(&JournalEntry::NearMiss {
+            time: self.clock.now().to_rfc3339(),
+            ticker: self.ticker.clone().unwrap_or_default(),
+            type_spread: type_spread.to_string(),
+            exp_date: legs[0].2.clone(),
+            filter: check.name.to_string(),
+            detail: check.detail,
+            shortfall: check.shortfall,
+            quotes,
+        }) {
+            log_warn(format!("Failed to journal near miss: {}.", e));
+        }
+    }
+
+    // Function that sends a GET request for portfolio ID.
+    fn get_account_id(&self) -> Result<String, Box<dyn Error>> {
+        let search_url: String = format!("{}/v1/api/portfolio/accounts", self.base_url());
+
+        let response: Response = self
+            .client
             .as_ref()
             .ok_or("Client is not initialized")?
             .get(search_url)
-            .header("Connection", "keep-alive")
-            .header("User-Agent", "trading_bot_rust/1.0")
-            .send()?;
+            .send_traced()?;
 
+        if response.status() == StatusCode::UNAUTHORIZED
+            || response.status() == StatusCode::FORBIDDEN
+        {
+            return Err(Box::new(Unauthenticated(response.status())));
+        }
         if !response.status().is_success() {
-            log_error(format!(
-                "{}\nBody: {:?}",
-                response.status(),
-                response.text()?
-            ));
-            exit(1);
+            return Err(format!("{}\nBody: {:?}", response.status(), response.text()?).into());
         }
 
-        let account_result: Vec<AccountResponse> = response.json()?;
-        if let Some(first_account) = account_result.get(0) {
-            return Ok(first_account.id.clone());
-        } else {
-            log_error(format!("No account found in the response"));
-            exit(1);
+        let account_result: Vec<AccountResponse> = response.read_json("/portfolio/accounts")?;
+        match account_result.first() {
+            Some(first_account) => Ok(first_account.id.clone()),
+            None => Err("No account found in the response".into()),
+        }
+    }
+
+    // Function that returns the base URL of the gateway requests currently go to.
+    fn base_url(&self) -> String {
+        self.base_url
+            .as_ref()
+            .map(|url| url.read().unwrap().clone())
+            .unwrap_or_default()
+    }
+
+    // Function that routes this profile's requests, its order monitor's included, to a gateway.
+    pub(crate) fn set_base_url(&self, url: &str) {
+        if let Some(base_url) = &self.base_url {
+            *base_url.write().unwrap() = url.to_string();
         }
     }
 
-    // Function that sends a GET request for ticker ID.
-    fn get_ticker_conid(&self) -> Result<(String, String, String), Box<dyn Error>> {
+    // Function that pings the gateway so the session isn't dropped while the bot is idle.
+    pub(crate) fn tickle(&self) -> Result<(), Box<dyn Error>> {
+        self.tickle_gateway(&self.base_url())
+    }
+
+    // Function that pings a gateway by its base URL, failing when it doesn't answer or its
+    // session isn't authenticated, the health check behind gateway failover.
+    pub(crate) fn tickle_gateway(&self, base_url: &str) -> Result<(), Box<dyn Error>> {
+        if !self.tickle_status(base_url)? {
+            return Err("Gateway session is not authenticated".into());
+        }
+        Ok(())
+    }
+
+    // Function that pings a gateway by its base URL and returns whether its session is
+    // authenticated, failing when it doesn't answer.
+    pub(crate) fn tickle_status(&self, base_url: &str) -> Result<bool, Box<dyn Error>> {
+        let tickle_url: String = format!("{}/v1/api/tickle", base_url);
+
+        let response: Response = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .post(&tickle_url)
+            .send_traced()?;
+
+        if !response.status().is_success() {
+            return Err(format!("Failed to tickle the gateway: {}", response.status()).into());
+        }
+        let tickle: Value = response.json().unwrap_or_default();
+        Ok(tickle.pointer("/iserver/authStatus/authenticated") != Some(&Value::Bool(false)))
+    }
+
+    // Function that checks whether the session on the gateway requests go to is authenticated.
+    pub(crate) fn session_authenticated(&self) -> Result<bool, Box<dyn Error>> {
+        self.tickle_status(&self.base_url())
+    }
+
+    // Function that asks the gateway to reauthenticate the brokerage session, which it drops
+    // overnight.
+    pub(crate) fn reauthenticate(&self) -> Result<(), Box<dyn Error>> {
+        let reauth_url: String = format!("{}/v1/api/iserver/reauthenticate", self.base_url());
+
+        let response: Response = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .post(&reauth_url)
+            .send_traced()?;
+
+        if !response.status().is_success() {
+            return Err(format!("Failed to reauthenticate: {}", response.status()).into());
+        }
+        Ok(())
+    }
+
+    // Function that returns how many seconds the local clock is behind the gateway's, from the
+    // Date header on a tickle, negative when ahead.
+    pub(crate) fn gateway_clock_drift(&self) -> Result<f64, Box<dyn Error>> {
+        let tickle_url: String = format!("{}/v1/api/tickle", self.base_url());
+
+        let sent: DateTime<Utc> = Utc::now();
+        let response: Response = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .post(&tickle_url)
+            .send_traced()?;
+        let received: DateTime<Utc> = Utc::now();
+
+        let date_header: &str = response
+            .headers()
+            .get(DATE)
+            .and_then(|date| date.to_str().ok())
+            .ok_or("Gateway response has no Date header")?;
+        Ok(clock_drift(date_header, sent, received).ok_or("Gateway Date header doesn't parse")?)
+    }
+
+    // Function that sends a GET request for ticker ID and the option months listed for it.
+    fn get_ticker_conid(&self) -> Result<(String, Vec<String>), Box<dyn Error>> {
         let search_url: String = format!(
             "{}/v1/api/iserver/secdef/search?symbol={}",
-            self.base_url.as_ref().unwrap(),
+            self.base_url(),
             self.ticker.as_ref().unwrap()
         );
 
@@ -775,76 +1775,44 @@ impl IBKR {
             .as_ref()
             .ok_or("Client is not initialized")?
             .get(&search_url)
-            .header("Connection", "keep-alive")
-            .header("User-Agent", "trading_bot_rust/1.0")
-            .send()?;
+            .send_traced()?;
 
         if !response.status().is_success() {
-            log_error(format!(
-                "{}\nBody: {:?}",
-                response.status(),
-                response.text()?
-            ));
-            exit(1);
+            return Err(format!("{}\nBody: {:?}", response.status(), response.text()?).into());
         }
 
-        let search_results: Vec<SecDefResponse> = response.json()?;
-        let mut month1: String = String::new();
-        let mut month2: String = String::new();
+        let search_results: Vec<SecDefResponse> = response.read_json("/iserver/secdef/search")?;
+        let mut months: Vec<String> = Vec::new();
 
-        let result: &SecDefResponse = &search_results[0];
+        let result: &SecDefResponse = search_results
+            .first()
+            .ok_or("No ticker conid found in the response")?;
         if let Some(conid) = &result.conid {
             if !conid.is_empty() {
                 if let Some(sections) = &result.sections {
                     for section in sections {
                         if section.sec_type == "OPT" {
-                            if let Some(months) = &section.months {
-                                let months_vec: Vec<&str> = months.split(';').collect();
-                                if months_vec.len() >= 2 {
-                                    month1 = months_vec[0].to_string();
-                                    month2 = months_vec[1].to_string();
-                                }
+                            if let Some(section_months) = &section.months {
+                                months = parse_option_months(section_months);
                             }
                             break;
                         }
                     }
                 }
 
-                return Ok((conid.to_string(), month1, month2));
+                return Ok((conid.to_string(), months));
             }
         }
 
-        log_error(format!("No ticker conid found in the response"));
-        exit(1);
+        Err("No ticker conid found in the response".into())
     }
 
-    // Function that gets a list of conids for all relevant contracts.
-    fn get_conids_map(
-        &self,
-        mut num_days: i64,
-        num_days_offset: i64,
-        current_month: String,
-        next_month: String,
-    ) -> Result<
-        (
-            Vec<String>,
-            Vec<String>,
-            HashMap<String, HashMap<String, Vec<f64>>>,
-            HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>,
-        ),
-        Box<dyn Error>,
-    > {
-        let mut conids_strings: Vec<String> = Vec::new();
-        let mut dates_slice: Vec<String> = Vec::new();
-        let mut strike_slice: HashMap<String, HashMap<String, Vec<f64>>> = HashMap::new();
-        let mut conids_map: HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>> =
-            HashMap::new();
-
+    // Function that sends a GET request for the conid of a symbol.
+    fn get_symbol_conid(&self, symbol: &str) -> Result<String, Box<dyn Error>> {
         let search_url: String = format!(
-            "{}/v1/api/iserver/secdef/info?conid={}&sectype=OPT&month={}&exchange=SMART&strike=0",
-            self.base_url.as_ref().unwrap(),
-            self.ticker_id.as_ref().unwrap(),
-            current_month
+            "{}/v1/api/iserver/secdef/search?symbol={}",
+            self.base_url(),
+            symbol
         );
 
         let response: Response = self
@@ -852,206 +1820,224 @@ impl IBKR {
             .as_ref()
             .ok_or("Client is not initialized")?
             .get(&search_url)
-            .header("Connection", "keep-alive")
-            .header("User-Agent", "trading_bot_rust/1.0")
-            .send()?;
+            .send_traced()?;
 
         if !response.status().is_success() {
-            log_error(format!(
-                "{}\nBody: {:?}",
-                response.status(),
-                response.text()?
-            ));
-            exit(1);
-        }
-
-        let search_results: Vec<SecDefInfoResponse> = response.json()?;
-        let current_date: String = Local::now().format("%y%m%d").to_string();
-        let mut conids_str: String = String::new();
-        let mut counter: i32 = 0;
-
-        for sec_def_info in search_results.iter() {
-            let type_opt: &String = &sec_def_info.right;
-            let exp_date: String = sec_def_info
-                .maturity_date
-                .get(2..)
-                .unwrap_or(&sec_def_info.maturity_date)
-                .to_string();
-            let strike: OrderedFloat<f64> = OrderedFloat(sec_def_info.strike);
-            let conid: f64 = sec_def_info.conid;
-
-            if calc_time_difference(&current_date, &exp_date) > (-1 + num_days_offset) {
-                if !strike_slice.contains_key(&exp_date) {
-                    num_days -= 1;
-                    if num_days < 0 {
-                        break;
-                    }
+            return Err(format!("Failed to search for {}: {}", symbol, response.status()).into());
+        }
 
-                    dates_slice.push(exp_date.to_string());
-
-                    strike_slice.insert(exp_date.to_string(), HashMap::new());
-                    strike_slice
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("C".to_string(), Vec::new());
-                    strike_slice
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("P".to_string(), Vec::new());
-
-                    conids_map.insert(exp_date.to_string(), HashMap::new());
-                    conids_map
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("C".to_string(), HashMap::new());
-                    conids_map
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("P".to_string(), HashMap::new());
-                }
+        let search_results: Vec<SecDefResponse> = response.read_json("/iserver/secdef/search")?;
+        search_results
+            .first()
+            .and_then(|result| result.conid.clone())
+            .filter(|conid| !conid.is_empty())
+            .ok_or_else(|| format!("No conid found for {}", symbol).into())
+    }
 
-                strike_slice
-                    .get_mut(&exp_date)
-                    .unwrap()
-                    .get_mut(type_opt)
-                    .unwrap()
-                    .push(*strike);
-
-                conids_map
-                    .get_mut(&exp_date)
-                    .unwrap()
-                    .get_mut(type_opt)
-                    .unwrap()
-                    .insert(strike, conid.to_string());
-
-                conids_str.push_str(&conid.to_string());
-                conids_str.push_str(",");
-                counter += 1;
-
-                if counter == 300 {
-                    conids_strings.push(conids_str);
-                    conids_str = String::new();
-                    counter = 0;
-                }
-            }
+    // Function that sends a GET request for a contract's symbol and instrument type.
+    fn get_contract_info(&self, conid: &str) -> Result<ContractInfoResponse, Box<dyn Error>> {
+        let info_url: String =
+            format!("{}/v1/api/iserver/contract/{}/info", self.base_url(), conid);
+
+        let response: Response = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .get(&info_url)
+            .send_traced()?;
+
+        if !response.status().is_success() {
+            return Err(format!("Failed to get contract {}: {}", conid, response.status()).into());
         }
 
-        if num_days > 0 {
-            let search_url_2: String = format!(
-                "{}/v1/api/iserver/secdef/info?conid={}&sectype=OPT&month={}&exchange=SMART&strike=0",
-                self.base_url.as_ref().unwrap(),
-                self.ticker_id.as_ref().unwrap(),
-                next_month
-            );
+        response.read_json("/iserver/contract/info")
+    }
 
-            let response_2: Response = self
-                .client
-                .as_ref()
-                .ok_or("Client is not initialized")?
-                .get(&search_url_2)
-                .header("Connection", "keep-alive")
-                .header("User-Agent", "trading_bot_rust/1.0")
-                .send()?;
-
-            if !response_2.status().is_success() {
-                log_error(format!(
-                    "{}\nBody: {:?}",
-                    response_2.status(),
-                    response_2.text()?
+    // Function that looks up the hedge instrument, keeping its symbol for the hedge orders, and
+    // disables hedging when it is an index, which can't be traded, or can't be looked up.
+    fn check_hedge_instrument(&mut self) {
+        let conid: String = match (&self.hedge_rule.conid, self.hedge_rule.threshold > 0.0) {
+            (Some(conid), true) => conid.clone(),
+            _ => return,
+        };
+        match self.get_contract_info(&conid) {
+            Ok(info) if is_tradable_hedge(&info.instrument_type) => {
+                self.hedge_symbol = Some(info.symbol);
+            }
+            Ok(info) => {
+                log_warn(format!(
+                    "HEDGE_CONID {} is the {} index, which can't be traded, disabling hedging.",
+                    conid, info.symbol
                 ));
-                exit(1);
+                self.hedge_rule.threshold = 0.0;
             }
+            Err(e) => {
+                log_warn(format!(
+                    "Failed to look up HEDGE_CONID {}: {}, disabling hedging.",
+                    conid, e
+                ));
+                self.hedge_rule.threshold = 0.0;
+            }
+        }
+    }
+
+    // Function that sends a GET request for the strikes listed in a month's option chain.
+    fn get_month_strikes(&self, month: &str) -> Result<Vec<f64>, Box<dyn Error>> {
+        let search_url: String = format!(
+            "{}/v1/api/iserver/secdef/strikes?conid={}&sectype=OPT&month={}&exchange=SMART",
+            self.base_url(),
+            self.ticker_id.as_ref().unwrap(),
+            month
+        );
+
+        let response: Response = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .get(&search_url)
+            .send_traced()?;
 
-            let search_results_2: Vec<SecDefInfoResponse> = response_2.json()?;
+        if !response.status().is_success() {
+            return Err(
+                format!("Failed to get strikes for {}: {}", month, response.status()).into(),
+            );
+        }
 
-            for sec_def_info in search_results_2.iter() {
-                let type_opt: &String = &sec_def_info.right;
-                let exp_date: String = sec_def_info
-                    .maturity_date
-                    .get(2..)
-                    .unwrap_or(&sec_def_info.maturity_date)
-                    .to_string();
-                let strike: OrderedFloat<f64> = OrderedFloat(sec_def_info.strike);
-                let conid: f64 = sec_def_info.conid;
+        let strikes: SecDefStrikesResponse = response.read_json("/iserver/secdef/strikes")?;
+        let mut strikes: Vec<f64> = strikes.call.into_iter().chain(strikes.put).collect();
+        strikes.sort_by(f64::total_cmp);
+        strikes.dedup();
+        Ok(strikes)
+    }
 
-                if !strike_slice.contains_key(&exp_date) {
-                    num_days -= 1;
-                    if num_days < 0 {
-                        break;
+    // Function that sends a GET request for the contracts of a month's option chain, one secdef
+    // info request per strike, a few strikes at a time.
+    fn get_month_contracts(&self, month: &str) -> Result<Vec<SecDefInfoResponse>, Box<dyn Error>> {
+        let strikes: Vec<f64> = self.get_month_strikes(month)?;
+        let client: Arc<Client> = Arc::new(
+            self.client
+                .as_ref()
+                .ok_or("Client is not initialized")?
+                .clone(),
+        );
+        let info_url: Arc<String> =
+            Arc::new(format!("{}/v1/api/iserver/secdef/info", self.base_url()));
+        let ticker_id: Arc<String> =
+            Arc::new(self.ticker_id.clone().ok_or("ticker ID is not set")?);
+
+        let mut contracts: Vec<SecDefInfoResponse> = Vec::new();
+        let mut progress: Progress = Progress::new(
+            format!(
+                "Loading {} {} chain",
+                self.ticker.as_deref().unwrap_or_default(),
+                month
+            ),
+            "strikes",
+            strikes.len(),
+        );
+        for chunk in strikes.chunks(STRIKE_LOOKUPS) {
+            let mut handles: Vec<thread::JoinHandle<Result<Vec<SecDefInfoResponse>, String>>> =
+                Vec::new();
+
+            for strike in chunk {
+                let client: Arc<Client> = Arc::clone(&client);
+                let info_url: Arc<String> = Arc::clone(&info_url);
+                let ticker_id: Arc<String> = Arc::clone(&ticker_id);
+                let month: String = month.to_string();
+                let strike: String = strike.to_string();
+
+                handles.push(thread::spawn(move || {
+                    let params: [(&str, &str); 5] = [
+                        ("conid", &ticker_id),
+                        ("sectype", "OPT"),
+                        ("month", &month),
+                        ("exchange", "SMART"),
+                        ("strike", &strike),
+                    ];
+                    let response: Response = client
+                        .get(info_url.as_ref())
+                        .query(&params)
+                        .send_traced()
+                        .map_err(|e| e.to_string())?;
+                    if !response.status().is_success() {
+                        return Err(format!(
+                            "Failed to get contracts at strike {}: {}",
+                            strike,
+                            response.status()
+                        ));
                     }
+                    response
+                        .read_json("/iserver/secdef/info")
+                        .map_err(|e| e.to_string())
+                }));
+            }
 
-                    dates_slice.push(exp_date.to_string());
-
-                    strike_slice.insert(exp_date.to_string(), HashMap::new());
-                    strike_slice
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("C".to_string(), Vec::new());
-                    strike_slice
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("P".to_string(), Vec::new());
-
-                    conids_map.insert(exp_date.to_string(), HashMap::new());
-                    conids_map
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("C".to_string(), HashMap::new());
-                    conids_map
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("P".to_string(), HashMap::new());
-                }
-
-                strike_slice
-                    .get_mut(&exp_date)
-                    .unwrap()
-                    .get_mut(type_opt)
-                    .unwrap()
-                    .push(*strike);
-
-                conids_map
-                    .get_mut(&exp_date)
-                    .unwrap()
-                    .get_mut(type_opt)
-                    .unwrap()
-                    .insert(strike, conid.to_string());
-
-                conids_str.push_str(&conid.to_string());
-                conids_str.push_str(",");
-                counter += 1;
-
-                if counter == 300 {
-                    conids_strings.push(conids_str);
-                    conids_str = String::new();
-                    counter = 0;
-                }
+            for handle in handles {
+                contracts.extend(handle.join().map_err(|_| "strike lookup panicked")??);
             }
+            progress.advance(chunk.len(), &format!("{} contracts", contracts.len()));
         }
 
-        if !conids_str.is_empty() {
-            conids_strings.push(conids_str);
+        Ok(contracts)
+    }
+
+    // Function that gets a list of conids for all relevant contracts, loading the listed months'
+    // chains in order until they hold num_days expiries or the months run out.
+    #[allow(clippy::type_complexity)]
+    fn get_conids_map(
+        &self,
+        num_days: i64,
+        num_days_offset: i64,
+        months: &[String],
+    ) -> Result<(Vec<String>, Vec<String>, StrikeSlice, ConidsMap), Box<dyn Error>> {
+        let current_date: String = self.clock.scan_date();
+
+        if months.is_empty() {
+            return Err("No option months listed for the ticker".into());
         }
 
-        for (_, strikes) in strike_slice.iter_mut() {
-            strikes
-                .get_mut("C")
-                .unwrap()
-                .sort_by(|a, b| a.partial_cmp(b).unwrap());
-            strikes
-                .get_mut("P")
-                .unwrap()
-                .sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mut contracts: Vec<SecDefInfoResponse> = Vec::new();
+        for month in months {
+            contracts.extend(self.get_month_contracts(month)?);
+            let (_, dates, _, conids): (Vec<String>, Vec<String>, StrikeSlice, ConidsMap) =
+                chain_from_contracts(&contracts, &current_date, num_days, num_days_offset);
+            log_message(format!(
+                "{} chain through {}: {} of {} expiries discovered, {} conids registered.",
+                self.ticker.as_deref().unwrap_or_default(),
+                month,
+                dates.len(),
+                num_days,
+                conids
+                    .values()
+                    .flat_map(|rights| rights.values())
+                    .map(|strikes| strikes.len())
+                    .sum::<usize>()
+            ));
+            if dates.len() as i64 >= num_days {
+                break;
+            }
         }
 
-        Ok((conids_strings, dates_slice, strike_slice, conids_map))
+        Ok(chain_from_contracts(
+            &contracts,
+            &current_date,
+            num_days,
+            num_days_offset,
+        ))
     }
 
-    // Function that sends a GET request for portfolio value.
-    pub(crate) fn get_portfolio_value(&self) -> Result<f64, Box<dyn Error>> {
+    // Function that returns the account summary, refetching it once the cached copy is older
+    // than ACCOUNT_SUMMARY_TTL so sizing and risk checks in the same cycle share one request.
+    pub(crate) fn get_account_summary(&mut self) -> Result<AccountSummary, Box<dyn Error>> {
+        if let Some((fetched_at, summary)) = &self.account_summary {
+            if fetched_at.elapsed() < ACCOUNT_SUMMARY_TTL {
+                return Ok(summary.clone());
+            }
+        }
+
         let search_url: String = format!(
             "{}/v1/api/portfolio/{}/summary",
-            self.base_url.as_ref().unwrap(),
+            self.base_url(),
             self.account_id.as_ref().unwrap()
         );
 
@@ -1060,50 +2046,903 @@ impl IBKR {
             .as_ref()
             .ok_or("Client is not initialized")?
             .get(&search_url)
-            .header("Connection", "keep-alive")
-            .header("User-Agent", "trading_bot_rust/1.0")
-            .send()?;
+            .send_traced()?;
 
         if !response.status().is_success() {
-            log_error(format!(
-                "{}\nBody: {:?}",
+            return Err(format!(
+                "Failed to get account summary: {}\nBody: {:?}",
                 response.status(),
                 response.text()?
+            )
+            .into());
+        }
+
+        let search_results: PortfolioResponse = response.read_json("/portfolio/summary")?;
+        let summary: AccountSummary = AccountSummary::from(search_results);
+        self.account_summary = Some((Instant::now(), summary.clone()));
+        Ok(summary)
+    }
+
+    // Function that writes the chain with its latest quotes to a CSV file.
+    pub(crate) fn dump_chain(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let chain: &ChainView = self.chain.as_ref().ok_or("chain is not set")?;
+        std::fs::write(path, chain.to_csv())?;
+        Ok(())
+    }
+
+    // Function that sets the configured referrer on an order built outside a scan.
+    fn referred(&self, mut order: OrderBody) -> OrderBody {
+        order.referrer = self.order_tags.referrer.clone();
+        order
+    }
+
+    // Function that returns what the profile already has on for the allocator, counting no
+    // held contracts when positions can't be fetched.
+    pub(crate) fn exposure(&self) -> Exposure {
+        Exposure {
+            held_contracts: self
+                .get_positions()
+                .map(|positions| {
+                    positions
+                        .iter()
+                        .map(|position| position.position.abs())
+                        .sum()
+                })
+                .unwrap_or(0.0),
+            working_orders: self.live_order_count(),
+        }
+    }
+
+    // Function that sends a GET request for the account's open positions in this ticker's options.
+    pub(crate) fn get_positions(&self) -> Result<Vec<PositionResponse>, Box<dyn Error>> {
+        let ticker_id: &String = self.ticker_id.as_ref().ok_or("ticker ID is not set")?;
+        Ok(self
+            .get_account_positions()?
+            .into_iter()
+            .filter(|position| {
+                position.und_conid.map(|conid| conid.to_string()).as_ref() == Some(ticker_id)
+            })
+            .collect())
+    }
+
+    // Function that sends a GET request for every position in the account.
+    fn get_account_positions(&self) -> Result<Vec<PositionResponse>, Box<dyn Error>> {
+        let search_url: String = format!(
+            "{}/v1/api/portfolio/{}/positions/0",
+            self.base_url(),
+            self.account_id.as_ref().unwrap()
+        );
+
+        let response: Response = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .get(&search_url)
+            .send_traced()?;
+
+        if !response.status().is_success() {
+            return Err(format!("Failed to get positions: {}", response.status()).into());
+        }
+
+        response.read_json("/portfolio/positions")
+    }
+
+    // Function that sends a GET request for the account's live and recently ended orders.
+    fn get_orders(&self) -> Result<OrdersResponse, Box<dyn Error>> {
+        let orders_url: String = format!("{}/v1/api/iserver/account/orders", self.base_url());
+        let response: Response = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .get(&orders_url)
+            .send_traced()?;
+        if !response.status().is_success() {
+            return Err(format!("Failed to get live orders: {}", response.status()).into());
+        }
+        response.read_json("/iserver/account/orders")
+    }
+
+    // Function that asks the gateway to drop its cached positions, so the next read is current.
+    fn invalidate_positions(&self) -> Result<(), Box<dyn Error>> {
+        let invalidate_url: String = format!(
+            "{}/v1/api/portfolio/{}/positions/invalidate",
+            self.base_url(),
+            self.account_id.as_ref().unwrap()
+        );
+        let response: Response = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .post(&invalidate_url)
+            .send_traced()?;
+        if !response.status().is_success() {
+            return Err(format!("Failed to invalidate positions: {}", response.status()).into());
+        }
+        Ok(())
+    }
+
+    // Function that compares the account's positions with the book saved by the last session,
+    // journaling and announcing the option legs assigned, exercised or expired in between, then
+    // saves the current positions as the new book.
+    pub(crate) fn reconcile_positions(&self) -> Result<Vec<Settlement>, Box<dyn Error>> {
+        if let Err(e) = self.invalidate_positions() {
+            log_warn(format!("{}, positions may be stale.", e));
+        }
+        let positions: Vec<PositionResponse> = self.get_account_positions()?;
+        let today: String = self
+            .clock
+            .now()
+            .with_timezone(&New_York)
+            .format("%Y%m%d")
+            .to_string();
+        let book: Option<Book> = load_book(BOOK_PATH);
+        let settlements: Vec<Settlement> = match &book {
+            Some(book) => reconcile_book(&book.positions, &positions, &today),
+            None => Vec::new(),
+        };
+
+        for settlement in &settlements {
+            log_message(format!(
+                "NOTICE: {:?} {} {} {}{} expiring {} since {}, {} shares of conid {}.",
+                settlement.kind,
+                settlement.contracts,
+                settlement.conid,
+                settlement.strike,
+                settlement.right,
+                settlement.expiry,
+                book.as_ref()
+                    .map(|book| book.time.as_str())
+                    .unwrap_or_default(),
+                settlement.shares,
+                settlement.und_conid
             ));
-            exit(1);
+            increment(
+                match settlement.kind {
+                    SettlementKind::Assigned => "assignments",
+                    SettlementKind::Exercised => "exercises",
+                    SettlementKind::Expired => "expirations",
+                },
+                1,
+            );
+            if let Err(e) = append_journal(&JournalEntry::Settlement {
+                time: self.clock.now().to_rfc3339(),
+                type_spread: "Settlement".to_string(),
+                kind: settlement.kind,
+                conid: settlement.conid.to_string(),
+                und_conid: settlement.und_conid.to_string(),
+                right: settlement.right.clone(),
+                strike: settlement.strike,
+                expiry: settlement.expiry.clone(),
+                contracts: settlement.contracts,
+                shares: settlement.shares,
+            }) {
+                log_warn(format!("Failed to write journal: {}.", e));
+            }
+        }
+
+        save_book(
+            BOOK_PATH,
+            &Book {
+                time: self.clock.now().to_rfc3339(),
+                positions,
+            },
+        )?;
+        Ok(settlements)
+    }
+
+    // Function that saves the account's positions as the book the next session reconciles with.
+    pub(crate) fn save_positions(&self) -> Result<(), Box<dyn Error>> {
+        save_book(
+            BOOK_PATH,
+            &Book {
+                time: self.clock.now().to_rfc3339(),
+                positions: self.get_account_positions()?,
+            },
+        )?;
+        Ok(())
+    }
+
+    // Function that checks the net delta of the options held on the underlying and, when it
+    // exceeds the hedge threshold, brings it back toward flat with a market order in the hedge
+    // instrument. Only logs the hedge unless submit is set, and waits for the last hedge sent to
+    // fill or be cancelled before judging the delta again.
+    pub(crate) fn hedge_delta(&mut self, submit: bool) -> Result<(), Box<dyn Error>> {
+        if self.hedge_rule.threshold <= 0.0 {
+            return Ok(());
+        }
+        let ticker_id: &String = self.ticker_id.as_ref().ok_or("ticker ID is not set")?;
+        let hedge_conid: &String = self
+            .hedge_rule
+            .conid
+            .as_ref()
+            .ok_or("hedge conid is not set")?;
+
+        if let Some(order_id) = &self.working_hedge {
+            let orders: OrdersResponse = self.get_orders()?;
+            if is_working(&orders.orders, order_id) {
+                log_debug(format!("Hedge order {} is still working.", order_id));
+                return Ok(());
+            }
+            self.working_hedge = None;
         }
 
-        let search_results: PortfolioResponse = response.json()?;
-        Ok(search_results.equity_with_loan_value.amount)
+        // The portfolio endpoint serves a cached copy, refresh it so a filled hedge counts.
+        self.invalidate_positions()?;
+        let positions: Vec<PositionResponse> = self.get_account_positions()?;
+        let spot: f64 = self.get_underlying_price()?;
+        let delta: f64 = net_delta(
+            &positions,
+            spot,
+            self.clock.today(),
+            ticker_id,
+            &self.hedge_rule,
+            hedge_conid,
+        );
+        let quantity: i32 = match hedge_quantity(delta, &self.hedge_rule) {
+            Some(quantity) => quantity,
+            None => return Ok(()),
+        };
+
+        log_message(format!(
+            "{} net delta {:.1} is beyond {:.1}, hedging with {} {} of conid {}.",
+            self.ticker.as_deref().unwrap_or_default(),
+            delta,
+            self.hedge_rule.threshold,
+            if quantity > 0 { "BUY" } else { "SELL" },
+            quantity.abs(),
+            hedge_conid
+        ));
+        if !submit || !matches!(self.market_state(), MarketState::Normal) {
+            return Ok(());
+        }
+
+        let request_data: RequestDataStruct = RequestDataStruct {
+            orders: vec![self.referred(build_market_order(
+                &self.account_id,
+                &self.hedge_symbol,
+                hedge_conid,
+                quantity,
+            ))],
+            contender_index: Vec::new(),
+            netted: Vec::new(),
+        };
+        let order_ids: Vec<String> = self.submit_orders(&request_data)?;
+        increment("hedge_orders", order_ids.len() as u64);
+        self.working_hedge = order_ids.into_iter().next();
+        Ok(())
+    }
+
+    // Function that handles option positions on their expiration day: it warns about short legs
+    // in the money on physically settled underlyings every cycle, and once the cutoff passes
+    // closes or rolls each expiring leg with one market order. Only logs the orders unless submit
+    // is set.
+    pub(crate) fn handle_expiring_positions(&mut self, submit: bool) -> Result<(), Box<dyn Error>> {
+        let now: DateTime<Tz> = self.clock.now().with_timezone(&New_York);
+        let positions: Vec<PositionResponse> = self
+            .get_positions()?
+            .into_iter()
+            .filter(|position| expires_on(position, now.date_naive()))
+            .collect();
+        if positions.is_empty() {
+            return Ok(());
+        }
+
+        if self.expiry_rule.physical_settlement {
+            let spot: f64 = self.get_underlying_price()?;
+            for position in positions.iter().filter(|p| is_itm_short(p, spot)) {
+                log_warn(format!(
+                    "WARNING: short {} {} {:?} {:?} x{} expires today in the money with the \
+                     underlying at {:.2}, it will be assigned into shares unless closed.",
+                    self.ticker.as_deref().unwrap_or_default(),
+                    position.conid,
+                    position.strike,
+                    position.put_or_call,
+                    position.position.abs(),
+                    spot
+                ));
+            }
+        }
+
+        if self.expiry_rule.action == ExpiryAction::Off || now.time() < self.expiry_rule.cutoff {
+            return Ok(());
+        }
+
+        let today: String = now.format("%y%m%d").to_string();
+        for position in positions {
+            // Legs already rolled by the calendar roll engine have an order working.
+            if self.expiry_handled.contains(&position.conid)
+                || self.rolled.contains(&position.conid)
+            {
+                continue;
+            }
+            let conid: String = position.conid.to_string();
+            let next_conid: Option<String> = match self.expiry_rule.action {
+                ExpiryAction::Roll => self.next_expiry_conid(&position, &today),
+                _ => None,
+            };
+            let (con_idex, quantity, action): (String, i32, &str) = match next_conid {
+                Some(next_conid) => {
+                    let (con_idex, quantity) = roll_conidex(&conid, &next_conid, position.position);
+                    (con_idex, quantity, "Rolling")
+                }
+                None => {
+                    if self.expiry_rule.action == ExpiryAction::Roll {
+                        log_message(format!(
+                            "No later expiry in the chain to roll conid {} into, closing it.",
+                            conid
+                        ));
+                    }
+                    (conid.clone(), -position.position.round() as i32, "Closing")
+                }
+            };
+
+            log_message(format!(
+                "{} expiring {} position of {} in conid {} with {}.",
+                action,
+                self.ticker.as_deref().unwrap_or_default(),
+                position.position,
+                conid,
+                con_idex
+            ));
+            if !submit || !matches!(self.market_state(), MarketState::Normal) {
+                continue;
+            }
+
+            let request_data: RequestDataStruct = RequestDataStruct {
+                orders: vec![self.referred(build_market_order(
+                    &self.account_id,
+                    &self.ticker,
+                    &con_idex,
+                    quantity,
+                ))],
+                contender_index: Vec::new(),
+                netted: Vec::new(),
+            };
+            let order_ids: Vec<String> = self.submit_orders(&request_data)?;
+            if !order_ids.is_empty() {
+                self.expiry_handled.insert(position.conid);
+                increment("expiry_orders", order_ids.len() as u64);
+            }
+        }
+        Ok(())
+    }
+
+    // Function that returns the conid of the same strike and right as a position in the first
+    // expiry of the chain after the given day.
+    fn next_expiry_conid(&self, position: &PositionResponse, today: &str) -> Option<String> {
+        let chain: &ChainView = self.chain.as_ref()?;
+        let strike: f64 = value_to_f64(position.strike.as_ref()?)?;
+        let right: &str = position.put_or_call.as_deref()?.get(..1)?;
+        chain
+            .dates()
+            .iter()
+            .filter(|date| date.as_str() > today)
+            .find_map(|date| chain.conid(date, right, strike))
+            .cloned()
+    }
+
+    // Function that rolls the short near leg of each held calendar that hit the roll rule to the
+    // next expiry before the far leg, as one combo order priced at the legs' mids, and journals
+    // the roll with the calendar's rolls so far. Only logs the rolls unless submit is set.
+    pub(crate) fn roll_calendars(&mut self, submit: bool) -> Result<(), Box<dyn Error>> {
+        if self.roll_rule.dte.is_none() && self.roll_rule.profit_pct <= 0.0 {
+            return Ok(());
+        }
+        let positions: Vec<PositionResponse> = self.get_positions()?;
+        let calendars: Vec<HeldCalendar> = held_calendars(&positions, self.clock.today());
+
+        let mut orders: Vec<(OrderBody, &HeldCalendar, Vec<JournalLeg>)> = Vec::new();
+        for calendar in &calendars {
+            if self.rolled.contains(&calendar.short.conid)
+                || !roll_due(
+                    calendar.front_days,
+                    calendar.short.avg_price,
+                    calendar.short.mkt_price,
+                    &self.roll_rule,
+                )
+            {
+                continue;
+            }
+            let chain: &ChainView = self.chain.as_ref().ok_or("chain is not set")?;
+            let near: &str = calendar.short.expiry.as_deref().unwrap_or_default();
+            let far: &str = calendar.long.expiry.as_deref().unwrap_or_default();
+            let (near, far): (&str, &str) =
+                (near.get(2..).unwrap_or(near), far.get(2..).unwrap_or(far));
+            let target: &String = match roll_target(chain.dates(), near, far) {
+                Some(target) => target,
+                None => continue,
+            };
+            let (next_conid, near_quote, next_quote): (&String, &Opt, &Opt) = match (
+                chain.conid(target, calendar.right, calendar.strike),
+                chain.quote(near, calendar.right, calendar.strike),
+                chain.quote(target, calendar.right, calendar.strike),
+            ) {
+                (Some(next_conid), Some(near_quote), Some(next_quote)) => {
+                    (next_conid, near_quote, next_quote)
+                }
+                _ => continue,
+            };
+            let price: f64 = match roll_price(near_quote, next_quote) {
+                Some(price) => round_to_tick(price, &self.tick_rule),
+                None => continue,
+            };
+
+            let order: OrderBody = self.referred(build_roll_order(
+                &self.account_id,
+                &self.ticker,
+                &calendar.short.conid.to_string(),
+                next_conid,
+                calendar.quantity.round() as i32,
+                price,
+            ));
+            let leg = |conid: String, date: &str, ratio: i32, quote: &Opt| JournalLeg {
+                conid,
+                strike: calendar.strike,
+                right: calendar.right.to_string(),
+                date: date.to_string(),
+                action: if ratio > 0 { "BUY" } else { "SELL" }.to_string(),
+                ratio,
+                intended_price: (quote.bid + quote.ask) / 2.0,
+                source: quote.provenance.source,
+                fetched_at: quote.provenance.fetched_at_rfc3339(),
+            };
+            let legs: Vec<JournalLeg> = vec![
+                leg(calendar.short.conid.to_string(), near, 1, near_quote),
+                leg(next_conid.clone(), target, -1, next_quote),
+            ];
+            log_message(format!(
+                "Rolling {} x{} {}{} calendar from {} to {} at {:.2}.",
+                self.ticker.as_deref().unwrap_or_default(),
+                order.quantity,
+                calendar.strike,
+                calendar.right,
+                near,
+                target,
+                order.price
+            ));
+            orders.push((order, calendar, legs));
+        }
+        if !submit || !matches!(self.market_state(), MarketState::Normal) {
+            return Ok(());
+        }
+
+        for (order, calendar, legs) in orders {
+            let request_data: RequestDataStruct = RequestDataStruct {
+                orders: vec![order],
+                contender_index: Vec::new(),
+                netted: Vec::new(),
+            };
+            let order_ids: Vec<String> = self.submit_orders(&request_data)?;
+            let order: &OrderBody = &request_data.orders[0];
+            let order_id: &String = match order_ids.first() {
+                Some(order_id) => order_id,
+                None => continue,
+            };
+            self.rolled.insert(calendar.short.conid);
+            increment("calendar_rolls", 1);
+
+            let far_conid: String = calendar.long.conid.to_string();
+            let (rolls, credit): (u32, f64) = load_roll_history(JOURNAL_PATH, &far_conid);
+            let c_oid: String = order.c_oid.clone().unwrap_or_default();
+            let entry: JournalEntry = JournalEntry::Roll {
+                time: self.clock.now().to_rfc3339(),
+                order_id: order_id.clone(),
+                c_oid: c_oid.clone(),
+                ticker: order.ticker.clone(),
+                type_spread: "Roll".to_string(),
+                far_conid,
+                strike: calendar.strike,
+                right: calendar.right.to_string(),
+                from_date: legs[0].date.clone(),
+                to_date: legs[1].date.clone(),
+                limit_price: order.price,
+                quantity: order.quantity,
+                legs: legs.clone(),
+                rolls: rolls + 1,
+                cumulative_credit: credit - order.price,
+            };
+            self.exposure_book.apply(&entry);
+            if let Err(e) = append_journal(&entry) {
+                log_warn(format!("Failed to write journal: {}.", e));
+            }
+            self.journal_orders.push(TrackedOrder {
+                order_id: order_id.clone(),
+                c_oid,
+                type_spread: "Roll".to_string(),
+                legs,
+                modeled_edge: None,
+                seen_executions: HashSet::new(),
+            });
+        }
+        Ok(())
+    }
+
+    // Function that starts tracking working orders the bot placed but never heard back about,
+    // after a submission failed midway, so they get cancelled like any other live order.
+    pub(crate) fn adopt_orphaned_orders(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.orphans_possible {
+            return Ok(());
+        }
+
+        let orders_url: String = format!("{}/v1/api/iserver/account/orders", self.base_url());
+        let response: Response = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .get(&orders_url)
+            .send_traced()?;
+        if !response.status().is_success() {
+            return Err(format!("Failed to get live orders: {}", response.status()).into());
+        }
+        let orders: OrdersResponse = response.read_json("/iserver/account/orders")?;
+
+        let live_orders: &Arc<Mutex<Vec<LiveOrder>>> =
+            self.live_orders.as_ref().ok_or("live orders are not set")?;
+        let mut live_orders: std::sync::MutexGuard<'_, Vec<LiveOrder>> =
+            live_orders.lock().unwrap();
+        let mut adopted: u64 = 0;
+        for order in orders.orders {
+            let order_id: String = order.order_id;
+            let ours: bool = order
+                .order_ref
+                .as_ref()
+                .is_some_and(|order_ref| order_ref.starts_with("tbr-"));
+            let working: bool = final_status(&order.status).is_none();
+            if !ours || !working || live_orders.iter().any(|live| live.order_id == order_id) {
+                continue;
+            }
+            live_orders.push(LiveOrder {
+                order_id,
+                submitted_at: Instant::now(),
+                order: None,
+                limit_price: 0.0,
+                priced_at: Instant::now(),
+                ladder_group: None,
+            });
+            adopted += 1;
+        }
+
+        if adopted > 0 {
+            increment("orders_orphaned", adopted);
+            log_message(format!(
+                "Tracking {} orders placed by a failed submission.",
+                adopted
+            ));
+        }
+        self.orphans_possible = false;
+        Ok(())
     }
 
     // Function that cancels all submitted and presubmitted orders.
     pub(crate) fn cancel_pending_orders(&mut self) {
-        log_message(format!("Cancelling all pending limit orders."));
+        log_message("Cancelling all pending limit orders.".to_string());
 
         if let Some(live_orders) = &self.live_orders {
-            let order_ids: Vec<String> = live_orders.iter().cloned().collect();
+            let orders: Vec<LiveOrder> = live_orders.lock().unwrap().drain(..).collect();
+
+            // Orders whose cancel didn't go through are still working, keep them to retry.
+            let mut still_live: Vec<LiveOrder> = Vec::new();
+            for order in orders {
+                match self.cancel_order(&order.order_id) {
+                    Ok(message) => log_warn(format!("{}.", message)),
+                    Err(e) => {
+                        log_warn(format!("{}.", e));
+                        still_live.push(order);
+                    }
+                }
+            }
+            if !still_live.is_empty() {
+                log_warn(format!(
+                    "{} orders failed to cancel, retrying next cycle.",
+                    still_live.len()
+                ));
+            }
+            live_orders.lock().unwrap().extend(still_live);
+        }
 
-            for order_id in order_ids {
-                match self.cancel_order(&order_id) {
-                    Ok(message) => log_message(format!("{}.", message)),
-                    Err(e) => log_message(format!("{}.", e)),
+        log_message("All pending limit orders cancelled.".to_string());
+    }
+
+    // Function that stops scanning a strategy for the rest of the session, returning whether it
+    // was enabled before.
+    pub(crate) fn disable_strategy(&mut self, code: &str) -> bool {
+        if self.disabled_strategies.iter().any(|s| s == code) {
+            return false;
+        }
+        self.disabled_strategies.push(code.to_string());
+        true
+    }
+
+    // Function that returns the number of conids requested every scan.
+    fn conids_count(&self) -> usize {
+        match &self.conids_strings {
+            Some(conids_strings) => conids_strings
+                .iter()
+                .map(|conids| conids.split(',').filter(|c| !c.is_empty()).count())
+                .sum(),
+            None => 0,
+        }
+    }
+
+    // Function that returns the number of orders still working.
+    pub(crate) fn live_order_count(&self) -> usize {
+        match &self.live_orders {
+            Some(live_orders) => live_orders.lock().unwrap().len(),
+            None => 0,
+        }
+    }
+
+    // Function that starts a background monitor cancelling orders that outlived their lifetime,
+    // or whose combo quote moved more than max_drift away from their price.
+    pub(crate) fn spawn_order_monitor(
+        &self,
+        order_lifetime: u64,
+        max_drift: f64,
+    ) -> thread::JoinHandle<()> {
+        let monitor: IBKR = IBKR {
+            base_url: self.base_url.clone(),
+            live_orders: self.live_orders.clone(),
+            client: self.client.clone(),
+            account_id: self.account_id.clone(),
+            ticker_id: self.ticker_id.clone(),
+            escalate_after: self.escalate_after,
+            escalate_step: self.escalate_step,
+            tick_rule: self.tick_rule,
+            ladder_cancel_on_fill: self.ladder_cancel_on_fill,
+            ..IBKR::new()
+        };
+
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(1));
+            monitor.cancel_expired_orders(order_lifetime, max_drift);
+            monitor.escalate_resting_orders();
+            if let Err(e) = monitor.cancel_filled_ladders() {
+                log_warn(format!("{}.", e));
+            }
+        })
+    }
+
+    // Function that sends a GET request for a combo's displayed bid and ask, by conidex.
+    fn get_combo_quote(&self, conidex: &str) -> Result<(Option<f64>, Option<f64>), Box<dyn Error>> {
+        let chain_url: String = format!("{}/v1/api/iserver/marketdata/snapshot", self.base_url());
+        let params: [(&str, &str); 2] = [("conids", conidex), ("fields", "84,86")];
+
+        // The first snapshot for a new combo usually comes back empty, so ask twice.
+        for attempt in 0..2 {
+            let response: Response = self
+                .client
+                .as_ref()
+                .ok_or("Client is not initialized")?
+                .get(&chain_url)
+                .query(&params)
+                .send_traced()?;
+
+            if !response.status().is_success() {
+                return Err(format!("Failed to get combo quote: {}", response.status()).into());
+            }
+
+            let generic_responses: Vec<MarketDataResponse> =
+                response.read_json("/iserver/marketdata/snapshot")?;
+            if let Some(quote) = generic_responses.first().and_then(parse_quote) {
+                if quote.bid.is_some() || quote.ask.is_some() {
+                    return Ok((quote.bid, quote.ask));
+                }
+            }
+            if attempt == 0 {
+                thread::sleep(Duration::from_millis(250));
+            }
+        }
+
+        Ok((None, None))
+    }
+
+    // Function that reprices joined orders that have rested longer than the escalation time,
+    // one step toward their limit.
+    fn escalate_resting_orders(&self) {
+        let live_orders: &Arc<Mutex<Vec<LiveOrder>>> = match &self.live_orders {
+            Some(live_orders) if self.escalate_after > 0 => live_orders,
+            _ => return,
+        };
+
+        let due: Vec<(String, OrderBody)> = {
+            let mut live_orders: std::sync::MutexGuard<'_, Vec<LiveOrder>> =
+                live_orders.lock().unwrap();
+            let mut due: Vec<(String, OrderBody)> = Vec::new();
+            for live_order in live_orders.iter_mut() {
+                if live_order.priced_at.elapsed() < Duration::from_secs(self.escalate_after) {
+                    continue;
                 }
+                if let Some(order) = live_order.order.as_mut() {
+                    if order.price < live_order.limit_price {
+                        // A step smaller than the tick would round back down, go to the limit.
+                        let next_price: f64 = round_to_tick(
+                            escalated_price(
+                                order.price,
+                                live_order.limit_price,
+                                self.escalate_step,
+                            ),
+                            &self.tick_rule,
+                        );
+                        order.price = if next_price > order.price {
+                            next_price
+                        } else {
+                            live_order.limit_price
+                        };
+                        live_order.priced_at = Instant::now();
+                        due.push((live_order.order_id.clone(), order.clone()));
+                    }
+                }
+            }
+            due
+        };
+
+        for (order_id, order) in due {
+            match self.modify_order(&order_id, &order) {
+                Ok(_) => log_message(format!(
+                    "Order ID {} escalated to {:.2} after resting.",
+                    order_id, order.price
+                )),
+                Err(e) => log_warn(format!("{}.", e)),
+            }
+        }
+    }
+
+    // Function that sends a POST request to reprice a working order. Confirmation prompts are
+    // declined, so a reprice never goes through on a warning.
+    fn modify_order(&self, order_id: &str, order: &OrderBody) -> Result<(), Box<dyn Error>> {
+        let modify_url: String = format!(
+            "{}/v1/api/iserver/account/{}/order/{}",
+            self.base_url(),
+            self.account_id.as_ref().unwrap(),
+            order_id
+        );
+
+        let response: Response = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .post(&modify_url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(order)?)
+            .send_traced()?;
+
+        if !response.status().is_success() {
+            return Err(format!(
+                "Failed to modify order ID {}: {}",
+                order_id,
+                response.status()
+            )
+            .into());
+        }
+
+        match parse_reply(&response.read_json::<Value>("/iserver/account/order")?) {
+            ReplyOutcome::Placed(_) => Ok(()),
+            ReplyOutcome::Confirm(reply) => Err(format!(
+                "Order ID {} reprice needs confirmation, leaving it: {}",
+                order_id,
+                reply.messages.join(" ")
+            )
+            .into()),
+            ReplyOutcome::Rejected(reason) => {
+                Err(format!("Order ID {} reprice rejected: {}", order_id, reason).into())
             }
         }
+    }
 
-        if let Some(live_orders) = &mut self.live_orders {
-            live_orders.clear();
+    // Function that cancels the live orders that expired or drifted.
+    fn cancel_expired_orders(&self, order_lifetime: u64, max_drift: f64) {
+        let live_orders: &Arc<Mutex<Vec<LiveOrder>>> = match &self.live_orders {
+            Some(live_orders) => live_orders,
+            None => return,
+        };
+
+        // Quote each working combo once, outside the lock, when the drift check is on.
+        let mut combo_bids: HashMap<String, f64> = HashMap::new();
+        if max_drift > 0.0 {
+            let conidexes: HashSet<String> = live_orders
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|order| order.order.as_ref().map(|order| order.con_idex.clone()))
+                .collect();
+            for conidex in conidexes {
+                if let Ok((Some(best_bid), _)) = self.get_combo_quote(&conidex) {
+                    combo_bids.insert(conidex, best_bid);
+                }
+            }
         }
 
-        log_message(format!("All pending limit orders cancelled."));
+        let expired: Vec<LiveOrder> = {
+            let mut live_orders: std::sync::MutexGuard<'_, Vec<LiveOrder>> =
+                live_orders.lock().unwrap();
+            let (expired, live): (Vec<LiveOrder>, Vec<LiveOrder>) = expired_orders(
+                live_orders.drain(..).collect(),
+                Instant::now(),
+                order_lifetime,
+                max_drift,
+                &combo_bids,
+            );
+            *live_orders = live;
+
+            expired
+        };
+
+        // Orders whose cancel didn't go through are still working, keep them to retry.
+        for order in expired {
+            match self.cancel_order(&order.order_id) {
+                Ok(message) => log_message(format!("{} by the order monitor.", message)),
+                Err(e) => {
+                    log_warn(format!("{}.", e));
+                    live_orders.lock().unwrap().push(order);
+                }
+            }
+        }
+    }
+
+    // Function that cancels the other rungs of laddered combos once one rung fills, and stops
+    // tracking the filled ones.
+    fn cancel_filled_ladders(&self) -> Result<(), Box<dyn Error>> {
+        let live_orders: &Arc<Mutex<Vec<LiveOrder>>> = match &self.live_orders {
+            Some(live_orders) if self.ladder_cancel_on_fill => live_orders,
+            _ => return Ok(()),
+        };
+        if !live_orders
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|order| order.ladder_group.is_some())
+        {
+            return Ok(());
+        }
+
+        let orders_url: String = format!("{}/v1/api/iserver/account/orders", self.base_url());
+        let response: Response = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .get(&orders_url)
+            .send_traced()?;
+        if !response.status().is_success() {
+            return Err(format!("Failed to get live orders: {}", response.status()).into());
+        }
+        let orders: OrdersResponse = response.read_json("/iserver/account/orders")?;
+        let filled: HashSet<String> = orders
+            .orders
+            .into_iter()
+            .filter(|order| order.status == "Filled")
+            .map(|order| order.order_id)
+            .collect();
+
+        let siblings: Vec<LiveOrder> = {
+            let mut live_orders: std::sync::MutexGuard<'_, Vec<LiveOrder>> =
+                live_orders.lock().unwrap();
+            let sibling_ids: Vec<String> = ladder_siblings(&live_orders, &filled);
+            let (siblings, live): (Vec<LiveOrder>, Vec<LiveOrder>) = live_orders
+                .drain(..)
+                .filter(|order| !filled.contains(&order.order_id))
+                .partition(|order| sibling_ids.contains(&order.order_id));
+            *live_orders = live;
+            siblings
+        };
+
+        // Rungs whose cancel didn't go through are still working, keep them to retry.
+        for order in siblings {
+            match self.cancel_order(&order.order_id) {
+                Ok(message) => log_message(format!("{} after another rung filled.", message)),
+                Err(e) => {
+                    log_warn(format!("{}.", e));
+                    live_orders.lock().unwrap().push(order);
+                }
+            }
+        }
+        Ok(())
     }
 
     // Function that cancels a single order.
     fn cancel_order(&self, order_id: &str) -> Result<String, Box<dyn Error>> {
         let cancel_order_url: String = format!(
             "{}/v1/api/iserver/account/{}/order/{}",
-            self.base_url.as_ref().unwrap(),
+            self.base_url(),
             self.account_id.as_ref().unwrap(),
             order_id
         );
@@ -1113,46 +2952,255 @@ impl IBKR {
             .as_ref()
             .ok_or("Client is not initialized")?
             .delete(&cancel_order_url)
-            .header("Connection", "keep-alive")
-            .header("User-Agent", "trading_bot_rust/1.0")
-            .send()?;
+            .send_traced()?;
 
         if response.status().is_success() {
+            let _: CancelResponse = response.read_json("/iserver/account/order")?;
             Ok(format!("Order ID {} cancelled successfully", order_id))
+        } else if response.status() == StatusCode::NOT_FOUND {
+            Ok(format!("Order ID {} is no longer working", order_id))
         } else {
-            Err(Box::new(io::Error::new(
-                ErrorKind::Other,
-                format!(
-                    "Failed to cancel order ID {}. HTTP status: {}",
-                    order_id,
-                    response.status()
-                ),
-            )))
+            Err(Box::new(io::Error::other(format!(
+                "Failed to cancel order ID {}. HTTP status: {}",
+                order_id,
+                response.status()
+            ))))
         }
     }
 
-    // Function that makes orders all contender contracts.
+    // Function that orders each contender contract with the fills allocated to it, returning the
+    // orders placed and the fills each contender got.
     pub(crate) fn order_contender_contracts(
         &mut self,
-        contender_contracts: &Vec<Contender>,
-        num_fills: i32,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let order_url: String = format!(
-            "{}/v1/api/iserver/account/{}/orders",
-            self.base_url.as_ref().unwrap(),
-            self.account_id.as_ref().unwrap()
-        );
-
-        let request_data: RequestDataStruct = build_request_data(
+        contender_contracts: &[Contender],
+        quantities: &[i32],
+    ) -> Result<(usize, Vec<f64>), Box<dyn std::error::Error>> {
+        let (quantities, capped): (Vec<i32>, usize) =
+            self.cap_expiry_exposure(contender_contracts, quantities);
+        increment("contenders_expiry_capped", capped as u64);
+        let (quantities, capped): (Vec<i32>, usize) =
+            self.cap_held_positions(contender_contracts, &quantities);
+        increment("contenders_position_capped", capped as u64);
+
+        // Spread each contender's fills over a ladder of prices, each rung giving up a little more.
+        let ladders: Vec<Vec<(i32, f64)>> = quantities
+            .iter()
+            .map(|num_fills| {
+                if *num_fills > 0 {
+                    ladder_rungs(*num_fills, self.ladder_rungs, self.ladder_step)
+                } else {
+                    Vec::new()
+                }
+            })
+            .collect();
+        let mut request_data: RequestDataStruct = build_request_data(
             contender_contracts,
-            num_fills,
+            &ladders,
             &self.account_id,
-            &self.conids_map,
+            &self.ticker,
+            self.chain.as_ref().ok_or("chain is not set")?,
             self.discount_value,
+            &self.order_tags,
+        );
+        if request_data.orders.is_empty() {
+            return Ok((0, vec![0.0; contender_contracts.len()]));
+        }
+
+        // Price off the combo's own quote: never pay above its ask, and join its bid rather than
+        // crossing, keeping the limit to escalate to.
+        let mut limit_prices: Vec<f64> = Vec::new();
+        for order in request_data.orders.iter_mut() {
+            if self.combo_quotes || self.join_best {
+                let (best_bid, best_ask): (Option<f64>, Option<f64>) = self
+                    .get_combo_quote(&order.con_idex)
+                    .unwrap_or((None, None));
+                if self.combo_quotes {
+                    order.price = cap_to_ask(order.price, best_ask);
+                }
+                order.price = round_to_tick(order.price, &self.tick_rule);
+                limit_prices.push(order.price);
+                if self.join_best {
+                    order.price = round_to_tick(join_price(order.price, best_bid), &self.tick_rule);
+                }
+            } else {
+                order.price = round_to_tick(order.price, &self.tick_rule);
+                limit_prices.push(order.price);
+            }
+        }
+
+        // Last guard against a pricing bug: drop every order of a contender with a limit, or a
+        // limit to escalate to, outside its strategy's bounds.
+        let rejected: HashSet<usize> = request_data
+            .orders
+            .iter()
+            .zip(&limit_prices)
+            .zip(&request_data.contender_index)
+            .filter(|((order, limit_price), index)| {
+                let bounds: PriceBounds = self
+                    .price_limits
+                    .bounds(&contender_contracts[**index].type_spread);
+                !bounds.allows(order.price) || !bounds.allows(**limit_price)
+            })
+            .map(|(_, index)| *index)
+            .collect();
+        for index in &rejected {
+            let contender: &Contender = &contender_contracts[*index];
+            increment("orders_price_rejected", 1);
+            log_warn(format!(
+                "{} {} {} order priced outside its bounds, not submitting it.",
+                self.ticker.as_deref().unwrap_or_default(),
+                contender.type_spread,
+                contender.exp_date
+            ));
+        }
+        if !rejected.is_empty() {
+            let keep: Vec<bool> = request_data
+                .contender_index
+                .iter()
+                .map(|index| !rejected.contains(index))
+                .collect();
+            let mut flags = keep.iter();
+            request_data.orders.retain(|_| *flags.next().unwrap());
+            let mut flags = keep.iter();
+            limit_prices.retain(|_| *flags.next().unwrap());
+            request_data
+                .contender_index
+                .retain(|index| !rejected.contains(index));
+            if request_data.orders.is_empty() {
+                return Ok((0, vec![0.0; contender_contracts.len()]));
+            }
+        }
+
+        // Consolidate orders trading a leg the other way from one another.
+        let (request_data, limit_prices): (RequestDataStruct, Vec<f64>) =
+            if self.net_orders && request_data.orders.len() > 1 {
+                self.net_batch(request_data, limit_prices, &ladders)
+            } else {
+                (request_data, limit_prices)
+            };
+
+        // A submission that failed midway, e.g. timed out, may still have placed orders.
+        let (order_ids, request_data, limit_prices): (Vec<String>, RequestDataStruct, Vec<f64>) =
+            if self.submit_delay_ms > 0 && request_data.orders.len() > 1 {
+                self.submit_staggered(request_data, limit_prices)
+            } else {
+                match self.submit_orders(&request_data) {
+                    Ok(order_ids) => (order_ids, request_data, limit_prices),
+                    Err(e) => {
+                        self.orphans_possible = true;
+                        return Err(e);
+                    }
+                }
+            };
+        if order_ids.is_empty() {
+            return Ok((0, vec![0.0; contender_contracts.len()]));
+        }
+        let placed: usize = order_ids.len();
+        let fills: Vec<f64> = placed_fills(&request_data, contender_contracts);
+        increment("orders_submitted", placed as u64);
+        self.journal_submitted(&order_ids, &request_data, contender_contracts);
+        if let Some(live_orders) = &self.live_orders {
+            let mut live_orders: std::sync::MutexGuard<'_, Vec<LiveOrder>> =
+                live_orders.lock().unwrap();
+
+            for (i, order_id) in order_ids.into_iter().enumerate() {
+                // The same combo at every rung of a contender's ladder shares a conidex.
+                let ladder_group: Option<String> = match (
+                    request_data.orders.get(i),
+                    request_data.contender_index.get(i),
+                ) {
+                    (Some(order), Some(index))
+                        if self.ladder_cancel_on_fill
+                            && ladders.get(*index).is_some_and(|ladder| ladder.len() > 1) =>
+                    {
+                        Some(format!("{}-{}", index, order.con_idex))
+                    }
+                    _ => None,
+                };
+                live_orders.push(LiveOrder {
+                    order_id,
+                    submitted_at: Instant::now(),
+                    order: request_data.orders.get(i).cloned(),
+                    limit_price: limit_prices.get(i).copied().unwrap_or(0.0),
+                    priced_at: Instant::now(),
+                    ladder_group,
+                });
+            }
+        }
+        Ok((placed, fills))
+    }
+
+    // Function that posts a batch's orders one at a time, a random delay of up to submit_delay_ms
+    // apart, returning the ids of the orders placed with just those orders and limit prices. A
+    // failed submission ends the batch.
+    fn submit_staggered(
+        &mut self,
+        request_data: RequestDataStruct,
+        limit_prices: Vec<f64>,
+    ) -> (Vec<String>, RequestDataStruct, Vec<f64>) {
+        let RequestDataStruct {
+            orders,
+            contender_index,
+            mut netted,
+        } = request_data;
+        let total: usize = orders.len();
+        let mut order_ids: Vec<String> = Vec::new();
+        let mut placed: RequestDataStruct = RequestDataStruct {
+            orders: Vec::new(),
+            contender_index: Vec::new(),
+            netted: Vec::new(),
+        };
+        let mut placed_limits: Vec<f64> = Vec::new();
+
+        for (i, order) in orders.into_iter().enumerate() {
+            if i > 0 {
+                thread::sleep(random_delay(self.submit_delay_ms, random_fraction()));
+            }
+            let single: RequestDataStruct = RequestDataStruct {
+                orders: vec![order],
+                contender_index: contender_index.get(i).copied().into_iter().collect(),
+                netted: netted.get_mut(i).map(std::mem::take).into_iter().collect(),
+            };
+            match self.submit_orders(&single) {
+                Ok(ids) => {
+                    if let Some(order_id) = ids.into_iter().next() {
+                        order_ids.push(order_id);
+                        placed.orders.extend(single.orders);
+                        placed.contender_index.extend(single.contender_index);
+                        placed.netted.extend(single.netted);
+                        placed_limits.extend(limit_prices.get(i).copied());
+                    }
+                }
+                Err(e) => {
+                    self.orphans_possible = true;
+                    log_warn(format!(
+                        "Failed to submit order {} of {}: {}, skipping the rest of the batch.",
+                        i + 1,
+                        total,
+                        e
+                    ));
+                    break;
+                }
+            }
+        }
+
+        (order_ids, placed, placed_limits)
+    }
+
+    // Function that posts orders and answers the gateway's confirmation prompts per the reply
+    // policy, returning the ids of the orders placed, none when declined or rejected.
+    fn submit_orders(
+        &self,
+        request_data: &RequestDataStruct,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let order_url: String = format!(
+            "{}/v1/api/iserver/account/{}/orders",
+            self.base_url(),
+            self.account_id.as_ref().unwrap()
         );
 
         // Serialize the request data to JSON, handle possible serialization error.
-        let json_data: Vec<u8> = serde_json::to_vec(&request_data)?;
+        let json_data: Vec<u8> = serde_json::to_vec(request_data)?;
 
         // Make the post request with the serialized JSON data.
         let response: Response = self
@@ -1161,66 +3209,384 @@ impl IBKR {
             .ok_or("Client is not initialized")?
             .post(&order_url)
             .header(CONTENT_TYPE, "application/json")
-            .header("Connection", "keep-alive")
-            .header("User-Agent", "trading_bot_rust/1.0")
             .body(json_data)
-            .send()?;
+            .send_traced()?;
 
         if !response.status().is_success() {
-            log_error(format!(
-                "{}\nBody: {:?}",
+            return Err(format!(
+                "Failed to submit orders: {}\nBody: {:?}",
                 response.status(),
                 response.text()?
-            ));
-            exit(1);
+            )
+            .into());
         }
 
-        let mut generic_responses: Vec<Value> = response.json()?;
+        let mut generic_responses: Value = response.read_json("/iserver/account/orders")?;
 
         loop {
-            if let Some(confirm_id) = generic_responses[0]["id"].as_str() {
-                let confirm_url = format!(
-                    "{}/v1/api/iserver/reply/{}",
-                    self.base_url.as_ref().unwrap(),
-                    confirm_id
-                );
-                let confirm_data: Confirmation = Confirmation { confirmed: true };
+            match parse_reply(&generic_responses) {
+                ReplyOutcome::Confirm(reply) => {
+                    for reason in &reply.reasons {
+                        increment(reason.counter(), 1);
+                    }
+                    let confirmed: bool = self.reply_policy.confirms(&reply);
+                    if !confirmed {
+                        log_message(format!(
+                            "Declining order prompt {:?} ({:?}): {}",
+                            reply.message_ids,
+                            reply.reasons,
+                            reply.messages.join(" ")
+                        ));
+                    }
 
-                let json_data_confirm: Vec<u8> = serde_json::to_vec(&confirm_data)?;
-                let confirm_response: Response = self
-                    .client
-                    .as_ref()
-                    .ok_or("Client is not initialized")?
-                    .post(&confirm_url)
-                    .header(CONTENT_TYPE, "application/json")
-                    .header("Connection", "keep-alive")
-                    .header("User-Agent", "trading_bot_rust/1.0")
-                    .body(json_data_confirm)
-                    .send()?;
-
-                if confirm_response.status().is_success() {
-                    generic_responses = confirm_response.json()?;
-                } else {
-                    log_error(format!(
-                        "{}\nBody: {:?}",
-                        confirm_response.status(),
-                        confirm_response.text()?
-                    ));
-                    exit(1);
+                    let confirm_url =
+                        format!("{}/v1/api/iserver/reply/{}", self.base_url(), reply.id);
+                    let confirm_data: Confirmation = Confirmation { confirmed };
+
+                    let json_data_confirm: Vec<u8> = serde_json::to_vec(&confirm_data)?;
+                    let confirm_response: Response = self
+                        .client
+                        .as_ref()
+                        .ok_or("Client is not initialized")?
+                        .post(&confirm_url)
+                        .header(CONTENT_TYPE, "application/json")
+                        .body(json_data_confirm)
+                        .send_traced()?;
+
+                    if !confirm_response.status().is_success() {
+                        return Err(format!(
+                            "Failed to answer order prompt: {}\nBody: {:?}",
+                            confirm_response.status(),
+                            confirm_response.text()?
+                        )
+                        .into());
+                    }
+                    if !confirmed {
+                        return Ok(Vec::new());
+                    }
+                    generic_responses = confirm_response.read_json("/iserver/reply")?;
+                }
+                ReplyOutcome::Placed(order_ids) => return Ok(order_ids),
+                ReplyOutcome::Rejected(reason) => {
+                    increment("orders_rejected", 1);
+                    log_warn(format!("Order rejected: {}.", reason));
+                    return Ok(Vec::new());
                 }
-            } else if generic_responses[0].get("order_id").is_some() {
-                if let Some(live_orders) = &mut self.live_orders {
-                    for order in &generic_responses {
-                        if let Some(order_id) = order["order_id"].as_str() {
-                            live_orders.push(order_id.to_string());
+            }
+        }
+    }
+
+    // Function that nets a batch's orders, leaving out contenders laddered over several rungs,
+    // with their limits to escalate to combined the same way. Netted prices are rounded down to
+    // the tick, so they never pay more than the members would have.
+    fn net_batch(
+        &self,
+        request_data: RequestDataStruct,
+        limit_prices: Vec<f64>,
+        ladders: &[Vec<(i32, f64)>],
+    ) -> (RequestDataStruct, Vec<f64>) {
+        let eligible: Vec<bool> = request_data
+            .contender_index
+            .iter()
+            .map(|index| ladders.get(*index).is_some_and(|ladder| ladder.len() == 1))
+            .collect();
+        let netted_orders: Vec<NettedOrder> = net_orders(&request_data.orders, &eligible);
+        if !netted_orders.iter().any(NettedOrder::is_netted) {
+            return (request_data, limit_prices);
+        }
+
+        let mut netted: RequestDataStruct = RequestDataStruct {
+            orders: Vec::new(),
+            contender_index: Vec::new(),
+            netted: Vec::new(),
+        };
+        let mut netted_limits: Vec<f64> = Vec::new();
+        for NettedOrder { mut order, members } in netted_orders {
+            let first: usize = members[0];
+            if members.len() > 1 {
+                increment("orders_netted", members.len() as u64);
+                log_message(format!(
+                    "Netted {} orders into {} x{}.",
+                    members.len(),
+                    order.con_idex,
+                    order.quantity
+                ));
+                order.price = round_to_tick(order.price, &self.tick_rule);
+                netted_limits.push(round_to_tick(
+                    netted_price(
+                        &request_data.orders,
+                        &members,
+                        &limit_prices,
+                        order.quantity,
+                    ),
+                    &self.tick_rule,
+                ));
+                netted.netted.push(
+                    members
+                        .iter()
+                        .map(|index| {
+                            (
+                                request_data.contender_index[*index],
+                                request_data.orders[*index].clone(),
+                            )
+                        })
+                        .collect(),
+                );
+            } else {
+                netted_limits.push(limit_prices[first]);
+                netted.netted.push(Vec::new());
+            }
+            netted
+                .contender_index
+                .push(request_data.contender_index[first]);
+            netted.orders.push(order);
+        }
+        (netted, netted_limits)
+    }
+
+    // Function that writes a submitted entry per placed order and starts tracking it.
+    fn journal_submitted(
+        &mut self,
+        order_ids: &[String],
+        request_data: &RequestDataStruct,
+        contender_contracts: &[Contender],
+    ) {
+        let chain: &ChainView = match self.chain.as_ref() {
+            Some(chain) => chain,
+            None => return,
+        };
+
+        let scan_date: String = self.clock.scan_date();
+
+        let mut tracked: Vec<TrackedOrder> = Vec::new();
+        for (i, order_id) in order_ids.iter().enumerate() {
+            let (order, contender): (&OrderBody, &Contender) = match (
+                request_data.orders.get(i),
+                request_data
+                    .contender_index
+                    .get(i)
+                    .and_then(|index| contender_contracts.get(*index)),
+            ) {
+                (Some(order), Some(contender)) => (order, contender),
+                _ => continue,
+            };
+            let c_oid: String = order.c_oid.clone().unwrap_or_default();
+
+            // A netted order is journaled as the orders it consolidates, sharing its id, and
+            // tracked as one.
+            let members: Vec<(&OrderBody, &Contender)> = match request_data.netted.get(i) {
+                Some(netted) if !netted.is_empty() => netted
+                    .iter()
+                    .filter_map(|(index, member)| Some((member, contender_contracts.get(*index)?)))
+                    .collect(),
+                _ => vec![(order, contender)],
+            };
+            if members.len() > 1 {
+                let mut legs: Vec<JournalLeg> = Vec::new();
+                for (_, contender) in &members {
+                    for leg in journal_legs(order, contender, chain) {
+                        if !legs.iter().any(|known| known.conid == leg.conid) {
+                            legs.push(leg);
                         }
                     }
                 }
-                break;
-            } else {
-                break;
+                tracked.push(TrackedOrder {
+                    order_id: order_id.clone(),
+                    c_oid: c_oid.clone(),
+                    type_spread: "Netted".to_string(),
+                    legs,
+                    modeled_edge: None,
+                    seen_executions: HashSet::new(),
+                });
+            }
+            for (order, contender) in members.iter().copied() {
+                for entry in
+                    self.member_entries(chain, order_id, &c_oid, order, contender, &scan_date)
+                {
+                    self.exposure_book.apply(&entry);
+                    if let Err(e) = append_journal(&entry) {
+                        log_warn(format!("Failed to write journal: {}.", e));
+                    }
+                }
+                if members.len() == 1 {
+                    tracked.push(TrackedOrder {
+                        order_id: order_id.clone(),
+                        c_oid: c_oid.clone(),
+                        type_spread: contender.type_spread.clone(),
+                        legs: journal_legs(order, contender, chain),
+                        modeled_edge: Some(
+                            contender.modeled_edge() / contender.orders_per_fill() as f64,
+                        ),
+                        seen_executions: HashSet::new(),
+                    });
+                }
             }
         }
+
+        self.journal_orders.extend(tracked);
+    }
+
+    // Function that returns the submitted and decision entries of an order built from a
+    // contender.
+    fn member_entries(
+        &self,
+        chain: &ChainView,
+        order_id: &str,
+        c_oid: &str,
+        order: &OrderBody,
+        contender: &Contender,
+        scan_date: &str,
+    ) -> [JournalEntry; 2] {
+        let modeled_edge: f64 = contender.modeled_edge() / contender.orders_per_fill() as f64;
+        let entry: JournalEntry = JournalEntry::Submitted {
+            time: self.clock.now().to_rfc3339(),
+            order_id: order_id.to_string(),
+            c_oid: c_oid.to_string(),
+            ticker: order.ticker.clone(),
+            type_spread: contender.type_spread.clone(),
+            exp_date: contender.exp_date.clone(),
+            limit_price: order.price,
+            quantity: order.quantity,
+            legs: journal_legs(order, contender, chain),
+            modeled_edge: Some(modeled_edge),
+        };
+        let (arb_threshold, strike_dif): (f64, f64) = self.scanned_thresholds(contender);
+        let decision: JournalEntry = JournalEntry::Decision {
+            time: self.clock.now().to_rfc3339(),
+            order_id: order_id.to_string(),
+            ticker: order.ticker.clone(),
+            type_spread: contender.type_spread.clone(),
+            exp_date: contender.exp_date.clone(),
+            scan_date: scan_date.to_string(),
+            arb_threshold,
+            regime_value: contender.regime_value,
+            strike_dif,
+            mean_strike: chain.mean_strike(),
+            underlying_price: chain.underlying_price(),
+            atm_vol: atm_vol(chain, scan_date),
+            rank_value: contender.rank_value,
+            profit_gate: self.profit_gate,
+            leg_filters: self.leg_filters.clone(),
+            quotes: leg_quotes(contender, chain),
+        };
+        [entry, decision]
+    }
+
+    // Function that polls executions and order statuses, journaling new leg fills and the final
+    // status of tracked orders.
+    pub(crate) fn poll_order_status(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.journal_orders.is_empty() {
+            return Ok(());
+        }
+
+        let trades_url: String = format!("{}/v1/api/iserver/account/trades", self.base_url());
+        let trades: Vec<TradeResponse> = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .get(&trades_url)
+            .send_traced()?
+            .read_json("/iserver/account/trades")?;
+
+        for trade in trades {
+            let (order_ref, execution_id): (&str, &str) = match &trade.order_ref {
+                Some(order_ref) => (order_ref, &trade.execution_id),
+                None => continue,
+            };
+            let order: &mut TrackedOrder = match self
+                .journal_orders
+                .iter_mut()
+                .find(|order| order.c_oid == order_ref)
+            {
+                Some(order) => order,
+                None => continue,
+            };
+            if !order.seen_executions.insert(execution_id.to_string()) {
+                continue;
+            }
+
+            let conid: String = trade.conid.clone();
+            let side: String = trade.side.clone();
+            let price: f64 = trade.price.as_deref().and_then(parse_price).unwrap_or(0.0);
+            let intended_price: Option<f64> = order
+                .legs
+                .iter()
+                .find(|leg| leg.conid == conid)
+                .map(|leg| leg.intended_price);
+
+            if let Err(e) = append_journal(&JournalEntry::Execution {
+                time: self.clock.now().to_rfc3339(),
+                order_id: order.order_id.clone(),
+                type_spread: order.type_spread.clone(),
+                execution_id: execution_id.to_string(),
+                conid,
+                side: side.clone(),
+                size: trade.size.as_deref().and_then(parse_price).unwrap_or(0.0),
+                price,
+                trade_time: trade.trade_time.clone(),
+                intended_price,
+                slippage: intended_price.map(|intended| slippage(&side, price, intended)),
+            }) {
+                log_warn(format!("Failed to write journal: {}.", e));
+            }
+        }
+
+        let orders_url: String = format!("{}/v1/api/iserver/account/orders", self.base_url());
+        let orders: OrdersResponse = self
+            .client
+            .as_ref()
+            .ok_or("Client is not initialized")?
+            .get(&orders_url)
+            .send_traced()?
+            .read_json("/iserver/account/orders")?;
+
+        for order in orders.orders {
+            let order_id: String = order.order_id;
+            let status: &str = match final_status(&order.status) {
+                Some(status) => status,
+                None => continue,
+            };
+            let index: usize = match self
+                .journal_orders
+                .iter()
+                .position(|tracked| tracked.order_id == order_id)
+            {
+                Some(index) => index,
+                None => continue,
+            };
+            let tracked: TrackedOrder = self.journal_orders.remove(index);
+            let avg_price: Option<f64> = order.avg_price.as_deref().and_then(parse_price);
+            self.record_blacklist(&tracked, status == "filled");
+
+            if let (Some(avg_price), Some(modeled_edge)) = (avg_price, tracked.modeled_edge) {
+                if status == "filled" && modeled_edge != 0.0 {
+                    let mid: f64 = combo_mid(&tracked.legs);
+                    let realized: f64 = realized_edge(modeled_edge, mid, avg_price);
+                    log_message(format!(
+                        "{} order {} filled at {:.2} against a {:.2} mid, capturing {:.0}% of its {:.2} modeled edge.",
+                        tracked.type_spread,
+                        order_id,
+                        avg_price,
+                        mid,
+                        realized / modeled_edge * 100.0,
+                        modeled_edge
+                    ));
+                }
+            }
+
+            let entry: JournalEntry = JournalEntry::Status {
+                time: self.clock.now().to_rfc3339(),
+                order_id,
+                type_spread: tracked.type_spread,
+                status: status.to_string(),
+                avg_price,
+            };
+            self.exposure_book.apply(&entry);
+            if let Err(e) = append_journal(&entry) {
+                log_warn(format!("Failed to write journal: {}.", e));
+            }
+        }
+
         Ok(())
     }
 }
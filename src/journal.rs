@@ -0,0 +1,366 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::OpenOptions,
+    io::{self, Write},
+};
+
+use crate::{
+    book::SettlementKind,
+    chain::ChainView,
+    encryption::{read_lines, seal_line},
+    filters::{legacy_leg_filters, LegFilter},
+    helpers::JOURNAL_PATH,
+    logging::cycle_id,
+    structs::{Contender, Opt, OrderBody, ProfitGate, QuoteSource},
+};
+
+// One leg of a submitted order and the price the scan intended to trade it at.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct JournalLeg {
+    pub(crate) conid: String,
+    pub(crate) strike: f64,
+    pub(crate) right: String,
+    pub(crate) date: String,
+    pub(crate) action: String,
+    pub(crate) ratio: i32,
+    pub(crate) intended_price: f64,
+    // Feed and fetch time of the quote the intended price came from.
+    #[serde(default)]
+    pub(crate) source: QuoteSource,
+    #[serde(default)]
+    pub(crate) fetched_at: Option<String>,
+}
+
+// A leg's quote at the time of the scan, enough to replay the scanner's checks on it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct LegQuote {
+    pub(crate) strike: f64,
+    pub(crate) right: String,
+    pub(crate) date: String,
+    pub(crate) bid: f64,
+    pub(crate) ask: f64,
+    pub(crate) mkt: f64,
+    pub(crate) asz: f64,
+    #[serde(default)]
+    pub(crate) source: QuoteSource,
+    #[serde(default)]
+    pub(crate) fetched_at: Option<String>,
+    #[serde(default)]
+    pub(crate) open_interest: Option<f64>,
+}
+
+impl LegQuote {
+    // Function that records a contract's quote.
+    pub(crate) fn new(strike: f64, right: &str, date: &str, quote: &Opt) -> Self {
+        LegQuote {
+            strike,
+            right: right.to_string(),
+            date: date.to_string(),
+            bid: quote.bid,
+            ask: quote.ask,
+            mkt: quote.mkt,
+            asz: quote.asz,
+            source: quote.provenance.source,
+            fetched_at: quote.provenance.fetched_at_rfc3339(),
+            open_interest: quote.open_interest,
+        }
+    }
+}
+
+// A line of the order journal. Every entry carries the spread type, so per strategy fill rates
+// and slippage can be computed straight from the file.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum JournalEntry {
+    Submitted {
+        time: String,
+        order_id: String,
+        c_oid: String,
+        ticker: String,
+        type_spread: String,
+        exp_date: String,
+        limit_price: f64,
+        quantity: i32,
+        legs: Vec<JournalLeg>,
+        // This order's share of the contender's edge at the scan's mid prices.
+        #[serde(default)]
+        modeled_edge: Option<f64>,
+    },
+    // The scan inputs that selected an order's contender, for replaying the decision.
+    Decision {
+        time: String,
+        order_id: String,
+        ticker: String,
+        type_spread: String,
+        exp_date: String,
+        scan_date: String,
+        arb_threshold: f64,
+        regime_value: f64,
+        strike_dif: f64,
+        mean_strike: f64,
+        underlying_price: Option<f64>,
+        // Implied volatility at the money, for the expected move filter.
+        #[serde(default)]
+        atm_vol: Option<f64>,
+        rank_value: f64,
+        #[serde(default)]
+        profit_gate: ProfitGate,
+        // Decisions recorded before leg filters were configurable ran the original pipeline.
+        #[serde(default = "legacy_leg_filters")]
+        leg_filters: Vec<LegFilter>,
+        quotes: Vec<LegQuote>,
+    },
+    // A candidate that failed exactly one of the scanner's checks, for calibrating thresholds.
+    NearMiss {
+        time: String,
+        ticker: String,
+        type_spread: String,
+        exp_date: String,
+        filter: String,
+        detail: String,
+        // How far the failed threshold would have to move for the candidate to pass.
+        shortfall: Option<f64>,
+        quotes: Vec<LegQuote>,
+    },
+    Execution {
+        time: String,
+        order_id: String,
+        type_spread: String,
+        execution_id: String,
+        conid: String,
+        side: String,
+        size: f64,
+        price: f64,
+        trade_time: String,
+        intended_price: Option<f64>,
+        // Positive when the fill was worse than intended.
+        slippage: Option<f64>,
+    },
+    // A roll of a held calendar's near leg, with the calendar's rolls so far counting this one.
+    // The far leg's conid stays the same across rolls, so it identifies the calendar.
+    Roll {
+        time: String,
+        order_id: String,
+        c_oid: String,
+        ticker: String,
+        type_spread: String,
+        far_conid: String,
+        strike: f64,
+        right: String,
+        from_date: String,
+        to_date: String,
+        limit_price: f64,
+        quantity: i32,
+        legs: Vec<JournalLeg>,
+        rolls: u32,
+        // Credit per share collected by the filled rolls, counting this one at its limit price.
+        cumulative_credit: f64,
+    },
+    // An option leg that was assigned, exercised or expired while the bot wasn't trading it.
+    Settlement {
+        time: String,
+        type_spread: String,
+        kind: SettlementKind,
+        conid: String,
+        und_conid: String,
+        right: String,
+        strike: f64,
+        expiry: String,
+        contracts: f64,
+        shares: f64,
+    },
+    Status {
+        time: String,
+        order_id: String,
+        type_spread: String,
+        status: String,
+        avg_price: Option<f64>,
+    },
+}
+
+// An order whose executions and final status are still being journaled.
+pub(crate) struct TrackedOrder {
+    pub(crate) order_id: String,
+    pub(crate) c_oid: String,
+    pub(crate) type_spread: String,
+    pub(crate) legs: Vec<JournalLeg>,
+    pub(crate) modeled_edge: Option<f64>,
+    pub(crate) seen_executions: HashSet<String>,
+}
+
+// Function that serializes an entry as a journal line tagged with the scan cycle's correlation
+// ID, so rows can be matched with the cycle's log lines.
+pub(crate) fn journal_line(entry: &JournalEntry, cycle_id: &str) -> serde_json::Result<String> {
+    let mut line: serde_json::Value = serde_json::to_value(entry)?;
+    if let Some(fields) = line.as_object_mut() {
+        fields.insert("cycle_id".to_string(), cycle_id.into());
+    }
+    serde_json::to_string(&line)
+}
+
+// Function that appends an entry to the journal file.
+pub(crate) fn append_journal(entry: &JournalEntry) -> io::Result<()> {
+    if cfg!(test) {
+        return Ok(());
+    }
+    let mut file: std::fs::File = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(JOURNAL_PATH)?;
+    writeln!(file, "{}", seal_line(&journal_line(entry, &cycle_id())?)?)
+}
+
+// Function that lists an order's legs from its combo conidex, e.g. "28812380;;;111/-1,222/1",
+// with the intended price of each leg taken from the contender it was built from.
+pub(crate) fn journal_legs(
+    order: &OrderBody,
+    contender: &Contender,
+    chain: &ChainView,
+) -> Vec<JournalLeg> {
+    let legs: &str = order
+        .con_idex
+        .split_once(";;;")
+        .map(|(_, legs)| legs)
+        .unwrap_or("");
+
+    legs.split(',')
+        .filter_map(|leg| {
+            let (conid, ratio) = leg.split_once('/')?;
+            let ratio: i32 = ratio.parse::<i32>().ok()?;
+            let contract = contender.contracts.iter().find(|contract| {
+                chain
+                    .conid(&contract.date, &contract.type_contract, contract.strike)
+                    .is_some_and(|leg_conid| leg_conid == conid)
+            })?;
+            Some(JournalLeg {
+                conid: conid.to_string(),
+                strike: contract.strike,
+                right: contract.type_contract.clone(),
+                date: contract.date.clone(),
+                action: if ratio > 0 { "BUY" } else { "SELL" }.to_string(),
+                ratio,
+                intended_price: contract.mkt_price,
+                source: contract.provenance.source,
+                fetched_at: contract.provenance.fetched_at_rfc3339(),
+            })
+        })
+        .collect()
+}
+
+// Function that records the quotes of a contender's legs from the chain, in contract order.
+pub(crate) fn leg_quotes(contender: &Contender, chain: &ChainView) -> Vec<LegQuote> {
+    contender
+        .contracts
+        .iter()
+        .filter_map(|contract| {
+            let quote = chain.quote(&contract.date, &contract.type_contract, contract.strike)?;
+            Some(LegQuote::new(
+                contract.strike,
+                &contract.type_contract,
+                &contract.date,
+                quote,
+            ))
+        })
+        .collect()
+}
+
+// Function that records the quotes of a candidate's legs, given as strike, right and expiry,
+// skipping legs without a quote.
+pub(crate) fn candidate_quotes(chain: &ChainView, legs: &[(f64, &str, &String)]) -> Vec<LegQuote> {
+    legs.iter()
+        .filter_map(|(strike, right, date)| {
+            Some(LegQuote::new(
+                *strike,
+                right,
+                date,
+                chain.quote(date, right, *strike)?,
+            ))
+        })
+        .collect()
+}
+
+// Function that finds the decision recorded for an order in journal lines.
+pub(crate) fn find_decision<I: Iterator<Item = String>>(
+    lines: I,
+    order_id: &str,
+) -> Option<JournalEntry> {
+    lines
+        .filter_map(|line| serde_json::from_str::<JournalEntry>(&line).ok())
+        .find(
+            |entry| matches!(entry, JournalEntry::Decision { order_id: id, .. } if id == order_id),
+        )
+}
+
+// Function that returns the number of filled rolls of a calendar in journal lines and the credit
+// per share they collected.
+pub(crate) fn roll_history<I: Iterator<Item = String>>(lines: I, far_conid: &str) -> (u32, f64) {
+    let entries: Vec<JournalEntry> = lines
+        .filter_map(|line| serde_json::from_str::<JournalEntry>(&line).ok())
+        .collect();
+    let filled: HashSet<&str> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            JournalEntry::Status {
+                order_id, status, ..
+            } if status == "filled" => Some(order_id.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    entries
+        .iter()
+        .filter_map(|entry| match entry {
+            JournalEntry::Roll {
+                order_id,
+                far_conid: conid,
+                limit_price,
+                ..
+            } if conid == far_conid && filled.contains(order_id.as_str()) => Some(-limit_price),
+            _ => None,
+        })
+        .fold((0, 0.0), |(rolls, credit), roll_credit| {
+            (rolls + 1, credit + roll_credit)
+        })
+}
+
+// Function that loads a calendar's roll history from the journal file, none without a journal.
+pub(crate) fn load_roll_history(path: &str, far_conid: &str) -> (u32, f64) {
+    match read_lines(path) {
+        Ok(lines) => roll_history(lines, far_conid),
+        Err(_) => (0, 0.0),
+    }
+}
+
+// Function that maps an IBKR order status to the journal's final status, None while working.
+pub(crate) fn final_status(status: &str) -> Option<&'static str> {
+    match status {
+        "Filled" => Some("filled"),
+        "Cancelled" | "ApiCancelled" => Some("cancelled"),
+        "Inactive" => Some("rejected"),
+        _ => None,
+    }
+}
+
+// Function that returns how much worse than intended a leg filled, positive is worse.
+pub(crate) fn slippage(side: &str, price: f64, intended_price: f64) -> f64 {
+    let slippage: f64 = if side.starts_with('B') {
+        price - intended_price
+    } else {
+        intended_price - price
+    };
+    (slippage * 100.0).round() / 100.0
+}
+
+// Function that returns an order's combo price at the legs' intended mid prices.
+pub(crate) fn combo_mid(legs: &[JournalLeg]) -> f64 {
+    legs.iter()
+        .map(|leg| leg.ratio as f64 * leg.intended_price)
+        .sum()
+}
+
+// Function that returns the edge an order kept after filling at its average combo price, the
+// modeled edge less whatever the fill paid over the mid.
+pub(crate) fn realized_edge(modeled_edge: f64, mid: f64, avg_price: f64) -> f64 {
+    modeled_edge - (avg_price - mid)
+}
@@ -1,15 +1,260 @@
 #[allow(dead_code)]
+mod allocator;
+#[allow(dead_code)]
+mod audit;
+#[allow(dead_code)]
+mod blacklist;
+#[allow(dead_code)]
+mod book;
+#[allow(dead_code)]
+mod chain;
+#[allow(dead_code)]
+mod clock;
+#[allow(dead_code)]
+mod combos;
+#[allow(dead_code)]
+mod config;
+#[allow(dead_code)]
+mod encryption;
+#[allow(dead_code)]
+mod execution;
+#[allow(dead_code)]
+mod expiry;
+#[allow(dead_code)]
+mod exposure;
+#[allow(dead_code)]
+mod failover;
+#[allow(dead_code)]
+mod fill_model;
+#[allow(dead_code)]
+mod filters;
+#[allow(dead_code)]
+mod greeks;
+#[allow(dead_code)]
+mod headers;
+#[allow(dead_code)]
+mod health;
+#[allow(dead_code)]
+mod hedge;
+#[allow(dead_code)]
 mod helpers;
 #[allow(dead_code)]
+mod ibkr;
+#[allow(dead_code)]
+mod journal;
+#[allow(dead_code)]
+mod logging;
+#[allow(dead_code)]
+mod market;
+#[allow(dead_code)]
+mod metrics;
+#[cfg(test)]
+mod mock_gateway;
+#[allow(dead_code)]
+mod near_miss;
+#[allow(dead_code)]
+mod netting;
+#[allow(dead_code)]
+mod orders;
+#[allow(dead_code)]
+mod performance;
+#[allow(dead_code)]
+mod pnl;
+#[allow(dead_code)]
+mod positions;
+#[allow(dead_code)]
+mod progress;
+#[allow(dead_code)]
+mod quote_diff;
+#[allow(dead_code)]
+mod replies;
+#[allow(dead_code)]
+mod resolver;
+#[allow(dead_code)]
+mod responses;
+#[allow(dead_code)]
+mod roll;
+#[allow(dead_code)]
+mod scheduler;
+#[allow(dead_code)]
+mod secrets;
+#[allow(dead_code)]
+mod session;
+#[allow(dead_code)]
+mod setup;
+#[allow(dead_code)]
+mod stress;
+#[allow(dead_code)]
 mod structs;
+#[allow(dead_code)]
+mod surface;
+#[allow(dead_code)]
+mod systemd;
+#[allow(dead_code)]
+mod throttle;
+#[allow(dead_code)]
+mod trace;
+#[allow(dead_code)]
+mod validation;
 
 #[cfg(test)]
 mod tests {
-    use std::{env, error::Error};
+    use std::{
+        collections::{HashMap, HashSet},
+        env,
+        error::Error,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
 
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+    use ordered_float::OrderedFloat;
+
+    use crate::allocator::{
+        allocator_for, jittered_quantity, Allocator, ExecutionAllocator, Exposure,
+        JitteredAllocator,
+    };
+    use crate::audit::{replay_decision, scanner_checks, FilterCheck, ScanContext};
+    use crate::blacklist::{BlacklistedQuote, QuoteBlacklist};
+    use crate::book::{reconcile_book, Settlement, SettlementKind};
+    use crate::chain::{chain_from_contracts, parse_option_months, ChainView};
+    use crate::clock::Clock;
+    use crate::combos::{Combo, ComboIndex};
+    use crate::config::{is_valid_order_tag, Config, ConfigSource, FillModelKind, MissingQuotes};
+    use crate::encryption::{load_or_create_salt, LineCipher};
+    use crate::execution::{fill_type_preset, parse_execution_profile, ExecutionProfile, Sizing};
+    use crate::expiry::{
+        expires_on, is_itm_short, is_physically_settled, roll_conidex, ExpiryAction,
+    };
+    use crate::exposure::{exposure_book, worst_case_loss, ExpiryExposure, ExposureBook};
+    use crate::failover::{Failover, GatewayRole};
+    use crate::fill_model::{parse_fill_rates, FillModel};
+    use crate::filters::{
+        default_leg_filters, first_rejection, legacy_leg_filters, parse_leg_filters, LegFilterKind,
+    };
+    use crate::greeks::{bs_delta, bs_price, calendar_theta_score, implied_vol};
+    use crate::headers::{auth_header, build_default_headers, parse_headers, DEFAULT_USER_AGENT};
+    use crate::health::{health_report, read_health_from, write_health_to};
+    use crate::hedge::{
+        hedge_quantity, is_tradable_hedge, is_working, net_delta, option_position_delta,
+    };
     use crate::helpers::{
-        calc_final_num_orders, calc_rank_value, calc_time_difference, get_dotenv_variable,
+        boxspread_risk_free_profit, butterfly_risk_free_profit, calc_calendar_rank_value,
+        calc_normalized_rank_value, calc_rank_value, calc_time_difference, clock_drift,
+        compare_contenders, dedupe_contenders, expected_move, gateway_base_url,
+        get_dotenv_variable, is_in_blackout, is_market_holiday, is_snapshot_warm,
+        is_us_stock_market_open, is_winding_down, next_market_open, parse_blackout_windows,
+        parse_history, parse_market_holidays, parse_price, parse_quote, realized_volatility,
+        underlying_moved_too_far,
+    };
+    use crate::ibkr::IBKR;
+    use crate::journal::{
+        final_status, find_decision, journal_legs, journal_line, roll_history, slippage,
+        JournalEntry, JournalLeg, LegQuote,
+    };
+    use crate::logging::{
+        correlation_tag, cycle_id, next_request_id, roll_log_at, start_cycle, LogLevel,
+    };
+    use crate::market::{
+        in_open_warmup, is_halted_field, is_quote_stable, median_spread, vix_regime, MarketMonitor,
+        MarketState, Regime,
     };
+    use crate::metrics::{endpoint_key, reset, snapshot, CycleSummary, EndpointStats, ScanStats};
+    use crate::mock_gateway::{Fault, MockGateway};
+    use crate::near_miss::{near_miss_report, near_misses};
+    use crate::netting::{net_orders, netted_price, NettedOrder};
+    use crate::orders::{
+        build_boxspread_call_order, build_boxspread_put_order, build_butterfly_bear_order,
+        build_butterfly_bull_order, build_calendar_order, build_market_order, build_request_data,
+        build_roll_order, cap_to_ask, combo_confirms, escalated_price, expired_orders, join_price,
+        ladder_rungs, ladder_siblings, round_to_tick,
+    };
+    use crate::performance::{session_stats, strategy_code, strategy_stats};
+    use crate::pnl::PnlSample;
+    use crate::positions::{
+        contender_legs, max_fills_within_short_limit, reduced_contracts, PositionRules,
+    };
+    use crate::progress::{eta, format_eta, progress_line};
+    use crate::quote_diff::{CachedScan, QuoteDiff, ScanKey};
+    use crate::replies::{parse_reply, should_confirm, ReplyOutcome, ReplyPolicy, ReplyReason};
+    use crate::resolver::{parse_static_hosts, IpVersion, ResolverConfig};
+    use crate::responses::{body_snippet, decode, ResponseError};
+    use crate::roll::{roll_due, roll_price, roll_target};
+    use crate::scheduler::{
+        get_scan_interval, jittered_sleep, parse_scan_schedule, parse_time_windows, random_delay,
+        random_fraction, ScanWindow, StrategySchedule, StrategySchedules,
+    };
+    use crate::secrets::{get_secret_from, run_secrets_command_with, SecretStore};
+    use crate::session::{record_status, take_session_lost, SessionWatch};
+    use crate::setup::{render_config, QUESTIONS};
+    use crate::stress::{
+        calendars_from_positions, simulate_calendar, CalendarPosition, StressConfig,
+    };
+    use crate::structs::{
+        AccountSummary, ButterflyWings, CancelResponse, ConidsMap, Contender, Contract, ExpiryRule,
+        Health, HedgeRule, LiveOrder, MarketDataResponse, Opt, Order, OrderBody, OrderTags,
+        OrdersResponse, PortfolioResponse, PositionResponse, PriceBounds, Profile, ProfitGate,
+        Provenance, Quote, QuoteSource, RequestDataStruct, RollRule, SecDefInfoResponse,
+        StrikeSlice, TickRule, TradeResponse,
+    };
+    use crate::surface::{fit_quadratic, VolSurface};
+    use crate::systemd::{notify_socket, parse_watchdog, ExitCode};
+    use crate::throttle::{contender_notional, NotionalThrottle};
+    use crate::trace::{redact, redact_as};
+    use crate::validation::{
+        check_availability, classify_availability, validate_quote, DataAvailability, QuoteRejection,
+    };
+
+    // Function that builds a single-leg contender for ordering tests.
+    fn contender(rank_value: f64, arb_val: f64, exp_date: &str, strike: f64) -> Contender {
+        Contender {
+            arb_val,
+            avg_ask: 1.0,
+            type_spread: "Calendar".to_string(),
+            exp_date: exp_date.to_string(),
+            rank_value,
+            regime_value: 0.0,
+            contracts: vec![Contract {
+                strike,
+                mkt_price: 1.0,
+                date: exp_date.to_string(),
+                type_contract: "C".to_string(),
+                provenance: Provenance::default(),
+            }],
+        }
+    }
+
+    // Function that builds the expiries, strikes and conids of a chain from (date, right, strike,
+    // conid) legs, listing expiries and strikes in the order given.
+    fn chain_maps(legs: &[(&str, &str, f64, String)]) -> (Vec<String>, StrikeSlice, ConidsMap) {
+        let mut dates: Vec<String> = Vec::new();
+        let mut strikes: StrikeSlice = StrikeSlice::new();
+        let mut conids: ConidsMap = ConidsMap::new();
+        for (date, right, strike, conid) in legs {
+            if !dates.iter().any(|listed| listed == date) {
+                dates.push(date.to_string());
+            }
+            strikes
+                .entry(date.to_string())
+                .or_default()
+                .entry(right.to_string())
+                .or_default()
+                .push(*strike);
+            conids
+                .entry(date.to_string())
+                .or_default()
+                .entry(right.to_string())
+                .or_default()
+                .insert(OrderedFloat(*strike), conid.clone());
+        }
+        (dates, strikes, conids)
+    }
+
+    // Function that builds a chain from (date, right, strike, conid) legs.
+    fn chain(legs: &[(&str, &str, f64, String)]) -> ChainView {
+        let (dates, strikes, conids) = chain_maps(legs);
+        ChainView::new(dates, strikes, conids)
+    }
 
     #[test]
     fn test_get_dotenv_variable() {
@@ -30,21 +275,391 @@ mod tests {
     }
 
     #[test]
-    fn test_calc_final_num_orders() {
-        // Test for port_val less than 600.
-        assert_eq!(calc_final_num_orders("1", 799.0), (0, 0));
+    fn test_execution_profile_sizes() {
+        let preset = |name: &str| -> ExecutionProfile {
+            parse_execution_profile(name, &ExecutionProfile::default()).unwrap()
+        };
+
+        // Test for port_val less than 800.
+        assert_eq!(preset("conservative").sizes(799.0), (0, 0));
+
+        // Test the conservative preset, fill type "1".
+        assert_eq!(preset("conservative").sizes(800.0), (1, 1));
+        assert_eq!(preset("conservative").sizes(1600.0), (1, 1));
+
+        // Test the concentrated preset, fill type "2".
+        assert_eq!(preset("concentrated").sizes(800.0), (1, 1));
+        assert_eq!(preset("concentrated").sizes(1600.0), (1, 2));
+
+        // Test the spread-across-orders preset, fill type "3".
+        assert_eq!(preset("spread-across-orders").sizes(800.0), (1, 1));
+        assert_eq!(preset("spread-across-orders").sizes(1600.0), (2, 1));
+
+        // Test the balanced preset grows orders and fills together up to 9 fills.
+        assert_eq!(preset("balanced").sizes(3200.0), (2, 2));
+        assert_eq!(preset("balanced").sizes(80000.0), (11, 9));
+
+        // Test the legacy fill type codes map to their presets.
+        assert_eq!(fill_type_preset("1"), "conservative");
+        assert_eq!(fill_type_preset("2"), "concentrated");
+        assert_eq!(fill_type_preset("3"), "spread-across-orders");
+        assert_eq!(fill_type_preset("all"), "balanced");
+        assert_eq!(fill_type_preset("7"), "balanced");
+    }
+
+    #[test]
+    fn test_parse_execution_profile() {
+        let base: ExecutionProfile = ExecutionProfile {
+            ladder_step: 0.05,
+            order_lifetime: 60,
+            ..ExecutionProfile::default()
+        };
+
+        // Test a bare preset keeps the base settings and its own name.
+        let profile: ExecutionProfile = parse_execution_profile(" Concentrated ", &base).unwrap();
+        assert_eq!(profile.name, "concentrated");
+        assert_eq!(profile.sizing, Sizing::Concentrated);
+        assert_eq!(profile.ladder_step, 0.05);
+        assert_eq!(profile.order_lifetime, 60);
+
+        // Test overrides replace the base settings and mark the profile custom.
+        let profile: ExecutionProfile = parse_execution_profile(
+            "spread-across-orders; max_orders=4; max_fills=2; ladder_step=0.1; ladder_rungs=5; \
+             order_lifetime=30",
+            &base,
+        )
+        .unwrap();
+        assert_eq!(profile.name, "spread-across-orders (custom)");
+        assert_eq!(profile.sizing, Sizing::Spread);
+        assert_eq!(
+            (profile.max_orders, profile.max_fills, profile.ladder_rungs),
+            (4, 2, 5)
+        );
+        assert_eq!(profile.ladder_step, 0.1);
+        assert_eq!(profile.order_lifetime, 30);
+
+        // Test max_orders caps the orders of a scan.
+        assert_eq!(profile.sizes(8000.0), (4, 1));
+
+        // Test overrides alone apply to the base preset.
+        let profile: ExecutionProfile = parse_execution_profile("max_fills=4", &base).unwrap();
+        assert_eq!(profile.name, "balanced (custom)");
+        assert_eq!(profile.sizes(80000.0), (25, 4));
+
+        // Test unknown presets, keys and out of range values are rejected.
+        for spec in [
+            "aggressive",
+            "balanced;max_fills=0",
+            "balanced;ladder_rungs=0",
+            "balanced;max_orders=-1",
+            "balanced;ladder_step=-0.05",
+            "balanced;ladder_step=NaN",
+            "balanced;order_lifetime=soon",
+            "balanced;speed=3",
+            "balanced;conservative",
+        ] {
+            assert!(parse_execution_profile(spec, &base).is_none(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn test_execution_allocator() {
+        let account = |equity_with_loan: f64| -> AccountSummary {
+            AccountSummary {
+                equity_with_loan,
+                available_funds: equity_with_loan,
+                buying_power: equity_with_loan,
+                maintenance_margin: 0.0,
+                net_liquidation: equity_with_loan,
+            }
+        };
+        let contenders: Vec<Contender> = (0..3)
+            .map(|i| contender(1.0, 0.1, "240119", 4700.0 + i as f64))
+            .collect();
+        let exposure: Exposure = Exposure::default();
+
+        let execution = |name: &str| -> ExecutionProfile {
+            parse_execution_profile(name, &ExecutionProfile::default()).unwrap()
+        };
+
+        // Test the default allocator sizes by the execution profile: spread-across-orders
+        // spreads single fills.
+        let allocator: ExecutionAllocator = ExecutionAllocator {
+            execution: execution("spread-across-orders"),
+        };
+        assert_eq!(allocator.max_contenders(&account(1600.0), &exposure), 2);
+        assert_eq!(
+            allocator.allocate(&contenders, &account(1600.0), &exposure),
+            vec![1, 1]
+        );
+
+        // Test concentrated puts every fill on the best contender.
+        let allocator: ExecutionAllocator = ExecutionAllocator {
+            execution: execution("concentrated"),
+        };
+        assert_eq!(
+            allocator.allocate(&contenders, &account(4000.0), &exposure),
+            vec![5]
+        );
+
+        // Test an account too small to trade gets nothing.
+        assert_eq!(allocator.max_contenders(&account(799.0), &exposure), 0);
+        assert!(allocator
+            .allocate(&contenders, &account(799.0), &exposure)
+            .is_empty());
+
+        // Test jittered sizes stay at or below the allocation.
+        let allocator: JitteredAllocator = JitteredAllocator {
+            inner: Box::new(ExecutionAllocator {
+                execution: execution("concentrated"),
+            }),
+            jitter_pct: 0.2,
+        };
+        assert_eq!(allocator.max_contenders(&account(4000.0), &exposure), 1);
+        let quantities: Vec<i32> = allocator.allocate(&contenders, &account(4000.0), &exposure);
+        assert!(quantities.len() == 1 && (4..=5).contains(&quantities[0]));
+    }
+
+    #[test]
+    fn test_jittered_quantity() {
+        // Test the band runs from jitter_pct below the allocation up to the allocation itself.
+        assert_eq!(jittered_quantity(10, 0.2, 0.0), 8);
+        assert_eq!(jittered_quantity(10, 0.2, 0.5), 9);
+        assert_eq!(jittered_quantity(10, 0.2, 0.999), 10);
+
+        // Test the widest jitter never sizes above the allocation.
+        assert_eq!(jittered_quantity(9, 0.5, 0.0), 5);
+        assert_eq!(jittered_quantity(9, 0.5, 0.5), 7);
+        assert_eq!(jittered_quantity(9, 0.5, 0.999), 9);
+
+        // Test a single fill is kept at the bottom of the band, and skips are left alone.
+        assert_eq!(jittered_quantity(1, 0.5, 0.0), 1);
+        assert_eq!(jittered_quantity(1, 0.5, 0.999), 1);
+        assert_eq!(jittered_quantity(0, 0.5, 0.999), 0);
+    }
+
+    #[test]
+    fn test_session_watch() {
+        let mut session: SessionWatch = SessionWatch::default();
+        assert!(!session.record(false));
+        assert!(!session.record(true));
+        assert!(!session.record(true));
+        assert!(session.record(false));
+        assert!(!session.record(false));
+
+        // A 401 only counts as a lost session once a request went through, and is taken once.
+        take_session_lost();
+        record_status(reqwest::StatusCode::OK);
+        record_status(reqwest::StatusCode::UNAUTHORIZED);
+        assert!(take_session_lost());
+        assert!(!take_session_lost());
+        record_status(reqwest::StatusCode::UNAUTHORIZED);
+        assert!(!take_session_lost());
+    }
+
+    #[test]
+    fn test_expiry_exposure() {
+        let leg = |strike: f64, right: &str, date: &str, ratio: i32| -> serde_json::Value {
+            serde_json::json!({
+                "conid": format!("{}{}{}", date, right, strike),
+                "strike": strike,
+                "right": right,
+                "date": date,
+                "action": if ratio > 0 { "BUY" } else { "SELL" },
+                "ratio": ratio,
+                "intended_price": 1.0,
+            })
+        };
+        let submitted = |order_id: &str,
+                         ticker: &str,
+                         type_spread: &str,
+                         exp_date: &str,
+                         price: f64,
+                         quantity: i32,
+                         legs: Vec<serde_json::Value>|
+         -> String {
+            serde_json::json!({
+                "event": "submitted",
+                "time": "2024-01-10T15:00:00Z",
+                "order_id": order_id,
+                "c_oid": "",
+                "ticker": ticker,
+                "type_spread": type_spread,
+                "exp_date": exp_date,
+                "limit_price": price,
+                "quantity": quantity,
+                "legs": legs,
+            })
+            .to_string()
+        };
+        let status = |order_id: &str, status: &str, avg_price: Option<f64>| -> String {
+            serde_json::json!({
+                "event": "status",
+                "time": "2024-01-10T15:01:00Z",
+                "order_id": order_id,
+                "type_spread": "",
+                "status": status,
+                "avg_price": avg_price,
+            })
+            .to_string()
+        };
+        let calendar: Vec<serde_json::Value> = vec![
+            leg(4700.0, "C", "240119", -1),
+            leg(4700.0, "C", "240216", 1),
+        ];
+        let butterfly: Vec<serde_json::Value> = vec![
+            leg(4700.0, "C", "240119", 1),
+            leg(4705.0, "C", "240119", -2),
+            leg(4710.0, "C", "240119", 1),
+        ];
+        let roll: String = serde_json::json!({
+            "event": "roll",
+            "time": "2024-01-18T15:00:00Z",
+            "order_id": "5",
+            "c_oid": "",
+            "ticker": "SPX",
+            "type_spread": "Roll",
+            "far_conid": "240216C4700",
+            "strike": 4700.0,
+            "right": "C",
+            "from_date": "240119",
+            "to_date": "240126",
+            "limit_price": -0.5,
+            "quantity": 1,
+            "legs": [],
+            "rolls": 1,
+            "cumulative_credit": 0.5,
+        })
+        .to_string();
+        let lines: Vec<String> = vec![
+            submitted("1", "SPX", "Calendar", "240119", 1.5, 2, calendar.clone()),
+            status("1", "filled", Some(1.4)),
+            submitted("2", "SPX", "Butterfly", "240119", 0.5, 2, butterfly.clone()),
+            submitted("3", "SPX", "Butterfly", "240119", 0.5, 2, butterfly),
+            status("3", "cancelled", None),
+            submitted("4", "RUT", "Calendar", "240119", 1.0, 9, calendar.clone()),
+        ];
+
+        // Test worst case losses: a calendar's debit, the debit over a butterfly's payoff and
+        // nothing for a butterfly bought for a credit.
+        let legs: Vec<JournalLeg> = serde_json::from_value(serde_json::Value::Array(vec![
+            leg(4700.0, "C", "240119", 1),
+            leg(4705.0, "C", "240119", -2),
+            leg(4710.0, "C", "240119", 1),
+        ]))
+        .unwrap();
+        assert_eq!(worst_case_loss("Calendar", &[], 1.5), 1.5);
+        assert_eq!(worst_case_loss("Calendar", &[], -0.2), 0.0);
+        assert_eq!(worst_case_loss("Butterfly", &legs, 0.5), 0.5);
+        assert_eq!(worst_case_loss("Butterfly", &legs, -0.05), 0.0);
+
+        // Test the filled calendar counts at its fill price, the working butterfly order as half
+        // its quantity at its limit, and cancelled orders and other tickers drop out.
+        let book: ExposureBook = exposure_book(lines.clone().into_iter(), "SPX");
+        assert_eq!(
+            book.report("240110"),
+            vec![ExpiryExposure {
+                ticker: "SPX".to_string(),
+                expiry: "240119".to_string(),
+                spreads: 3.0,
+                net_debit: 380.0,
+                worst_case_loss: 380.0,
+            }]
+        );
+        assert_eq!(book.exposure("SPX", "240119").spreads, 3.0);
+        assert_eq!(book.exposure("SPX", "240126"), ExpiryExposure::default());
+
+        // Test a filled roll moves part of the calendar order to the new expiry.
+        let mut lines: Vec<String> = lines;
+        lines.push(roll);
+        lines.push(status("5", "filled", Some(-0.5)));
+        let book: ExposureBook = exposure_book(lines.into_iter(), "SPX");
+        let report: Vec<ExpiryExposure> = book.report("240110");
+        assert_eq!(
+            report
+                .iter()
+                .map(|exposure| (exposure.expiry.as_str(), exposure.spreads))
+                .collect::<Vec<(&str, f64)>>(),
+            vec![("240119", 2.0), ("240126", 1.0)]
+        );
+
+        // Test expired expiries drop out of the report.
+        assert_eq!(book.report("240120").len(), 1);
+
+        // Test a netted order keeps each spread it consolidates at its limit once filled.
+        let book: ExposureBook = exposure_book(
+            vec![
+                submitted("6", "SPX", "Calendar", "240216", 1.0, 1, calendar.clone()),
+                submitted("6", "SPX", "Calendar", "240216", 2.0, 1, calendar),
+                status("6", "filled", Some(0.3)),
+            ]
+            .into_iter(),
+            "SPX",
+        );
+        let exposure: ExpiryExposure = book.exposure("SPX", "240216");
+        assert_eq!((exposure.spreads, exposure.net_debit), (2.0, 300.0));
+    }
+
+    #[test]
+    fn test_notional_throttle() {
+        // Calendars buying their back month leg for 2.00, $200 of notional a fill.
+        let calendar = |strike: f64| -> Contender {
+            let mut calendar: Contender = contender(1.0, 0.1, "240119", strike);
+            calendar.contracts.push(Contract {
+                strike,
+                mkt_price: 2.0,
+                date: "240216".to_string(),
+                type_contract: "C".to_string(),
+                provenance: Provenance::default(),
+            });
+            calendar
+        };
+        let both: Vec<Contender> = vec![calendar(4700.0), calendar(4705.0)];
+        let (a, b): (&[Contender], &[Contender]) = (&both[..1], &both[1..]);
+        assert_eq!(contender_notional(&b[0], 3), 600.0);
+        let start: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 1, 31, 15, 0, 0).unwrap();
+        let at = |seconds: i64| start + chrono::Duration::seconds(seconds);
+
+        // Test no caps leaves the quantities alone.
+        let mut throttle: NotionalThrottle = NotionalThrottle::new(0.0, 0.0);
+        assert_eq!(
+            throttle.admit("SPX", &both, &[9, 9], start),
+            (vec![9, 9], 0)
+        );
+
+        // Test fills past the minute cap are cut and their contender queued.
+        let mut throttle: NotionalThrottle = NotionalThrottle::new(1000.0, 1500.0);
+        assert_eq!(
+            throttle.admit("SPX", &both, &[3, 3], start),
+            (vec![3, 2], 1)
+        );
+        assert_eq!(throttle.queued_count(), 1);
 
-        // Test for fill type "1".
-        assert_eq!(calc_final_num_orders("1", 800.0), (1, 1));
-        assert_eq!(calc_final_num_orders("1", 1600.0), (1, 1));
+        throttle.charge(&both, &[3.0, 2.0], start);
+        assert_eq!(throttle.admit("SPX", b, &[1], at(30)), (vec![0], 1));
+        assert_eq!(throttle.queued_count(), 1);
 
-        // Test for fill type "2".
-        assert_eq!(calc_final_num_orders("2", 800.0), (1, 1));
-        assert_eq!(calc_final_num_orders("2", 1600.0), (1, 2));
+        // Test a queued contender goes first once the minute rolls over, within the hour cap.
+        assert_eq!(
+            throttle.admit("SPX", &both, &[2, 2], at(61)),
+            (vec![0, 2], 1)
+        );
+        throttle.charge(&both, &[0.0, 2.0], at(61));
 
-        // Test for fill type "3".
-        assert_eq!(calc_final_num_orders("3", 800.0), (1, 1));
-        assert_eq!(calc_final_num_orders("3", 1600.0), (2, 1));
+        // Test admitted fills that were never placed don't use up the caps, only placed ones.
+        let mut unplaced: NotionalThrottle = NotionalThrottle::new(1000.0, 0.0);
+        assert_eq!(unplaced.admit("SPX", a, &[5], start), (vec![5], 0));
+        assert_eq!(unplaced.admit("SPX", a, &[5], at(1)), (vec![5], 0));
+        unplaced.charge(a, &[2.5], at(1));
+        assert_eq!(unplaced.admit("SPX", a, &[5], at(2)), (vec![2], 1));
+
+        // Test the same legs on another ticker are a different contender.
+        assert_eq!(throttle.admit("RUT", a, &[1], at(62)), (vec![0], 1));
+        assert_eq!(throttle.queued_count(), 2);
+
+        // Test the hour cap frees up an hour later and admitted contenders leave the queue.
+        assert_eq!(throttle.admit("SPX", a, &[2], at(3601)), (vec![2], 0));
+        assert_eq!(throttle.queued_count(), 1);
     }
 
     #[test]
@@ -87,4 +702,3563 @@ mod tests {
         let rank_value: f64 = calc_rank_value(10.0, 5.0, "220101", "220101");
         assert!((rank_value - (50.0 / 1.0)).abs() < 1e-9);
     }
+
+    #[test]
+    fn test_calc_calendar_rank_value() {
+        let (spot, strike): (f64, f64) = (4700.0, 4700.0);
+        let front: f64 = bs_price(spot, strike, 1.0 / 365.0, 0.20, "C");
+        let back: f64 = bs_price(spot, strike, 2.0 / 365.0, 0.18, "C");
+        let far_back: f64 = bs_price(spot, strike, 30.0 / 365.0, 0.18, "C");
+
+        // Test the front leg decays faster than the back, more so against a far back leg.
+        let near_score: f64 = calendar_theta_score(spot, strike, "C", front, back, 1, 2).unwrap();
+        let far_score: f64 =
+            calendar_theta_score(spot, strike, "C", front, far_back, 1, 30).unwrap();
+        assert!(near_score > 0.0 && near_score <= 1.0);
+        assert!(far_score > near_score);
+
+        // Test no score without an implied volatility, e.g. a price below intrinsic.
+        assert_eq!(
+            calendar_theta_score(spot, 4600.0, "C", 50.0, back, 1, 2),
+            None
+        );
+
+        // Test the theta score replaces the days factor, falling back to it without one.
+        assert!(
+            (calc_calendar_rank_value(10.0, 0.5, Some(0.4), "220101", "220103") - 2.0).abs() < 1e-9
+        );
+        assert_eq!(
+            calc_calendar_rank_value(10.0, 0.5, None, "220101", "220103"),
+            calc_rank_value(10.0, 0.5, "220101", "220103")
+        );
+    }
+
+    #[test]
+    fn test_parse_history() {
+        let history: serde_json::Value = serde_json::from_str(
+            r#"{"symbol":"SPX","data":[
+                {"o":4710.0,"c":4720.5,"h":4725.0,"l":4700.0,"v":0,"t":1704292200000},
+                {"o":4690.0,"c":"4705.25","h":4712.0,"l":4685.0,"v":12,"t":1704205800000},
+                {"o":4700.0,"h":4702.0,"l":4698.0,"t":1704378600000}
+            ]}"#,
+        )
+        .unwrap();
+
+        // Test bars come back oldest first, reading string prices and skipping bars without a
+        // close.
+        let bars = parse_history(&history);
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].time, 1704205800000);
+        assert_eq!(
+            (bars[0].open, bars[0].high, bars[0].low, bars[0].close),
+            (4690.0, 4712.0, 4685.0, 4705.25)
+        );
+        assert_eq!(bars[0].volume, 12.0);
+        assert_eq!(bars[1].close, 4720.5);
+        assert!(parse_history(&serde_json::Value::Null).is_empty());
+    }
+
+    #[test]
+    fn test_realized_volatility() {
+        // Test a steady climb has no volatility, and a swing up and back is annualized.
+        assert!(realized_volatility(&[100.0, 101.0, 102.01]).unwrap() < 1e-9);
+        let swing: f64 = (2.0 * 1.1_f64.ln().powi(2) * 252.0).sqrt();
+        assert!((realized_volatility(&[100.0, 110.0, 100.0]).unwrap() - swing).abs() < 1e-9);
+
+        // Test too few closes or a bad close give no estimate.
+        assert_eq!(realized_volatility(&[100.0, 101.0]), None);
+        assert_eq!(realized_volatility(&[100.0, 0.0, 101.0]), None);
+
+        // Test a 16% volatility moves a 4000 underlying about 1% a day.
+        assert!((expected_move(4000.0, 0.16, 1.0) - 40.32).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_calc_normalized_rank_value() {
+        let leg = |strike: f64, mkt_price: f64, type_contract: &str| -> Contract {
+            Contract {
+                strike,
+                mkt_price,
+                date: "220102".to_string(),
+                type_contract: type_contract.to_string(),
+                provenance: Provenance::default(),
+            }
+        };
+        let calendar: Contender = Contender {
+            arb_val: 0.30,
+            avg_ask: 10.0,
+            type_spread: "Calendar".to_string(),
+            exp_date: "220102".to_string(),
+            rank_value: 0.0,
+            regime_value: 0.0,
+            contracts: vec![leg(4700.0, 1.50, "C"), leg(4700.0, 1.20, "C")],
+        };
+        let boxspread: Contender = Contender {
+            arb_val: 5.10,
+            type_spread: "Boxspread".to_string(),
+            contracts: vec![
+                leg(4700.0, 3.0, "C"),
+                leg(4700.0, 0.5, "P"),
+                leg(4705.0, 0.4, "C"),
+                leg(4705.0, 2.0, "P"),
+            ],
+            avg_ask: 10.0,
+            exp_date: "220102".to_string(),
+            rank_value: 0.0,
+            regime_value: 0.0,
+        };
+
+        // Test a calendar ties up its long leg's premium and a box its strike width.
+        assert!((calendar.margin() - 1.20).abs() < 1e-9);
+        assert!((boxspread.margin() - 5.0).abs() < 1e-9);
+        assert!((boxspread.modeled_edge() - 0.10).abs() < 1e-9);
+
+        // Test edge per margin dollar per day, so a small calendar edge can outrank a box.
+        let calendar_rank: f64 = calc_normalized_rank_value(&calendar, "220101");
+        let box_rank: f64 = calc_normalized_rank_value(&boxspread, "220101");
+        assert!((calendar_rank - 10.0 * 0.30 / 1.20 / 2.0).abs() < 1e-9);
+        assert!((box_rank - 10.0 * 0.10 / 5.0 / 2.0).abs() < 1e-9);
+        assert!(calendar_rank > box_rank);
+    }
+
+    #[test]
+    fn test_underlying_moved_too_far() {
+        // Test with both limits disabled.
+        assert!(!underlying_moved_too_far(5000.0, 5100.0, 0.0, 0.0));
+
+        // Test with a points limit.
+        assert!(!underlying_moved_too_far(5000.0, 5004.0, 5.0, 0.0));
+        assert!(underlying_moved_too_far(5000.0, 4994.0, 5.0, 0.0));
+
+        // Test with a percent limit, 0.1% of 5000 is 5 points.
+        assert!(!underlying_moved_too_far(5000.0, 5004.0, 0.0, 0.1));
+        assert!(underlying_moved_too_far(5000.0, 5006.0, 0.0, 0.1));
+
+        // Test with both limits, either one tripping is enough.
+        assert!(underlying_moved_too_far(5000.0, 5006.0, 10.0, 0.1));
+    }
+
+    #[test]
+    fn test_clock_drift() {
+        let sent: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 1, 31, 15, 0, 0).unwrap();
+        let received: DateTime<Utc> = sent + chrono::Duration::seconds(2);
+
+        // Test the server time is compared with the midpoint of the round trip.
+        assert_eq!(
+            clock_drift("Wed, 31 Jan 2024 15:00:01 GMT", sent, received),
+            Some(0.0)
+        );
+
+        // Test a local clock running behind is positive, ahead negative.
+        assert_eq!(
+            clock_drift("Wed, 31 Jan 2024 15:00:31 GMT", sent, received),
+            Some(30.0)
+        );
+        assert_eq!(
+            clock_drift("Wed, 31 Jan 2024 14:59:51 GMT", sent, received),
+            Some(-10.0)
+        );
+
+        // Test a header that isn't an HTTP date.
+        assert_eq!(clock_drift("yesterday", sent, received), None);
+    }
+
+    // A clock that stands still at a set time until moved.
+    struct FixedClock {
+        now: Mutex<DateTime<Utc>>,
+    }
+
+    impl FixedClock {
+        fn new(now: DateTime<Utc>) -> Self {
+            FixedClock {
+                now: Mutex::new(now),
+            }
+        }
+
+        fn set(&self, now: DateTime<Utc>) {
+            *self.now.lock().unwrap() = now;
+        }
+
+        fn advance(&self, duration: chrono::Duration) {
+            let mut now = self.now.lock().unwrap();
+            *now += duration;
+        }
+    }
+
+    impl Clock for FixedClock {
+        fn now(&self) -> DateTime<Utc> {
+            *self.now.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_clock() {
+        // Test a fixed clock holds its time.
+        let clock: FixedClock =
+            FixedClock::new(Utc.with_ymd_and_hms(2024, 2, 6, 17, 0, 0).unwrap());
+        assert_eq!(
+            clock.now(),
+            Utc.with_ymd_and_hms(2024, 2, 6, 17, 0, 0).unwrap()
+        );
+        assert_eq!(clock.scan_date(), "240206");
+        assert_eq!(clock.today(), NaiveDate::from_ymd_opt(2024, 2, 6).unwrap());
+        assert!(is_us_stock_market_open(clock.now()));
+
+        // Test ranking against the clock's date.
+        assert!(
+            (calc_rank_value(10.0, 5.0, &clock.scan_date(), "240207") - 50.0 / 2.0).abs() < 1e-9
+        );
+
+        // Test moving the clock past the close and UTC midnight keeps New York's date, so days to
+        // expiry don't change in the evening.
+        clock.advance(chrono::Duration::hours(9));
+        assert!(!is_us_stock_market_open(clock.now()));
+        assert_eq!(
+            clock.now().date_naive(),
+            NaiveDate::from_ymd_opt(2024, 2, 7).unwrap()
+        );
+        assert_eq!(clock.scan_date(), "240206");
+        assert_eq!(calc_time_difference(&clock.scan_date(), "240207"), 1);
+
+        // Test moving the clock into the weekend.
+        clock.set(Utc.with_ymd_and_hms(2024, 2, 10, 17, 0, 0).unwrap());
+        assert_eq!(clock.scan_date(), "240210");
+        assert!(!is_us_stock_market_open(clock.now()));
+        assert_eq!(
+            next_market_open(clock.now(), &[]),
+            Utc.with_ymd_and_hms(2024, 2, 12, 14, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_next_market_open() {
+        // Test Saturday waits for Monday's open, 9:30 New York is 14:30 UTC in winter.
+        assert_eq!(
+            next_market_open(Utc.with_ymd_and_hms(2024, 2, 3, 12, 0, 0).unwrap(), &[]),
+            Utc.with_ymd_and_hms(2024, 2, 5, 14, 30, 0).unwrap()
+        );
+
+        // Test a weekday before the open waits for the same day.
+        assert_eq!(
+            next_market_open(Utc.with_ymd_and_hms(2024, 2, 6, 11, 0, 0).unwrap(), &[]),
+            Utc.with_ymd_and_hms(2024, 2, 6, 14, 30, 0).unwrap()
+        );
+
+        // Test after the close waits for the next day, and Friday's close for Monday.
+        assert_eq!(
+            next_market_open(Utc.with_ymd_and_hms(2024, 2, 6, 22, 0, 0).unwrap(), &[]),
+            Utc.with_ymd_and_hms(2024, 2, 7, 14, 30, 0).unwrap()
+        );
+        assert_eq!(
+            next_market_open(Utc.with_ymd_and_hms(2024, 2, 9, 22, 0, 0).unwrap(), &[]),
+            Utc.with_ymd_and_hms(2024, 2, 12, 14, 30, 0).unwrap()
+        );
+
+        // Test while the market is open there is nothing to wait for.
+        let now: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 2, 6, 16, 0, 0).unwrap();
+        assert_eq!(next_market_open(now, &[]), now);
+
+        // Test holidays are skipped, including one falling while the market would be open.
+        let holidays: Vec<NaiveDate> = parse_market_holidays("2024-02-06; bad;2024-02-07");
+        assert_eq!(holidays.len(), 2);
+        assert!(is_market_holiday(now, &holidays));
+        assert_eq!(
+            next_market_open(now, &holidays),
+            Utc.with_ymd_and_hms(2024, 2, 8, 14, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_is_winding_down() {
+        // Test 15:25 New York, 20:25 UTC in winter, is inside a 5 minute wind down but not 4.
+        let time: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 2, 6, 20, 25, 0).unwrap();
+        assert!(is_winding_down(time, 5));
+        assert!(!is_winding_down(time, 4));
+
+        // Test 0 only winds down at the close, and never while the market is closed.
+        assert!(is_winding_down(
+            Utc.with_ymd_and_hms(2024, 2, 6, 20, 30, 0).unwrap(),
+            0
+        ));
+        assert!(!is_winding_down(
+            Utc.with_ymd_and_hms(2024, 2, 6, 21, 0, 0).unwrap(),
+            60
+        ));
+        assert!(!is_winding_down(
+            Utc.with_ymd_and_hms(2024, 2, 10, 20, 25, 0).unwrap(),
+            5
+        ));
+    }
+
+    #[test]
+    fn test_blackout_windows() {
+        // Test parsing, invalid windows are skipped.
+        let windows: Vec<(NaiveDateTime, NaiveDateTime)> = parse_blackout_windows(
+            "2024-01-31 13:55-14:30; bad; 2024-02-13 08:45-08:25;2024-02-13 08:25-08:45",
+        );
+        assert_eq!(windows.len(), 2);
+
+        // Test inside the FOMC window, 14:00 New York is 19:00 UTC in winter.
+        assert!(is_in_blackout(
+            Utc.with_ymd_and_hms(2024, 1, 31, 19, 0, 0).unwrap(),
+            &windows
+        ));
+
+        // Test at the end of the window, which is exclusive.
+        assert!(!is_in_blackout(
+            Utc.with_ymd_and_hms(2024, 1, 31, 19, 30, 0).unwrap(),
+            &windows
+        ));
+
+        // Test on a different day.
+        assert!(!is_in_blackout(
+            Utc.with_ymd_and_hms(2024, 2, 1, 19, 0, 0).unwrap(),
+            &windows
+        ));
+    }
+
+    #[test]
+    fn test_scan_schedule() {
+        // Test parsing, invalid windows and intervals under 5 seconds are skipped.
+        let schedule: Vec<ScanWindow> =
+            parse_scan_schedule("09:30-10:30=15; 10:30-15:30=60; 12:00-11:00=30; 15:00-15:30=1");
+        assert_eq!(schedule.len(), 2);
+
+        // Test in the first hour, 10:00 New York is 15:00 UTC in winter.
+        let time: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 1, 31, 15, 0, 0).unwrap();
+        assert_eq!(get_scan_interval(time, &schedule, 30), 15);
+
+        // Test midday, 13:00 New York is 18:00 UTC in winter.
+        let time: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 1, 31, 18, 0, 0).unwrap();
+        assert_eq!(get_scan_interval(time, &schedule, 30), 60);
+
+        // Test outside every window falls back to the default.
+        let time: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 1, 31, 21, 0, 0).unwrap();
+        assert_eq!(get_scan_interval(time, &schedule, 30), 30);
+    }
+
+    #[test]
+    fn test_strategy_schedule() {
+        // Test parsing, windows that don't parse or end before they start are skipped.
+        let windows: Vec<(NaiveTime, NaiveTime)> =
+            parse_time_windows("14:00-16:00; 11:00-10:00; 9-10; 09:30-10:00");
+        assert_eq!(
+            windows,
+            vec![
+                (
+                    NaiveTime::from_hms_opt(14, 0, 0).unwrap(),
+                    NaiveTime::from_hms_opt(16, 0, 0).unwrap()
+                ),
+                (
+                    NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+                    NaiveTime::from_hms_opt(10, 0, 0).unwrap()
+                ),
+            ]
+        );
+
+        // 15:00 New York is 20:00 UTC in winter, 12:00 is 17:00 UTC.
+        let afternoon: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 1, 31, 20, 0, 0).unwrap();
+        let midday: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 1, 31, 17, 0, 0).unwrap();
+
+        // Test the default schedule scans every cycle.
+        let schedule: StrategySchedule = StrategySchedule::default();
+        assert!(schedule.is_due(midday, Some(midday)));
+
+        // Test a strategy is only scanned inside its windows.
+        let schedule: StrategySchedule = StrategySchedule {
+            windows: parse_time_windows("14:00-16:00"),
+            ..StrategySchedule::default()
+        };
+        assert!(schedule.is_due(afternoon, None));
+        assert!(!schedule.is_due(midday, None));
+
+        // Test the interval counts from the last scan.
+        let schedule: StrategySchedule = StrategySchedule {
+            interval: 300,
+            ..schedule
+        };
+        assert!(!schedule.is_due(afternoon, Some(afternoon - chrono::Duration::seconds(299))));
+        assert!(schedule.is_due(afternoon, Some(afternoon - chrono::Duration::seconds(300))));
+
+        // Test a disabled strategy is never scanned.
+        let schedule: StrategySchedule = StrategySchedule {
+            enabled: false,
+            ..StrategySchedule::default()
+        };
+        assert!(!schedule.is_due(afternoon, None));
+    }
+
+    #[test]
+    fn test_jitter() {
+        // Test the sleep spreads evenly either side of the cadence and is unchanged without jitter.
+        assert_eq!(jittered_sleep(60, 0.0, 0.9), Duration::from_secs(60));
+        assert_eq!(jittered_sleep(60, 0.1, 0.0), Duration::from_secs(54));
+        assert_eq!(jittered_sleep(60, 0.1, 0.5), Duration::from_secs(60));
+        assert_eq!(random_delay(400, 0.25), Duration::from_millis(100));
+        assert_eq!(random_delay(0, 0.9), Duration::ZERO);
+
+        // Test the generator stays in range and doesn't repeat itself.
+        let fractions: Vec<f64> = (0..100).map(|_| random_fraction()).collect();
+        assert!(fractions.iter().all(|f| (0.0..1.0).contains(f)));
+        assert!(fractions.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn test_chain_view() {
+        // Build a chain with two expiries and three call strikes on the first one.
+        let legs: Vec<(&str, &str, f64, String)> = [
+            ("240102", 4700.0),
+            ("240102", 4705.0),
+            ("240102", 4710.0),
+            ("240103", 4705.0),
+        ]
+        .into_iter()
+        .map(|(date, strike)| (date, "C", strike, format!("{}{}", date, strike)))
+        .collect();
+        let mut chain: ChainView = chain(&legs);
+        chain.set_quotes(
+            [(
+                "2401024705".to_string(),
+                Opt {
+                    asz: 10.0,
+                    mkt: 12.5,
+                    bid: 12.0,
+                    ask: 13.0,
+                    open_interest: None,
+                    provenance: Provenance::default(),
+                },
+            )]
+            .into_iter()
+            .collect(),
+        );
+
+        // Test quote lookups, only one contract has a quote.
+        assert_eq!(chain.quote("240102", "C", 4705.0).unwrap().mkt, 12.5);
+        assert!(chain.quote("240102", "C", 4700.0).is_none());
+        assert!(chain.quote("240102", "P", 4705.0).is_none());
+
+        // Test conids are interned once per contract, in chain order.
+        assert_eq!(chain.contract_count(), 4);
+        assert_eq!(chain.contract_id("240102", "C", 4700.0), Some(0));
+        assert_eq!(chain.contract_id("240103", "C", 4705.0), Some(3));
+        assert!(chain.contract_id("240102", "C", 4702.5).is_none());
+        assert_eq!(chain.conid("240102", "C", 4710.0).unwrap(), "2401024710");
+
+        // Test adjacent strikes, including the edges of the chain.
+        assert_eq!(
+            chain.adjacent_strikes("240102", "C", 4705.0),
+            (Some(4700.0), Some(4710.0))
+        );
+        assert_eq!(
+            chain.adjacent_strikes("240102", "C", 4700.0),
+            (None, Some(4705.0))
+        );
+        assert_eq!(chain.adjacent_strikes("240103", "C", 4705.0), (None, None));
+
+        // Test expiry navigation.
+        assert_eq!(chain.next_date("240102").unwrap(), "240103");
+        assert!(chain.next_date("240103").is_none());
+        assert_eq!(chain.expiries_within("240101", 1).len(), 1);
+        assert_eq!(chain.expiries_within("240101", 2).len(), 2);
+
+        // Test the CSV export, contracts without a quote keep their row with blank quote columns.
+        let csv: String = chain.to_csv();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows[0], "expiry,right,strike,bid,mid,ask,ask_size,conid");
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[1], "240102,C,4700,,,,,2401024700");
+        assert_eq!(rows[2], "240102,C,4705,12.00,12.50,13.00,10,2401024705");
+        assert_eq!(rows[4], "240103,C,4705,,,,,2401034705");
+
+        // Test a quote failing validation is dropped, leaving the contract missing its quote.
+        chain.set_quotes(
+            [
+                (
+                    "2401024705".to_string(),
+                    Opt::zeroed(None, Provenance::default()),
+                ),
+                (
+                    "2401024710".to_string(),
+                    Opt {
+                        asz: 10.0,
+                        mkt: 12.5,
+                        bid: 13.0,
+                        ask: 12.0,
+                        open_interest: None,
+                        provenance: Provenance::default(),
+                    },
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(
+            chain.validate_quotes(None, 10000.0),
+            vec![QuoteRejection::Crossed]
+        );
+        assert!(chain.quote("240102", "C", 4710.0).is_none());
+        assert_eq!(chain.quote("240102", "C", 4705.0).unwrap().mkt, 0.0);
+    }
+
+    #[test]
+    fn test_quote_diff() {
+        let opt = |bid: f64| -> Opt {
+            Opt {
+                asz: 10.0,
+                mkt: bid + 0.5,
+                bid,
+                ask: bid + 1.0,
+                open_interest: None,
+                provenance: Provenance::default(),
+            }
+        };
+        let date: String = "240102".to_string();
+        let mut chain: ChainView = chain(&[
+            ("240102", "C", 4700.0, "4700".to_string()),
+            ("240102", "C", 4705.0, "4705".to_string()),
+        ]);
+
+        // Test the first snapshot rescans everything.
+        chain.set_quotes(HashMap::from([
+            ("4700".to_string(), opt(12.0)),
+            ("4705".to_string(), opt(9.0)),
+        ]));
+        assert!(chain.quote_diff().is_full());
+
+        // Test only the requoted contract changed, and only candidates on it are touched.
+        chain.set_quotes(HashMap::from([
+            ("4700".to_string(), opt(12.0)),
+            ("4705".to_string(), opt(9.5)),
+        ]));
+        let diff: QuoteDiff = chain.quote_diff();
+        assert_eq!(diff.changed_count(), Some(1));
+        assert!(!diff.touches(&chain, &[(4700.0, "C", &date)]));
+        assert!(diff.touches(&chain, &[(4700.0, "C", &date), (4705.0, "C", &date)]));
+        assert!(diff.touches(&chain, &[(4710.0, "C", &date)]));
+        assert!(QuoteDiff::full().touches(&chain, &[(4700.0, "C", &date)]));
+
+        // Test the cached contenders on unchanged legs carry over while the scan inputs match.
+        let key: ScanKey = ScanKey {
+            scan_date: "240101".to_string(),
+            arb_threshold: 0.1,
+            underlying_price: None,
+        };
+        let cached: CachedScan = CachedScan {
+            key: key.clone(),
+            contenders: vec![
+                contender(1.0, 0.5, &date, 4700.0),
+                contender(2.0, 0.5, &date, 4705.0),
+            ],
+        };
+        let carried: Vec<Contender> = cached.carry_over(&key, &chain, &diff).unwrap();
+        assert_eq!(carried.len(), 1);
+        assert_eq!(carried[0].contracts[0].strike, 4700.0);
+        let moved: ScanKey = ScanKey {
+            arb_threshold: 0.2,
+            ..key.clone()
+        };
+        assert!(cached.carry_over(&moved, &chain, &diff).is_none());
+        assert!(cached
+            .carry_over(&key, &chain, &QuoteDiff::full())
+            .is_none());
+
+        // Test a contract dropping out of the snapshot counts as changed.
+        chain.set_quotes(HashMap::from([("4705".to_string(), opt(9.5))]));
+        assert_eq!(chain.quote_diff().changed_count(), Some(1));
+    }
+
+    #[test]
+    fn test_combo_index() {
+        let opt = |bid: f64| -> Opt {
+            Opt {
+                asz: 10.0,
+                mkt: bid + 0.5,
+                bid,
+                ask: bid + 1.0,
+                open_interest: None,
+                provenance: Provenance::default(),
+            }
+        };
+        // Build a chain of three call and put strikes on the first expiry, two on the second.
+        let mut legs: Vec<(&str, &str, f64, String)> = Vec::new();
+        for (date, date_strikes) in [
+            ("240102", vec![4700.0, 4705.0, 4710.0]),
+            ("240103", vec![4705.0, 4710.0]),
+        ] {
+            for right in ["C", "P"] {
+                for strike in &date_strikes {
+                    legs.push((date, right, *strike, format!("{}{}{}", date, right, strike)));
+                }
+            }
+        }
+        let (dates, strikes, mut conids) = chain_maps(&legs);
+        // Leave one strike without a conid, as an inconsistent secdef lookup would.
+        conids
+            .get_mut("240102")
+            .and_then(|rights| rights.get_mut("P"))
+            .unwrap()
+            .remove(&OrderedFloat(4700.0));
+        let mut chain: ChainView = ChainView::new(dates, strikes, conids);
+
+        // Test every combination is indexed once: 4 calendars, 2 butterflies and 3 boxes.
+        let combos: &ComboIndex = chain.combos();
+        assert_eq!(combos.len(), 9);
+        let full: QuoteDiff = QuoteDiff::full();
+        let calendars: Vec<&Combo> = combos.queue("Calendar", &full);
+        assert_eq!(calendars.len(), 4);
+        assert_eq!(calendars[0].legs[0].date, "240102");
+        assert_eq!(calendars[0].legs[1].date, "240103");
+        assert_eq!(calendars[0].legs[0].strike, 4705.0);
+        assert_eq!(combos.queue("Butterfly", &full).len(), 2);
+        let boxes: Vec<&Combo> = combos.queue("Boxspread", &full);
+        assert_eq!(boxes.len(), 3);
+        assert_eq!(
+            boxes[0]
+                .legs
+                .iter()
+                .map(|leg| leg.right)
+                .collect::<Vec<&str>>(),
+            vec!["P", "C", "C", "P"]
+        );
+
+        // Test combinations with a leg missing its conid are flagged for the scanners to skip.
+        assert!(!boxes[0].has_conids(&chain));
+        assert!(boxes[1].has_conids(&chain));
+        assert_eq!(
+            combos
+                .queue("Butterfly", &full)
+                .iter()
+                .map(|combo| combo.has_conids(&chain))
+                .collect::<Vec<bool>>(),
+            vec![true, false]
+        );
+        assert!(calendars.iter().all(|combo| combo.has_conids(&chain)));
+
+        // Test a requote only queues the combinations the contract is a leg of, in chain order.
+        let conids: Vec<String> = chain
+            .combos()
+            .queue("Calendar", &full)
+            .iter()
+            .flat_map(|combo| combo.legs.iter())
+            .map(|leg| format!("{}{}{}", leg.date, leg.right, leg.strike))
+            .collect();
+        for requoted in [5.0, 6.0] {
+            chain.set_quotes(
+                conids
+                    .iter()
+                    .map(|conid| {
+                        let bid: f64 = if conid == "240102C4710" {
+                            requoted
+                        } else {
+                            5.0
+                        };
+                        (conid.clone(), opt(bid))
+                    })
+                    .collect(),
+            );
+        }
+        let diff: QuoteDiff = chain.quote_diff();
+        assert_eq!(diff.changed_count(), Some(1));
+        let touched: Vec<&Combo> = chain.combos().queue("Calendar", &diff);
+        assert_eq!(touched.len(), 1);
+        assert_eq!(touched[0].legs[0].strike, 4710.0);
+        assert_eq!(chain.combos().queue("Butterfly", &diff).len(), 1);
+        assert_eq!(chain.combos().queue("Boxspread", &diff).len(), 1);
+    }
+
+    #[test]
+    fn test_butterfly_wings() {
+        // Build a chain of five call strikes 5 apart.
+        let legs: Vec<(&str, &str, f64, String)> = [4700.0, 4705.0, 4710.0, 4715.0, 4720.0]
+            .into_iter()
+            .map(|strike| ("240102", "C", strike, format!("C{}", strike)))
+            .collect();
+        let chain: ChainView = chain(&legs);
+
+        // Test butterflies are indexed at every wing width that fits, in strike order.
+        let wings: Vec<(f64, f64)> = chain
+            .combos()
+            .queue("Butterfly", &QuoteDiff::full())
+            .iter()
+            .map(|combo| (combo.legs[0].strike, combo.legs[2].strike))
+            .collect();
+        assert_eq!(
+            wings,
+            vec![
+                (4700.0, 4710.0),
+                (4705.0, 4715.0),
+                (4700.0, 4720.0),
+                (4710.0, 4720.0)
+            ]
+        );
+
+        // Test a wing width is matched to its multiple of the strike difference.
+        let butterfly_wings: ButterflyWings = ButterflyWings {
+            widths: 3,
+            arb_values: vec![0.2, 0.5],
+        };
+        assert_eq!(butterfly_wings.multiple(5.0, 5.0), Some(1));
+        assert_eq!(butterfly_wings.multiple(15.0000001, 5.0), Some(3));
+        assert_eq!(butterfly_wings.multiple(20.0, 5.0), None);
+        assert_eq!(butterfly_wings.multiple(2.5, 5.0), None);
+        assert_eq!(ButterflyWings::default().multiple(10.0, 5.0), None);
+
+        // Test per-width arb values follow a regime scaling of the ticker's, and widths without
+        // one scale it by the multiple.
+        assert_eq!(butterfly_wings.arb_threshold(2, 0.2, 0.2), 0.5);
+        assert_eq!(butterfly_wings.arb_threshold(2, 0.4, 0.2), 1.0);
+        assert!((butterfly_wings.arb_threshold(3, 0.2, 0.2) - 0.6).abs() < 1e-9);
+        assert_eq!(ButterflyWings::default().arb_threshold(1, 0.2, 0.2), 0.2);
+    }
+
+    #[test]
+    fn test_quote_blacklist() {
+        let start: DateTime<Utc> = "2024-01-02T15:00:00Z".parse().unwrap();
+        let minutes = |m: i64| -> DateTime<Utc> { start + chrono::Duration::minutes(m) };
+        let mut blacklist: QuoteBlacklist = QuoteBlacklist::new(3, 30.0);
+
+        // Test a conid is listed on its third unfilled arb in a row, and only reported once.
+        assert!(!blacklist.record("111", "SPX 240102 4700C", false, start));
+        assert!(!blacklist.record("111", "SPX 240102 4700C", false, start));
+        assert!(blacklist.record("111", "SPX 240102 4700C", false, start));
+        assert!(!blacklist.record("111", "SPX 240102 4700C", false, start));
+        assert!(blacklist.is_blacklisted("111", minutes(1)));
+        assert!(!blacklist.is_blacklisted("222", minutes(1)));
+        let report: Vec<BlacklistedQuote> = blacklist.report(start);
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].contract, "SPX 240102 4700C");
+        assert_eq!(report[0].score, 4.0);
+
+        // Test the score halves every half-life until the conid comes back and is forgotten.
+        assert!(blacklist.is_blacklisted("111", minutes(12)));
+        assert!(!blacklist.is_blacklisted("111", minutes(30)));
+        assert!(blacklist.report(minutes(30)).is_empty());
+        assert!(blacklist.report(minutes(300)).is_empty());
+        assert!(!blacklist.record("111", "SPX 240102 4700C", false, minutes(300)));
+
+        // Test a fill clears the score and keeps the sibling rungs' cancels from counting.
+        for _ in 0..2 {
+            blacklist.record("333", "SPX 240102 4705C", false, start);
+        }
+        assert!(!blacklist.record("333", "SPX 240102 4705C", true, minutes(1)));
+        for _ in 0..3 {
+            assert!(!blacklist.record("333", "SPX 240102 4705C", false, minutes(2)));
+        }
+        assert!(!blacklist.is_blacklisted("333", minutes(2)));
+
+        // Test a threshold of 0 disables the blacklist.
+        let mut disabled: QuoteBlacklist = QuoteBlacklist::new(0, 30.0);
+        for _ in 0..5 {
+            assert!(!disabled.record("111", "SPX 240102 4700C", false, start));
+        }
+        assert!(!disabled.is_blacklisted("111", start));
+    }
+
+    #[test]
+    fn test_compare_contenders() {
+        // Test that NaN ranks sort last instead of panicking.
+        let mut contenders: Vec<Contender> = vec![
+            contender(f64::NAN, 1.0, "240102", 4700.0),
+            contender(2.0, 1.0, "240102", 4700.0),
+            contender(3.0, 1.0, "240102", 4700.0),
+        ];
+        contenders.sort_by(compare_contenders);
+        assert_eq!(contenders[0].rank_value, 3.0);
+        assert_eq!(contenders[1].rank_value, 2.0);
+        assert!(contenders[2].rank_value.is_nan());
+
+        // Test tiebreaks on equal ranks: arb value desc, then expiry asc, then strike asc.
+        let mut contenders: Vec<Contender> = vec![
+            contender(1.0, 0.5, "240103", 4710.0),
+            contender(1.0, 0.5, "240102", 4710.0),
+            contender(1.0, 0.5, "240102", 4705.0),
+            contender(1.0, 0.7, "240104", 4700.0),
+        ];
+        contenders.sort_by(compare_contenders);
+        let order: Vec<(f64, &str, f64)> = contenders
+            .iter()
+            .map(|c| (c.arb_val, c.exp_date.as_str(), c.contracts[0].strike))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (0.7, "240104", 4700.0),
+                (0.5, "240102", 4705.0),
+                (0.5, "240102", 4710.0),
+                (0.5, "240103", 4710.0),
+            ]
+        );
+
+        // Test that the ordering doesn't depend on the input order.
+        let mut reversed: Vec<Contender> = vec![
+            contender(1.0, 0.7, "240104", 4700.0),
+            contender(1.0, 0.5, "240102", 4705.0),
+            contender(1.0, 0.5, "240102", 4710.0),
+            contender(1.0, 0.5, "240103", 4710.0),
+        ];
+        reversed.reverse();
+        reversed.sort_by(compare_contenders);
+        assert!(reversed
+            .iter()
+            .zip(contenders.iter())
+            .all(|(a, b)| compare_contenders(a, b).is_eq()));
+    }
+
+    #[test]
+    fn test_dedupe_contenders() {
+        let leg = |strike: f64, right: &str| -> Contract {
+            Contract {
+                strike,
+                mkt_price: 1.0,
+                date: "240102".to_string(),
+                type_contract: right.to_string(),
+                provenance: Provenance::default(),
+            }
+        };
+        let spread = |type_spread: &str, rank_value: f64, legs: Vec<Contract>| -> Contender {
+            let mut spread: Contender = contender(rank_value, 0.5, "240102", 0.0);
+            spread.type_spread = type_spread.to_string();
+            spread.contracts = legs;
+            spread
+        };
+
+        // Test a box sharing the butterfly's mispriced call keeps only the better ranked one,
+        // while spreads of the same strategy and disjoint spreads stay.
+        let mut contenders: Vec<Contender> = vec![
+            spread(
+                "Butterfly",
+                3.0,
+                vec![leg(4700.0, "C"), leg(4705.0, "C"), leg(4710.0, "C")],
+            ),
+            spread(
+                "Butterfly",
+                2.5,
+                vec![leg(4705.0, "C"), leg(4710.0, "C"), leg(4715.0, "C")],
+            ),
+            spread(
+                "Boxspread",
+                2.0,
+                vec![
+                    leg(4705.0, "P"),
+                    leg(4705.0, "C"),
+                    leg(4710.0, "C"),
+                    leg(4710.0, "P"),
+                ],
+            ),
+            spread(
+                "Boxspread",
+                1.0,
+                vec![
+                    leg(4720.0, "P"),
+                    leg(4720.0, "C"),
+                    leg(4725.0, "C"),
+                    leg(4725.0, "P"),
+                ],
+            ),
+        ];
+        assert_eq!(dedupe_contenders(&mut contenders), 1);
+        assert_eq!(
+            contenders
+                .iter()
+                .map(|c| (c.type_spread.as_str(), c.rank_value))
+                .collect::<Vec<(&str, f64)>>(),
+            vec![("Butterfly", 3.0), ("Butterfly", 2.5), ("Boxspread", 1.0)]
+        );
+
+        // Test the same strike in another expiry or right is a different leg.
+        let mut contenders: Vec<Contender> = vec![
+            spread("Butterfly", 2.0, vec![leg(4705.0, "C")]),
+            spread("Calendar", 1.0, vec![leg(4705.0, "P")]),
+        ];
+        assert_eq!(dedupe_contenders(&mut contenders), 0);
+    }
+
+    #[test]
+    fn test_validate_quote() {
+        let quote = |bid: f64, ask: f64, asz: f64| -> Opt {
+            Opt {
+                asz,
+                mkt: ((bid + ask) / 2.0 * 100.0).round() / 100.0,
+                bid,
+                ask,
+                open_interest: None,
+                provenance: Provenance::default(),
+            }
+        };
+
+        // Test a normal quote and an empty one.
+        assert_eq!(
+            validate_quote(&quote(4.9, 5.1, 10.0), 4700.0, "C", Some(4690.0), 10000.0),
+            Ok(())
+        );
+        assert_eq!(
+            validate_quote(&quote(0.0, 0.0, 0.0), 4700.0, "C", Some(4800.0), 10000.0),
+            Ok(())
+        );
+
+        // Test a crossed market.
+        assert_eq!(
+            validate_quote(&quote(5.2, 5.1, 10.0), 4700.0, "C", None, 10000.0),
+            Err(QuoteRejection::Crossed)
+        );
+
+        // Test a negative price.
+        assert_eq!(
+            validate_quote(&quote(-1.0, 5.1, 10.0), 4700.0, "C", None, 10000.0),
+            Err(QuoteRejection::NegativePrice)
+        );
+
+        // Test an absurd size, and with the size check disabled.
+        assert_eq!(
+            validate_quote(&quote(4.9, 5.1, 50000.0), 4700.0, "C", None, 10000.0),
+            Err(QuoteRejection::AbsurdSize)
+        );
+        assert_eq!(
+            validate_quote(&quote(4.9, 5.1, 50000.0), 4700.0, "C", None, 0.0),
+            Ok(())
+        );
+
+        // Test a deep in the money put priced near zero, and an out of the money one.
+        assert_eq!(
+            validate_quote(&quote(0.05, 0.1, 10.0), 4800.0, "P", Some(4700.0), 10000.0),
+            Err(QuoteRejection::ZeroPricedInTheMoney)
+        );
+        assert_eq!(
+            validate_quote(&quote(0.05, 0.1, 10.0), 4600.0, "P", Some(4700.0), 10000.0),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_is_snapshot_warm() {
+        let snapshot =
+            |status: Option<&str>, bid: Option<&str>, ask: Option<&str>| -> MarketDataResponse {
+                MarketDataResponse {
+                    conid_ex: Some("123".to_string()),
+                    field_84: bid.map(|bid| bid.to_string()),
+                    field_86: ask.map(|ask| ask.to_string()),
+                    field_6509: status.map(|status| status.to_string()),
+                    ..Default::default()
+                }
+            };
+
+        // Test an empty batch and a batch still waiting on the farm.
+        assert!(!is_snapshot_warm(&[]));
+        assert!(!is_snapshot_warm(&[snapshot(None, None, None)]));
+
+        // Test a connected batch that has no quotes yet.
+        assert!(!is_snapshot_warm(&[snapshot(Some("R"), None, None)]));
+
+        // Test a connected batch with quotes, including one leg without a market.
+        assert!(is_snapshot_warm(&[
+            snapshot(Some("R"), Some("4.90"), Some("5.10")),
+            snapshot(Some("R"), None, None),
+        ]));
+
+        // Test a batch where one conid hasn't reported its farm status.
+        assert!(!is_snapshot_warm(&[
+            snapshot(Some("R"), Some("4.90"), Some("5.10")),
+            snapshot(None, None, None),
+        ]));
+    }
+
+    #[test]
+    fn test_check_availability() {
+        let codes =
+            |codes: &[&str]| -> Vec<String> { codes.iter().map(|c| c.to_string()).collect() };
+
+        // Test classifying the leading 6509 code.
+        assert_eq!(classify_availability("RpB"), DataAvailability::RealTime);
+        assert_eq!(classify_availability("Dp"), DataAvailability::Delayed);
+        assert_eq!(classify_availability("Z"), DataAvailability::Frozen);
+        assert_eq!(classify_availability("N"), DataAvailability::NotSubscribed);
+        assert_eq!(classify_availability(""), DataAvailability::Unknown);
+
+        // Test live data, missing statuses, and a mix of live and unsubscribed conids.
+        assert!(check_availability("SPX", &codes(&["RpB", "R"])).is_ok());
+        assert!(check_availability("SPX", &[]).is_ok());
+        assert!(check_availability("SPX", &codes(&["R", "N"])).is_ok());
+
+        // Test a missing subscription and delayed only data.
+        assert!(check_availability("SPX", &codes(&["N", "N"]))
+            .unwrap_err()
+            .contains("No market data subscription"));
+        assert!(check_availability("SPX", &codes(&["Dp", "N"]))
+            .unwrap_err()
+            .contains("Only delayed market data"));
+    }
+
+    #[test]
+    fn test_fill_model() {
+        let mut thin: Contender = contender(1.0, 5.0, "240119", 4700.0);
+        thin.avg_ask = 10.0;
+        let mut empty: Contender = contender(1.0, 5.0, "240119", 4700.0);
+        empty.avg_ask = 0.0;
+
+        // Test the off and size based models.
+        assert_eq!(FillModel::Off.probability(&thin), 1.0);
+        let size: FillModel = FillModel::Size { half_size: 10.0 };
+        assert_eq!(size.probability(&thin), 0.5);
+        assert_eq!(size.probability(&empty), 0.0);
+
+        // Test learning smoothed fill rates from journal lines, skipping bad and open records.
+        let lines: Vec<String> = vec![
+            r#"{"type_spread": "Calendar", "status": "filled"}"#.to_string(),
+            r#"{"type_spread": "Calendar", "status": "filled"}"#.to_string(),
+            r#"{"type_spread": "Calendar", "status": "cancelled"}"#.to_string(),
+            r#"{"type_spread": "Calendar", "status": "submitted"}"#.to_string(),
+            "not json".to_string(),
+        ];
+        let rates: std::collections::HashMap<String, f64> = parse_fill_rates(lines.into_iter());
+        assert_eq!(rates.get("Calendar"), Some(&0.6));
+
+        // Test the historical model, falling back to even odds for unseen strategies.
+        thin.type_spread = "Calendar".to_string();
+        empty.type_spread = "Butterfly".to_string();
+        let historical: FillModel = FillModel::Historical { rates };
+        assert_eq!(historical.probability(&thin), 0.6);
+        assert_eq!(historical.probability(&empty), 0.5);
+    }
+
+    #[test]
+    fn test_black_scholes() {
+        let (spot, strike, years, vol): (f64, f64, f64, f64) = (4700.0, 4750.0, 30.0 / 365.0, 0.15);
+        let call: f64 = bs_price(spot, strike, years, vol, "C");
+        let put: f64 = bs_price(spot, strike, years, vol, "P");
+
+        // Test put-call parity with zero rates, and intrinsic value at expiry.
+        assert!((call - put - (spot - strike)).abs() < 1e-6);
+        assert_eq!(bs_price(spot, strike, 0.0, vol, "P"), 50.0);
+
+        // Test deltas are in range and backing out the volatility from a price.
+        assert!(bs_delta(spot, strike, years, vol, "C") > 0.0);
+        assert!(bs_delta(spot, strike, years, vol, "P") < 0.0);
+        assert!((implied_vol(call, spot, strike, years, "C").unwrap() - vol).abs() < 1e-4);
+        assert!(implied_vol(40.0, spot, strike, years, "P").is_none());
+    }
+
+    #[test]
+    fn test_stress_calendar() {
+        let position: CalendarPosition = CalendarPosition {
+            strike: 4700.0,
+            right: "C".to_string(),
+            front_days: 0,
+            back_days: 1,
+            credit: 1.0,
+            back_price: 15.0,
+            exit_cost: 0.25,
+        };
+        let result = simulate_calendar(&position, 4700.0, &StressConfig::default()).unwrap();
+
+        // The long leg is never worth less than the short leg's intrinsic value at front expiry.
+        assert!(result.worst >= position.credit - position.exit_cost - 1e-9);
+        assert!(result.p1 <= result.p5 && result.p5 <= result.mean);
+        assert_eq!(result.loss_probability, 0.0);
+
+        // Test the simulation is reproducible for a given seed.
+        let again = simulate_calendar(&position, 4700.0, &StressConfig::default()).unwrap();
+        assert_eq!(result.p1, again.p1);
+    }
+
+    #[test]
+    fn test_calendars_from_positions() {
+        let leg = |conid: i64, position: f64, avg_price: f64, expiry: &str| -> PositionResponse {
+            PositionResponse {
+                conid,
+                position,
+                mkt_price: avg_price,
+                avg_price,
+                mkt_value: avg_price * position * 100.0,
+                put_or_call: Some("C".to_string()),
+                strike: Some(serde_json::json!("4700")),
+                expiry: Some(expiry.to_string()),
+                und_conid: Some(416904),
+            }
+        };
+        let positions: Vec<PositionResponse> = vec![
+            leg(1, -2.0, 16.0, "20240102"),
+            leg(2, 3.0, 15.0, "20240103"),
+            leg(3, 1.0, 20.0, "20240110"),
+        ];
+        let today: chrono::NaiveDate = chrono::NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+
+        // Test the short leg pairs with the nearest later long leg.
+        let calendars = calendars_from_positions(&positions, today);
+        assert_eq!(calendars.len(), 1);
+        let (calendar, quantity) = &calendars[0];
+        assert_eq!((calendar.front_days, calendar.back_days), (0, 1));
+        assert_eq!(calendar.credit, 1.0);
+        assert_eq!(*quantity, 2.0);
+    }
+
+    #[test]
+    fn test_vol_surface() {
+        // Test recovering an exact smile.
+        let points: Vec<(f64, f64)> = [-0.1, -0.05, 0.0, 0.05, 0.1]
+            .iter()
+            .map(|k| (*k, 0.2 - 0.3 * k + 2.0 * k * k))
+            .collect();
+        let fit: [f64; 3] = fit_quadratic(&points).unwrap();
+        assert!((fit[0] - 0.2).abs() < 1e-9 && (fit[1] + 0.3).abs() < 1e-9);
+        assert!(fit_quadratic(&points[..2]).is_none());
+
+        // Build a chain of out of the money calls priced at a flat 20% volatility.
+        let (spot, years): (f64, f64) = (4700.0, 30.0 / 365.0);
+        let call_strikes: Vec<f64> = vec![4700.0, 4750.0, 4800.0, 4850.0, 4900.0];
+        let legs: Vec<(&str, &str, f64, String)> = call_strikes
+            .iter()
+            .map(|strike| ("240201", "C", *strike, format!("240201C{}", strike)))
+            .collect();
+        let mut quotes: std::collections::HashMap<String, Opt> = std::collections::HashMap::new();
+        for strike in &call_strikes {
+            let conid: String = format!("240201C{}", strike);
+            let mkt: f64 = bs_price(spot, *strike, years, 0.2, "C");
+            quotes.insert(
+                conid,
+                Opt {
+                    asz: 10.0,
+                    mkt,
+                    bid: mkt - 0.1,
+                    ask: mkt + 0.1,
+                    open_interest: None,
+                    provenance: Provenance::default(),
+                },
+            );
+        }
+        let mut chain: ChainView = chain(&legs);
+        chain.set_quotes(quotes);
+
+        // Test the surface needs an underlying price.
+        assert!(VolSurface::build(&chain, "240102").is_none());
+        chain.set_underlying_price(Some(spot));
+        let surface: VolSurface = VolSurface::build(&chain, "240102").unwrap();
+        assert!((surface.fitted_vol("240201", 4825.0).unwrap() - 0.2).abs() < 1e-3);
+
+        // Test a sold leg priced on the surface passes, and one rich by five vol points doesn't.
+        let mut calendar: Contender = contender(1.0, 1.0, "240201", 4800.0);
+        calendar.contracts[0].date = "240201".to_string();
+        calendar.contracts[0].mkt_price = bs_price(spot, 4800.0, years, 0.2, "C");
+        assert!(!surface.explains_arb(&calendar, 0.02));
+        calendar.contracts[0].mkt_price = bs_price(spot, 4800.0, years, 0.25, "C");
+        assert!(surface.explains_arb(&calendar, 0.02));
+    }
+
+    #[test]
+    fn test_redact() {
+        // Test account ids in paths and bodies are masked.
+        assert_eq!(
+            redact("/v1/api/portfolio/U1234567/summary"),
+            "/v1/api/portfolio/[ACCOUNT]/summary"
+        );
+        assert_eq!(
+            redact(r#"{"acctId":"DU7654321"}"#),
+            r#"{"acctId":"[ACCOUNT]"}"#
+        );
+
+        // Test conids, short ids and words starting with U are left alone.
+        assert_eq!(redact("conids=416904&fields=31"), "conids=416904&fields=31");
+        assert_eq!(redact("U123 USD U12345X"), "U123 USD U12345X");
+
+        // Test logged lines can name the account by an alias instead.
+        assert_eq!(
+            redact_as("Trading account DU7654321.", "main"),
+            "Trading account main."
+        );
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let allowlist: Vec<String> = vec!["o354".to_string()];
+
+        // Test a benign prompt on the allowlist is confirmed.
+        let reply: serde_json::Value = serde_json::json!([{
+            "id": "abc",
+            "message": ["You are trying to submit an order without having market data for this instrument."],
+            "messageIds": ["o354"]
+        }]);
+        match parse_reply(&reply) {
+            ReplyOutcome::Confirm(reply) => {
+                assert_eq!(reply.reasons, vec![ReplyReason::NoMarketData]);
+                assert!(should_confirm(&reply, &allowlist));
+            }
+            other => panic!("unexpected outcome {:?}", other),
+        }
+
+        // Test a price cap warning is never confirmed, even when allowlisted.
+        let reply: serde_json::Value = serde_json::json!([{
+            "id": "def",
+            "message": ["The following order exceeds the price percentage limit"],
+            "messageIds": ["o163"]
+        }]);
+        match parse_reply(&reply) {
+            ReplyOutcome::Confirm(reply) => {
+                assert_eq!(reply.reasons, vec![ReplyReason::PriceCap]);
+                assert!(!should_confirm(&reply, &["o163".to_string()]));
+
+                // Test the policies, only auto confirm all answers yes to a price cap.
+                assert!(ReplyPolicy::AutoConfirmAll.confirms(&reply));
+                assert!(!ReplyPolicy::ConfirmAllowlist(allowlist.clone()).confirms(&reply));
+                assert!(!ReplyPolicy::NeverConfirm.confirms(&reply));
+            }
+            other => panic!("unexpected outcome {:?}", other),
+        }
+
+        // Test an unknown prompt isn't confirmed without being allowlisted.
+        let reply: serde_json::Value = serde_json::json!([{"id": "ghi", "message": ["Are you sure?"], "messageIds": ["o999"]}]);
+        match parse_reply(&reply) {
+            ReplyOutcome::Confirm(reply) => assert!(!should_confirm(&reply, &allowlist)),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+
+        // Test placed orders and rejections.
+        assert!(matches!(
+            parse_reply(&serde_json::json!([{"order_id": "123", "order_status": "Submitted"}])),
+            ReplyOutcome::Placed(ids) if ids == vec!["123".to_string()]
+        ));
+        assert!(matches!(
+            parse_reply(&serde_json::json!({"error": "Insufficient margin"})),
+            ReplyOutcome::Rejected(_)
+        ));
+    }
+
+    #[test]
+    fn test_decode_response() {
+        // Test ids arrive as strings or numbers, and unknown fields are ignored.
+        let orders: OrdersResponse = decode(
+            "/iserver/account/orders",
+            r#"{"orders": [
+                {"orderId": 101, "status": "Filled", "avgPrice": "1.25", "order_ref": "tbr-1"},
+                {"orderId": "102", "status": "Submitted", "ticker": "SPX"}
+            ], "snapshot": true}"#,
+        )
+        .unwrap();
+        assert_eq!(orders.orders[0].order_id, "101");
+        assert_eq!(orders.orders[0].avg_price.as_deref(), Some("1.25"));
+        assert_eq!(orders.orders[1].order_id, "102");
+        assert!(orders.orders[1].order_ref.is_none());
+
+        // Test a gateway with nothing to report leaves the list out.
+        let orders: OrdersResponse = decode("/iserver/account/orders", "{}").unwrap();
+        assert!(orders.orders.is_empty());
+
+        // Test a missing required field names the endpoint, the field and the body.
+        let error: ResponseError = decode::<OrdersResponse>(
+            "/iserver/account/orders",
+            r#"{"orders": [{"orderId": 101}]}"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(error.endpoint, "/iserver/account/orders");
+        assert!(error.reason.contains("missing field `status`"));
+        assert_eq!(error.snippet, r#"{"orders": [{"orderId": 101}]}"#);
+        assert!(error
+            .to_string()
+            .starts_with("Unexpected response from /iserver/account/orders: missing field"));
+
+        // Test a mistyped id and an execution without its id are rejected.
+        assert!(decode::<OrdersResponse>(
+            "/iserver/account/orders",
+            r#"{"orders": [{"orderId": null, "status": "Filled"}]}"#,
+        )
+        .err()
+        .unwrap()
+        .reason
+        .contains("expected an id"));
+        let error: ResponseError = decode::<Vec<TradeResponse>>(
+            "/iserver/account/trades",
+            r#"[{"conid": 1, "side": "B", "size": 1, "price": "1.5"}]"#,
+        )
+        .err()
+        .unwrap();
+        assert!(error.reason.contains("missing field `execution_id`"));
+
+        // Test an error answered with a 200 doesn't pass for a cancel acknowledgement.
+        assert!(decode::<CancelResponse>(
+            "/iserver/account/order",
+            r#"{"msg": "Request was submitted", "order_id": 101}"#,
+        )
+        .is_ok());
+        assert!(decode::<CancelResponse>(
+            "/iserver/account/order",
+            r#"{"error": "OrderID 101 doesn't exist"}"#,
+        )
+        .is_err());
+
+        // Test an HTML error page is quoted on one line and cut short.
+        let page: String = format!("<html>\n<body>{}</body>\n</html>", "x".repeat(300));
+        let error: ResponseError = decode::<Vec<TradeResponse>>("/iserver/account/trades", &page)
+            .err()
+            .unwrap();
+        assert!(error.snippet.starts_with("<html> <body>xxx"));
+        assert!(error.snippet.ends_with("..."));
+        assert_eq!(error.snippet.chars().count(), 203);
+        assert_eq!(body_snippet("  "), "(empty)");
+    }
+
+    #[test]
+    fn test_account_summary() {
+        // Test the typed fields, with margin missing from the response.
+        let response: PortfolioResponse = serde_json::from_value(serde_json::json!({
+            "equitywithloanvalue": {"amount": 100000.0, "currency": "USD"},
+            "availablefunds": {"amount": 80000.0},
+            "buyingpower": {"amount": 320000.0},
+            "netliquidation": {"amount": 101000.0},
+            "accruedcash": {"amount": 12.0}
+        }))
+        .unwrap();
+        assert_eq!(
+            AccountSummary::from(response),
+            AccountSummary {
+                equity_with_loan: 100000.0,
+                available_funds: 80000.0,
+                buying_power: 320000.0,
+                maintenance_margin: 0.0,
+                net_liquidation: 101000.0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_position_rules() {
+        // Build a chain holding the two legs of a calendar.
+        let chain: ChainView = chain(&[
+            ("240102", "C", 4700.0, "111".to_string()),
+            ("240103", "C", 4700.0, "222".to_string()),
+        ]);
+        let mut calendar: Contender = contender(1.0, 0.5, "240102", 4700.0);
+        calendar.contracts.push(Contract {
+            strike: 4700.0,
+            mkt_price: 0.5,
+            date: "240103".to_string(),
+            type_contract: "C".to_string(),
+            provenance: Provenance::default(),
+        });
+
+        // Test a calendar sells its near leg and buys its far leg.
+        let legs: Vec<(String, i64)> = contender_legs(&calendar, &chain);
+        assert_eq!(legs, vec![("111".to_string(), -1), ("222".to_string(), 1)]);
+
+        // Test the short limit counts what is already held short, and a long position gives room.
+        let positions = |held: &[(&str, f64)]| -> HashMap<String, f64> {
+            held.iter()
+                .map(|(conid, position)| (conid.to_string(), *position))
+                .collect()
+        };
+        assert_eq!(
+            max_fills_within_short_limit(&legs, &positions(&[]), 0.0),
+            None
+        );
+        assert_eq!(
+            max_fills_within_short_limit(&legs, &positions(&[]), 5.0),
+            Some(5)
+        );
+        assert_eq!(
+            max_fills_within_short_limit(&legs, &positions(&[("111", -4.0)]), 5.0),
+            Some(1)
+        );
+        assert_eq!(
+            max_fills_within_short_limit(&legs, &positions(&[("111", -7.0)]), 5.0),
+            Some(0)
+        );
+        assert_eq!(
+            max_fills_within_short_limit(&legs, &positions(&[("111", 3.0)]), 5.0),
+            Some(8)
+        );
+
+        // Test a fill closes held contracts trading against them, up to their size.
+        assert_eq!(reduced_contracts(&legs, &positions(&[("111", 3.0)])), 1.0);
+        assert_eq!(reduced_contracts(&legs, &positions(&[("222", -1.0)])), 1.0);
+        assert_eq!(reduced_contracts(&legs, &positions(&[("111", -3.0)])), 0.0);
+
+        assert!(!PositionRules::default().is_enabled());
+        assert!(PositionRules {
+            prefer_reducing: true,
+            ..PositionRules::default()
+        }
+        .is_enabled());
+    }
+
+    #[test]
+    fn test_progress() {
+        let seconds = |s: u64| -> Duration { Duration::from_secs(s) };
+
+        // Test the time left follows the pace so far.
+        assert_eq!(eta(0, 40, seconds(10)), None);
+        assert_eq!(eta(10, 40, seconds(20)), Some(seconds(60)));
+        assert_eq!(eta(40, 40, seconds(80)), Some(seconds(0)));
+        assert_eq!(format_eta(seconds(42)), "42s");
+        assert_eq!(format_eta(seconds(185)), "3m 05s");
+
+        // Test a line shows the time left while running and the time taken once done.
+        assert_eq!(
+            progress_line(
+                "Loading RUT 2401 chain",
+                "strikes",
+                10,
+                40,
+                "120 contracts",
+                seconds(20)
+            ),
+            "Loading RUT 2401 chain: 10/40 strikes, 120 contracts, ETA 1m 00s."
+        );
+        assert_eq!(
+            progress_line("Priming RUT market data", "batches", 0, 8, "", seconds(1)),
+            "Priming RUT market data: 0/8 batches, ETA unknown."
+        );
+        assert_eq!(
+            progress_line(
+                "Priming RUT market data",
+                "batches",
+                8,
+                8,
+                "attempt 2/10",
+                seconds(12)
+            ),
+            "Priming RUT market data: 8/8 batches, attempt 2/10, took 12s."
+        );
+    }
+
+    #[test]
+    fn test_journal() {
+        let chain: ChainView = chain(&[
+            ("240102", "C", 4700.0, "111".to_string()),
+            ("240103", "C", 4700.0, "222".to_string()),
+        ]);
+        let mut calendar: Contender = contender(1.0, 0.5, "240102", 4700.0);
+        calendar.contracts.push(Contract {
+            strike: 4700.0,
+            mkt_price: 0.5,
+            date: "240103".to_string(),
+            type_contract: "C".to_string(),
+            provenance: Provenance {
+                source: QuoteSource::Ibkr,
+                fetched_at: Some(Utc.with_ymd_and_hms(2024, 1, 2, 15, 0, 0).unwrap()),
+            },
+        });
+        let order: OrderBody = OrderBody {
+            acct_id: "U1234567".to_string(),
+            con_idex: "28812380;;;111/-1,222/1".to_string(),
+            order_type: "LMT".to_string(),
+            listing_exchange: "SMART".to_string(),
+            outside_rth: false,
+            price: -0.45,
+            side: "BUY".to_string(),
+            ticker: "SPX".to_string(),
+            tif: "DAY".to_string(),
+            referrer: "NO_REFERRER_PROVIDED".to_string(),
+            quantity: 1,
+            use_adaptive: false,
+            c_oid: Some("tbr-1-0-0".to_string()),
+        };
+
+        // Test legs are read from the conidex with their intended prices.
+        let legs = journal_legs(&order, &calendar, &chain);
+        assert_eq!(legs.len(), 2);
+        assert_eq!(
+            (legs[0].action.as_str(), legs[0].intended_price),
+            ("SELL", 1.0)
+        );
+        assert_eq!(
+            (legs[1].action.as_str(), legs[1].date.as_str()),
+            ("BUY", "240103")
+        );
+
+        // Test each leg records the feed and fetch time of the quote it was priced from.
+        assert_eq!(
+            (legs[0].source, legs[0].fetched_at.as_deref()),
+            (QuoteSource::Unknown, None)
+        );
+        assert_eq!(
+            (legs[1].source, legs[1].fetched_at.as_deref()),
+            (QuoteSource::Ibkr, Some("2024-01-02T15:00:00+00:00"))
+        );
+        let line: String = serde_json::to_string(&legs[1]).unwrap();
+        assert!(line.contains(r#""source":"ibkr""#));
+
+        // Test status mapping and slippage, positive when the fill is worse.
+        assert_eq!(final_status("Filled"), Some("filled"));
+        assert_eq!(final_status("Submitted"), None);
+        assert_eq!(slippage("B", 0.55, 0.5), 0.05);
+        assert_eq!(slippage("S", 0.95, 1.0), 0.05);
+
+        // Test status lines feed the historical fill model.
+        let line: String = serde_json::to_string(&JournalEntry::Status {
+            time: "2024-01-02T15:00:00Z".to_string(),
+            order_id: "1".to_string(),
+            type_spread: "Calendar".to_string(),
+            status: "filled".to_string(),
+            avg_price: Some(-0.45),
+        })
+        .unwrap();
+        assert!(line.contains(r#""event":"status""#));
+        assert_eq!(
+            parse_fill_rates(vec![line].into_iter()).get("Calendar"),
+            Some(&(2.0 / 3.0))
+        );
+    }
+
+    #[test]
+    fn test_line_cipher() {
+        let cipher: LineCipher = LineCipher::new("passphrase", b"salt").unwrap();
+        let line: &str = r#"{"event":"submitted","account":"U1234567"}"#;
+        let sealed: String = cipher.encrypt(line).unwrap();
+        assert!(sealed.starts_with("enc:") && !sealed.contains("U1234567"));
+        assert_ne!(cipher.encrypt(line).unwrap(), sealed);
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), line);
+        assert_eq!(cipher.decrypt(line).unwrap(), line);
+
+        // The wrong key, the wrong salt or a tampered line fails rather than reading garbage.
+        let other = |passphrase: &str, salt: &[u8]| LineCipher::new(passphrase, salt).unwrap();
+        assert!(other("other", b"salt").decrypt(&sealed).is_err());
+        assert!(other("passphrase", b"pepper").decrypt(&sealed).is_err());
+        assert_eq!(other("passphrase", b"salt").decrypt(&sealed).unwrap(), line);
+        let mut tampered: Vec<char> = sealed.chars().collect();
+        let last: usize = tampered.len() - 3;
+        tampered[last] = if tampered[last] == 'A' { 'B' } else { 'A' };
+        assert!(cipher
+            .decrypt(&tampered.into_iter().collect::<String>())
+            .is_err());
+        assert!(cipher.decrypt("enc:AAAA").is_err());
+
+        // Test the salt is created once and read back after.
+        let path: std::path::PathBuf =
+            std::env::temp_dir().join(format!("tbr-salt-{}", std::process::id()));
+        let path: &str = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        let salt: Vec<u8> = load_or_create_salt(path).unwrap();
+        assert_eq!(salt.len(), 16);
+        assert_eq!(load_or_create_salt(path).unwrap(), salt);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_health_round_trip() {
+        let path: std::path::PathBuf =
+            std::env::temp_dir().join(format!("tbr-health-{}.json", std::process::id()));
+        let path: &str = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        // Test a missing file is an error rather than a default snapshot.
+        assert!(read_health_from(path).is_err());
+
+        // Test what `--health` reads back is what the bot wrote, stamped with the write time.
+        let mut health: Health = Health {
+            state: "scanning".to_string(),
+            last_scan_time: Some("2024-01-02T15:00:00+00:00".to_string()),
+            live_order_count: 2,
+            account: Some(AccountSummary {
+                equity_with_loan: 100000.0,
+                available_funds: 50000.0,
+                buying_power: 200000.0,
+                maintenance_margin: 25000.0,
+                net_liquidation: 101000.5,
+            }),
+            active_gateway: Some("primary".to_string()),
+            ..Default::default()
+        };
+        write_health_to(&mut health, path).unwrap();
+        assert!(!health.updated_at.is_empty());
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+        let read: Health = read_health_from(path).unwrap();
+        assert_eq!(
+            serde_json::to_value(&read).unwrap(),
+            serde_json::to_value(&health).unwrap()
+        );
+        assert_eq!(read.state, "scanning");
+        assert_eq!(read.account, health.account);
+
+        // Test a later write replaces the snapshot.
+        health.state = "exited".to_string();
+        write_health_to(&mut health, path).unwrap();
+        assert_eq!(read_health_from(path).unwrap().state, "exited");
+        let _ = std::fs::remove_file(path);
+
+        // Test `--health` prints the balances as *** when they are masked, and only them.
+        let report: serde_json::Value =
+            serde_json::from_str(&health_report(&health, true)).unwrap();
+        assert_eq!(report["account"]["net_liquidation"], "***");
+        assert_eq!(report["account"]["available_funds"], "***");
+        assert_eq!(report["state"], "exited");
+        assert_eq!(report["live_order_count"], 2);
+        let report: serde_json::Value =
+            serde_json::from_str(&health_report(&health, false)).unwrap();
+        assert_eq!(report["account"]["net_liquidation"], 101000.5);
+        health.account = None;
+        let report: serde_json::Value =
+            serde_json::from_str(&health_report(&health, true)).unwrap();
+        assert!(report["account"].is_null());
+    }
+
+    // An in-memory stand-in for the OS keyring.
+    #[derive(Default)]
+    struct MemoryStore(std::cell::RefCell<std::collections::HashMap<String, String>>);
+
+    impl SecretStore for MemoryStore {
+        fn get(&self, key: &str) -> Result<String, Box<dyn std::error::Error>> {
+            self.0
+                .borrow()
+                .get(key)
+                .cloned()
+                .ok_or("no such secret".into())
+        }
+
+        fn set(&self, key: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+            self.0
+                .borrow_mut()
+                .insert(key.to_string(), value.to_string());
+            Ok(())
+        }
+
+        fn delete(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+            self.0
+                .borrow_mut()
+                .remove(key)
+                .map(|_| ())
+                .ok_or("no such secret".into())
+        }
+    }
+
+    #[test]
+    fn test_secrets() {
+        let store: MemoryStore = MemoryStore::default();
+        let key: String = format!("TBR_TEST_SECRET_{}", std::process::id());
+        let args = |args: &[&str]| -> Vec<String> { args.iter().map(|s| s.to_string()).collect() };
+
+        // Test `secrets set` stores the trimmed line read from input.
+        assert_eq!(
+            run_secrets_command_with(&store, &args(&["set", &key]), &mut "hunter2\n".as_bytes())
+                .unwrap(),
+            format!("Stored {} in the OS keyring", key)
+        );
+        assert_eq!(store.get(&key).unwrap(), "hunter2");
+
+        // Test the store is the fallback, the environment/.env wins when both are set.
+        assert_eq!(get_secret_from(&store, &key).unwrap(), "hunter2");
+        std::env::set_var(&key, "from-env");
+        assert_eq!(get_secret_from(&store, &key).unwrap(), "from-env");
+        std::env::remove_var(&key);
+
+        // Test `secrets delete` removes it, leaving the lookup to fail.
+        assert!(
+            run_secrets_command_with(&store, &args(&["delete", &key]), &mut "".as_bytes()).is_ok()
+        );
+        assert!(get_secret_from(&store, &key).is_err());
+        assert!(
+            run_secrets_command_with(&store, &args(&["delete", &key]), &mut "".as_bytes()).is_err()
+        );
+
+        // Test a missing key or unknown action prints the usage.
+        assert!(run_secrets_command_with(&store, &args(&["set"]), &mut "".as_bytes()).is_err());
+        assert!(
+            run_secrets_command_with(&store, &args(&["get", &key]), &mut "".as_bytes()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_pnl_sample() {
+        // Test the CSV row matches the header's column order.
+        let sample: PnlSample = PnlSample {
+            time: "2024-01-02T15:00:00+00:00".to_string(),
+            equity_with_loan: 100000.0,
+            net_liquidation: 101000.5,
+            pnl: 1000.5,
+            positions_value: -250.0,
+        };
+        assert_eq!(
+            sample.to_csv(),
+            "2024-01-02T15:00:00+00:00,100000.00,101000.50,1000.50,-250.00"
+        );
+    }
+
+    #[test]
+    fn test_strategy_stats() {
+        let status = |order_id: &str, type_spread: &str, status: &str| -> String {
+            format!(
+                r#"{{"event":"status","time":"2024-02-06T15:00:00+00:00","order_id":"{}","type_spread":"{}","status":"{}"}}"#,
+                order_id, type_spread, status
+            )
+        };
+        let execution = |order_id: &str, slippage: f64| -> String {
+            format!(
+                r#"{{"event":"execution","order_id":"{}","type_spread":"Boxspread","slippage":{}}}"#,
+                order_id, slippage
+            )
+        };
+        let lines: Vec<String> = vec![
+            status("1", "Boxspread", "cancelled"),
+            execution("2", 0.5),
+            execution("2", 0.3),
+            status("2", "Boxspread", "filled"),
+            status("3", "Boxspread", "cancelled"),
+            status("4", "Boxspread", "cancelled"),
+            status("5", "Calendar", "filled"),
+        ];
+
+        // Test the window keeps only the most recent orders per strategy.
+        let since: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 2, 6, 14, 30, 0).unwrap();
+        let stats = strategy_stats(lines.clone().into_iter(), 3, since);
+        let boxspread = &stats["Boxspread"];
+        assert_eq!((boxspread.orders, boxspread.fills), (3, 1));
+        assert!((boxspread.avg_slippage.unwrap() - 0.4).abs() < 1e-9);
+
+        // Test the limits, which only apply to a full window.
+        assert!(boxspread.degraded(3, 0.5, 0.0));
+        assert!(boxspread.degraded(3, 0.0, 0.25));
+        assert!(!boxspread.degraded(3, 0.2, 0.5));
+        assert!(!stats["Calendar"].degraded(3, 0.9, 0.0));
+        assert_eq!(strategy_code("Boxspread"), Some("3"));
+
+        // Test a strategy disabled one session recovers the next, as it writes no new orders
+        // while disabled and the earlier session's orders no longer count.
+        let next_session: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 2, 7, 14, 30, 0).unwrap();
+        let stats = strategy_stats(lines.clone().into_iter(), 3, next_session);
+        assert!(stats.is_empty());
+        let mut lines: Vec<String> = lines;
+        lines.push(status("6", "Boxspread", "filled").replace("2024-02-06", "2024-02-07"));
+        let stats = strategy_stats(lines.into_iter(), 3, next_session);
+        assert_eq!(
+            (stats["Boxspread"].orders, stats["Boxspread"].fills),
+            (1, 1)
+        );
+        assert!(!stats["Boxspread"].degraded(3, 0.5, 0.0));
+    }
+
+    #[test]
+    fn test_session_stats() {
+        let lines: Vec<String> = vec![
+            r#"{"event":"submitted","time":"2024-02-05T20:00:00+00:00","type_spread":"Boxspread"}"#,
+            r#"{"event":"submitted","time":"2024-02-06T15:00:00+00:00","type_spread":"Boxspread"}"#,
+            r#"{"event":"submitted","time":"2024-02-06T15:01:00+00:00","type_spread":"Boxspread"}"#,
+            r#"{"event":"execution","time":"2024-02-06T15:02:00+00:00","type_spread":"Boxspread","slippage":0.2}"#,
+            r#"{"event":"execution","time":"2024-02-06T15:02:00+00:00","type_spread":"Boxspread","slippage":0.4}"#,
+            r#"{"event":"status","time":"2024-02-06T15:03:00+00:00","type_spread":"Boxspread","status":"filled"}"#,
+            r#"{"event":"status","time":"2024-02-06T15:04:00+00:00","type_spread":"Boxspread","status":"cancelled"}"#,
+            r#"{"event":"status","time":"2024-02-06T15:04:00+00:00","type_spread":"Calendar","status":"rejected"}"#,
+            r#"{"event":"submitted","time":"2024-02-06T15:05:00+00:00","order_id":"7","type_spread":"Calendar","modeled_edge":0.3,"legs":[{"ratio":-1,"intended_price":1.5},{"ratio":1,"intended_price":1.2}]}"#,
+            r#"{"event":"status","time":"2024-02-06T15:06:00+00:00","order_id":"7","type_spread":"Calendar","status":"filled","avg_price":-0.25}"#,
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        // Test only entries since the session started are counted, per strategy.
+        let since: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 2, 6, 14, 30, 0).unwrap();
+        let stats = session_stats(lines.into_iter(), since);
+        let boxspread = &stats["Boxspread"];
+        assert_eq!(
+            (
+                boxspread.submitted,
+                boxspread.filled,
+                boxspread.cancelled,
+                boxspread.rejected
+            ),
+            (2, 1, 1, 0)
+        );
+        assert!((boxspread.avg_slippage().unwrap() - 0.3).abs() < 1e-9);
+        assert_eq!(stats["Calendar"].rejected, 1);
+        assert_eq!(stats["Calendar"].avg_slippage(), None);
+
+        // Test a credit calendar filling 0.05 short of its mid keeps 0.25 of a 0.30 edge.
+        assert!((stats["Calendar"].edge_capture().unwrap() - 0.25 / 0.3).abs() < 1e-9);
+        assert_eq!(boxspread.edge_capture(), None);
+    }
+
+    #[test]
+    fn test_order_placement() {
+        // Test joining a displayed bid below the limit, including a credit calendar.
+        assert_eq!(join_price(1.20, Some(1.10)), 1.10);
+        assert_eq!(join_price(-0.45, Some(-0.60)), -0.60);
+
+        // Test never joining above the limit or without a displayed bid.
+        assert_eq!(join_price(1.20, Some(1.25)), 1.20);
+        assert_eq!(join_price(1.20, None), 1.20);
+
+        // Test escalating one step at a time, capped at the limit.
+        assert_eq!(escalated_price(-0.60, -0.45, 0.05), -0.55);
+        assert_eq!(escalated_price(-0.50, -0.45, 0.10), -0.45);
+        assert_eq!(escalated_price(1.10, 1.20, 0.0), 1.20);
+
+        // Test checking a limit against the combo's own quote.
+        assert!(combo_confirms(-0.45, Some(-0.60)));
+        assert!(!combo_confirms(-0.45, Some(-0.30)));
+        assert!(combo_confirms(-0.45, None));
+        assert_eq!(cap_to_ask(1.20, Some(1.15)), 1.15);
+        assert_eq!(cap_to_ask(1.20, Some(1.30)), 1.20);
+    }
+
+    // Function that checks an order body serializes to its golden file under tests/golden/orders.
+    fn assert_golden(actual: &impl serde::Serialize, golden: &str) {
+        let actual: serde_json::Value = serde_json::to_value(actual).unwrap();
+        let golden: serde_json::Value = serde_json::from_str(golden).unwrap();
+        assert_eq!(
+            actual,
+            golden,
+            "order body differs from its golden file:\n{}",
+            serde_json::to_string_pretty(&actual).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_order_bodies() {
+        // Build a chain with calls 101-103 and puts 201-203 on the first expiry, and calls
+        // 111-113 on the second, so each conid names its leg.
+        let mut legs: Vec<(&str, &str, f64, String)> = Vec::new();
+        for (date, right, first_conid) in [
+            ("240102", "C", 101),
+            ("240102", "P", 201),
+            ("240103", "C", 111),
+        ] {
+            for (i, strike) in [4700.0, 4705.0, 4710.0].into_iter().enumerate() {
+                legs.push((date, right, strike, (first_conid + i).to_string()));
+            }
+        }
+        let chain: ChainView = chain(&legs);
+        let account_id: Option<String> = Some("U1234567".to_string());
+        let ticker: Option<String> = Some("SPX".to_string());
+        let discount: Option<f64> = Some(0.1);
+        let spread = |type_spread: &str, arb_val: f64, legs: &[(f64, &str, &str, f64)]| Contender {
+            arb_val,
+            avg_ask: 1.0,
+            type_spread: type_spread.to_string(),
+            exp_date: "240102".to_string(),
+            rank_value: 1.0,
+            regime_value: 0.0,
+            contracts: legs
+                .iter()
+                .map(|(strike, right, date, mkt_price)| Contract {
+                    strike: *strike,
+                    mkt_price: *mkt_price,
+                    date: date.to_string(),
+                    type_contract: right.to_string(),
+                    provenance: Provenance::default(),
+                })
+                .collect(),
+        };
+
+        // Test a calendar sells the near leg and buys the far one for a credit of the arb value
+        // less the discount.
+        let calendar: Contender = spread(
+            "Calendar",
+            0.57,
+            &[(4705.0, "C", "240102", 10.0), (4705.0, "C", "240103", 12.0)],
+        );
+        assert_golden(
+            &build_calendar_order(&calendar, 3, &account_id, &ticker, &chain, discount),
+            include_str!("../tests/golden/orders/calendar.json"),
+        );
+
+        // Test a butterfly is ordered as a bull and a bear vertical, each selling the body.
+        let butterfly: Contender = spread(
+            "Butterfly",
+            0.4,
+            &[
+                (4700.0, "C", "240102", 20.0),
+                (4705.0, "C", "240102", 15.3),
+                (4710.0, "C", "240102", 11.0),
+            ],
+        );
+        assert_golden(
+            &build_butterfly_bull_order(&butterfly, 3, &account_id, &ticker, &chain, discount),
+            include_str!("../tests/golden/orders/butterfly_bull.json"),
+        );
+        assert_golden(
+            &build_butterfly_bear_order(&butterfly, 3, &account_id, &ticker, &chain, discount),
+            include_str!("../tests/golden/orders/butterfly_bear.json"),
+        );
+
+        // Test a box is ordered as a put and a call vertical, legs in (put, call, call, put)
+        // order.
+        let boxspread: Contender = spread(
+            "Boxspread",
+            0.2,
+            &[
+                (4700.0, "P", "240102", 5.0),
+                (4700.0, "C", "240102", 20.0),
+                (4705.0, "C", "240102", 15.3),
+                (4705.0, "P", "240102", 6.1),
+            ],
+        );
+        assert_golden(
+            &build_boxspread_put_order(&boxspread, 3, &account_id, &ticker, &chain, discount),
+            include_str!("../tests/golden/orders/boxspread_put.json"),
+        );
+        assert_golden(
+            &build_boxspread_call_order(&boxspread, 3, &account_id, &ticker, &chain, discount),
+            include_str!("../tests/golden/orders/boxspread_call.json"),
+        );
+
+        // Test a negative market order quantity sells.
+        assert_golden(
+            &build_market_order(&account_id, &ticker, "101", -3),
+            include_str!("../tests/golden/orders/market.json"),
+        );
+
+        // Test a roll buys back the near contract and sells the next expiry's, the client order
+        // id naming the near conid.
+        let mut roll: OrderBody = build_roll_order(&account_id, &ticker, "101", "111", 2, 0.35);
+        let c_oid: String = roll.c_oid.take().unwrap();
+        assert!(c_oid.starts_with("tbr-roll-") && c_oid.ends_with("-101"));
+        assert_golden(&roll, include_str!("../tests/golden/orders/roll.json"));
+
+        // Test a laddered butterfly request orders both verticals per rung, each rung giving up
+        // its step, tagged and indexed back to the contender.
+        let order_tags: OrderTags = OrderTags {
+            referrer: "tbr".to_string(),
+            butterfly: Some("fly".to_string()),
+            ..OrderTags::default()
+        };
+        let mut request_data: RequestDataStruct = build_request_data(
+            &[butterfly],
+            &[ladder_rungs(3, 2, 0.05)],
+            &account_id,
+            &ticker,
+            &chain,
+            discount,
+            &order_tags,
+        );
+        assert_eq!(request_data.contender_index, vec![0, 0, 0, 0]);
+        for (i, order) in request_data.orders.iter_mut().enumerate() {
+            let c_oid: String = order.c_oid.take().unwrap();
+            assert!(c_oid.starts_with("tbr-fly-"));
+            assert!(c_oid.ends_with(&format!("-0-{}", i)));
+        }
+        assert_golden(
+            &request_data,
+            include_str!("../tests/golden/orders/request_data.json"),
+        );
+    }
+
+    #[test]
+    fn test_net_orders() {
+        let order = |con_idex: &str, side: &str, quantity: i32, price: f64| -> OrderBody {
+            OrderBody {
+                acct_id: "U1234567".to_string(),
+                con_idex: con_idex.to_string(),
+                order_type: "LMT".to_string(),
+                listing_exchange: "SMART".to_string(),
+                outside_rth: false,
+                price,
+                side: side.to_string(),
+                ticker: "SPX".to_string(),
+                tif: "DAY".to_string(),
+                referrer: "NO_REFERRER_PROVIDED".to_string(),
+                quantity,
+                use_adaptive: false,
+                c_oid: Some(format!("tbr-{}", con_idex)),
+            }
+        };
+        let orders: Vec<OrderBody> = vec![
+            order("28812380;;;111/-1,222/1", "BUY", 2, 1.0),
+            order("28812380;;;444/1,555/-1", "BUY", 1, 0.3),
+            order("28812380;;;222/-1,333/1", "BUY", 2, 0.5),
+            order("28812380;;;333/-1,666/1", "BUY", 1, 0.2),
+        ];
+
+        // Test the contender selling what another buys is merged into one combo at the combined
+        // limit, and orders that don't oppose pass through in batch order.
+        let netted: Vec<NettedOrder> = net_orders(&orders, &[true, true, true, false]);
+        assert_eq!(
+            netted
+                .iter()
+                .map(|netted| (
+                    netted.order.con_idex.as_str(),
+                    netted.order.quantity,
+                    netted.order.price,
+                    netted.members.clone()
+                ))
+                .collect::<Vec<(&str, i32, f64, Vec<usize>)>>(),
+            vec![
+                ("28812380;;;111/-1,333/1", 2, 1.5, vec![0, 2]),
+                ("28812380;;;444/1,555/-1", 1, 0.3, vec![1]),
+                ("28812380;;;333/-1,666/1", 1, 0.2, vec![3]),
+            ]
+        );
+        assert!(netted[0].is_netted() && !netted[1].is_netted());
+        assert!((netted_price(&orders, &[0, 2], &[1.2, 0.3, 0.6, 0.2], 2) - 1.8).abs() < 1e-9);
+
+        // Test a sell nets against a buy, reducing to the smallest ratios, and orders that would
+        // cancel out to fewer than two legs aren't merged.
+        let netted: Vec<NettedOrder> = net_orders(
+            &[
+                order("28812380;;;111/-1,222/1", "BUY", 4, 1.0),
+                order("28812380;;;222/1,333/-1", "SELL", 2, 0.25),
+            ],
+            &[true, true],
+        );
+        assert_eq!(netted.len(), 1);
+        assert_eq!(netted[0].order.con_idex, "28812380;;;111/-2,222/1,333/1");
+        assert_eq!((netted[0].order.quantity, netted[0].order.price), (2, 1.75));
+        let netted: Vec<NettedOrder> = net_orders(
+            &[
+                order("28812380;;;111/-1,222/1", "BUY", 1, 1.0),
+                order("28812380;;;111/1,222/-1", "BUY", 1, -0.9),
+            ],
+            &[true, true],
+        );
+        assert_eq!(netted.len(), 2);
+    }
+
+    #[test]
+    fn test_ladder_rungs() {
+        // Test fills split evenly over the rungs, earlier rungs taking the remainder.
+        assert_eq!(
+            ladder_rungs(8, 3, 0.05),
+            vec![(3, 0.0), (3, 0.05), (2, 0.1)]
+        );
+
+        // Test never more rungs than fills.
+        assert_eq!(ladder_rungs(2, 3, 0.05), vec![(1, 0.0), (1, 0.05)]);
+
+        // Test no step, one rung or a single fill keep one order at the discount.
+        assert_eq!(ladder_rungs(9, 3, 0.0), vec![(9, 0.0)]);
+        assert_eq!(ladder_rungs(9, 1, 0.05), vec![(9, 0.0)]);
+        assert_eq!(ladder_rungs(1, 3, 0.05), vec![(1, 0.0)]);
+
+        // Test a filled rung cancels the rest of its ladder, but not other combos or the
+        // contender's other leg order.
+        let live = |order_id: &str, group: Option<&str>| -> LiveOrder {
+            LiveOrder {
+                order_id: order_id.to_string(),
+                submitted_at: std::time::Instant::now(),
+                order: None,
+                limit_price: 0.0,
+                priced_at: std::time::Instant::now(),
+                ladder_group: group.map(String::from),
+            }
+        };
+        let live_orders: Vec<LiveOrder> = vec![
+            live("1", Some("0-bull")),
+            live("2", Some("0-bear")),
+            live("3", Some("0-bull")),
+            live("4", Some("0-bear")),
+            live("5", Some("1-bull")),
+            live("6", None),
+        ];
+        let filled: HashSet<String> = ["1".to_string()].into_iter().collect();
+        assert_eq!(
+            ladder_siblings(&live_orders, &filled),
+            vec!["3".to_string()]
+        );
+        assert!(ladder_siblings(&live_orders, &HashSet::new()).is_empty());
+
+        // Test orders are cancelled past their lifetime or once their combo's bid moves away by
+        // more than the drift, while orders without a quote or a body stay working.
+        let now: std::time::Instant = std::time::Instant::now();
+        let working = |order_id: &str, age: u64, conidex: &str, price: f64| -> LiveOrder {
+            let mut order: OrderBody = build_market_order(
+                &Some("U1".to_string()),
+                &Some("SPX".to_string()),
+                conidex,
+                1,
+            );
+            order.price = price;
+            LiveOrder {
+                submitted_at: now - std::time::Duration::from_secs(age),
+                order: Some(order),
+                ..live(order_id, None)
+            }
+        };
+        let orders: Vec<LiveOrder> = vec![
+            working("1", 40, "a", -0.50),
+            working("2", 5, "a", -0.50),
+            working("3", 5, "b", -0.50),
+            working("4", 5, "c", -0.50),
+            live("5", None),
+        ];
+        let combo_bids: HashMap<String, f64> = [("a".to_string(), -0.40), ("b".to_string(), -0.25)]
+            .into_iter()
+            .collect();
+        let ids = |orders: &[LiveOrder]| -> Vec<String> {
+            orders.iter().map(|order| order.order_id.clone()).collect()
+        };
+        let (expired, live_orders) = expired_orders(orders, now, 30, 0.2, &combo_bids);
+        assert_eq!(ids(&expired), vec!["1", "3"]);
+        assert_eq!(ids(&live_orders), vec!["2", "4", "5"]);
+        let (expired, _) = expired_orders(live_orders, now, 0, 0.05, &combo_bids);
+        assert_eq!(ids(&expired), vec!["2"]);
+    }
+
+    #[test]
+    fn test_round_to_tick() {
+        let spx: TickRule = TickRule {
+            tick: 0.01,
+            threshold: 3.0,
+            tick_above: 0.05,
+        };
+
+        // Test cents below the threshold and nickels at and above it, including credits.
+        assert_eq!(round_to_tick(2.987, &spx), 2.98);
+        assert_eq!(round_to_tick(3.27, &spx), 3.25);
+        assert_eq!(round_to_tick(3.25, &spx), 3.25);
+        assert_eq!(round_to_tick(-3.27, &spx), -3.30);
+
+        // Test the default rule leaves cent prices alone.
+        assert_eq!(round_to_tick(1.23, &TickRule::default()), 1.23);
+        assert_eq!(round_to_tick(-0.45, &TickRule::default()), -0.45);
+    }
+
+    #[test]
+    fn test_replay_decision() {
+        let quote = |strike: f64, mkt: f64, asz: f64| -> LegQuote {
+            LegQuote {
+                strike,
+                right: "C".to_string(),
+                date: "240119".to_string(),
+                bid: mkt - 0.05,
+                ask: mkt + 0.05,
+                mkt,
+                asz,
+                source: QuoteSource::Unknown,
+                fetched_at: None,
+                open_interest: None,
+            }
+        };
+        let decision: String = serde_json::to_string(&JournalEntry::Decision {
+            time: "2024-01-10T15:00:00+00:00".to_string(),
+            order_id: "123".to_string(),
+            ticker: "SPX".to_string(),
+            type_spread: "Butterfly".to_string(),
+            exp_date: "240119".to_string(),
+            scan_date: "240110".to_string(),
+            arb_threshold: 0.15,
+            regime_value: 14.2,
+            strike_dif: 5.0,
+            mean_strike: 4750.0,
+            underlying_price: Some(4751.0),
+            atm_vol: Some(0.15),
+            rank_value: 3.2,
+            profit_gate: ProfitGate::default(),
+            leg_filters: default_leg_filters(),
+            quotes: vec![
+                quote(4745.0, 12.10, 10.0),
+                quote(4750.0, 9.20, 0.0),
+                quote(4755.0, 6.10, 4.0),
+            ],
+        })
+        .unwrap();
+
+        // Test finding the decision among other journal lines.
+        let lines: Vec<String> = vec![
+            r#"{"event":"status","time":"t","order_id":"123","type_spread":"Butterfly","status":"filled","avg_price":null}"#.to_string(),
+            decision,
+        ];
+        let entry: JournalEntry = find_decision(lines.into_iter(), "123").unwrap();
+
+        // Test replaying the checks with the numbers that decided them.
+        let checks: Vec<FilterCheck> = replay_decision(&entry).unwrap();
+        assert_eq!(checks.len(), 6);
+        assert_eq!(checks[0].name, "arb value");
+        assert!(checks[0].passed);
+        assert_eq!(checks[0].detail, "2 * 9.20 - (12.10 + 6.10) = 0.20 >= 0.15");
+        assert!(!checks[2].passed);
+        assert!(checks[3].passed && checks[4].passed);
+        assert_eq!(checks[5].detail, "0.20 > 0.00");
+        assert_eq!(checks[2].shortfall, None);
+    }
+
+    #[test]
+    fn test_near_misses() {
+        let quote = |strike: f64, mkt: f64| -> LegQuote {
+            LegQuote {
+                strike,
+                right: "C".to_string(),
+                date: "240119".to_string(),
+                bid: mkt - 0.05,
+                ask: mkt + 0.05,
+                mkt,
+                asz: 10.0,
+                source: QuoteSource::Unknown,
+                fetched_at: None,
+                open_interest: None,
+            }
+        };
+        let context: ScanContext = ScanContext {
+            scan_date: "240110".to_string(),
+            arb_threshold: 0.15,
+            strike_dif: 5.0,
+            mean_strike: 4750.0,
+            underlying_price: Some(4751.0),
+            atm_vol: None,
+            profit_gate: ProfitGate::default(),
+            scanned_at: None,
+            leg_filters: default_leg_filters(),
+        };
+
+        // Test a butterfly 0.03 short of the arb threshold fails only that check.
+        let checks: Vec<FilterCheck> = scanner_checks(
+            "Butterfly",
+            &[
+                quote(4745.0, 12.10),
+                quote(4750.0, 9.16),
+                quote(4755.0, 6.10),
+            ],
+            &context,
+        )
+        .unwrap();
+        let failed: Vec<&FilterCheck> = checks.iter().filter(|check| !check.passed).collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].name, "arb value");
+        assert!((failed[0].shortfall.unwrap() - 0.03).abs() < 1e-9);
+
+        // Test grouping journaled near misses and the threshold sensitivity.
+        let near_miss = |filter: &str, shortfall: &str| -> String {
+            format!(
+                r#"{{"event":"near_miss","type_spread":"Butterfly","filter":"{}","shortfall":{}}}"#,
+                filter, shortfall
+            )
+        };
+        let lines: Vec<String> = vec![
+            near_miss("arb value", "0.01"),
+            near_miss("arb value", "0.03"),
+            near_miss("arb value", "0.2"),
+            near_miss("leg bids", "null"),
+            r#"{"event":"status","type_spread":"Butterfly","status":"filled"}"#.to_string(),
+        ];
+        let misses = near_misses(lines.into_iter());
+        let arb = &misses[&("Butterfly".to_string(), "arb value".to_string())];
+        assert_eq!(arb.count, 3);
+        assert_eq!(
+            (arb.gained(0.01), arb.gained(0.05), arb.gained(0.25)),
+            (1, 2, 3)
+        );
+        assert_eq!(
+            misses[&("Butterfly".to_string(), "leg bids".to_string())].count,
+            1
+        );
+        let report: String = near_miss_report(&misses);
+        assert!(report.contains(
+            "Butterfly arb value: 3, threshold lowered by 0.01: +1, 0.02: +1, 0.05: +2, 0.10: +2, 0.25: +3"
+        ));
+        assert!(report.contains("Butterfly leg bids: 1\n"));
+    }
+
+    #[test]
+    fn test_leg_filters() {
+        // Test parsing steps in order, with a spread scope and invalid steps skipped.
+        let filters =
+            parse_leg_filters("max_spread=0.5; calendar:strike_window=200;bogus=1;min_bid=x");
+        assert_eq!(filters.len(), 2);
+        assert_eq!(filters[0].kind, LegFilterKind::MaxSpread);
+        assert_eq!(filters[1].spread.as_deref(), Some("calendar"));
+        assert!(filters[1].applies_to("Calendar") && !filters[1].applies_to("Butterfly"));
+        assert_eq!(default_leg_filters().len(), 3);
+
+        let quote = |strike: f64, bid: f64, ask: f64, fetched_at: &str, oi: Option<f64>| LegQuote {
+            strike,
+            right: "C".to_string(),
+            date: "240119".to_string(),
+            bid,
+            ask,
+            mkt: (bid + ask) / 2.0,
+            asz: 10.0,
+            source: QuoteSource::Ibkr,
+            fetched_at: Some(fetched_at.to_string()),
+            open_interest: oi,
+        };
+        let mut context: ScanContext = ScanContext {
+            scan_date: "240110".to_string(),
+            arb_threshold: 0.15,
+            strike_dif: 5.0,
+            mean_strike: 4750.0,
+            underlying_price: Some(4751.0),
+            atm_vol: None,
+            profit_gate: ProfitGate::default(),
+            scanned_at: Some("2024-01-10T15:00:10+00:00".parse().unwrap()),
+            leg_filters: parse_leg_filters(
+                "min_bid=1;max_spread=0.5;max_age=5;min_open_interest=100;strike_window=200",
+            ),
+        };
+        let quotes: Vec<LegQuote> = vec![
+            quote(4745.0, 2.0, 2.4, "2024-01-10T15:00:08+00:00", Some(500.0)),
+            quote(4990.0, 1.5, 2.2, "2024-01-10T15:00:00+00:00", None),
+        ];
+
+        // Test the first failing filter in pipeline order rejects the candidate.
+        let rejection = first_rejection("Calendar", &quotes, &context).unwrap();
+        assert_eq!(rejection.kind, LegFilterKind::MaxSpread);
+        assert_eq!(rejection.kind.counter(), "filter_rejected_max_spread");
+
+        // Test the quote age and strike window, with unknown open interest passing.
+        context.leg_filters = parse_leg_filters("max_age=5;min_open_interest=100");
+        let rejection = first_rejection("Calendar", &quotes, &context).unwrap();
+        assert_eq!(rejection.kind, LegFilterKind::MaxAge);
+        let check = rejection.check(&quotes, &context);
+        assert_eq!(check.detail, "[2.0s, 10.0s] <= 5s");
+        assert_eq!(check.shortfall, Some(5.0));
+        context.leg_filters = parse_leg_filters("min_open_interest=100;strike_window=200");
+        assert_eq!(
+            first_rejection("Calendar", &quotes, &context).unwrap().kind,
+            LegFilterKind::StrikeWindow
+        );
+        context.leg_filters =
+            parse_leg_filters("min_open_interest=100;butterfly:strike_window=200");
+        assert!(first_rejection("Calendar", &quotes, &context).is_none());
+
+        // Test bounding strikes by a percent of spot, falling back to the mean strike.
+        context.leg_filters = parse_leg_filters("strike_pct=10");
+        assert!(first_rejection("Calendar", &quotes, &context).is_none());
+        context.leg_filters = parse_leg_filters("strike_pct=5");
+        let rejection = first_rejection("Calendar", &quotes, &context).unwrap();
+        assert_eq!(rejection.kind, LegFilterKind::StrikePct);
+        assert_eq!(rejection.kind.counter(), "filter_rejected_strike_pct");
+        let check = rejection.check(&quotes, &context);
+        assert!((check.shortfall.unwrap() - 1.45).abs() < 1e-9);
+        context.underlying_price = None;
+        assert_eq!(
+            first_rejection("Calendar", &quotes, &context)
+                .unwrap()
+                .check(&quotes, &context)
+                .detail,
+            "|[4745, 4990] - 4750.0| <= 237.5"
+        );
+        context.underlying_price = Some(4751.0);
+
+        // Test bounding strikes by the expected move to expiry, passing without a volatility.
+        context.leg_filters = parse_leg_filters("strike_move=2");
+        assert!(first_rejection("Calendar", &quotes, &context).is_none());
+        context.atm_vol = Some(0.15);
+        let rejection = first_rejection("Calendar", &quotes, &context).unwrap();
+        assert_eq!(rejection.kind, LegFilterKind::StrikeMove);
+        assert_eq!(rejection.kind.counter(), "filter_rejected_strike_move");
+        context.leg_filters = parse_leg_filters("strike_move=3");
+        assert!(first_rejection("Calendar", &quotes, &context).is_none());
+
+        // Test the default pipeline bounds calendars by spot while old decisions replay the
+        // original strike window.
+        assert!(default_leg_filters()
+            .iter()
+            .any(|filter| filter.kind == LegFilterKind::StrikePct));
+        assert!(legacy_leg_filters()
+            .iter()
+            .any(|filter| filter.kind == LegFilterKind::StrikeWindow));
+    }
+
+    #[test]
+    fn test_risk_free_profit() {
+        let gate: ProfitGate = ProfitGate {
+            fee_per_contract: 1.25,
+            risk_free_rate: 0.05,
+            ..ProfitGate::default()
+        };
+
+        // Test a butterfly credit less fees on its four contracts.
+        assert!((butterfly_risk_free_profit(0.20, &gate) - 0.15).abs() < 1e-9);
+        assert!(butterfly_risk_free_profit(0.04, &gate) < gate.butterfly_min_profit);
+
+        // Test a short box credit carried to expiry, less the width owed and fees.
+        assert!((boxspread_risk_free_profit(5.10, 5.0, 0, &gate) - 0.05).abs() < 1e-9);
+        let carried: f64 = boxspread_risk_free_profit(5.10, 5.0, 365, &gate);
+        assert!((carried - (5.10 * 0.05_f64.exp() - 5.05)).abs() < 1e-9);
+        assert!(boxspread_risk_free_profit(4.98, 5.0, 30, &ProfitGate::default()) < 0.0);
+    }
+
+    #[test]
+    fn test_default_headers() {
+        // Test parsing extra headers, skipping malformed entries.
+        let extra: Vec<(String, String)> =
+            parse_headers("X-Proxy-Token: abc;  Authorization: Basic dXNlcjpwYXNz ;broken;");
+        assert_eq!(
+            extra,
+            vec![
+                ("X-Proxy-Token".to_string(), "abc".to_string()),
+                (
+                    "Authorization".to_string(),
+                    "Basic dXNlcjpwYXNz".to_string()
+                ),
+            ]
+        );
+
+        // Test the defaults are always sent and extra headers can override them.
+        let headers = build_default_headers(DEFAULT_USER_AGENT, &extra).unwrap();
+        assert_eq!(headers["connection"], "keep-alive");
+        assert_eq!(headers["user-agent"], DEFAULT_USER_AGENT);
+        assert_eq!(headers["x-proxy-token"], "abc");
+        let headers = build_default_headers(
+            "custom/2.0",
+            &[("Connection".to_string(), "close".to_string())],
+        )
+        .unwrap();
+        assert_eq!(headers["connection"], "close");
+        assert_eq!(headers["user-agent"], "custom/2.0");
+
+        // Test invalid header names are rejected.
+        assert!(build_default_headers(
+            DEFAULT_USER_AGENT,
+            &[("Bad Name".to_string(), "x".to_string())]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_remote_gateway() {
+        // Test the default local gateway and a reverse proxy on the scheme's default port.
+        assert_eq!(
+            gateway_base_url("https", "localhost", "5000", ""),
+            "https://localhost:5000"
+        );
+        assert_eq!(
+            gateway_base_url("https", "gw.example.com", "", "/ibkr/"),
+            "https://gw.example.com/ibkr"
+        );
+
+        // Test bearer tokens take precedence over basic credentials.
+        assert_eq!(
+            auth_header(Some("abc123"), Some("user:pass")),
+            Some(("Authorization".to_string(), "Bearer abc123".to_string()))
+        );
+        assert_eq!(
+            auth_header(None, Some("user:pass")),
+            Some((
+                "Authorization".to_string(),
+                "Basic dXNlcjpwYXNz".to_string()
+            ))
+        );
+        assert_eq!(
+            auth_header(Some(""), Some("ab:c")),
+            Some(("Authorization".to_string(), "Basic YWI6Yw==".to_string()))
+        );
+        assert_eq!(auth_header(None, Some("no-colon")), None);
+
+        // Test credentials are hidden from debug output.
+        let header: (String, String) = auth_header(Some("abc123"), None).unwrap();
+        let headers = build_default_headers(DEFAULT_USER_AGENT, &[header]).unwrap();
+        assert!(headers["authorization"].is_sensitive());
+    }
+
+    #[test]
+    fn test_parse_static_hosts() {
+        // Test IPv4 and bracketed IPv6 mappings, skipping malformed entries.
+        let hosts: Vec<(String, Vec<std::net::IpAddr>)> =
+            parse_static_hosts("GW.example.com=10.0.0.5,[fd00::5];broken;empty=;bad=not-an-ip");
+        assert_eq!(hosts.len(), 1);
+        assert_eq!(hosts[0].0, "gw.example.com");
+        assert_eq!(
+            hosts[0].1,
+            vec![
+                "10.0.0.5".parse::<std::net::IpAddr>().unwrap(),
+                "fd00::5".parse::<std::net::IpAddr>().unwrap(),
+            ]
+        );
+
+        // Test the default resolver leaves DNS alone.
+        let config: ResolverConfig = ResolverConfig::default();
+        assert_eq!(config.ip_version, IpVersion::Any);
+        assert!(config.static_hosts.is_empty());
+    }
+
+    #[test]
+    fn test_config_from_env() {
+        let source: ConfigSource = ConfigSource::from_pairs([
+            ("PROFILES", "SPX, RUT"),
+            ("TEST_MODE", "yes"),
+            ("SECONDS_TO_SLEEP", "3"),
+            ("ARB_VALUE", "0.2"),
+            ("RUT_ARB_VALUE", "0.3"),
+            ("RUT_TICK_SIZE", "0.05"),
+            ("FILL_MODEL", "Size"),
+            ("GATEWAY_IP_VERSION", "ipv4"),
+            ("VIX_DISABLED_STRATEGIES", "2, 3"),
+            ("LOG_LEVEL", "debug"),
+            ("LEG_FILTERS", "min_size=0;min_bid=0.5"),
+            ("SECONDARY_PORT", "5001"),
+            ("FAILOVER_AFTER", "0"),
+            ("ORDER_REFERRER", "bot_a"),
+            ("RUT_CALENDAR_ORDER_TAG", "RUT_CAL"),
+            ("BUTTERFLY_ORDER_TAG", "fly-1"),
+            ("INCREMENTAL_SCAN", "false"),
+            ("DEDUPE_CONTENDERS", "no"),
+            ("BLACKLIST_AFTER", "4"),
+            ("BLACKLIST_HALF_LIFE", "-1"),
+            ("CALENDAR_MAX_PRICE", "0"),
+            ("RUT_CALENDAR_MIN_PRICE", "-25"),
+            ("BUTTERFLY_MIN_PRICE", "10"),
+            ("BUTTERFLY_MAX_PRICE", "5"),
+            ("BUTTERFLY_WIDTHS", "4"),
+            ("RUT_BUTTERFLY_WIDTHS", "3"),
+            ("RUT_BUTTERFLY_ARB_VALUES", "0.3, 0.5"),
+            ("HEDGE_DELTA_THRESHOLD", "50"),
+            ("RUT_HEDGE_CONID", "730283085"),
+        ]);
+        let config: Config = Config::from_source(&source, false).unwrap();
+
+        // Test shared settings are typed, validated and defaulted.
+        assert!(config.test_mode());
+        assert_eq!(config.seconds_to_sleep(), 60);
+        assert_eq!(config.fill_model, FillModelKind::Size);
+        assert_eq!(config.resolver().ip_version, IpVersion::V4);
+        assert_eq!(config.vix_disabled_strategies, vec!["2", "3"]);
+        assert_eq!(config.log_level, LogLevel::Debug);
+        assert_eq!(config.leg_filters[1].kind, LegFilterKind::MinBid);
+        assert_eq!(config.max_quote_size, 10000.0);
+        assert_eq!(config.missing_quotes, MissingQuotes::Skip);
+        assert!(!config.incremental_scan);
+        assert!(!config.dedupe_contenders);
+        assert_eq!(config.blacklist_after, 4);
+        assert_eq!(config.blacklist_half_life, 30.0);
+        assert_eq!(config.base_url(), "https://localhost:5000");
+        assert_eq!(
+            config.secondary_base_url().as_deref(),
+            Some("https://localhost:5001")
+        );
+        assert_eq!(config.failover_after, 3);
+
+        // Test per-ticker overrides fall back to the shared values.
+        assert_eq!(config.profiles.len(), 2);
+        assert_eq!(config.profiles[0].ticker, "SPX");
+        assert_eq!(config.profiles[0].arb_val, 0.2);
+        assert_eq!(config.profiles[0].tick_rule.tick, 0.01);
+        assert_eq!(config.profiles[1].arb_val, 0.3);
+        assert_eq!(config.profiles[1].tick_rule.tick_above, 0.05);
+        assert_eq!(config.profiles[1].order_tags.referrer, "bot_a");
+        assert_eq!(
+            config.profiles[1].order_tags.tag("Calendar"),
+            Some("RUT_CAL")
+        );
+        assert_eq!(config.profiles[0].order_tags.tag("Calendar"), None);
+        assert_eq!(config.profiles[0].order_tags.tag("Butterfly"), None);
+        assert!(is_valid_order_tag("SPX_CAL_1") && !is_valid_order_tag("a".repeat(17).as_str()));
+
+        // Test butterfly wing widths are capped, falling back to the base width.
+        assert_eq!(config.profiles[0].butterfly_wings.widths, 1);
+        assert_eq!(
+            config.profiles[1].butterfly_wings,
+            ButterflyWings {
+                widths: 3,
+                arb_values: vec![0.3, 0.5],
+            }
+        );
+
+        // Test hedging stays off without a hedge instrument rather than trading the index.
+        assert_eq!(config.profiles[0].hedge_rule.threshold, 0.0);
+        assert_eq!(config.profiles[1].hedge_rule.threshold, 50.0);
+        assert_eq!(
+            config.profiles[1].hedge_rule.conid.as_deref(),
+            Some("730283085")
+        );
+
+        // Test price bounds: a calendar must be a credit, at most 25 on RUT, and crossed bounds
+        // are dropped.
+        let calendar: PriceBounds = config.profiles[1].price_limits.bounds("Calendar");
+        assert!(calendar.allows(-0.35) && calendar.allows(-25.0));
+        assert!(!calendar.allows(0.35) && !calendar.allows(-25.5) && !calendar.allows(f64::NAN));
+        assert!(config.profiles[0]
+            .price_limits
+            .bounds("Calendar")
+            .allows(-250.0));
+        assert_eq!(
+            config.profiles[0].price_limits.bounds("Butterfly"),
+            PriceBounds::default()
+        );
+        assert!(PriceBounds::default().allows(-1000.0));
+
+        // Test values of the wrong type are rejected with the key that held them.
+        let source: ConfigSource = ConfigSource::from_pairs([("ORDER_LIFETIME", "soon")]);
+        let error: String = Config::from_source(&source, false).err().unwrap();
+        assert!(error.contains("ORDER_LIFETIME"));
+    }
+
+    #[test]
+    fn test_failover() {
+        let mut failover: Failover = Failover::new(
+            "https://gw1:5000".to_string(),
+            "https://gw2:5000".to_string(),
+            2,
+            3,
+        );
+        assert_eq!(failover.active_url(), "https://gw1:5000");
+
+        // Test a single failed check or a secondary that is down too keeps the primary.
+        assert_eq!(failover.record_primary(false, || true), None);
+        assert_eq!(failover.record_primary(true, || true), None);
+        assert_eq!(failover.record_primary(false, || true), None);
+        assert_eq!(failover.record_primary(false, || false), None);
+        assert_eq!(failover.active(), GatewayRole::Primary);
+
+        // Test failing over once the secondary answers, and failing back after enough passes.
+        assert_eq!(
+            failover.record_primary(false, || true),
+            Some(GatewayRole::Secondary)
+        );
+        assert_eq!(failover.active_url(), "https://gw2:5000");
+        assert_eq!(failover.record_primary(true, || true), None);
+        assert_eq!(failover.record_primary(true, || true), None);
+        assert_eq!(
+            failover.record_primary(true, || true),
+            Some(GatewayRole::Primary)
+        );
+        assert_eq!(failover.active().name(), "primary");
+    }
+
+    #[test]
+    fn test_log_level() {
+        // Test level names from the control file, ignoring case and the trailing newline.
+        assert_eq!(LogLevel::parse("debug\n"), Some(LogLevel::Debug));
+        assert_eq!(LogLevel::parse("WARN"), Some(LogLevel::Warn));
+        assert_eq!(LogLevel::parse("verbose"), None);
+
+        // Test each level includes the ones below it.
+        assert!(LogLevel::Error < LogLevel::Warn);
+        assert!(LogLevel::Info < LogLevel::Debug);
+        assert!(LogLevel::Debug < LogLevel::Trace);
+        assert_eq!(LogLevel::default(), LogLevel::Info);
+    }
+
+    #[test]
+    fn test_config_from_toml() {
+        let source: ConfigSource = ConfigSource::from_toml(
+            r#"
+profiles = ["SPX", "RUT"]
+test_mode = false
+num_days = 3
+order_join_best = true
+reply_policy = "never"
+order_lifetime = 45
+
+[spx]
+option = "1"
+fill_type = "2"
+discount_value = 0.05
+calendar_min_profit = 0.5
+
+[rut]
+option = "1"
+fill_type = "2"
+execution_profile = "conservative;order_lifetime=10"
+boxspread_enabled = true
+boxspread_scan_interval = 300
+boxspread_scan_windows = "14:00-16:00"
+"#,
+        )
+        .unwrap();
+        let config: Config = Config::from_source(&source, false).unwrap();
+
+        assert!(!config.test_mode());
+        assert_eq!(config.num_days(), 3);
+        assert!(config.order_join_best);
+        assert_eq!(config.reply_policy(), ReplyPolicy::NeverConfirm);
+        assert!(config.profiles[0].strategies.calendar.enabled);
+        assert!(!config.profiles[0].strategies.butterfly.enabled);
+        assert!(!config.profiles[0].strategies.boxspread.enabled);
+        assert_eq!(config.profiles[0].discount_value, 0.05);
+        assert_eq!(config.profiles[0].profit_gate.calendar_min_profit, 0.5);
+
+        // Test the legacy fill type picks its preset, EXECUTION_PROFILE takes precedence and
+        // the shared order lifetime applies unless overridden.
+        assert_eq!(config.profiles[0].execution.name, "concentrated");
+        assert_eq!(config.profiles[0].execution.order_lifetime, 45);
+        assert_eq!(config.profiles[1].execution.name, "conservative (custom)");
+        assert_eq!(config.profiles[1].execution.sizing, Sizing::Single);
+        assert_eq!(config.profiles[1].execution.order_lifetime, 10);
+
+        // Test a strategy's own settings turn it on alongside OPTION with its schedule.
+        let strategies: &StrategySchedules = &config.profiles[1].strategies;
+        assert!(strategies.calendar.enabled && !strategies.butterfly.enabled);
+        assert!(strategies.boxspread.enabled);
+        assert_eq!(strategies.boxspread.interval, 300);
+        assert_eq!(
+            strategies.boxspread.windows,
+            parse_time_windows("14:00-16:00")
+        );
+        assert_eq!(strategies.calendar, StrategySchedule::default());
+    }
+
+    #[test]
+    fn test_setup_answers() {
+        // Test every default answer passes its check and the rendered file loads.
+        let answers: Vec<(&str, String)> = QUESTIONS
+            .iter()
+            .map(|question| (question.key, (question.check)(question.default).unwrap()))
+            .collect();
+        let source: ConfigSource = ConfigSource::from_toml(&render_config(&answers)).unwrap();
+        let config: Config = Config::from_source(&source, false).unwrap();
+        assert!(config.test_mode());
+        assert_eq!(config.seconds_to_sleep(), 60);
+        assert_eq!(config.base_url(), "https://localhost:5000");
+        assert_eq!(config.profiles[0].ticker, "SPX");
+        assert_eq!(config.profiles[0].strategies, StrategySchedules::default());
+        assert_eq!(config.profiles[0].execution.name, "balanced");
+        assert_eq!(config.profiles[0].arb_val, 0.10);
+
+        // Test answers are normalized and out of range ones are rejected.
+        let check = |key: &str, answer: &str| -> Result<String, String> {
+            let question = QUESTIONS.iter().find(|q| q.key == key).unwrap();
+            (question.check)(answer)
+        };
+        assert_eq!(check("profiles", "spx, rut").unwrap(), "[\"SPX\", \"RUT\"]");
+        assert!(check("profiles", " , ").is_err());
+        assert!(check("option", "4").is_err());
+        assert_eq!(
+            check("execution_profile", "conservative;max_orders=2").unwrap(),
+            "\"conservative;max_orders=2\""
+        );
+        assert!(check("execution_profile", "1x").is_err());
+        assert!(check("arb_value", "0.05").is_err());
+        assert!(check("discount_value", "0.2").is_err());
+        assert!(check("num_days", "10").is_err());
+        assert_eq!(check("port", "5001").unwrap(), "\"5001\"");
+        assert!(check("port", "70000").is_err());
+        assert!(check("domain", "https://gw.example.com").is_err());
+        assert_eq!(check("gateway_scheme", "HTTP").unwrap(), "\"http\"");
+        assert_eq!(check("test_mode", "n").unwrap(), "false");
+    }
+
+    #[test]
+    fn test_parse_price() {
+        // Test the formats the gateway sends.
+        assert_eq!(parse_price("123.45"), Some(123.45));
+        assert_eq!(parse_price("C123.45"), Some(123.45));
+        assert_eq!(parse_price("H4,512.25"), Some(4512.25));
+        assert_eq!(parse_price("1,234.5"), Some(1234.5));
+        assert_eq!(parse_price("1,234"), Some(1234.0));
+        assert_eq!(parse_price("1,234,567"), Some(1234567.0));
+        assert_eq!(parse_price(" -0.05 "), Some(-0.05));
+        assert_eq!(parse_price("1.2K"), Some(1200.0));
+        assert_eq!(parse_price("3M"), Some(3_000_000.0));
+
+        // Test European formatted numbers use the comma as the decimal separator.
+        assert_eq!(parse_price("1.234,50"), Some(1234.5));
+        assert_eq!(parse_price("123,45"), Some(123.45));
+        assert_eq!(parse_price("1.234.567"), Some(1234567.0));
+
+        // Test placeholders and garbage have no value.
+        assert_eq!(parse_price("N/A"), None);
+        assert_eq!(parse_price(""), None);
+        assert_eq!(parse_price("C"), None);
+        assert_eq!(parse_price("-"), None);
+        assert_eq!(parse_price("."), None);
+        assert_eq!(parse_price("12a"), None);
+    }
+
+    #[test]
+    fn test_vix_regime() {
+        let disabled: Vec<String> = vec!["1".to_string(), "3".to_string()];
+        let codes: [&str; 3] = ["1", "2", "3"];
+        let calm: Regime = Regime {
+            arb_val: 0.1,
+            enabled: vec!["1", "2", "3"],
+        };
+
+        // Test a calm VIX, one at the threshold, and the filter turned off leave the scan as is,
+        // the last even when the VIX wasn't read.
+        assert_eq!(
+            vix_regime(20.0, Some(15.0), 0.1, 2.0, &disabled, &codes),
+            Some(calm)
+        );
+        assert_eq!(
+            vix_regime(20.0, Some(20.0), 0.1, 2.0, &disabled, &codes).map(|r| r.enabled.len()),
+            Some(3)
+        );
+        assert_eq!(
+            vix_regime(0.0, None, 0.1, 2.0, &disabled, &codes).map(|r| r.arb_val),
+            Some(0.1)
+        );
+
+        // Test a high VIX scales the arb value and disables the regime's strategies.
+        assert_eq!(
+            vix_regime(20.0, Some(25.0), 0.1, 2.0, &disabled, &codes),
+            Some(Regime {
+                arb_val: 0.2,
+                enabled: vec!["2"],
+            })
+        );
+
+        // Test a VIX that couldn't be read skips the scan rather than failing open.
+        assert_eq!(vix_regime(20.0, None, 0.1, 2.0, &disabled, &codes), None);
+    }
+
+    #[test]
+    fn test_market_monitor() {
+        // Test the median spread skips one sided and crossed quotes.
+        assert_eq!(
+            median_spread(&[(0.0, 1.0), (1.0, 1.1), (2.0, 1.0), (9.5, 10.5), (1.9, 2.1)]),
+            Some(0.1)
+        );
+        assert_eq!(median_spread(&[(0.0, 0.0)]), None);
+        assert!(is_halted_field("H4512.25"));
+        assert!(!is_halted_field("C4512.25"));
+
+        let mut monitor: MarketMonitor = MarketMonitor::new(3.0, 10);
+
+        // Test no fast market is called before the baseline has enough scans.
+        assert_eq!(monitor.update(false, Some(0.5)), None);
+        for _ in 0..4 {
+            assert_eq!(monitor.update(false, Some(0.05)), None);
+        }
+
+        // Test a spread blowout pauses, and a return to normal resumes.
+        assert_eq!(
+            monitor.update(false, Some(0.3)),
+            Some(MarketState::FastMarket {
+                spread: 0.3,
+                baseline: 0.05
+            })
+        );
+        assert_eq!(monitor.update(false, Some(0.4)), None);
+        assert_eq!(monitor.update(false, Some(0.06)), Some(MarketState::Normal));
+
+        // Test a halt marker wins over normal spreads.
+        assert_eq!(monitor.update(true, Some(0.05)), Some(MarketState::Halted));
+        assert_eq!(monitor.state(), MarketState::Halted);
+        assert_eq!(monitor.update(false, None), Some(MarketState::Normal));
+
+        // Test a multiple of 0.0 disables fast market detection.
+        let mut monitor: MarketMonitor = MarketMonitor::new(0.0, 10);
+        for _ in 0..5 {
+            monitor.update(false, Some(0.05));
+        }
+        assert_eq!(monitor.update(false, Some(5.0)), None);
+    }
+
+    #[test]
+    fn test_hedge() {
+        let position =
+            |conid: i64, position: f64, mkt_price: f64, right: Option<&str>| PositionResponse {
+                conid,
+                position,
+                mkt_price,
+                avg_price: mkt_price,
+                mkt_value: 0.0,
+                put_or_call: right.map(str::to_string),
+                strike: right.map(|_| serde_json::json!("4000")),
+                expiry: right.map(|_| "20240119".to_string()),
+                und_conid: right.map(|_| 416904),
+            };
+        let today: NaiveDate = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        let rule: HedgeRule = HedgeRule {
+            threshold: 25.0,
+            conid: None,
+            delta_per_unit: 1.0,
+        };
+
+        // Test a mark below intrinsic counts as fully in the money, a mark with time value doesn't.
+        let short_calls: PositionResponse = position(11, -2.0, 600.0, Some("C"));
+        assert_eq!(
+            option_position_delta(&short_calls, 4700.0, today),
+            Some(-200.0)
+        );
+        let delta: f64 =
+            option_position_delta(&position(12, 1.0, 30.0, Some("P")), 4000.0, today).unwrap();
+        assert!(delta < -40.0 && delta > -60.0);
+
+        // Test shares of the underlying offset the options, and other positions are ignored.
+        let positions: Vec<PositionResponse> = vec![
+            short_calls,
+            position(416904, 150.0, 4700.0, None),
+            position(99, 500.0, 10.0, None),
+        ];
+        let delta: f64 = net_delta(&positions, 4700.0, today, "416904", &rule, "416904");
+        assert_eq!(delta, -50.0);
+        assert_eq!(hedge_quantity(delta, &rule), Some(50));
+        assert_eq!(hedge_quantity(20.0, &rule), None);
+
+        // Test a future carrying five deltas per contract, and a threshold of 0.0 disabling hedges.
+        let future: HedgeRule = HedgeRule {
+            delta_per_unit: 5.0,
+            ..rule.clone()
+        };
+        assert_eq!(hedge_quantity(-52.0, &future), Some(10));
+        assert_eq!(hedge_quantity(33.0, &future), Some(-7));
+        let disabled: HedgeRule = HedgeRule {
+            threshold: 0.0,
+            ..rule
+        };
+        assert_eq!(hedge_quantity(500.0, &disabled), None);
+
+        // Test an index can't be the hedge instrument while futures and stock can.
+        assert!(!is_tradable_hedge("IND") && !is_tradable_hedge(""));
+        assert!(is_tradable_hedge("FUT") && is_tradable_hedge("STK"));
+
+        // Test an order counts as working until the gateway lists it as ended or drops it.
+        let order = |order_id: &str, status: &str| -> Order {
+            serde_json::from_value(serde_json::json!({ "orderId": order_id, "status": status }))
+                .unwrap()
+        };
+        let orders: Vec<Order> = vec![order("1", "Submitted"), order("2", "Filled")];
+        assert!(is_working(&orders, "1"));
+        assert!(!is_working(&orders, "2"));
+        assert!(!is_working(&orders, "3"));
+    }
+
+    #[test]
+    fn test_hedge_cycles() {
+        let gateway: MockGateway = MockGateway::start(11, Duration::from_millis(200));
+        let port: String = gateway.port().to_string();
+        let mut config: Config = Config::from_source(
+            &ConfigSource::from_pairs([
+                ("TICKER", "SPX"),
+                ("TEST_MODE", "false"),
+                ("NUM_DAYS", "3"),
+                ("GATEWAY_SCHEME", "http"),
+                ("DOMAIN", "127.0.0.1"),
+                ("PORT", port.as_str()),
+                ("HEDGE_DELTA_THRESHOLD", "100"),
+                ("HEDGE_CONID", "111"),
+                ("HEDGE_DELTA_PER_UNIT", "50"),
+            ]),
+            false,
+        )
+        .unwrap();
+        let profile: Profile = config.profiles.remove(0);
+        let mut ibkr: IBKR = IBKR::new();
+        ibkr.init(&profile, &config).unwrap();
+        let hedge_position =
+            |position: f64| vec![serde_json::json!({ "conid": 111, "position": position })];
+
+        // Test a delta beyond the threshold sends one hedge, read from fresh positions.
+        gateway.set_positions(hedge_position(10.0));
+        ibkr.hedge_delta(true).unwrap();
+        assert_eq!(gateway.orders_placed(), 1);
+        assert_eq!(gateway.positions_invalidated(), 1);
+
+        // Test the next cycles don't hedge again while the hedge is working, even though the
+        // positions still show the old delta.
+        ibkr.hedge_delta(true).unwrap();
+        ibkr.hedge_delta(true).unwrap();
+        assert_eq!(gateway.orders_placed(), 1);
+
+        // Test once it fills the delta is judged again, flat now so nothing is sent.
+        gateway.fill_working();
+        gateway.set_positions(hedge_position(0.0));
+        ibkr.hedge_delta(true).unwrap();
+        assert_eq!(gateway.orders_placed(), 1);
+        assert_eq!(gateway.positions_invalidated(), 2);
+
+        // Test a new delta beyond the threshold is hedged again.
+        gateway.set_positions(hedge_position(-10.0));
+        ibkr.hedge_delta(true).unwrap();
+        assert_eq!(gateway.orders_placed(), 2);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let leg = |position: f64, right: &str, expiry: &str| PositionResponse {
+            conid: 7,
+            position,
+            mkt_price: 1.0,
+            avg_price: 1.0,
+            mkt_value: 0.0,
+            put_or_call: Some(right.to_string()),
+            strike: Some(serde_json::json!(450)),
+            expiry: Some(expiry.to_string()),
+            und_conid: Some(756733),
+        };
+        let today: NaiveDate = NaiveDate::from_ymd_opt(2024, 1, 19).unwrap();
+
+        // Test index options settle in cash and everything else into shares.
+        assert!(!is_physically_settled("spx"));
+        assert!(is_physically_settled("SPY"));
+
+        // Test only open legs expiring today are picked up.
+        assert!(expires_on(&leg(-1.0, "C", "20240119"), today));
+        assert!(!expires_on(&leg(-1.0, "C", "20240126"), today));
+        assert!(!expires_on(&leg(0.0, "C", "20240119"), today));
+
+        // Test only short legs in the money are flagged.
+        assert!(is_itm_short(&leg(-1.0, "C", "20240119"), 451.0));
+        assert!(!is_itm_short(&leg(-1.0, "C", "20240119"), 449.0));
+        assert!(is_itm_short(&leg(-2.0, "P", "20240119"), 449.0));
+        assert!(!is_itm_short(&leg(2.0, "P", "20240119"), 449.0));
+
+        // Test a short leg rolls by buying it back and selling the later contract.
+        assert_eq!(
+            roll_conidex("7", "8", -2.0),
+            ("28812380;;;7/1,8/-1".to_string(), 2)
+        );
+        assert_eq!(
+            roll_conidex("7", "8", 3.0),
+            ("28812380;;;7/-1,8/1".to_string(), 3)
+        );
+
+        // Test the settlement default follows the ticker and the cutoff falls back when invalid.
+        let source: ConfigSource = ConfigSource::from_pairs([
+            ("PROFILES", "SPX, SPY"),
+            ("EXPIRY_ACTION", "roll"),
+            ("EXPIRY_CUTOFF", "25:00"),
+            ("SPY_EXPIRY_CUTOFF", "15:45"),
+        ]);
+        let config: Config = Config::from_source(&source, false).unwrap();
+        let spx: ExpiryRule = config.profiles[0].expiry_rule;
+        let spy: ExpiryRule = config.profiles[1].expiry_rule;
+        assert_eq!(spx.action, ExpiryAction::Roll);
+        assert!(!spx.physical_settlement);
+        assert_eq!(spx.cutoff, ExpiryRule::default().cutoff);
+        assert!(spy.physical_settlement);
+        assert_eq!(spy.cutoff, NaiveTime::from_hms_opt(15, 45, 0).unwrap());
+    }
+
+    #[test]
+    fn test_calendar_roll() {
+        let rule: RollRule = RollRule {
+            dte: Some(1),
+            profit_pct: 0.8,
+        };
+
+        // Test either trigger rolls the near leg, and disabled triggers never do.
+        assert!(roll_due(1, 2.0, 1.5, &rule));
+        assert!(roll_due(5, 2.0, 0.4, &rule));
+        assert!(!roll_due(5, 2.0, 0.5, &rule));
+        assert!(!roll_due(0, 2.0, 0.0, &RollRule::default()));
+
+        // Test the roll lands in the first expiry between the legs, never on the far leg.
+        let dates: Vec<String> = ["240102", "240103", "240105", "240112"]
+            .iter()
+            .map(|date| date.to_string())
+            .collect();
+        assert_eq!(
+            roll_target(&dates, "240102", "240112"),
+            Some(&"240103".to_string())
+        );
+        assert_eq!(roll_target(&dates, "240105", "240112"), None);
+
+        // Test the roll is priced at the mids, negative for a credit, and needs two sided quotes.
+        let quote = |bid: f64, ask: f64| Opt {
+            asz: 10.0,
+            mkt: (bid + ask) / 2.0,
+            bid,
+            ask,
+            open_interest: None,
+            provenance: Provenance::default(),
+        };
+        let price: f64 = roll_price(&quote(0.4, 0.6), &quote(1.9, 2.1)).unwrap();
+        assert!((price + 1.5).abs() < 1e-9);
+        assert_eq!(roll_price(&quote(0.0, 0.6), &quote(1.9, 2.1)), None);
+
+        // Test the history counts only filled rolls of the same calendar.
+        let roll = |order_id: &str, far_conid: &str, limit_price: f64| {
+            serde_json::to_string(&JournalEntry::Roll {
+                time: "2024-01-02T15:00:00Z".to_string(),
+                order_id: order_id.to_string(),
+                c_oid: String::new(),
+                ticker: "SPX".to_string(),
+                type_spread: "Roll".to_string(),
+                far_conid: far_conid.to_string(),
+                strike: 4700.0,
+                right: "C".to_string(),
+                from_date: "240102".to_string(),
+                to_date: "240103".to_string(),
+                limit_price,
+                quantity: 1,
+                legs: Vec::new(),
+                rolls: 1,
+                cumulative_credit: -limit_price,
+            })
+            .unwrap()
+        };
+        let status = |order_id: &str, status: &str| {
+            serde_json::to_string(&JournalEntry::Status {
+                time: "2024-01-02T15:01:00Z".to_string(),
+                order_id: order_id.to_string(),
+                type_spread: "Roll".to_string(),
+                status: status.to_string(),
+                avg_price: None,
+            })
+            .unwrap()
+        };
+        let lines: Vec<String> = vec![
+            roll("1", "222", -1.5),
+            status("1", "filled"),
+            roll("2", "222", -1.0),
+            status("2", "cancelled"),
+            roll("3", "333", -2.0),
+            status("3", "filled"),
+            roll("4", "222", -1.25),
+            status("4", "filled"),
+        ];
+        assert_eq!(roll_history(lines.into_iter(), "222"), (2, 2.75));
+    }
+
+    #[test]
+    fn test_reconcile_book() {
+        let leg = |conid: i64, position: f64, right: Option<&str>, expiry: &str| PositionResponse {
+            conid,
+            position,
+            mkt_price: 0.0,
+            avg_price: 0.0,
+            mkt_value: 0.0,
+            put_or_call: right.map(str::to_string),
+            strike: right.map(|_| serde_json::json!(190)),
+            expiry: right.map(|_| expiry.to_string()),
+            und_conid: right.map(|_| 265598),
+        };
+        let previous: Vec<PositionResponse> = vec![
+            leg(1, -2.0, Some("C"), "20240119"),
+            leg(2, 1.0, Some("C"), "20240119"),
+            leg(3, 1.0, Some("P"), "20240216"),
+            leg(4, -1.0, Some("P"), "20240216"),
+        ];
+        let current: Vec<PositionResponse> = vec![
+            leg(4, -1.0, Some("P"), "20240216"),
+            leg(265598, -200.0, None, ""),
+        ];
+        let settlements: Vec<Settlement> = reconcile_book(&previous, &current, "20240122");
+
+        // Test the short calls account for the shares taken, the long call expired and the put
+        // closed before expiry is left out.
+        assert_eq!(settlements.len(), 2);
+        assert_eq!(settlements[0].kind, SettlementKind::Assigned);
+        assert_eq!(settlements[0].contracts, -2.0);
+        assert_eq!(settlements[0].shares, -200.0);
+        assert_eq!(settlements[1].kind, SettlementKind::Expired);
+        assert_eq!(settlements[1].conid, 2);
+
+        // Test an exercised long put delivers shares away and an unchanged book has no events.
+        let previous: Vec<PositionResponse> = vec![leg(3, 1.0, Some("P"), "20240119")];
+        let current: Vec<PositionResponse> = vec![leg(265598, -100.0, None, "")];
+        let settlements: Vec<Settlement> = reconcile_book(&previous, &current, "20240122");
+        assert_eq!(settlements[0].kind, SettlementKind::Exercised);
+        assert!(reconcile_book(&previous, &previous, "20240122").is_empty());
+    }
+
+    #[test]
+    fn test_open_warmup() {
+        // Test the warm-up runs for the first minutes after the New York open, 0 disables it.
+        let at =
+            |hour: u32, minute: u32| Utc.with_ymd_and_hms(2024, 1, 2, hour, minute, 0).unwrap();
+        assert!(!in_open_warmup(at(14, 29), 5));
+        assert!(in_open_warmup(at(14, 30), 5));
+        assert!(in_open_warmup(at(14, 34), 5));
+        assert!(!in_open_warmup(at(14, 35), 5));
+        assert!(!in_open_warmup(at(14, 30), 0));
+
+        // Test quotes are stable within a tick and only when two sided.
+        let quote = |bid: f64, ask: f64| Opt {
+            asz: 10.0,
+            mkt: (bid + ask) / 2.0,
+            bid,
+            ask,
+            open_interest: None,
+            provenance: Provenance::default(),
+        };
+        assert!(is_quote_stable(&quote(1.0, 1.2), &quote(1.05, 1.2), 0.05));
+        assert!(!is_quote_stable(&quote(1.0, 1.2), &quote(1.0, 1.5), 0.05));
+        assert!(!is_quote_stable(&quote(0.0, 1.2), &quote(0.0, 1.2), 0.05));
+
+        // Test the chain keeps the replaced snapshot as the previous one.
+        let mut chain: ChainView = chain(&[("240102", "C", 4700.0, "111".to_string())]);
+        chain.set_quotes(HashMap::from([("111".to_string(), quote(1.0, 1.2))]));
+        assert!(chain.previous_quote("240102", "C", 4700.0).is_none());
+        chain.set_quotes(HashMap::from([("111".to_string(), quote(3.0, 3.2))]));
+        assert_eq!(
+            chain.previous_quote("240102", "C", 4700.0).unwrap().bid,
+            1.0
+        );
+        assert_eq!(chain.quote("240102", "C", 4700.0).unwrap().bid, 3.0);
+    }
+
+    #[test]
+    fn test_parse_quote() {
+        let parse = |payload: &str| -> Vec<Option<Quote>> {
+            serde_json::from_str::<Vec<MarketDataResponse>>(payload)
+                .unwrap()
+                .iter()
+                .map(parse_quote)
+                .collect()
+        };
+
+        // Test a full snapshot, with the gateway's bookkeeping fields ignored.
+        let quotes: Vec<Option<Quote>> = parse(
+            r#"[{"conidEx":"708846212","conid":708846212,"server_id":"q0","_updated":1704205800123,
+                "6119":"q0","31":"5.00","84":"4.90","85":"12","86":"5.10","6509":"RpB","7741":"4.75"}]"#,
+        );
+        let quote: &Quote = quotes[0].as_ref().unwrap();
+        assert_eq!(quote.conid, "708846212");
+        assert_eq!(
+            (quote.bid, quote.ask, quote.ask_size),
+            (Some(4.9), Some(5.1), Some(12.0))
+        );
+        assert_eq!((quote.last, quote.prior_close), (Some(5.0), Some(4.75)));
+        assert_eq!(quote.to_opt(Provenance::default()).unwrap().mkt, 5.0);
+
+        // Test the first snapshot of a subscription, which only carries the farm status.
+        let quotes: Vec<Option<Quote>> = parse(
+            r#"[{"conidEx":"708846212","conid":708846212,"_updated":1704205800123,"6509":"RpB"}]"#,
+        );
+        let quote: &Quote = quotes[0].as_ref().unwrap();
+        assert_eq!(quote.bid, None);
+        assert!(quote.to_opt(Provenance::default()).is_none());
+
+        // Test a closed market, with the last price marked and a thousands separated size.
+        let quotes: Vec<Option<Quote>> = parse(
+            r#"[{"conidEx":"708846212","31":"C5.00","84":"4.90","85":"1,200","86":"5.10","7741":"C4.75"}]"#,
+        );
+        let quote: &Quote = quotes[0].as_ref().unwrap();
+        assert_eq!((quote.last, quote.prior_close), (Some(5.0), Some(4.75)));
+        assert_eq!(quote.ask_size, Some(1200.0));
+
+        // Test numeric fields from older gateways, keyed by conid without conidEx.
+        let quotes: Vec<Option<Quote>> =
+            parse(r#"[{"conid":708846212,"84":4.9,"85":12,"86":5.1,"31":null}]"#);
+        let quote: &Quote = quotes[0].as_ref().unwrap();
+        assert_eq!(quote.conid, "708846212");
+        assert_eq!((quote.bid, quote.last), (Some(4.9), None));
+
+        // Test a combo keeps its conidex and a snapshot without any conid is dropped.
+        let quotes: Vec<Option<Quote>> = parse(
+            r#"[{"conidEx":"28812380;;;111/-1,222/1","84":"-1.20","86":"-1.05"},{"84":"4.90"}]"#,
+        );
+        assert_eq!(quotes[0].as_ref().unwrap().conid, "28812380;;;111/-1,222/1");
+        assert_eq!(quotes[0].as_ref().unwrap().bid, Some(-1.2));
+        assert_eq!(quotes[1], None);
+    }
+
+    #[test]
+    fn test_chain_from_contracts() {
+        let contract =
+            |conid: f64, maturity_date: &str, right: &str, strike: f64| SecDefInfoResponse {
+                conid,
+                maturity_date: maturity_date.to_string(),
+                right: right.to_string(),
+                strike,
+            };
+        // Per-strike lookups come back grouped by strike rather than by expiry.
+        let contracts: Vec<SecDefInfoResponse> = vec![
+            contract(11.0, "20240105", "C", 4710.0),
+            contract(12.0, "20240103", "C", 4710.0),
+            contract(13.0, "20240102", "P", 4710.0),
+            contract(21.0, "20240103", "C", 4700.0),
+            contract(22.0, "20240102", "C", 4700.0),
+            contract(23.0, "20240102", "C", 4700.0),
+        ];
+
+        // Test the nearest expiries are kept with sorted strikes and duplicates dropped.
+        let (batches, dates, strikes, conids) = chain_from_contracts(&contracts, "240102", 2, 0);
+        assert_eq!(dates, vec!["240102", "240103"]);
+        assert_eq!(strikes["240103"]["C"], vec![4700.0, 4710.0]);
+        assert_eq!(strikes["240102"]["C"], vec![4700.0]);
+        assert_eq!(conids["240102"]["C"][&OrderedFloat(4700.0)], "22");
+        assert!(conids["240103"]["P"].is_empty());
+        assert_eq!(batches, vec!["13,22,12,21"]);
+
+        // Test the offset skips expiries too close to the current date.
+        let (_, dates, _, _) = chain_from_contracts(&contracts, "240102", 5, 1);
+        assert_eq!(dates, vec!["240103", "240105"]);
+    }
+
+    #[test]
+    fn test_parse_option_months() {
+        // Test every listed month is kept in order, not just the first two.
+        assert_eq!(
+            parse_option_months("JAN24;FEB24;MAR24;APR24"),
+            vec!["JAN24", "FEB24", "MAR24", "APR24"]
+        );
+
+        // Test blanks and a trailing separator are dropped.
+        assert_eq!(
+            parse_option_months(" JAN24;;FEB24;"),
+            vec!["JAN24", "FEB24"]
+        );
+        assert!(parse_option_months("").is_empty());
+    }
+
+    #[test]
+    fn test_systemd() {
+        // Test the watchdog is pinged at half its timeout, only for the process it was set for.
+        assert_eq!(
+            parse_watchdog(Some("30000000"), None, 42),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            parse_watchdog(Some("30000000"), Some("42"), 42),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(parse_watchdog(Some("30000000"), Some("7"), 42), None);
+        assert_eq!(parse_watchdog(Some("0"), None, 42), None);
+        assert_eq!(parse_watchdog(None, None, 42), None);
+
+        // Test states reach a listening notify socket as one datagram.
+        let path: std::path::PathBuf =
+            std::env::temp_dir().join(format!("tbr-notify-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+        notify_socket(path.to_str().unwrap(), "READY=1").unwrap();
+        let mut buf: [u8; 64] = [0; 64];
+        let len: usize = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+        let _ = std::fs::remove_file(&path);
+
+        // Test the exit codes restart policies key on.
+        assert_eq!(ExitCode::ConfigError as i32, 78);
+        assert_eq!(ExitCode::AuthFailure as i32, 77);
+        assert_eq!(ExitCode::RiskShutdown as i32, 3);
+    }
+
+    #[test]
+    fn test_correlation_ids() {
+        // Test request IDs belong to the cycle and tag the thread's log lines until a new cycle.
+        let cycle: String = start_cycle();
+        assert_eq!(cycle, cycle_id());
+        let request_id: String = next_request_id();
+        assert!(request_id.starts_with(&format!("{}.", cycle)));
+        assert_eq!(correlation_tag(), format!("[{}]", request_id));
+        assert_ne!(next_request_id(), request_id);
+        let next_cycle: String = start_cycle();
+        assert_ne!(next_cycle, cycle);
+        assert_eq!(correlation_tag(), format!("[{}]", next_cycle));
+
+        // Test journal rows carry the cycle and still parse as entries.
+        let line: String = journal_line(
+            &JournalEntry::Status {
+                time: "2024-01-02T15:00:00Z".to_string(),
+                order_id: "1".to_string(),
+                type_spread: "Calendar".to_string(),
+                status: "filled".to_string(),
+                avg_price: None,
+            },
+            "1a2b-7",
+        )
+        .unwrap();
+        assert!(line.contains(r#""cycle_id":"1a2b-7""#));
+        assert!(matches!(
+            serde_json::from_str::<JournalEntry>(&line),
+            Ok(JournalEntry::Status { .. })
+        ));
+    }
+
+    #[test]
+    fn test_endpoint_stats() {
+        assert_eq!(
+            endpoint_key("POST", "/v1/api/iserver/account/DU1234567/orders"),
+            "POST /iserver/account/{id}/orders"
+        );
+        assert_eq!(
+            endpoint_key("GET", "/v1/api/iserver/marketdata/snapshot"),
+            "GET /iserver/marketdata/snapshot"
+        );
+        assert_eq!(
+            endpoint_key("DELETE", "/v1/api/iserver/account/U123/order/98765?x=1"),
+            "DELETE /iserver/account/{id}/order/{id}"
+        );
+
+        let mut stats: EndpointStats = EndpointStats::default();
+        assert_eq!(stats.mean_ms(), 0);
+        stats.record(Some(200), Duration::from_millis(100));
+        stats.record(Some(429), Duration::from_millis(50));
+        stats.record(Some(429), Duration::from_millis(30));
+        stats.record(Some(500), Duration::from_millis(400));
+        stats.record(None, Duration::from_millis(20));
+        assert_eq!((stats.calls, stats.errors, stats.paced), (5, 4, 2));
+        assert_eq!((stats.mean_ms(), stats.max_ms), (120, 400));
+        assert_eq!(
+            stats
+                .error_codes
+                .into_iter()
+                .collect::<Vec<(String, u64)>>(),
+            vec![
+                ("429".to_string(), 2),
+                ("500".to_string(), 1),
+                ("none".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cycle_summary() {
+        // Test an idle cycle reports what it couldn't measure.
+        let mut summary: CycleSummary = CycleSummary::default();
+        assert_eq!(
+            summary.to_line("1a2b-1"),
+            "Cycle summary: cycle=1a2b-1 quoted=0 valid_pct=na contenders=none best_rank=na orders=0 latency_ms=0"
+        );
+
+        // Test scans across profiles add up, counting contenders per strategy.
+        let mut butterfly: Contender = contender(7.5, 0.5, "20240119", 4800.0);
+        butterfly.type_spread = "Butterfly".to_string();
+        summary.add_scan(
+            ScanStats {
+                quoted: 90,
+                validated: 100,
+                rejected: 20,
+            },
+            &[
+                contender(2.0, 0.4, "20240119", 4800.0),
+                contender(3.0, 0.4, "20240126", 4800.0),
+            ],
+        );
+        summary.add_scan(
+            ScanStats {
+                quoted: 60,
+                validated: 60,
+                rejected: 0,
+            },
+            &[butterfly],
+        );
+        summary.orders_submitted = 2;
+        summary.latency = Duration::from_millis(1250);
+        assert_eq!(summary.valid_pct(), Some(87.5));
+        assert_eq!(
+            summary.to_line("1a2b-2"),
+            "Cycle summary: cycle=1a2b-2 quoted=150 valid_pct=87.5 contenders=Butterfly:1,Calendar:2 best_rank=7.50 orders=2 latency_ms=1250"
+        );
+    }
+
+    // Function that runs one cycle of the main loop's gateway calls for a live profile, as the
+    // loop does: size, scan, order, poll fills, then pick up orphans and pull working orders. Returns whether the
+    // scan went through.
+    fn soak_cycle(ibkr: &mut IBKR, profile: &Profile) -> bool {
+        let account: AccountSummary = match ibkr.get_account_summary() {
+            Ok(account) => account,
+            Err(_) => return false,
+        };
+        let allocator: Box<dyn Allocator> = allocator_for(profile, 0.0);
+        let exposure: Exposure = ibkr.exposure();
+        let num_orders: usize = allocator.max_contenders(&account, &exposure);
+        let scanned: bool = match ibkr
+            .get_contender_contracts(&profile.strategies, num_orders as i32)
+        {
+            Ok(contenders) => {
+                if !contenders.is_empty() {
+                    let quantities: Vec<i32> = allocator.allocate(&contenders, &account, &exposure);
+                    let _ = ibkr.order_contender_contracts(&contenders, &quantities);
+                }
+                true
+            }
+            Err(_) => false,
+        };
+        let _ = ibkr.poll_order_status();
+        let _ = ibkr.adopt_orphaned_orders();
+        ibkr.cancel_pending_orders();
+        scanned
+    }
+
+    #[test]
+    fn test_soak_mock_gateway() {
+        // SOAK_CYCLES runs a longer soak, e.g. SOAK_CYCLES=20000 cargo test soak.
+        let cycles: usize = env::var("SOAK_CYCLES")
+            .ok()
+            .and_then(|cycles| cycles.parse().ok())
+            .unwrap_or(2000);
+        let gateway: MockGateway = MockGateway::start(42, Duration::from_millis(200));
+        let port: String = gateway.port().to_string();
+        let mut config: Config = Config::from_source(
+            &ConfigSource::from_pairs([
+                ("TICKER", "SPX"),
+                ("TEST_MODE", "false"),
+                ("NUM_DAYS", "3"),
+                ("OPEN_WARMUP_MINUTES", "0"),
+                ("OPTION", "2"),
+                ("EXECUTION_PROFILE", "conservative"),
+                ("DISCOUNT_VALUE", "0"),
+                ("GATEWAY_SCHEME", "http"),
+                ("DOMAIN", "127.0.0.1"),
+                ("PORT", port.as_str()),
+                ("HTTP_TIMEOUT_MS", "50"),
+            ]),
+            false,
+        )
+        .unwrap();
+        let profile: Profile = config.profiles.remove(0);
+        let mut ibkr: IBKR = IBKR::new();
+        ibkr.init(&profile, &config).unwrap();
+
+        // Test a clean cycle finds the butterfly, orders it and pulls it again.
+        assert!(soak_cycle(&mut ibkr, &profile));
+        assert!(gateway.orders_placed() > 0);
+        assert!(gateway.working_orders().is_empty());
+
+        // Test thousands of cycles with timeouts, 500s, malformed JSON and expired sessions
+        // never panic, and orders placed by submissions that timed out get picked up.
+        gateway.set_fault_rate(0.05);
+        let mut failed_cycles: usize = 0;
+        for _ in 0..cycles {
+            if !soak_cycle(&mut ibkr, &profile) {
+                failed_cycles += 1;
+            }
+        }
+        let faults: Vec<Fault> = gateway.faults();
+        for fault in [
+            Fault::Timeout,
+            Fault::ServerError,
+            Fault::MalformedJson,
+            Fault::AuthExpired,
+        ] {
+            assert!(faults.contains(&fault), "{:?} was never injected", fault);
+        }
+        assert!(failed_cycles > 0 && failed_cycles < cycles);
+        assert!(gateway.requests() > cycles as u64 * 4);
+        assert!(snapshot()
+            .get("orders_orphaned")
+            .is_some_and(|orphans| *orphans > 0));
+
+        // Test the bot recovers once the gateway does, leaving nothing working.
+        gateway.set_fault_rate(0.0);
+        let placed: u64 = gateway.orders_placed();
+        assert!(soak_cycle(&mut ibkr, &profile));
+        assert!(soak_cycle(&mut ibkr, &profile));
+        assert!(gateway.orders_placed() > placed);
+        assert!(
+            gateway.working_orders().is_empty(),
+            "{:?}",
+            gateway.working_orders()
+        );
+        assert_eq!(ibkr.live_order_count(), 0);
+
+        // Test a new day starts its counters from zero. It runs here, after the only test that
+        // reads the counters, so a parallel test can't clear them from under it.
+        reset();
+        assert!(!snapshot().contains_key("orders_orphaned"));
+    }
+
+    #[test]
+    fn test_day_rollover() {
+        let gateway: MockGateway = MockGateway::start(7, Duration::from_millis(200));
+        let port: String = gateway.port().to_string();
+        let mut config: Config = Config::from_source(
+            &ConfigSource::from_pairs([
+                ("TICKER", "SPX"),
+                ("TEST_MODE", "false"),
+                ("NUM_DAYS", "3"),
+                ("OPTION", "2"),
+                ("GATEWAY_SCHEME", "http"),
+                ("DOMAIN", "127.0.0.1"),
+                ("PORT", port.as_str()),
+            ]),
+            false,
+        )
+        .unwrap();
+        let profile: Profile = config.profiles.remove(0);
+        let clock: Arc<FixedClock> = Arc::new(FixedClock::new(Utc::now()));
+        let mut ibkr: IBKR = IBKR::new();
+        ibkr.set_clock(clock.clone());
+        ibkr.init(&profile, &config).unwrap();
+        let path: std::path::PathBuf =
+            std::env::temp_dir().join(format!("tbr-rollover-{}.csv", std::process::id()));
+        let path: &str = path.to_str().unwrap();
+        let dates = |ibkr: &IBKR| -> Vec<String> {
+            ibkr.dump_chain(path).unwrap();
+            let mut dates: Vec<String> = std::fs::read_to_string(path)
+                .unwrap()
+                .lines()
+                .skip(1)
+                .filter_map(|line| line.split(',').next().map(str::to_string))
+                .collect();
+            dates.dedup();
+            dates
+        };
+        let first_day: Vec<String> = dates(&ibkr);
+        assert_eq!(first_day.len(), 3);
+        assert!(ibkr.disable_strategy("B"));
+
+        // Test the next day's reload drops the expiry that passed and forgets the strategies
+        // disabled the day before.
+        clock.advance(chrono::Duration::days(8));
+        ibkr.refresh_chain(&config).unwrap();
+        assert_eq!(dates(&ibkr), first_day[1..].to_vec());
+        assert!(ibkr.disable_strategy("B"));
+        let _ = std::fs::remove_file(path);
+
+        // Test the day's log moves to a dated file, leaving an empty one for the next day.
+        let log: std::path::PathBuf =
+            std::env::temp_dir().join(format!("tbr-log-{}.txt", std::process::id()));
+        let log: &str = log.to_str().unwrap();
+        std::fs::write(log, "Starting scan cycle 1.\n").unwrap();
+        roll_log_at(log, NaiveDate::from_ymd_opt(2024, 2, 6).unwrap()).unwrap();
+        let rolled: String = format!("{}-2024-02-06.txt", log.strip_suffix(".txt").unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&rolled).unwrap(),
+            "Starting scan cycle 1.\n"
+        );
+        assert_eq!(std::fs::read_to_string(log).unwrap(), "");
+        let _ = std::fs::remove_file(log);
+        let _ = std::fs::remove_file(&rolled);
+    }
 }
@@ -1,37 +1,225 @@
-use chrono::{DateTime, Utc};
-use std::{fs::OpenOptions, io::Write, path::Path, process::exit};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::{
+    cell::RefCell,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    process::exit,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering},
+        OnceLock,
+    },
+};
+
+use serde::Deserialize;
+
+use crate::{
+    systemd::{notify, ExitCode},
+    trace::redact_as,
+};
+
+// Scan cycle in progress, shared by every thread so snapshot requests carry their cycle.
+static CYCLE_SEQ: AtomicU64 = AtomicU64::new(0);
+static REQUEST_SEQ: AtomicU64 = AtomicU64::new(0);
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+// What the console and log file leave out so they can be shared, the journal keeps it all.
+static MASK_BALANCES: AtomicBool = AtomicBool::new(false);
+static ACCOUNT_ALIAS: OnceLock<Option<String>> = OnceLock::new();
+
+// Verbosity chosen in LOG_LEVEL, each level also logging everything below it.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    // Function that parses a level name, e.g. from the log_level control file.
+    pub(crate) fn parse(name: &str) -> Option<LogLevel> {
+        match name.trim().to_lowercase().as_str() {
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            "trace" => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+}
+
+// Function that changes the verbosity of every thread's logging.
+pub(crate) fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::SeqCst);
+}
+
+// Function that returns the current verbosity.
+pub(crate) fn log_level() -> LogLevel {
+    match LOG_LEVEL.load(Ordering::SeqCst) {
+        0 => LogLevel::Error,
+        1 => LogLevel::Warn,
+        2 => LogLevel::Info,
+        3 => LogLevel::Debug,
+        _ => LogLevel::Trace,
+    }
+}
+
+// Function that sets what logged lines mask: account ids, replaced by the alias (None leaves
+// them), and balances.
+pub(crate) fn set_log_masking(account_alias: Option<String>, mask_balances: bool) {
+    let _ = ACCOUNT_ALIAS.set(account_alias);
+    MASK_BALANCES.store(mask_balances, Ordering::SeqCst);
+}
+
+// Function that masks the account ids in a logged line.
+pub(crate) fn mask_accounts(status: String) -> String {
+    match ACCOUNT_ALIAS.get() {
+        Some(Some(alias)) => redact_as(&status, alias),
+        _ => status,
+    }
+}
+
+// Function that formats an account balance or P&L for the log, masked when balances are.
+pub(crate) fn balance(value: f64) -> String {
+    if MASK_BALANCES.load(Ordering::SeqCst) {
+        "***".to_string()
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+thread_local! {
+    // The last request sent from this thread, so the lines logged about it can be matched up.
+    static REQUEST_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// Function that starts a new scan cycle and returns its correlation ID.
+pub(crate) fn start_cycle() -> String {
+    CYCLE_SEQ.fetch_add(1, Ordering::SeqCst);
+    cycle_id()
+}
+
+// Function that returns the current scan cycle's correlation ID, the process id and the cycle
+// number so IDs from different runs don't collide in the log and journal.
+pub(crate) fn cycle_id() -> String {
+    format!(
+        "{:x}-{}",
+        std::process::id(),
+        CYCLE_SEQ.load(Ordering::SeqCst)
+    )
+}
+
+// Function that returns a correlation ID for an outbound request in the current cycle and makes
+// it the current thread's request.
+pub(crate) fn next_request_id() -> String {
+    let request_id: String = format!(
+        "{}.{}",
+        cycle_id(),
+        REQUEST_SEQ.fetch_add(1, Ordering::SeqCst) + 1
+    );
+    REQUEST_ID.with(|id| *id.borrow_mut() = Some(request_id.clone()));
+    request_id
+}
+
+// Function that returns the tag logged lines carry: the cycle, and the thread's last request
+// when it has sent one.
+pub(crate) fn correlation_tag() -> String {
+    REQUEST_ID.with(|id| match id.borrow().as_ref() {
+        Some(request_id) if request_id.starts_with(&format!("{}.", cycle_id())) => {
+            format!("[{}]", request_id)
+        }
+        _ => format!("[{}]", cycle_id()),
+    })
+}
 
 // Function that logs a message to text file.
 fn log_to_file<P: AsRef<Path>>(path: P, message: &str) -> std::io::Result<()> {
-    let mut file: std::fs::File = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .append(true)
-        .open(path)?;
+    let mut file: std::fs::File = OpenOptions::new().create(true).append(true).open(path)?;
 
     writeln!(file, "{}", message)?; // Writes the message and a newline character.
 
     Ok(())
 }
 
-// Function that logs a message.
+// Function that moves the day's log to log-YYYY-MM-DD.txt and starts an empty one.
+pub(crate) fn roll_log(date: NaiveDate) -> io::Result<()> {
+    roll_log_at("log.txt", date)
+}
+
+// Function that moves a log to the same name dated YYYY-MM-DD and starts an empty one.
+pub(crate) fn roll_log_at(path: &str, date: NaiveDate) -> io::Result<()> {
+    fs::rename(
+        path,
+        format!(
+            "{}-{}.txt",
+            path.strip_suffix(".txt").unwrap_or(path),
+            date.format("%Y-%m-%d")
+        ),
+    )?;
+    File::create(path)?;
+    Ok(())
+}
+
+// Function that logs a message at info level.
 pub(crate) fn log_message(status: String) {
+    log_at(LogLevel::Info, status);
+}
+
+// Function that logs a failure the bot recovers from.
+pub(crate) fn log_warn(status: String) {
+    log_at(LogLevel::Warn, status);
+}
+
+// Function that logs detail only worth reading while investigating.
+pub(crate) fn log_debug(status: String) {
+    log_at(LogLevel::Debug, status);
+}
+
+// Function that logs a message when the current verbosity includes its level.
+pub(crate) fn log_at(level: LogLevel, status: String) {
+    if level > log_level() {
+        return;
+    }
+    let status: String = mask_accounts(status);
     println!("{}", status);
     if !cfg!(test) {
         let now: DateTime<Utc> = Utc::now();
         let formatted_now: String = now.format("%Y-%m-%d %H:%M:%S%.9f UTC").to_string();
-        let _ = log_to_file("log.txt", &format!("{}   {}", formatted_now, status));
+        let _ = log_to_file(
+            "log.txt",
+            &format!("{}   {} {}", formatted_now, correlation_tag(), status),
+        );
     }
 }
 
 // Function that logs an error message and exits the program.
 pub(crate) fn log_error(error: String) {
-    eprintln!("{}", format!("Error: {}.", error));
+    log_exit(error, ExitCode::Error);
+}
+
+// Function that logs an error message and exits the program with the given code.
+pub(crate) fn log_exit(error: String, code: ExitCode) -> ! {
+    let error: String = mask_accounts(error);
+    eprintln!("Error: {}.", error);
     if !cfg!(test) {
         let now: DateTime<Utc> = Utc::now();
         let formatted_now: String = now.format("%Y-%m-%d %H:%M:%S%.9f UTC").to_string();
-        let _ = log_to_file("log.txt", &format!("{}   Error: {}.", formatted_now, error));
+        let _ = log_to_file(
+            "log.txt",
+            &format!(
+                "{}   {} Error: {}.",
+                formatted_now,
+                correlation_tag(),
+                error
+            ),
+        );
     }
-    log_message(format!("Exiting..."));
-    exit(1);
+    log_message("Exiting...".to_string());
+    let _ = notify("STOPPING=1");
+    exit(code as i32);
 }
@@ -1,103 +1,692 @@
+mod allocator;
+mod audit;
+mod blacklist;
+mod book;
+mod chain;
+mod clock;
+mod combos;
+mod config;
+mod control;
+mod encryption;
+mod execution;
+mod expiry;
+mod exposure;
+mod failover;
+mod fill_model;
+mod filters;
+mod greeks;
+mod headers;
+mod health;
+mod hedge;
 mod helpers;
 mod ibkr;
+mod journal;
 mod logging;
+mod market;
+mod metrics;
+mod near_miss;
+mod netting;
 mod orders;
+mod performance;
+mod pnl;
+mod positions;
+mod progress;
+mod quote_diff;
+mod replies;
+mod resolver;
+mod responses;
+mod roll;
+mod scheduler;
+mod secrets;
+mod session;
+mod setup;
+mod stress;
 mod structs;
+mod surface;
+mod systemd;
+mod throttle;
+mod trace;
+mod validation;
 
 use std::{
+    collections::HashMap,
+    env,
     fs::File,
     process::exit,
-    thread::sleep,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
-use chrono::Utc;
+use allocator::{allocator_for, Allocator, Exposure};
+use audit::audit_order;
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::America::New_York;
+use clock::{Clock, SystemClock};
+use config::{Config, ConsoleSettings};
+use control::{chain_dump_path, is_paused, log_level_override, take_chain_dump_request};
+use encryption::{encryption_enabled, run_decrypt_command, set_encryption_key};
+use failover::{Failover, GatewayRole};
+use health::{health_report, read_health, write_health};
 use helpers::{
-    calc_final_num_orders, format_strike, get_arb_value, get_discount_value, get_dotenv_variable,
-    get_fill_type, get_mode, get_num_days, get_num_days_offset, get_option, get_seconds_to_sleep,
-    get_strike_dif_value, get_ticker, is_us_stock_market_open,
+    format_strike, is_in_blackout, is_market_holiday, is_us_stock_market_open, is_winding_down,
+    next_market_open, underlying_moved_too_far, JOURNAL_PATH,
 };
 use ibkr::IBKR;
-use logging::{log_error, log_message};
+use logging::{
+    balance, log_debug, log_error, log_exit, log_level, log_message, log_warn, roll_log,
+    set_log_level, set_log_masking, start_cycle, LogLevel,
+};
+use market::MarketState;
+use metrics::{endpoint_snapshot, increment, reset, snapshot, CycleSummary};
+use near_miss::load_near_miss_report;
+use performance::{load_session_stats, load_strategy_stats, strategy_code, StrategyStats};
+use pnl::{append_pnl_sample, PnlSample, PNL_PATH};
+use scheduler::{get_scan_interval, jittered_sleep, random_fraction};
+use secrets::{get_secret, run_secrets_command};
+use session::{take_session_lost, SessionWatch};
+use setup::run_setup_wizard;
+use stress::{calendars_from_positions, simulate_calendar, StressConfig};
+use structs::{AccountSummary, Health, Profile};
+use systemd::{notify, ping_watchdog, sleep_watched, ExitCode};
+use throttle::NotionalThrottle;
+use trace::TRACE_TOGGLE_PATH;
+
+// How often the gateway is pinged while waiting for the open.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(60);
 
 fn main() {
+    // Print the heartbeat of a running bot and exit, for liveness probes.
+    if env::args().any(|arg| arg == "--health") {
+        match read_health() {
+            Ok(health) => {
+                println!(
+                    "{}",
+                    health_report(&health, ConsoleSettings::load().mask_balances)
+                );
+                exit(if health.state == "exited" { 1 } else { 0 });
+            }
+            Err(e) => {
+                eprintln!("Error: Failed to read health file: {}.", e);
+                exit(1);
+            }
+        }
+    }
+
+    // Manage credentials stored in the OS keyring and exit.
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(|arg| arg.as_str()) == Some("secrets") {
+        match run_secrets_command(&args[2..]) {
+            Ok(message) => {
+                println!("{}.", message);
+                exit(0);
+            }
+            Err(e) => {
+                eprintln!("Error: {}.", e);
+                exit(1);
+            }
+        }
+    }
+
+    // Walk through creating the config file and testing the gateway, then exit.
+    if args.get(1).map(|arg| arg.as_str()) == Some("init") {
+        match run_setup_wizard(|key| get_secret(key).ok()) {
+            Ok(message) => {
+                println!("{}.", message);
+                exit(0);
+            }
+            Err(e) => {
+                eprintln!("Error: {}.", e);
+                exit(1);
+            }
+        }
+    }
+
+    // Encrypt the journal, the P&L file and the book at rest when a key is set, reading back
+    // either.
+    if let Ok(key) = get_secret("ENCRYPTION_KEY") {
+        if let Err(e) = set_encryption_key(&key) {
+            eprintln!("Error: Failed to derive the encryption key: {}.", e);
+            exit(1);
+        }
+    }
+
+    // Print an encrypted file's lines decrypted and exit.
+    if args.get(1).map(|arg| arg.as_str()) == Some("decrypt") {
+        match run_decrypt_command(&args[2..]) {
+            Ok(()) => exit(0),
+            Err(e) => {
+                eprintln!("Error: {}.", e);
+                exit(1);
+            }
+        }
+    }
+
+    // Replay the scanner's checks on a journaled order's decision and exit.
+    if args.get(1).map(|arg| arg.as_str()) == Some("--audit") {
+        let order_id: &str = match args.get(2) {
+            Some(order_id) => order_id,
+            None => {
+                eprintln!("Error: Usage is --audit <order_id>.");
+                exit(1);
+            }
+        };
+        match audit_order(JOURNAL_PATH, order_id) {
+            Ok(report) => {
+                print!("{}", report);
+                exit(0);
+            }
+            Err(e) => {
+                eprintln!("Error: {}.", e);
+                exit(1);
+            }
+        }
+    }
+
+    // Print how many contenders each scanner threshold is costing and exit.
+    if args.get(1).map(|arg| arg.as_str()) == Some("--near-misses") {
+        match load_near_miss_report(JOURNAL_PATH) {
+            Ok(report) => {
+                print!("{}", report);
+                exit(0);
+            }
+            Err(e) => {
+                eprintln!("Error: {}.", e);
+                exit(1);
+            }
+        }
+    }
+
     let _ = File::create("log.txt");
-    let mut num_orders: i32;
-    let mut num_fills: i32;
-    let mut port_val: f64;
-    let mut ibkr: IBKR = IBKR::new();
-
-    let ticker: String = get_ticker();
-    let option: String = get_option();
-    let fill: String = get_fill_type();
-    let mode: bool = get_mode();
-    let seconds_to_sleep: u64 = get_seconds_to_sleep();
-
-    match ibkr.init(
-        ticker,
-        get_discount_value(),
-        get_arb_value(),
-        get_strike_dif_value(),
-        match get_dotenv_variable("DOMAIN") {
-            Ok(val) => val,
-            Err(_) => "localhost".to_string(),
-        },
-        match get_dotenv_variable("PORT") {
-            Ok(val) => val,
-            Err(_) => "5000".to_string(),
-        },
-        get_num_days(),
-        get_num_days_offset(),
-    ) {
-        Ok(_) => log_message(format!("Bot is live.")),
-        Err(e) => log_error(format!("{}", e)),
+    let mut config: Config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => log_exit(format!("Invalid config: {}", e), ExitCode::ConfigError),
+    };
+    config.fill_secrets(|key| get_secret(key).ok());
+    set_log_level(log_level_override().unwrap_or(config.log_level));
+    set_log_masking(
+        config
+            .mask_account_ids
+            .then(|| config.account_alias.clone()),
+        config.mask_balances,
+    );
+    if config.http_trace {
+        let _ = File::create(TRACE_TOGGLE_PATH);
+    }
+    let mut health: Health = Health {
+        state: "starting".to_string(),
+        ..Default::default()
+    };
+    let _ = write_health(&mut health);
+    if encryption_enabled() {
+        log_message("Encrypting the journal, the P&L file and the book.".to_string());
+    }
+    let mut account: AccountSummary;
+
+    let mode: bool = !config.test_mode();
+    let price_guard: bool =
+        config.max_underlying_move > 0.0 || config.max_underlying_move_pct > 0.0;
+    let mut in_blackout: bool = false;
+    let mut paused: bool = false;
+    let mut start_net_liquidation: Option<f64> = None;
+
+    // Working orders are cancelled by the monitor instead of after each sleep when enabled, the
+    // order lifetime coming from each profile's execution profile.
+    let order_monitor = |profile: &Profile| -> bool {
+        mode && (profile.execution.order_lifetime > 0 || config.order_max_drift > 0.0)
+    };
+    let spawn_monitor = |profile: &Profile| -> bool {
+        order_monitor(profile)
+            || (mode && (config.order_escalate_after > 0 || config.ladder_cancel_on_fill))
+    };
+
+    // Every profile runs through the same code path with its own IBKR session state, all on one
+    // clock.
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    let mut bots: Vec<(Profile, IBKR)> = Vec::new();
+    for profile in std::mem::take(&mut config.profiles) {
+        let mut ibkr: IBKR = IBKR::new();
+        ibkr.set_clock(Arc::clone(&clock));
+
+        match ibkr.init(&profile, &config) {
+            Ok(_) => log_message(format!(
+                "Bot is live for {} with the {} execution profile.",
+                profile.ticker, profile.execution.name
+            )),
+            Err(e) => log_error(format!("{}", e)),
+        }
+
+        if spawn_monitor(&profile) {
+            ibkr.spawn_order_monitor(profile.execution.order_lifetime, config.order_max_drift);
+        }
+
+        if let Err(e) = ibkr.init_volatility_regime(
+            config.vix_threshold,
+            config.vix_arb_scale,
+            config.vix_disabled_strategies.clone(),
+        ) {
+            log_error(format!("Failed to init VIX regime filter: {}", e));
+        }
+
+        bots.push((profile, ibkr));
     }
 
+    // Expiry dates and market hours come from the local clock, so refuse to trade on a bad one.
+    if config.max_clock_drift > 0.0 {
+        if let Some((_, ibkr)) = bots.first() {
+            match ibkr.gateway_clock_drift() {
+                Ok(drift) if drift.abs() > config.max_clock_drift => log_exit(
+                    format!(
+                        "Local clock is {:.1} seconds {} the gateway's, more than MAX_CLOCK_DRIFT allows, sync it with NTP before trading",
+                        drift.abs(),
+                        if drift > 0.0 { "behind" } else { "ahead of" }
+                    ),
+                    ExitCode::ConfigError,
+                ),
+                Ok(drift) => log_message(format!(
+                    "Local clock is within {:.1} seconds of the gateway's.",
+                    drift.abs()
+                )),
+                Err(e) => log_warn(format!("Failed to check the clock: {}.", e)),
+            }
+        }
+    }
+
+    // Stress test the calendars currently held in each profile's underlying and exit.
+    if env::args().any(|arg| arg == "--stress") {
+        for (profile, ibkr) in &bots {
+            match (ibkr.get_positions(), ibkr.get_underlying_price()) {
+                (Ok(positions), Ok(spot)) => {
+                    let today: NaiveDate = clock.today();
+                    for (position, quantity) in calendars_from_positions(&positions, today) {
+                        match simulate_calendar(&position, spot, &StressConfig::default()) {
+                            Some(result) => log_message(format!(
+                                "{} x{} {}{} {}d/{}d: mean {:.2}, p5 {:.2}, p1 {:.2}, worst {:.2}, P(loss) {:.1}%, delta {:.3}, gamma {:.4}, vega {:.2}, theta {:.2}",
+                                profile.ticker,
+                                quantity,
+                                format_strike(position.strike),
+                                position.right,
+                                position.front_days,
+                                position.back_days,
+                                result.mean,
+                                result.p5,
+                                result.p1,
+                                result.worst,
+                                result.loss_probability * 100.0,
+                                result.delta,
+                                result.gamma,
+                                result.vega,
+                                result.theta
+                            )),
+                            None => log_message(format!(
+                                "{} {}{}: no implied volatility for the back leg, skipping.",
+                                profile.ticker,
+                                format_strike(position.strike),
+                                position.right
+                            )),
+                        }
+                    }
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    log_message(format!("Stress test for {}: {}.", profile.ticker, e))
+                }
+            }
+        }
+        exit(0);
+    }
+
+    // Catch up on assignments, exercises and expirations since the last session before trading.
+    if let Some((_, ibkr)) = bots.first() {
+        match ibkr.reconcile_positions() {
+            Ok(settlements) if settlements.is_empty() => {
+                log_message("Positions match the last session's book.".to_string())
+            }
+            Ok(settlements) => log_message(format!(
+                "Reconciled {} assigned, exercised or expired legs into the book.",
+                settlements.len()
+            )),
+            Err(e) => log_warn(format!("Failed to reconcile positions: {}.", e)),
+        }
+    }
+
+    // Route to the backup gateway while the primary is unhealthy, when one is configured.
+    let mut failover: Option<Failover> = config.secondary_base_url().map(|secondary| {
+        Failover::new(
+            config.base_url(),
+            secondary,
+            config.failover_after,
+            config.failback_after,
+        )
+    });
+    if let Some(failover) = &failover {
+        health.active_gateway = Some(failover.active().name().to_string());
+    }
+
+    let allocators: Vec<Box<dyn Allocator>> = bots
+        .iter()
+        .map(|(profile, _)| allocator_for(profile, config.size_jitter_pct))
+        .collect();
+
+    // Notional submitted across every profile is capped per rolling minute and hour.
+    let mut throttle: NotionalThrottle =
+        NotionalThrottle::new(config.max_notional_per_minute, config.max_notional_per_hour);
+
+    // Tell systemd the bot is up, its watchdog is pinged from here on.
+    let _ = notify("READY=1");
+
+    let mut session_start: Option<DateTime<Utc>> = None;
+    let mut new_day: bool = false;
+    let mut session: SessionWatch = SessionWatch::default();
+    let mut reinit_pending: bool = false;
+    let mut shutdown: Option<ExitCode> = None;
     loop {
-        if !mode || is_us_stock_market_open(Utc::now()) {
+        // Follow the log_level control file, falling back to LOG_LEVEL once it's removed.
+        let level: LogLevel = log_level_override().unwrap_or(config.log_level);
+        if level != log_level() {
+            set_log_level(level);
+            log_message(format!("Log level set to {:?}.", level));
+        }
+
+        let now: DateTime<Utc> = clock.now();
+        let open: bool =
+            is_us_stock_market_open(now) && !is_market_holiday(now, &config.market_holidays);
+        let winding_down: bool = open && is_winding_down(now, config.wind_down_minutes);
+
+        if let Some(since) = session_start.filter(|_| mode && (winding_down || !open)) {
+            if winding_down {
+                log_message(format!(
+                    "{} minutes to the close, winding down.",
+                    config.wind_down_minutes
+                ));
+            } else {
+                log_message("Market is closed.".to_string());
+            }
+            end_session(&mut bots, since, start_net_liquidation, &mut health);
+            session_start = None;
+            if !config.daemon {
+                break;
+            }
+
+            // Start the next day with its own log and counters, the chain reloads at the open.
+            if let Some((_, ibkr)) = bots.first() {
+                if let Err(e) = ibkr.save_positions() {
+                    log_warn(format!("Failed to save the book: {}.", e));
+                }
+            }
+            if let Err(e) = roll_log(since.with_timezone(&New_York).date_naive()) {
+                log_warn(format!("Failed to roll the log: {}.", e));
+            }
+            reset();
+            start_net_liquidation = None;
+            new_day = true;
+        } else if !mode || (open && !winding_down) {
+            // Reauthenticate and reload each profile's chain before a new day's first scan.
+            if new_day {
+                let mut ready: bool = true;
+                for (profile, ibkr) in bots.iter_mut() {
+                    if let Err(e) = ibkr
+                        .reauthenticate()
+                        .and_then(|_| ibkr.refresh_chain(&config))
+                    {
+                        log_warn(format!(
+                            "Failed to start the day for {}: {}.",
+                            profile.ticker, e
+                        ));
+                        ready = false;
+                    }
+                }
+                if !ready {
+                    sleep_watched(Duration::from_secs(config.seconds_to_sleep()));
+                    continue;
+                }
+                if let Some((_, ibkr)) = bots.first() {
+                    if let Err(e) = ibkr.reconcile_positions() {
+                        log_warn(format!("Failed to reconcile positions: {}.", e));
+                    }
+                }
+                new_day = false;
+            }
+
+            // A gateway restart drops the session and the chain loaded through it, shown by a
+            // request rejected as unauthorized or the health check's auth status flipping.
+            if mode {
+                let authenticated: Option<bool> = bots
+                    .first()
+                    .and_then(|(_, ibkr)| ibkr.session_authenticated().ok());
+                if take_session_lost() || authenticated.is_some_and(|auth| session.record(auth)) {
+                    reinit_pending = true;
+                }
+            }
+            if reinit_pending {
+                log_warn("Gateway session lost, re-initializing.".to_string());
+                increment("gateway_restarts", 1);
+                health.state = "reconnecting".to_string();
+                let _ = write_health(&mut health);
+                let mut ready: bool = true;
+                for (profile, ibkr) in bots.iter_mut() {
+                    if let Err(e) = ibkr.reinit(&config) {
+                        log_warn(format!(
+                            "Failed to re-initialize {}: {}.",
+                            profile.ticker, e
+                        ));
+                        ready = false;
+                        break;
+                    }
+                }
+                if !ready {
+                    sleep_watched(Duration::from_secs(config.seconds_to_sleep()));
+                    continue;
+                }
+                log_message("Gateway session restored, resuming trading.".to_string());
+                reinit_pending = false;
+            }
+
+            session_start.get_or_insert_with(|| clock.now());
+            if let Some(failover) = failover.as_mut() {
+                check_gateway(failover, &bots, &mut health);
+            }
+            let cycle: String = start_cycle();
+            let cycle_start: Instant = Instant::now();
+            let mut summary: CycleSummary = CycleSummary::default();
+            log_message(format!("Starting scan cycle {}.", cycle));
+            ping_watchdog();
+            let _ = notify(&format!("STATUS=Scanning, cycle {}", cycle));
+            health.state = "scanning".to_string();
+            health.live_order_count = bots.iter().map(|(_, ibkr)| ibkr.live_order_count()).sum();
+            let _ = write_health(&mut health);
+
+            // All profiles trade the same account.
             if !mode {
-                port_val = 100000.0;
+                account = AccountSummary {
+                    equity_with_loan: 100000.0,
+                    available_funds: 100000.0,
+                    buying_power: 100000.0,
+                    maintenance_margin: 0.0,
+                    net_liquidation: 100000.0,
+                };
             } else {
-                match ibkr.get_portfolio_value() {
-                    Ok(port_value) => {
-                        port_val = port_value;
+                match bots[0].1.get_account_summary() {
+                    Ok(summary) => {
+                        account = summary;
+                        health.account = Some(account.clone());
+                        health.last_api_success = Some(Utc::now().to_rfc3339());
+
+                        // Sample equity and open position marks for the intraday P&L curve.
+                        if let Some(account) = &health.account {
+                            let start: f64 =
+                                *start_net_liquidation.get_or_insert(account.net_liquidation);
+                            let positions_value: f64 = bots
+                                .iter()
+                                .filter_map(|(_, ibkr)| ibkr.get_positions().ok())
+                                .flatten()
+                                .map(|position| position.mkt_value)
+                                .sum();
+                            let sample: PnlSample = PnlSample {
+                                time: clock.now().to_rfc3339(),
+                                equity_with_loan: account.equity_with_loan,
+                                net_liquidation: account.net_liquidation,
+                                pnl: account.net_liquidation - start,
+                                positions_value,
+                            };
+                            if let Err(e) = append_pnl_sample(PNL_PATH, &sample) {
+                                log_warn(format!("Failed to write P&L sample: {}.", e));
+                            }
+                        }
                     }
                     Err(e) => {
-                        log_error(format!("{}", e));
-                        exit(1);
+                        // A gateway hiccup skips the cycle rather than ending the session.
+                        increment("cycle_errors", 1);
+                        let seconds_to_sleep: u64 = get_scan_interval(
+                            clock.now(),
+                            &config.scan_schedule,
+                            config.seconds_to_sleep(),
+                        );
+                        log_warn(format!(
+                            "Failed to get portfolio value: {}, retrying in {} seconds.",
+                            e, seconds_to_sleep
+                        ));
+                        sleep_watched(Duration::from_secs(seconds_to_sleep));
+                        continue;
                     }
                 }
             }
 
-            (num_orders, num_fills) = calc_final_num_orders(&fill, port_val);
+            // Pause new orders around scheduled events, optionally pulling working orders.
+            let blackout: bool = is_in_blackout(clock.now(), &config.blackout_windows);
+            if blackout && !in_blackout {
+                log_message("Entering blackout window, pausing order submission.".to_string());
+                if mode && config.cancel_on_blackout {
+                    for (_, ibkr) in bots.iter_mut() {
+                        ibkr.cancel_pending_orders();
+                    }
+                }
+            } else if !blackout && in_blackout {
+                log_message("Blackout window ended, resuming order submission.".to_string());
+            }
+            in_blackout = blackout;
+
+            // Keep scanning and logging contenders while paused, but submit nothing.
+            let pause: bool = is_paused();
+            if pause && !paused {
+                log_message("Paused, scanning without submitting orders.".to_string());
+            } else if !pause && paused {
+                log_message("Resumed order submission.".to_string());
+            }
+            paused = pause;
+
+            let dump_chain: bool = take_chain_dump_request();
+            let mut enough_equity: bool = true;
+            for ((profile, ibkr), allocator) in bots.iter_mut().zip(&allocators) {
+                let exposure: Exposure = ibkr.exposure();
+                let num_orders: usize = allocator.max_contenders(&account, &exposure);
+
+                if num_orders == 0 {
+                    enough_equity = false;
+                    break;
+                }
 
-            if num_orders > 0 {
                 let start_time: Instant = Instant::now();
                 let mut end_time: Option<Duration> = None;
 
-                match ibkr.get_contender_contracts(&option, num_orders) {
+                // Record the underlying's price at scan start for the price movement guard.
+                let start_price: Option<f64> = if mode && price_guard {
+                    match ibkr.get_underlying_price() {
+                        Ok(price) => Some(price),
+                        Err(e) => {
+                            log_warn(format!("Price guard: {}.", e));
+                            None
+                        }
+                    }
+                } else {
+                    None
+                };
+
+                match ibkr.get_contender_contracts(&profile.strategies, num_orders as i32) {
                     Ok(contender_contracts) => {
+                        summary.add_scan(ibkr.scan_stats(), &contender_contracts);
+                        health.last_scan_time = Some(Utc::now().to_rfc3339());
+                        health.last_api_success = health.last_scan_time.clone();
                         if !contender_contracts.is_empty() {
-                            if mode {
+                            let mut quantities: Vec<i32> =
+                                allocator.allocate(&contender_contracts, &account, &exposure);
+                            let mut price_moved: bool = false;
+                            if mode && price_guard {
+                                price_moved = match (start_price, ibkr.get_underlying_price()) {
+                                    (Some(start), Ok(current)) => {
+                                        if underlying_moved_too_far(
+                                            start,
+                                            current,
+                                            config.max_underlying_move,
+                                            config.max_underlying_move_pct,
+                                        ) {
+                                            log_message(format!(
+                                                "{} moved from {:.2} to {:.2} during the scan, skipping order submission.",
+                                                profile.ticker, start, current
+                                            ));
+                                            true
+                                        } else {
+                                            false
+                                        }
+                                    }
+                                    _ => {
+                                        log_message(format!(
+                                            "{} price unavailable, skipping order submission.",
+                                            profile.ticker
+                                        ));
+                                        true
+                                    }
+                                };
+                            }
+
+                            // Arbs seen during a halt or a fast market aren't executable.
+                            let tradable: bool = matches!(ibkr.market_state(), MarketState::Normal);
+                            if mode && !price_moved && !in_blackout && !paused && tradable {
+                                let throttled: usize;
+                                (quantities, throttled) = throttle.admit(
+                                    &profile.ticker,
+                                    &contender_contracts,
+                                    &quantities,
+                                    clock.now(),
+                                );
+                                if throttled > 0 {
+                                    increment("contenders_throttled", throttled as u64);
+                                    log_message(format!(
+                                        "Notional cap reached, queued {} {} contenders for the next cycles ({} waiting).",
+                                        throttled,
+                                        profile.ticker,
+                                        throttle.queued_count()
+                                    ));
+                                }
                                 match ibkr
-                                    .order_contender_contracts(&contender_contracts, num_fills)
+                                    .order_contender_contracts(&contender_contracts, &quantities)
                                 {
-                                    Ok(_) => log_message(format!("Ordering Contracts...")),
-                                    Err(e) => log_error(format!("{}", e)),
+                                    Ok((placed, fills)) => {
+                                        throttle.charge(&contender_contracts, &fills, clock.now());
+                                        summary.orders_submitted += placed;
+                                        log_message("Ordering Contracts...".to_string())
+                                    }
+                                    Err(e) => {
+                                        increment("order_errors", 1);
+                                        log_warn(format!("Failed to submit orders: {}.", e))
+                                    }
                                 }
                             }
                             end_time = Some(start_time.elapsed());
-                            for contender in contender_contracts {
-                                log_message(format!(
-                                    "Submitting Order for {} * {} {} @ {:.2}:",
+                            for (contender, num_fills) in contender_contracts
+                                .iter()
+                                .zip(quantities.iter().copied().chain(std::iter::repeat(0)))
+                            {
+                                log_debug(format!(
+                                    "Submitting Order for {} * {} {} {} @ {:.2} (VIX {:.2}):",
                                     num_fills,
+                                    profile.ticker,
                                     contender.type_spread,
                                     contender.exp_date,
-                                    contender.arb_val
+                                    contender.arb_val,
+                                    contender.regime_value
                                 ));
 
                                 for i in 0..contender.contracts.len() {
-                                    log_message(format!(
+                                    log_debug(format!(
                                         "\tLeg {}: {} {} * {}{} {} @ {:.2}",
                                         i + 1,
                                         contender.action(i),
@@ -111,33 +700,344 @@ fn main() {
                             }
                         }
                     }
-                    Err(e) => log_error(format!("{}", e)),
+                    Err(e) => {
+                        increment("scan_errors", 1);
+                        log_warn(format!("Scan failed for {}: {}.", profile.ticker, e))
+                    }
+                }
+
+                // Export the chain this scan saw when asked through the control file.
+                if dump_chain {
+                    let path: String = chain_dump_path(&profile.ticker);
+                    match ibkr.dump_chain(&path) {
+                        Ok(()) => {
+                            log_message(format!("Exported {} chain to {}.", profile.ticker, path))
+                        }
+                        Err(e) => {
+                            log_warn(format!("Failed to export {} chain: {}.", profile.ticker, e))
+                        }
+                    }
                 }
 
                 // Record the current time after running the program.
                 if let Some(duration) = end_time {
-                    log_message(format!("Total time taken: {:?}.", duration));
+                    log_message(format!(
+                        "Total time taken for {}: {:?}.",
+                        profile.ticker, duration
+                    ));
                 }
-            } else {
-                log_message(format!("Not enough equity in account to make a trade."));
+            }
+
+            if !enough_equity {
+                log_message("Not enough equity in account to make a trade.".to_string());
+                shutdown = Some(ExitCode::RiskShutdown);
                 break;
             }
 
-            // Sleep to avoid throttling resources.
-            log_message(format!(""));
-            log_message(format!("Sleeping for {} seconds.", seconds_to_sleep));
-            sleep(Duration::from_secs(seconds_to_sleep));
-            log_message(format!("Awake after {} seconds.", seconds_to_sleep));
-            log_message(format!(""));
+            summary.latency = cycle_start.elapsed();
+            log_message(summary.to_line(&cycle));
+            increment("cycles_completed", 1);
+            increment("cycle_latency_ms_total", summary.latency.as_millis() as u64);
+
+            // Sleep to avoid throttling resources, using the cadence for this time of day.
+            let seconds_to_sleep: u64 = get_scan_interval(
+                clock.now(),
+                &config.scan_schedule,
+                config.seconds_to_sleep(),
+            );
+            health.state = if paused {
+                "paused"
+            } else if in_blackout {
+                "blackout"
+            } else {
+                "sleeping"
+            }
+            .to_string();
+            health.live_order_count = bots.iter().map(|(_, ibkr)| ibkr.live_order_count()).sum();
+            health.blacklist = bots
+                .iter_mut()
+                .flat_map(|(_, ibkr)| ibkr.blacklist_report())
+                .collect();
+            health.expiry_exposure = bots
+                .iter()
+                .flat_map(|(_, ibkr)| ibkr.expiry_exposure())
+                .collect();
+            let _ = write_health(&mut health);
 
+            // Jitter the cadence so scans don't land on a fixed beat.
+            let sleep_for: Duration =
+                jittered_sleep(seconds_to_sleep, config.scan_jitter_pct, random_fraction());
+            log_message(String::new());
+            log_message(format!(
+                "Sleeping for {:.1} seconds.",
+                sleep_for.as_secs_f64()
+            ));
+            sleep_watched(sleep_for);
+            log_message(format!(
+                "Awake after {:.1} seconds.",
+                sleep_for.as_secs_f64()
+            ));
+            log_message(String::new());
+
+            // Journal leg fills and final statuses before working orders are pulled.
             if mode {
-                ibkr.cancel_pending_orders();
+                for (_, ibkr) in bots.iter_mut() {
+                    if let Err(e) = ibkr.poll_order_status() {
+                        log_warn(format!("Failed to poll order status: {}.", e));
+                    }
+                }
             }
-            sleep(Duration::from_secs(5));
+
+            // Disable strategies whose live results this session have degraded.
+            let since: Option<DateTime<Utc>> = session_start.filter(|_| {
+                config.strategy_min_fill_rate > 0.0 || config.strategy_max_slippage > 0.0
+            });
+            if let (true, Some(since)) = (mode, since) {
+                let stats: HashMap<String, StrategyStats> =
+                    load_strategy_stats(JOURNAL_PATH, config.strategy_window, since);
+                for (type_spread, stat) in &stats {
+                    if !stat.degraded(
+                        config.strategy_window,
+                        config.strategy_min_fill_rate,
+                        config.strategy_max_slippage,
+                    ) {
+                        continue;
+                    }
+                    let code: &str = match strategy_code(type_spread) {
+                        Some(code) => code,
+                        None => continue,
+                    };
+                    let mut disabled: bool = false;
+                    for (_, ibkr) in bots.iter_mut() {
+                        disabled |= ibkr.disable_strategy(code);
+                    }
+                    if disabled {
+                        increment("strategies_auto_disabled", 1);
+                        log_message(format!(
+                            "Disabling {} for the session: fill rate {:.0}%, mean slippage {} and edge capture {} over the last {} orders.",
+                            type_spread,
+                            stat.fill_rate() * 100.0,
+                            stat.avg_slippage
+                                .map(|slip| format!("{:.2}", slip))
+                                .unwrap_or_else(|| "n/a".to_string()),
+                            stat.edge_capture
+                                .map(|capture| format!("{:.0}%", capture * 100.0))
+                                .unwrap_or_else(|| "n/a".to_string()),
+                            stat.orders
+                        ));
+                    }
+                }
+            }
+
+            // Roll the near legs of held calendars that hit the roll rule.
+            for (_, ibkr) in bots.iter_mut() {
+                if let Err(e) = ibkr.roll_calendars(mode && !paused) {
+                    log_warn(format!("Failed to roll calendars: {}.", e));
+                }
+            }
+
+            // Close or roll legs expiring today once the cutoff passes, before hedging what's left.
+            for (_, ibkr) in bots.iter_mut() {
+                if let Err(e) = ibkr.handle_expiring_positions(mode && !paused) {
+                    log_warn(format!("Failed to handle expiring positions: {}.", e));
+                }
+            }
+
+            // Keep the delta accumulated by held spreads within each profile's hedge threshold.
+            for (_, ibkr) in bots.iter_mut() {
+                if let Err(e) = ibkr.hedge_delta(mode && !paused) {
+                    log_warn(format!("Failed to hedge delta: {}.", e));
+                }
+            }
+
+            // Orders a failed submission placed anyway are cancelled like the rest.
+            if mode {
+                for (_, ibkr) in bots.iter_mut() {
+                    if let Err(e) = ibkr.adopt_orphaned_orders() {
+                        log_warn(format!("Failed to check for orphaned orders: {}.", e));
+                    }
+                }
+            }
+
+            if mode {
+                for (profile, ibkr) in bots.iter_mut() {
+                    if !order_monitor(profile) {
+                        ibkr.cancel_pending_orders();
+                    }
+                }
+            }
+            sleep_watched(Duration::from_secs(5));
         } else {
-            log_message(format!("Market is closed."));
-            break;
+            // Before the open, over a weekend or holiday, or too close to the close to start a
+            // session, wait for the next open keeping the gateway session alive.
+            let after: DateTime<Utc> = if winding_down {
+                now + chrono::Duration::minutes(config.wind_down_minutes + 1)
+            } else {
+                now
+            };
+            let next_open: DateTime<Utc> = next_market_open(after, &config.market_holidays);
+            log_message(format!(
+                "Market is closed, sleeping until the open at {}.",
+                next_open
+                    .with_timezone(&New_York)
+                    .format("%Y-%m-%d %H:%M %Z")
+            ));
+            health.state = "waiting_for_open".to_string();
+            let _ = write_health(&mut health);
+            let _ = notify(&format!(
+                "STATUS=Waiting for the open at {}",
+                next_open.to_rfc3339()
+            ));
+            while let Ok(remaining) = (next_open - clock.now()).to_std() {
+                sleep_watched(remaining.min(KEEPALIVE_INTERVAL));
+                if let Some(failover) = failover.as_mut() {
+                    check_gateway(failover, &bots, &mut health);
+                }
+                for (_, ibkr) in bots.iter() {
+                    if let Err(e) = ibkr.tickle() {
+                        log_warn(format!("{}.", e));
+                    }
+                }
+                let _ = write_health(&mut health);
+            }
+            log_message("Market is open.".to_string());
+        }
+    }
+    // Pull the orders still working and report on a session cut short before exiting.
+    if let (true, Some(since)) = (mode, session_start) {
+        end_session(&mut bots, since, start_net_liquidation, &mut health);
+    }
+
+    if let Some((_, ibkr)) = bots.first() {
+        if let Err(e) = ibkr.save_positions() {
+            log_warn(format!("Failed to save the book: {}.", e));
+        }
+    }
+    health.state = "exited".to_string();
+    health.live_order_count = bots.iter().map(|(_, ibkr)| ibkr.live_order_count()).sum();
+    let _ = write_health(&mut health);
+    log_message("Exiting...".to_string());
+    let _ = notify("STOPPING=1");
+    if let Some(code) = shutdown {
+        exit(code as i32);
+    }
+}
+
+// Function that winds a session down: pulls the orders still working, journals their final
+// statuses and logs the end of day report.
+fn end_session(
+    bots: &mut [(Profile, IBKR)],
+    since: DateTime<Utc>,
+    start_net_liquidation: Option<f64>,
+    health: &mut Health,
+) {
+    health.state = "winding_down".to_string();
+    let _ = write_health(health);
+    for (_, ibkr) in bots.iter_mut() {
+        if let Err(e) = ibkr.adopt_orphaned_orders() {
+            log_warn(format!("Failed to check for orphaned orders: {}.", e));
+        }
+        ibkr.cancel_pending_orders();
+        if let Err(e) = ibkr.poll_order_status() {
+            log_warn(format!("Failed to poll order status: {}.", e));
+        }
+    }
+
+    log_message("End of day report:".to_string());
+    for (type_spread, stat) in load_session_stats(JOURNAL_PATH, since) {
+        log_message(format!(
+            "\t{}: {} submitted, {} filled, {} cancelled, {} rejected, mean slippage {}, edge capture {}",
+            type_spread,
+            stat.submitted,
+            stat.filled,
+            stat.cancelled,
+            stat.rejected,
+            stat.avg_slippage()
+                .map(|slip| format!("{:.2}", slip))
+                .unwrap_or_else(|| "n/a".to_string()),
+            stat.edge_capture()
+                .map(|capture| format!("{:.0}%", capture * 100.0))
+                .unwrap_or_else(|| "n/a".to_string())
+        ));
+    }
+    if let (Some(start), Some(account)) = (start_net_liquidation, &health.account) {
+        log_message(format!(
+            "\tP&L {}, net liquidation {} to {}",
+            balance(account.net_liquidation - start),
+            balance(start),
+            balance(account.net_liquidation)
+        ));
+    }
+    let counters: Vec<String> = snapshot()
+        .iter()
+        .map(|(name, count)| format!("{} {}", name, count))
+        .collect();
+    if !counters.is_empty() {
+        log_message(format!("\tCounters: {}", counters.join(", ")));
+    }
+    for (endpoint, stats) in endpoint_snapshot() {
+        let codes: Vec<String> = stats
+            .error_codes
+            .iter()
+            .map(|(code, count)| format!("{} x{}", code, count))
+            .collect();
+        log_message(format!(
+            "\t{}: {} calls, mean {}ms, max {}ms, {} errors{}, {} paced",
+            endpoint,
+            stats.calls,
+            stats.mean_ms(),
+            stats.max_ms,
+            stats.errors,
+            if codes.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", codes.join(", "))
+            },
+            stats.paced
+        ));
+    }
+}
+
+// Function that health checks the primary gateway, routing every profile and its order monitor
+// to the other gateway when that tips the failover over.
+fn check_gateway(failover: &mut Failover, bots: &[(Profile, IBKR)], health: &mut Health) {
+    let ibkr: &IBKR = match bots.first() {
+        Some((_, ibkr)) => ibkr,
+        None => return,
+    };
+    let primary: Result<(), Box<dyn std::error::Error>> =
+        ibkr.tickle_gateway(failover.primary_url());
+    if let Err(e) = &primary {
+        log_debug(format!("Primary gateway health check failed: {}.", e));
+    }
+    let secondary_url: String = failover.secondary_url().to_string();
+    let role: GatewayRole = match failover.record_primary(primary.is_ok(), || {
+        ibkr.tickle_gateway(&secondary_url).is_ok()
+    }) {
+        Some(role) => role,
+        None => return,
+    };
+
+    for (_, ibkr) in bots {
+        ibkr.set_base_url(failover.active_url());
+    }
+    match role {
+        GatewayRole::Secondary => {
+            increment("gateway_failovers", 1);
+            log_warn(format!(
+                "Primary gateway is unhealthy, failing over to the secondary at {}.",
+                failover.active_url()
+            ));
+        }
+        GatewayRole::Primary => {
+            increment("gateway_failbacks", 1);
+            log_warn(format!(
+                "Primary gateway is healthy again, failing back to {}.",
+                failover.active_url()
+            ));
         }
     }
-    log_message(format!("Exiting..."));
+    let _ = notify(&format!("STATUS=Routing to the {} gateway", role.name()));
+    health.active_gateway = Some(role.name().to_string());
+    let _ = write_health(health);
 }
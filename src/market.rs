@@ -0,0 +1,154 @@
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::America::New_York;
+use std::collections::VecDeque;
+
+use crate::structs::Opt;
+
+// Scans with normal conditions needed before the spread baseline is trusted.
+const MIN_BASELINE_SCANS: usize = 5;
+
+// Trading conditions in an underlying, judged every scan. Arbs seen outside normal conditions
+// aren't executable, so orders are only submitted in the normal state.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MarketState {
+    Normal,
+    // The underlying's last price carries IBKR's "H" halted marker.
+    Halted,
+    // The chain's median bid/ask spread blew out against its recent baseline.
+    FastMarket { spread: f64, baseline: f64 },
+}
+
+// The arb threshold and strategies a scan runs with under the VIX regime filter.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Regime<'a> {
+    pub(crate) arb_val: f64,
+    pub(crate) enabled: Vec<&'a str>,
+}
+
+// Detector of halts and fast markets for one underlying.
+pub(crate) struct MarketMonitor {
+    spread_multiple: f64,
+    window: usize,
+    spreads: VecDeque<f64>,
+    state: MarketState,
+}
+
+impl MarketMonitor {
+    // Function that creates a monitor, a spread multiple of 0.0 disables fast market detection.
+    pub(crate) fn new(spread_multiple: f64, window: usize) -> Self {
+        MarketMonitor {
+            spread_multiple,
+            window: window.max(MIN_BASELINE_SCANS),
+            spreads: VecDeque::new(),
+            state: MarketState::Normal,
+        }
+    }
+
+    // Function that returns the current conditions.
+    pub(crate) fn state(&self) -> MarketState {
+        self.state
+    }
+
+    // Function that returns the median spread of recent scans with normal conditions.
+    fn baseline(&self) -> Option<f64> {
+        if self.spreads.len() < MIN_BASELINE_SCANS {
+            return None;
+        }
+        let mut spreads: Vec<f64> = self.spreads.iter().copied().collect();
+        spreads.sort_by(f64::total_cmp);
+        Some(spreads[spreads.len() / 2])
+    }
+
+    // Function that judges a scan's conditions from the halt marker and the chain's median
+    // spread, returning the new state when it changed. Only spreads seen in normal conditions
+    // feed the baseline, so a long fast market can't become the new normal.
+    pub(crate) fn update(&mut self, halted: bool, spread: Option<f64>) -> Option<MarketState> {
+        let state: MarketState = match (halted, spread, self.baseline()) {
+            (true, _, _) => MarketState::Halted,
+            (false, Some(spread), Some(baseline))
+                if self.spread_multiple > 0.0 && spread > baseline * self.spread_multiple =>
+            {
+                MarketState::FastMarket { spread, baseline }
+            }
+            _ => MarketState::Normal,
+        };
+
+        if let (MarketState::Normal, Some(spread)) = (state, spread) {
+            self.spreads.push_back(spread);
+            if self.spreads.len() > self.window {
+                self.spreads.pop_front();
+            }
+        }
+
+        let changed: bool = std::mem::discriminant(&state) != std::mem::discriminant(&self.state);
+        self.state = state;
+        if changed {
+            Some(state)
+        } else {
+            None
+        }
+    }
+}
+
+// Function that checks a last price field for IBKR's halted marker.
+pub(crate) fn is_halted_field(field: &str) -> bool {
+    field.trim_start().starts_with('H')
+}
+
+// Function that returns the median bid/ask spread as a fraction of mid over two sided quotes.
+pub(crate) fn median_spread(quotes: &[(f64, f64)]) -> Option<f64> {
+    let mut spreads: Vec<f64> = quotes
+        .iter()
+        .filter(|(bid, ask)| *bid > 0.0 && *ask >= *bid)
+        .map(|(bid, ask)| (ask - bid) / ((ask + bid) / 2.0))
+        .collect();
+    if spreads.is_empty() {
+        return None;
+    }
+    spreads.sort_by(f64::total_cmp);
+    Some(spreads[spreads.len() / 2])
+}
+
+// Function that applies the VIX regime filter to a scan: above the threshold the arb value is
+// scaled and the regime's strategies are disabled. A threshold of 0.0 disables the filter. With it
+// on, a VIX that couldn't be read leaves the regime unknown, and None skips the scan.
+pub(crate) fn vix_regime<'a>(
+    threshold: f64,
+    value: Option<f64>,
+    arb_val: f64,
+    scale: f64,
+    disabled: &[String],
+    codes: &[&'a str],
+) -> Option<Regime<'a>> {
+    if threshold <= 0.0 || value? <= threshold {
+        return Some(Regime {
+            arb_val,
+            enabled: codes.to_vec(),
+        });
+    }
+    Some(Regime {
+        arb_val: arb_val * scale,
+        enabled: codes
+            .iter()
+            .filter(|code| !disabled.iter().any(|s| s == *code))
+            .copied()
+            .collect(),
+    })
+}
+
+// Function that checks if the time falls within the first minutes after the 9:30 New York open.
+pub(crate) fn in_open_warmup(now: DateTime<Utc>, minutes: i64) -> bool {
+    let open: NaiveTime = NaiveTime::from_hms_opt(9, 30, 0).unwrap();
+    let since_open: i64 = (now.with_timezone(&New_York).time() - open).num_seconds();
+    (0..minutes * 60).contains(&since_open)
+}
+
+// Function that checks a quote's bid and ask moved at most max_change since the previous
+// snapshot, only two sided quotes count as stable.
+pub(crate) fn is_quote_stable(previous: &Opt, current: &Opt, max_change: f64) -> bool {
+    let two_sided = |quote: &Opt| quote.bid > 0.0 && quote.ask >= quote.bid;
+    two_sided(previous)
+        && two_sided(current)
+        && (current.bid - previous.bid).abs() <= max_change + 1e-9
+        && (current.ask - previous.ask).abs() <= max_change + 1e-9
+}
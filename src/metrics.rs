@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use crate::structs::Contender;
+
+// Process-wide counters, exported through the health file.
+static COUNTERS: OnceLock<Mutex<BTreeMap<String, u64>>> = OnceLock::new();
+
+fn counters() -> &'static Mutex<BTreeMap<String, u64>> {
+    COUNTERS.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+// Function that adds to a named counter.
+pub(crate) fn increment(name: &str, by: u64) {
+    *counters()
+        .lock()
+        .unwrap()
+        .entry(name.to_string())
+        .or_insert(0) += by;
+}
+
+// Function that returns a copy of all counters.
+pub(crate) fn snapshot() -> BTreeMap<String, u64> {
+    counters().lock().unwrap().clone()
+}
+
+// Process-wide request stats by endpoint, exported through the health file.
+static ENDPOINTS: OnceLock<Mutex<BTreeMap<String, EndpointStats>>> = OnceLock::new();
+
+fn endpoints() -> &'static Mutex<BTreeMap<String, EndpointStats>> {
+    ENDPOINTS.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+// Function that zeroes every counter and the endpoint stats, for the start of a new trading day.
+pub(crate) fn reset() {
+    counters().lock().unwrap().clear();
+    endpoints().lock().unwrap().clear();
+}
+
+// The gateway requests made to one endpoint: how many, how long they took, how many failed and
+// how many of those were pacing violations (429), with the count of each failed status. A
+// request that got no response counts under "none".
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct EndpointStats {
+    pub(crate) calls: u64,
+    pub(crate) errors: u64,
+    pub(crate) paced: u64,
+    pub(crate) total_ms: u64,
+    pub(crate) max_ms: u64,
+    pub(crate) error_codes: BTreeMap<String, u64>,
+}
+
+impl EndpointStats {
+    // Function that adds a request, with its status when it got a response.
+    pub(crate) fn record(&mut self, status: Option<u16>, latency: Duration) {
+        let ms: u64 = latency.as_millis() as u64;
+        self.calls += 1;
+        self.total_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+        if status.is_some_and(|status| (200..400).contains(&status)) {
+            return;
+        }
+        self.errors += 1;
+        if status == Some(429) {
+            self.paced += 1;
+        }
+        *self
+            .error_codes
+            .entry(status.map_or("none".to_string(), |status| status.to_string()))
+            .or_insert(0) += 1;
+    }
+
+    // Function that returns the mean latency in milliseconds, 0 without any calls.
+    pub(crate) fn mean_ms(&self) -> u64 {
+        self.total_ms.checked_div(self.calls).unwrap_or(0)
+    }
+}
+
+// Function that names a request's endpoint by its method and path, dropping the API prefix and
+// the query and standing in {id} for path segments with digits (account, conid and order ids),
+// so the calls to one endpoint count together.
+pub(crate) fn endpoint_key(method: &str, path: &str) -> String {
+    let path: &str = path.split('?').next().unwrap_or_default();
+    let path: &str = path.strip_prefix("/v1/api").unwrap_or(path);
+    let segments: Vec<&str> = path
+        .split('/')
+        .map(|segment| {
+            if segment.chars().any(|c| c.is_ascii_digit()) {
+                "{id}"
+            } else {
+                segment
+            }
+        })
+        .collect();
+    format!("{} {}", method, segments.join("/"))
+}
+
+// Function that records a request to an endpoint.
+pub(crate) fn record_request(endpoint: &str, status: Option<u16>, latency: Duration) {
+    endpoints()
+        .lock()
+        .unwrap()
+        .entry(endpoint.to_string())
+        .or_default()
+        .record(status, latency);
+}
+
+// Function that returns a copy of the stats of every endpoint.
+pub(crate) fn endpoint_snapshot() -> BTreeMap<String, EndpointStats> {
+    endpoints().lock().unwrap().clone()
+}
+
+// The quotes behind one profile's scan.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct ScanStats {
+    // Contracts with both a bid and an ask after validation.
+    pub(crate) quoted: usize,
+    pub(crate) validated: usize,
+    pub(crate) rejected: usize,
+}
+
+// One scan cycle's key numbers across every profile, logged as a single key=value line at the
+// end of the cycle so long logs can be skimmed and grepped for trends.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct CycleSummary {
+    pub(crate) quotes: ScanStats,
+    pub(crate) contenders: BTreeMap<String, usize>,
+    pub(crate) best_rank: Option<f64>,
+    pub(crate) orders_submitted: usize,
+    pub(crate) latency: Duration,
+}
+
+impl CycleSummary {
+    // Function that adds a profile's scan and the contenders it found.
+    pub(crate) fn add_scan(&mut self, stats: ScanStats, contenders: &[Contender]) {
+        self.quotes.quoted += stats.quoted;
+        self.quotes.validated += stats.validated;
+        self.quotes.rejected += stats.rejected;
+        for contender in contenders {
+            *self
+                .contenders
+                .entry(contender.type_spread.clone())
+                .or_insert(0) += 1;
+            if self
+                .best_rank
+                .is_none_or(|best| contender.rank_value > best)
+            {
+                self.best_rank = Some(contender.rank_value);
+            }
+        }
+    }
+
+    // Function that returns the share of validated quotes that passed, None without any.
+    pub(crate) fn valid_pct(&self) -> Option<f64> {
+        (self.quotes.validated > 0).then(|| {
+            (self.quotes.validated - self.quotes.rejected.min(self.quotes.validated)) as f64
+                / self.quotes.validated as f64
+                * 100.0
+        })
+    }
+
+    // Function that formats the summary as one line of key=value pairs.
+    pub(crate) fn to_line(&self, cycle: &str) -> String {
+        let contenders: Vec<String> = self
+            .contenders
+            .iter()
+            .map(|(type_spread, count)| format!("{}:{}", type_spread, count))
+            .collect();
+        format!(
+            "Cycle summary: cycle={} quoted={} valid_pct={} contenders={} best_rank={} orders={} latency_ms={}",
+            cycle,
+            self.quotes.quoted,
+            self.valid_pct()
+                .map(|pct| format!("{:.1}", pct))
+                .unwrap_or("na".to_string()),
+            if contenders.is_empty() {
+                "none".to_string()
+            } else {
+                contenders.join(",")
+            },
+            self.best_rank
+                .map(|rank| format!("{:.2}", rank))
+                .unwrap_or("na".to_string()),
+            self.orders_submitted,
+            self.latency.as_millis()
+        )
+    }
+}
@@ -0,0 +1,406 @@
+use chrono::{Duration as ChronoDuration, Utc};
+use chrono_tz::America::New_York;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+// Account, underlying and strikes the mock gateway lists.
+pub(crate) const MOCK_ACCOUNT: &str = "DU1234567";
+const MOCK_TICKER_CONID: u64 = 416904;
+const MOCK_STRIKES: [f64; 3] = [4995.0, 5000.0, 5005.0];
+// Expiries listed, in days from today.
+const MOCK_EXPIRIES: [i64; 3] = [7, 14, 21];
+// Cancelled orders still listed by the orders endpoint, like the gateway's recent history.
+const RECENT_CANCELS: usize = 50;
+
+// A failure injected in place of the gateway's answer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Fault {
+    // The request goes through, but no answer comes until well after the client's timeout,
+    // then the connection drops.
+    Timeout,
+    ServerError,
+    MalformedJson,
+    AuthExpired,
+}
+
+const FAULTS: [Fault; 4] = [
+    Fault::Timeout,
+    Fault::ServerError,
+    Fault::MalformedJson,
+    Fault::AuthExpired,
+];
+
+// What the mock gateway has been asked so far and the orders still working on it.
+#[derive(Default)]
+struct MockState {
+    fault_rate: f64,
+    stall: Duration,
+    rng: u64,
+    faults: Vec<Fault>,
+    requests: u64,
+    next_order_id: u64,
+    // Working orders by id, with the client order id they were placed with.
+    working: HashMap<String, String>,
+    cancelled: Vec<String>,
+    filled: Vec<String>,
+    // The account's portfolio positions, and how often their cache was invalidated.
+    positions: Vec<Value>,
+    positions_invalidated: u64,
+}
+
+impl MockState {
+    // Function that draws the next fault, if any, from a xorshift generator so runs repeat.
+    fn draw_fault(&mut self) -> Option<Fault> {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let roll: f64 = (self.rng % 1_000_000) as f64 / 1_000_000.0;
+        if roll >= self.fault_rate {
+            return None;
+        }
+        let fault: Fault = FAULTS[(self.rng >> 32) as usize % FAULTS.len()];
+        self.faults.push(fault);
+        Some(fault)
+    }
+}
+
+// An in-process stand-in for the Client Portal gateway, serving a small option chain with a
+// butterfly arb over plain HTTP and injecting faults at a configurable rate.
+pub(crate) struct MockGateway {
+    port: u16,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockGateway {
+    // Function that starts the gateway on a free local port, answering without faults.
+    pub(crate) fn start(seed: u64, stall: Duration) -> MockGateway {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port: u16 = listener.local_addr().unwrap().port();
+        let state: Arc<Mutex<MockState>> = Arc::new(Mutex::new(MockState {
+            rng: seed.max(1),
+            stall,
+            next_order_id: 1,
+            ..Default::default()
+        }));
+
+        let server_state: Arc<Mutex<MockState>> = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().map_while(Result::ok) {
+                let state: Arc<Mutex<MockState>> = Arc::clone(&server_state);
+                thread::spawn(move || serve_connection(stream, state));
+            }
+        });
+
+        MockGateway { port, state }
+    }
+
+    pub(crate) fn port(&self) -> u16 {
+        self.port
+    }
+
+    // Function that sets the share of requests answered with a fault.
+    pub(crate) fn set_fault_rate(&self, fault_rate: f64) {
+        self.state.lock().unwrap().fault_rate = fault_rate;
+    }
+
+    // Function that returns the faults injected so far.
+    pub(crate) fn faults(&self) -> Vec<Fault> {
+        self.state.lock().unwrap().faults.clone()
+    }
+
+    // Function that returns the number of requests answered so far.
+    pub(crate) fn requests(&self) -> u64 {
+        self.state.lock().unwrap().requests
+    }
+
+    // Function that returns the ids of the orders placed and not yet cancelled.
+    pub(crate) fn working_orders(&self) -> Vec<String> {
+        self.state.lock().unwrap().working.keys().cloned().collect()
+    }
+
+    // Function that returns the number of orders placed so far.
+    pub(crate) fn orders_placed(&self) -> u64 {
+        self.state.lock().unwrap().next_order_id - 1
+    }
+
+    // Function that fills every working order.
+    pub(crate) fn fill_working(&self) {
+        let mut state = self.state.lock().unwrap();
+        let filled: Vec<String> = state
+            .working
+            .drain()
+            .map(|(order_id, _)| order_id)
+            .collect();
+        state.filled.extend(filled);
+    }
+
+    // Function that sets the positions the portfolio endpoint lists.
+    pub(crate) fn set_positions(&self, positions: Vec<Value>) {
+        self.state.lock().unwrap().positions = positions;
+    }
+
+    // Function that returns how often the positions cache was invalidated.
+    pub(crate) fn positions_invalidated(&self) -> u64 {
+        self.state.lock().unwrap().positions_invalidated
+    }
+}
+
+// Function that answers requests on a keep-alive connection until the client hangs up.
+fn serve_connection(stream: TcpStream, state: Arc<Mutex<MockState>>) {
+    let mut reader: BufReader<TcpStream> = match stream.try_clone() {
+        Ok(read_half) => BufReader::new(read_half),
+        Err(_) => return,
+    };
+    let mut stream: TcpStream = stream;
+    let _ = stream.set_nodelay(true);
+
+    loop {
+        let mut request_line: String = String::new();
+        if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+            return;
+        }
+        let mut content_length: usize = 0;
+        loop {
+            let mut header: String = String::new();
+            if reader.read_line(&mut header).unwrap_or(0) == 0 {
+                return;
+            }
+            let header: &str = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, val)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = val.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+        let mut body: Vec<u8> = vec![0; content_length];
+        if reader.read_exact(&mut body).is_err() {
+            return;
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let method: String = parts.next().unwrap_or("").to_string();
+        let target: String = parts.next().unwrap_or("").to_string();
+
+        let (fault, stall): (Option<Fault>, Duration) = {
+            let mut state = state.lock().unwrap();
+            state.requests += 1;
+            (state.draw_fault(), state.stall)
+        };
+        let (status, payload): (u16, String) = match fault {
+            Some(Fault::Timeout) => {
+                route(&method, &target, &body, &state);
+                thread::sleep(stall);
+                return;
+            }
+            Some(Fault::ServerError) => (500, r#"{"error":"Internal Server Error"}"#.to_string()),
+            Some(Fault::MalformedJson) => (200, r#"[{"conid": 12, "84": "1.0"#.to_string()),
+            Some(Fault::AuthExpired) => (401, r#"{"error":"not authenticated"}"#.to_string()),
+            None => route(&method, &target, &body, &state),
+        };
+
+        let reason: &str = match status {
+            200 => "OK",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            _ => "Internal Server Error",
+        };
+        let response: String = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            status,
+            reason,
+            payload.len(),
+            payload
+        );
+        if stream.write_all(response.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+// Function that returns the value of a query parameter in a request target.
+fn query_param<'a>(target: &'a str, key: &str) -> Option<&'a str> {
+    target
+        .split_once('?')?
+        .1
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == key)
+        .map(|(_, val)| val)
+}
+
+// Function that returns the conid, expiry, right and strike of every listed option, conids
+// counting up from 1000.
+fn mock_contracts() -> Vec<(u64, String, &'static str, f64)> {
+    let today = Utc::now().with_timezone(&New_York).date_naive();
+    let mut contracts: Vec<(u64, String, &'static str, f64)> = Vec::new();
+    for (i, days) in MOCK_EXPIRIES.iter().enumerate() {
+        let expiry: String = (today + ChronoDuration::days(*days))
+            .format("%Y%m%d")
+            .to_string();
+        for (j, right) in ["C", "P"].iter().enumerate() {
+            for (k, strike) in MOCK_STRIKES.iter().enumerate() {
+                let conid: u64 = 1000 + (i * 100 + j * 10 + k) as u64;
+                contracts.push((conid, expiry.clone(), right, *strike));
+            }
+        }
+    }
+    contracts
+}
+
+// Function that returns an option's bid and ask. Call mids of 10, 8 and 5 across the strikes
+// leave a butterfly credit of 1.0 at the middle one.
+fn mock_quote(right: &str, strike: f64) -> (f64, f64) {
+    let mid: f64 = match (right, strike as i64) {
+        ("C", 4995) => 10.0,
+        ("C", 5000) => 8.0,
+        ("C", _) => 5.0,
+        (_, 4995) => 5.0,
+        (_, 5000) => 7.0,
+        _ => 9.5,
+    };
+    (mid - 0.05, mid + 0.05)
+}
+
+// Function that answers a request the way the gateway would.
+fn route(method: &str, target: &str, body: &[u8], state: &Mutex<MockState>) -> (u16, String) {
+    let path: &str = target.split('?').next().unwrap_or("");
+    let ok = |payload: Value| (200, payload.to_string());
+
+    match (method, path) {
+        ("GET", "/v1/api/portfolio/accounts") => ok(json!([{ "id": MOCK_ACCOUNT }])),
+        ("GET", "/v1/api/iserver/secdef/search") => {
+            let symbol: &str = query_param(target, "symbol").unwrap_or("");
+            if symbol == "VIX" {
+                return ok(json!([]));
+            }
+            ok(json!([{
+                "companyName": symbol,
+                "conid": MOCK_TICKER_CONID.to_string(),
+                "sections": [{ "secType": "OPT", "months": "JAN25;FEB25;MAR25" }]
+            }]))
+        }
+        ("GET", "/v1/api/iserver/secdef/strikes") => {
+            ok(json!({ "call": MOCK_STRIKES, "put": MOCK_STRIKES }))
+        }
+        ("GET", "/v1/api/iserver/secdef/info") => {
+            let strike: f64 = query_param(target, "strike")
+                .and_then(|strike| strike.parse().ok())
+                .unwrap_or(0.0);
+            let contracts: Vec<Value> = mock_contracts()
+                .into_iter()
+                .filter(|(_, _, _, s)| *s == strike)
+                .map(|(conid, expiry, right, strike)| {
+                    json!({ "conid": conid, "maturityDate": expiry, "right": right, "strike": strike })
+                })
+                .collect();
+            ok(Value::Array(contracts))
+        }
+        ("GET", "/v1/api/iserver/marketdata/snapshot") => {
+            let contracts = mock_contracts();
+            let quotes: Vec<Value> = query_param(target, "conids")
+                .unwrap_or("")
+                .split("%2C")
+                .flat_map(|conids| conids.split(','))
+                .filter_map(|conid| {
+                    if conid == MOCK_TICKER_CONID.to_string() {
+                        return Some(json!({ "conid": conid, "31": "5000.00" }));
+                    }
+                    let (_, _, right, strike) =
+                        contracts.iter().find(|c| c.0.to_string() == conid)?;
+                    let (bid, ask): (f64, f64) = mock_quote(right, *strike);
+                    Some(json!({
+                        "conid": conid.parse::<u64>().ok()?,
+                        "84": format!("{:.2}", bid),
+                        "85": "50",
+                        "86": format!("{:.2}", ask),
+                        "6509": "RpB"
+                    }))
+                })
+                .collect();
+            ok(Value::Array(quotes))
+        }
+        ("GET", path) if path == format!("/v1/api/portfolio/{}/summary", MOCK_ACCOUNT) => {
+            ok(json!({
+                "equitywithloanvalue": { "amount": 100000.0 },
+                "netliquidation": { "amount": 100000.0 }
+            }))
+        }
+        ("POST", path) if path == format!("/v1/api/iserver/account/{}/orders", MOCK_ACCOUNT) => {
+            let request: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+            let mut state = state.lock().unwrap();
+            let placed: Vec<Value> = request["orders"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|order| {
+                    let order_id: String = state.next_order_id.to_string();
+                    state.next_order_id += 1;
+                    let c_oid: String = order["cOID"].as_str().unwrap_or("").to_string();
+                    state.working.insert(order_id.clone(), c_oid);
+                    json!({ "order_id": order_id, "order_status": "Submitted" })
+                })
+                .collect();
+            ok(Value::Array(placed))
+        }
+        ("DELETE", path) => {
+            let order_id: &str = path.rsplit('/').next().unwrap_or("");
+            let mut state = state.lock().unwrap();
+            if state.working.remove(order_id).is_some() {
+                state.cancelled.push(order_id.to_string());
+                if state.cancelled.len() > RECENT_CANCELS {
+                    state.cancelled.remove(0);
+                }
+                ok(json!({ "msg": "Request was submitted", "order_id": order_id }))
+            } else {
+                (404, json!({ "error": "order not found" }).to_string())
+            }
+        }
+        ("GET", "/v1/api/iserver/account/trades") => ok(json!([])),
+        ("GET", path) if path == format!("/v1/api/portfolio/{}/positions/0", MOCK_ACCOUNT) => {
+            ok(Value::Array(state.lock().unwrap().positions.clone()))
+        }
+        ("POST", path)
+            if path == format!("/v1/api/portfolio/{}/positions/invalidate", MOCK_ACCOUNT) =>
+        {
+            state.lock().unwrap().positions_invalidated += 1;
+            ok(json!({ "message": "success" }))
+        }
+        ("GET", path) if path.starts_with("/v1/api/iserver/contract/") => {
+            ok(json!({ "symbol": "MES", "instrument_type": "FUT" }))
+        }
+        ("GET", "/v1/api/iserver/account/orders") => {
+            let state = state.lock().unwrap();
+            let orders: Vec<Value> = state
+                .working
+                .iter()
+                .map(|(order_id, c_oid)| {
+                    json!({ "orderId": order_id, "status": "Submitted", "order_ref": c_oid })
+                })
+                .chain(
+                    state
+                        .cancelled
+                        .iter()
+                        .map(|order_id| json!({ "orderId": order_id, "status": "Cancelled" })),
+                )
+                .chain(
+                    state
+                        .filled
+                        .iter()
+                        .map(|order_id| json!({ "orderId": order_id, "status": "Filled" })),
+                )
+                .collect();
+            ok(json!({ "orders": orders }))
+        }
+        _ => (404, json!({ "error": "not found" }).to_string()),
+    }
+}
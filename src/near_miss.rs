@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::encryption::read_lines;
+
+// How far each threshold is lowered in the sensitivity table.
+const THRESHOLD_STEPS: [f64; 5] = [0.01, 0.02, 0.05, 0.10, 0.25];
+
+// Near misses turned down by one of a strategy's filters.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct FilterMisses {
+    pub(crate) count: usize,
+    // How far each miss fell short, for the filters that compare against a threshold.
+    pub(crate) shortfalls: Vec<f64>,
+}
+
+impl FilterMisses {
+    // Function that counts the misses the filter's threshold lowered by `step` would have passed.
+    pub(crate) fn gained(&self, step: f64) -> usize {
+        self.shortfalls
+            .iter()
+            .filter(|shortfall| **shortfall <= step + 1e-9)
+            .count()
+    }
+}
+
+// Function that groups the near misses in journal lines by strategy and the filter that turned
+// them down.
+pub(crate) fn near_misses<I: Iterator<Item = String>>(
+    lines: I,
+) -> BTreeMap<(String, String), FilterMisses> {
+    let mut misses: BTreeMap<(String, String), FilterMisses> = BTreeMap::new();
+    for record in lines.filter_map(|line| serde_json::from_str::<Value>(&line).ok()) {
+        if record["event"].as_str() != Some("near_miss") {
+            continue;
+        }
+        let (type_spread, filter) =
+            match (record["type_spread"].as_str(), record["filter"].as_str()) {
+                (Some(type_spread), Some(filter)) => (type_spread, filter),
+                _ => continue,
+            };
+        let entry: &mut FilterMisses = misses
+            .entry((type_spread.to_string(), filter.to_string()))
+            .or_default();
+        entry.count += 1;
+        entry.shortfalls.extend(record["shortfall"].as_f64());
+    }
+    misses
+}
+
+// Function that formats the near misses per strategy and filter, with how many more contenders
+// each threshold would have passed if it were lowered.
+pub(crate) fn near_miss_report(misses: &BTreeMap<(String, String), FilterMisses>) -> String {
+    if misses.is_empty() {
+        return "No near misses recorded, set NEAR_MISS_MARGIN to track them.\n".to_string();
+    }
+
+    let mut report: String =
+        "Near misses by strategy and the one filter they failed:\n".to_string();
+    for ((type_spread, filter), entry) in misses {
+        report.push_str(&format!("  {} {}: {}", type_spread, filter, entry.count));
+        if !entry.shortfalls.is_empty() {
+            let steps: Vec<String> = THRESHOLD_STEPS
+                .iter()
+                .map(|step| format!("{:.2}: +{}", step, entry.gained(*step)))
+                .collect();
+            report.push_str(&format!(", threshold lowered by {}", steps.join(", ")));
+        }
+        report.push('\n');
+    }
+    report
+}
+
+// Function that formats the near miss report from the journal file.
+pub(crate) fn load_near_miss_report(path: &str) -> Result<String, String> {
+    let lines = read_lines(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    Ok(near_miss_report(&near_misses(lines)))
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use crate::structs::OrderBody;
+
+// A batch order after netting: the order to submit and the batch orders it stands for, by
+// index. An order that netted with nothing stands for itself alone.
+#[derive(Clone)]
+pub(crate) struct NettedOrder {
+    pub(crate) order: OrderBody,
+    pub(crate) members: Vec<usize>,
+}
+
+impl NettedOrder {
+    // Function that checks whether the order consolidates several batch orders.
+    pub(crate) fn is_netted(&self) -> bool {
+        self.members.len() > 1
+    }
+}
+
+// Orders merged so far: the combo prefix of the first, the contracts each leg nets to (positive
+// to buy) in the order they were first seen, and the orders merged.
+struct Group {
+    prefix: String,
+    legs: Vec<(String, i64)>,
+    members: Vec<usize>,
+}
+
+impl Group {
+    // Function that returns the position of a leg's contracts, 0 when the group has none.
+    fn position(&self, conid: &str) -> i64 {
+        self.legs
+            .iter()
+            .find(|(leg, _)| leg == conid)
+            .map_or(0, |(_, contracts)| *contracts)
+    }
+
+    // Function that adds an order's legs to the group, dropping the legs that net to nothing.
+    fn add(&mut self, legs: &[(String, i64)], index: usize) {
+        for (conid, contracts) in legs {
+            match self.legs.iter_mut().find(|(leg, _)| leg == conid) {
+                Some((_, position)) => *position += contracts,
+                None => self.legs.push((conid.clone(), *contracts)),
+            }
+        }
+        self.legs.retain(|(_, contracts)| *contracts != 0);
+        self.members.push(index);
+    }
+}
+
+// Function that returns a combo order's legs as the contracts it trades of each, positive to
+// buy, with its combo prefix. None for an order that isn't a combo.
+fn combo_legs(order: &OrderBody) -> Option<(String, Vec<(String, i64)>)> {
+    let (prefix, legs) = order.con_idex.split_once(";;;")?;
+    let side: i64 = if order.side == "SELL" { -1 } else { 1 };
+    let legs: Vec<(String, i64)> = legs
+        .split(',')
+        .map(|leg| {
+            let (conid, ratio) = leg.split_once('/')?;
+            let ratio: i64 = ratio.parse::<i64>().ok()?;
+            Some((conid.to_string(), ratio * order.quantity as i64 * side))
+        })
+        .collect::<Option<Vec<(String, i64)>>>()?;
+    Some((prefix.to_string(), legs))
+}
+
+// Function that returns the greatest common divisor of two numbers.
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// Function that returns a netted order's price per combo from its members' prices, what the
+// members pay (or receive, selling) in all divided over the netted quantity.
+pub(crate) fn netted_price(
+    orders: &[OrderBody],
+    members: &[usize],
+    prices: &[f64],
+    quantity: i32,
+) -> f64 {
+    members
+        .iter()
+        .map(|index| {
+            let side: f64 = if orders[*index].side == "SELL" {
+                -1.0
+            } else {
+                1.0
+            };
+            prices[*index] * orders[*index].quantity as f64 * side
+        })
+        .sum::<f64>()
+        / quantity as f64
+}
+
+// Function that nets the eligible orders of a batch: an order trading a leg the other way from
+// an earlier one is merged into it, as long as at least two legs are left, so contracts bought
+// by one contender and sold by another never go to the exchange. The merged combo keeps the
+// legs' net contracts at their smallest ratios, and its limit is the members' limits combined,
+// so it gives up no edge. Orders that aren't eligible or don't net pass through unchanged.
+pub(crate) fn net_orders(orders: &[OrderBody], eligible: &[bool]) -> Vec<NettedOrder> {
+    let mut groups: Vec<Group> = Vec::new();
+    let mut passed: Vec<usize> = Vec::new();
+    for (index, order) in orders.iter().enumerate() {
+        let (prefix, legs) = match combo_legs(order) {
+            Some(combo) if eligible.get(index).copied().unwrap_or(false) => combo,
+            _ => {
+                passed.push(index);
+                continue;
+            }
+        };
+        let fits = |group: &Group| -> bool {
+            let first: &OrderBody = &orders[group.members[0]];
+            group.prefix == prefix
+                && first.ticker == order.ticker
+                && first.order_type == order.order_type
+                && first.tif == order.tif
+                && legs
+                    .iter()
+                    .any(|(conid, contracts)| group.position(conid).signum() == -contracts.signum())
+                && {
+                    let mut left: HashMap<&str, i64> = group
+                        .legs
+                        .iter()
+                        .map(|(conid, contracts)| (conid.as_str(), *contracts))
+                        .collect();
+                    for (conid, contracts) in &legs {
+                        *left.entry(conid.as_str()).or_insert(0) += contracts;
+                    }
+                    left.values().filter(|contracts| **contracts != 0).count() >= 2
+                }
+        };
+        match groups.iter_mut().find(|group| fits(group)) {
+            Some(group) => group.add(&legs, index),
+            None => {
+                let mut group: Group = Group {
+                    prefix,
+                    legs: Vec::new(),
+                    members: Vec::new(),
+                };
+                group.add(&legs, index);
+                groups.push(group);
+            }
+        }
+    }
+
+    let prices: Vec<f64> = orders.iter().map(|order| order.price).collect();
+    let mut netted: Vec<NettedOrder> = groups
+        .into_iter()
+        .map(|group| {
+            let first: &OrderBody = &orders[group.members[0]];
+            if group.members.len() == 1 {
+                return NettedOrder {
+                    order: first.clone(),
+                    members: group.members,
+                };
+            }
+            let quantity: i64 = group
+                .legs
+                .iter()
+                .fold(0, |divisor, (_, contracts)| gcd(divisor, *contracts));
+            let legs: Vec<String> = group
+                .legs
+                .iter()
+                .map(|(conid, contracts)| format!("{}/{}", conid, contracts / quantity))
+                .collect();
+            NettedOrder {
+                order: OrderBody {
+                    con_idex: format!("{};;;{}", group.prefix, legs.join(",")),
+                    price: netted_price(orders, &group.members, &prices, quantity as i32),
+                    side: "BUY".to_string(),
+                    quantity: quantity as i32,
+                    ..first.clone()
+                },
+                members: group.members,
+            }
+        })
+        .collect();
+    netted.extend(passed.into_iter().map(|index| NettedOrder {
+        order: orders[index].clone(),
+        members: vec![index],
+    }));
+    netted.sort_by_key(|order| order.members[0]);
+    netted
+}
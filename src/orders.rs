@@ -1,15 +1,85 @@
-use std::collections::HashMap;
+use chrono::Utc;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
 
-use ordered_float::OrderedFloat;
+use crate::{
+    chain::ChainView,
+    structs::{Contender, Contract, LiveOrder, OrderBody, OrderTags, RequestDataStruct, TickRule},
+};
 
-use crate::structs::{Contender, OrderBody, RequestDataStruct};
+// Function that returns the conid of a contender's leg.
+fn leg_conid<'a>(chain: &'a ChainView, contract: &Contender, index: usize) -> &'a String {
+    let leg: &Contract = &contract.contracts[index];
+    chain
+        .conid(&leg.date, &leg.type_contract, leg.strike)
+        .expect("contender leg is missing from the chain")
+}
+
+// Function that builds a market order for a quantity of a conid or combo conidex, negative to
+// sell.
+pub(crate) fn build_market_order(
+    account_id: &Option<String>,
+    ticker: &Option<String>,
+    con_idex: &str,
+    quantity: i32,
+) -> OrderBody {
+    OrderBody {
+        acct_id: account_id.clone().unwrap(),
+        con_idex: con_idex.to_string(),
+        order_type: "MKT".to_string(),
+        listing_exchange: "SMART".to_string(),
+        outside_rth: false,
+        price: 0.0,
+        side: if quantity > 0 { "BUY" } else { "SELL" }.to_string(),
+        ticker: ticker.clone().unwrap(),
+        tif: "DAY".to_string(),
+        referrer: "NO_REFERRER_PROVIDED".to_string(),
+        quantity: quantity.abs(),
+        use_adaptive: false,
+        c_oid: None,
+    }
+}
+
+// Function that builds the combo order rolling a calendar's near leg: buying back the expiring
+// short contract and selling the same strike in the next expiry, quantity times.
+pub(crate) fn build_roll_order(
+    account_id: &Option<String>,
+    ticker: &Option<String>,
+    near_conid: &str,
+    next_conid: &str,
+    quantity: i32,
+    price: f64,
+) -> OrderBody {
+    OrderBody {
+        acct_id: account_id.clone().unwrap(),
+        con_idex: format!("28812380;;;{}/1,{}/-1", near_conid, next_conid),
+        order_type: "LMT".to_string(),
+        listing_exchange: "SMART".to_string(),
+        outside_rth: false,
+        price,
+        side: "BUY".to_string(),
+        ticker: ticker.clone().unwrap(),
+        tif: "DAY".to_string(),
+        referrer: "NO_REFERRER_PROVIDED".to_string(),
+        quantity,
+        use_adaptive: false,
+        c_oid: Some(format!(
+            "tbr-roll-{}-{}",
+            Utc::now().timestamp_millis(),
+            near_conid
+        )),
+    }
+}
 
 // Function that builds calendar order body.
 pub(crate) fn build_calendar_order(
     contract: &Contender,
     num_fills: i32,
     account_id: &Option<String>,
-    conids_map: &Option<HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>>,
+    ticker: &Option<String>,
+    chain: &ChainView,
     discount_value: Option<f64>,
 ) -> OrderBody {
     let order_val: f64 = contract.arb_val;
@@ -17,23 +87,20 @@ pub(crate) fn build_calendar_order(
         acct_id: account_id.clone().unwrap(),
         con_idex: format!(
             "28812380;;;{}/-1,{}/1",
-            conids_map.as_ref().unwrap()[contract.contracts[0].date.as_str()]
-                [contract.contracts[0].type_contract.as_str()]
-                [(&contract.contracts[0].strike).into()],
-            conids_map.as_ref().unwrap()[contract.contracts[1].date.as_str()]
-                [contract.contracts[1].type_contract.as_str()]
-                [(&contract.contracts[1].strike).into()]
+            leg_conid(chain, contract, 0),
+            leg_conid(chain, contract, 1),
         ),
         order_type: "LMT".to_string(),
         listing_exchange: "SMART".to_string(),
         outside_rth: false,
-        price: -1.0 * (((order_val - discount_value.unwrap()) * 100.0).round() / 100.0),
+        price: -(((order_val - discount_value.unwrap()) * 100.0).round() / 100.0),
         side: "BUY".to_string(),
-        ticker: "SPX".to_string(),
+        ticker: ticker.clone().unwrap(),
         tif: "DAY".to_string(),
         referrer: "NO_REFERRER_PROVIDED".to_string(),
         quantity: num_fills,
         use_adaptive: false,
+        c_oid: None,
     }
 }
 
@@ -42,7 +109,8 @@ pub(crate) fn build_butterfly_bull_order(
     contract: &Contender,
     num_fills: i32,
     account_id: &Option<String>,
-    conids_map: &Option<HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>>,
+    ticker: &Option<String>,
+    chain: &ChainView,
     discount_value: Option<f64>,
 ) -> OrderBody {
     let order_val: f64 = contract.contracts[0].mkt_price - contract.contracts[1].mkt_price;
@@ -50,23 +118,20 @@ pub(crate) fn build_butterfly_bull_order(
         acct_id: account_id.clone().unwrap(),
         con_idex: format!(
             "28812380;;;{}/-1,{}/1",
-            conids_map.as_ref().unwrap()[contract.contracts[1].date.as_str()]
-                [contract.contracts[1].type_contract.as_str()]
-                [(&contract.contracts[1].strike).into()],
-            conids_map.as_ref().unwrap()[contract.contracts[0].date.as_str()]
-                [contract.contracts[0].type_contract.as_str()]
-                [(&contract.contracts[0].strike).into()]
+            leg_conid(chain, contract, 1),
+            leg_conid(chain, contract, 0),
         ),
         order_type: "LMT".to_string(),
         listing_exchange: "SMART".to_string(),
         outside_rth: false,
         price: ((order_val + discount_value.unwrap()) * 100.0).round() / 100.0,
         side: "BUY".to_string(),
-        ticker: "SPX".to_string(),
+        ticker: ticker.clone().unwrap(),
         tif: "DAY".to_string(),
         referrer: "NO_REFERRER_PROVIDED".to_string(),
         quantity: num_fills,
         use_adaptive: false,
+        c_oid: None,
     }
 }
 
@@ -75,7 +140,8 @@ pub(crate) fn build_butterfly_bear_order(
     contract: &Contender,
     num_fills: i32,
     account_id: &Option<String>,
-    conids_map: &Option<HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>>,
+    ticker: &Option<String>,
+    chain: &ChainView,
     discount_value: Option<f64>,
 ) -> OrderBody {
     let order_val: f64 = contract.contracts[2].mkt_price - contract.contracts[1].mkt_price;
@@ -83,23 +149,20 @@ pub(crate) fn build_butterfly_bear_order(
         acct_id: account_id.clone().unwrap(),
         con_idex: format!(
             "28812380;;;{}/-1,{}/1",
-            conids_map.as_ref().unwrap()[contract.contracts[1].date.as_str()]
-                [contract.contracts[1].type_contract.as_str()]
-                [(&contract.contracts[1].strike).into()],
-            conids_map.as_ref().unwrap()[contract.contracts[2].date.as_str()]
-                [contract.contracts[2].type_contract.as_str()]
-                [(&contract.contracts[2].strike).into()]
+            leg_conid(chain, contract, 1),
+            leg_conid(chain, contract, 2),
         ),
         order_type: "LMT".to_string(),
         listing_exchange: "SMART".to_string(),
         outside_rth: false,
         price: (((order_val + discount_value.unwrap()) * 100.0).round() / 100.0),
         side: "BUY".to_string(),
-        ticker: "SPX".to_string(),
+        ticker: ticker.clone().unwrap(),
         tif: "DAY".to_string(),
         referrer: "NO_REFERRER_PROVIDED".to_string(),
         quantity: num_fills,
         use_adaptive: false,
+        c_oid: None,
     }
 }
 
@@ -108,7 +171,8 @@ pub(crate) fn build_boxspread_put_order(
     contract: &Contender,
     num_fills: i32,
     account_id: &Option<String>,
-    conids_map: &Option<HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>>,
+    ticker: &Option<String>,
+    chain: &ChainView,
     discount_value: Option<f64>,
 ) -> OrderBody {
     let order_val: f64 = contract.contracts[0].mkt_price - contract.contracts[3].mkt_price;
@@ -116,23 +180,20 @@ pub(crate) fn build_boxspread_put_order(
         acct_id: account_id.clone().unwrap(),
         con_idex: format!(
             "28812380;;;{}/-1,{}/1",
-            conids_map.as_ref().unwrap()[contract.contracts[3].date.as_str()] // Short right dated Put.
-                [contract.contracts[3].type_contract.as_str()]
-                [(&contract.contracts[3].strike).into()],
-            conids_map.as_ref().unwrap()[contract.contracts[0].date.as_str()] // Long current dated Put.
-                [contract.contracts[0].type_contract.as_str()]
-                [(&contract.contracts[0].strike).into()]
+            leg_conid(chain, contract, 3), // Short right dated Put.
+            leg_conid(chain, contract, 0)  // Long current dated Put.
         ),
         order_type: "LMT".to_string(),
         listing_exchange: "SMART".to_string(),
         outside_rth: false,
         price: (((order_val + discount_value.unwrap()) * 100.0).round() / 100.0),
         side: "BUY".to_string(),
-        ticker: "SPX".to_string(),
+        ticker: ticker.clone().unwrap(),
         tif: "DAY".to_string(),
         referrer: "NO_REFERRER_PROVIDED".to_string(),
         quantity: num_fills,
         use_adaptive: false,
+        c_oid: None,
     }
 }
 
@@ -141,7 +202,8 @@ pub(crate) fn build_boxspread_call_order(
     contract: &Contender,
     num_fills: i32,
     account_id: &Option<String>,
-    conids_map: &Option<HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>>,
+    ticker: &Option<String>,
+    chain: &ChainView,
     discount_value: Option<f64>,
 ) -> OrderBody {
     let order_val: f64 = contract.contracts[2].mkt_price - contract.contracts[1].mkt_price;
@@ -149,82 +211,244 @@ pub(crate) fn build_boxspread_call_order(
         acct_id: account_id.clone().unwrap(),
         con_idex: format!(
             "28812380;;;{}/1,{}/-1",
-            conids_map.as_ref().unwrap()[contract.contracts[2].date.as_str()] // Long right dated Call.
-                [contract.contracts[2].type_contract.as_str()]
-                [(&contract.contracts[2].strike).into()],
-            conids_map.as_ref().unwrap()[contract.contracts[1].date.as_str()] // Short current dated Call.
-                [contract.contracts[1].type_contract.as_str()]
-                [(&contract.contracts[1].strike).into()]
+            leg_conid(chain, contract, 2), // Long right dated Call.
+            leg_conid(chain, contract, 1)  // Short current dated Call.
         ),
         order_type: "LMT".to_string(),
         listing_exchange: "SMART".to_string(),
         outside_rth: false,
         price: (((order_val + discount_value.unwrap()) * 100.0).round() / 100.0),
         side: "BUY".to_string(),
-        ticker: "SPX".to_string(),
+        ticker: ticker.clone().unwrap(),
         tif: "DAY".to_string(),
         referrer: "NO_REFERRER_PROVIDED".to_string(),
         quantity: num_fills,
         use_adaptive: false,
+        c_oid: None,
+    }
+}
+
+// Function that splits a contender's fills across the rungs of a price ladder, returning each
+// rung's quantity and how much more edge than the discount it gives up. Earlier rungs take the
+// remainder, and a step of 0 or a single fill keeps one order at the discount.
+pub(crate) fn ladder_rungs(num_fills: i32, rungs: i32, step: f64) -> Vec<(i32, f64)> {
+    if step <= 0.0 || num_fills <= 1 || rungs <= 1 {
+        return vec![(num_fills, 0.0)];
     }
+    let rungs: i32 = rungs.min(num_fills);
+    (0..rungs)
+        .map(|rung| {
+            let quantity: i32 = num_fills / rungs + i32::from(rung < num_fills % rungs);
+            (quantity, ((rung as f64 * step) * 100.0).round() / 100.0)
+        })
+        .collect()
+}
+
+// Function that splits working orders into the ones to cancel and the ones left working. An
+// order is cancelled once it has worked order_lifetime seconds, or once its combo's bid has
+// moved more than max_drift above its price, the market having left the order behind. Orders
+// without a quote for their combo aren't judged on drift, and 0 disables either check.
+pub(crate) fn expired_orders(
+    orders: Vec<LiveOrder>,
+    now: Instant,
+    order_lifetime: u64,
+    max_drift: f64,
+    combo_bids: &HashMap<String, f64>,
+) -> (Vec<LiveOrder>, Vec<LiveOrder>) {
+    orders.into_iter().partition(|order| {
+        let too_old: bool = order_lifetime > 0
+            && now.duration_since(order.submitted_at) >= Duration::from_secs(order_lifetime);
+        let drifted: bool = max_drift > 0.0
+            && order.order.as_ref().is_some_and(|body| {
+                combo_bids
+                    .get(&body.con_idex)
+                    .is_some_and(|best_bid| best_bid - body.price > max_drift)
+            });
+        too_old || drifted
+    })
+}
+
+// Function that returns the working orders laddered alongside an order that filled, the rungs
+// to cancel so the combo isn't bought more than once.
+pub(crate) fn ladder_siblings(live_orders: &[LiveOrder], filled: &HashSet<String>) -> Vec<String> {
+    let groups: HashSet<&String> = live_orders
+        .iter()
+        .filter(|order| filled.contains(&order.order_id))
+        .filter_map(|order| order.ladder_group.as_ref())
+        .collect();
+    live_orders
+        .iter()
+        .filter(|order| {
+            !filled.contains(&order.order_id)
+                && order
+                    .ladder_group
+                    .as_ref()
+                    .is_some_and(|group| groups.contains(group))
+        })
+        .map(|order| order.order_id.clone())
+        .collect()
 }
 
-// Function that builds request data for json body to submit an order.
+// Function that builds request data for json body to submit an order, one order per contender
+// and rung of its ladder, or a pair for butterflies and boxes. Contenders without a ladder are
+// skipped.
 pub(crate) fn build_request_data(
-    contender_contracts: &Vec<Contender>,
-    num_fills: i32,
+    contender_contracts: &[Contender],
+    ladders: &[Vec<(i32, f64)>],
     account_id: &Option<String>,
-    conids_map: &Option<HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>>,
+    ticker: &Option<String>,
+    chain: &ChainView,
     discount_value: Option<f64>,
+    order_tags: &OrderTags,
 ) -> RequestDataStruct {
-    let mut request_data: RequestDataStruct = RequestDataStruct { orders: Vec::new() };
-
-    for contract in contender_contracts {
-        match contract.type_spread.as_str() {
-            "Calendar" => {
-                request_data.orders.push(build_calendar_order(
-                    contract,
-                    num_fills,
-                    account_id,
-                    conids_map,
-                    discount_value,
-                ));
+    let mut request_data: RequestDataStruct = RequestDataStruct {
+        orders: Vec::new(),
+        contender_index: Vec::new(),
+        netted: Vec::new(),
+    };
+    let batch: i64 = Utc::now().timestamp_millis();
+
+    for (index, contract) in contender_contracts.iter().enumerate() {
+        let before: usize = request_data.orders.len();
+        let ladder: &[(i32, f64)] = ladders.get(index).map(Vec::as_slice).unwrap_or_default();
+        for (num_fills, give_up) in ladder.iter().copied() {
+            let discount_value: Option<f64> = discount_value.map(|discount| discount + give_up);
+            match contract.type_spread.as_str() {
+                "Calendar" => {
+                    request_data.orders.push(build_calendar_order(
+                        contract,
+                        num_fills,
+                        account_id,
+                        ticker,
+                        chain,
+                        discount_value,
+                    ));
+                }
+                "Butterfly" => {
+                    request_data.orders.push(build_butterfly_bull_order(
+                        contract,
+                        num_fills,
+                        account_id,
+                        ticker,
+                        chain,
+                        discount_value,
+                    ));
+                    request_data.orders.push(build_butterfly_bear_order(
+                        contract,
+                        num_fills,
+                        account_id,
+                        ticker,
+                        chain,
+                        discount_value,
+                    ));
+                }
+                "Boxspread" => {
+                    request_data.orders.push(build_boxspread_put_order(
+                        contract,
+                        num_fills,
+                        account_id,
+                        ticker,
+                        chain,
+                        discount_value,
+                    ));
+                    request_data.orders.push(build_boxspread_call_order(
+                        contract,
+                        num_fills,
+                        account_id,
+                        ticker,
+                        chain,
+                        discount_value,
+                    ));
+                }
+                _ => {}
             }
-            "Butterfly" => {
-                request_data.orders.push(build_butterfly_bull_order(
-                    contract,
-                    num_fills,
-                    account_id,
-                    conids_map,
-                    discount_value,
-                ));
-                request_data.orders.push(build_butterfly_bear_order(
-                    contract,
-                    num_fills,
-                    account_id,
-                    conids_map,
-                    discount_value,
-                ));
+        }
+
+        // Tag each order with a client order id, executions report it back as order_ref.
+        let tag: String = order_tags
+            .tag(&contract.type_spread)
+            .map(|tag| format!("{}-", tag))
+            .unwrap_or_default();
+        for order in request_data.orders[before..].iter_mut() {
+            order.referrer = order_tags.referrer.clone();
+            order.c_oid = Some(format!(
+                "tbr-{}{}-{}-{}",
+                tag,
+                batch,
+                index,
+                request_data.contender_index.len()
+            ));
+            request_data.contender_index.push(index);
+        }
+    }
+
+    request_data
+}
+
+// Function that returns the fills of each contender a batch placed, counting its netted members,
+// and half a fill for a butterfly or box with only one of its two orders placed.
+pub(crate) fn placed_fills(request_data: &RequestDataStruct, contenders: &[Contender]) -> Vec<f64> {
+    let mut quantities: Vec<f64> = vec![0.0; contenders.len()];
+    for (i, order) in request_data.orders.iter().enumerate() {
+        match request_data.netted.get(i) {
+            Some(members) if !members.is_empty() => {
+                for (index, member) in members {
+                    quantities[*index] += member.quantity as f64;
+                }
             }
-            "Boxspread" => {
-                request_data.orders.push(build_boxspread_put_order(
-                    contract,
-                    num_fills,
-                    account_id,
-                    conids_map,
-                    discount_value,
-                ));
-                request_data.orders.push(build_boxspread_call_order(
-                    contract,
-                    num_fills,
-                    account_id,
-                    conids_map,
-                    discount_value,
-                ));
+            _ => {
+                if let Some(index) = request_data.contender_index.get(i) {
+                    quantities[*index] += order.quantity as f64;
+                }
             }
-            _ => {}
         }
     }
+    quantities
+        .into_iter()
+        .zip(contenders)
+        .map(|(quantity, contender)| quantity / contender.orders_per_fill() as f64)
+        .collect()
+}
 
-    request_data
+// Function that returns the price to join the combo's displayed bid at instead of paying up to
+// the limit, never above the limit.
+pub(crate) fn join_price(limit_price: f64, best_bid: Option<f64>) -> f64 {
+    match best_bid {
+        Some(bid) if bid < limit_price => bid,
+        _ => limit_price,
+    }
+}
+
+// Function that returns the next price for a resting order, one step toward the limit, or the
+// limit itself with no step.
+pub(crate) fn escalated_price(current_price: f64, limit_price: f64, step: f64) -> f64 {
+    if step <= 0.0 {
+        return limit_price;
+    }
+    (((current_price + step).min(limit_price)) * 100.0).round() / 100.0
+}
+
+// Function that checks a leg-derived order against the combo's own quote: a buy limit below the
+// displayed combo bid means the legs overstated the edge and the order won't fill.
+pub(crate) fn combo_confirms(limit_price: f64, best_bid: Option<f64>) -> bool {
+    best_bid.is_none_or(|bid| bid <= limit_price)
+}
+
+// Function that caps a buy limit at the combo's displayed ask, there's no need to pay more.
+pub(crate) fn cap_to_ask(limit_price: f64, best_ask: Option<f64>) -> f64 {
+    match best_ask {
+        Some(ask) if ask < limit_price => ask,
+        _ => limit_price,
+    }
+}
+
+// Function that rounds a buy limit down to a valid increment for the underlying, rounding down
+// keeps the order no more aggressive than the computed limit.
+pub(crate) fn round_to_tick(price: f64, rule: &TickRule) -> f64 {
+    let tick: f64 = rule.tick_for(price);
+    if tick <= 0.0 {
+        return price;
+    }
+    let rounded: f64 = ((price / tick) + 1e-9).floor() * tick;
+    (rounded * 100.0).round() / 100.0
 }
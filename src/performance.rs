@@ -0,0 +1,276 @@
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde_json::Value;
+
+use crate::{encryption::read_lines, journal::realized_edge};
+
+// Live results of one strategy over its most recent finished orders of the session.
+#[derive(Debug, PartialEq)]
+pub(crate) struct StrategyStats {
+    pub(crate) orders: usize,
+    pub(crate) fills: usize,
+    // Mean leg slippage of the window's fills, positive is worse than intended.
+    pub(crate) avg_slippage: Option<f64>,
+    // Share of the modeled edge the window's fills kept.
+    pub(crate) edge_capture: Option<f64>,
+}
+
+impl StrategyStats {
+    // Function that returns the share of finished orders that filled.
+    pub(crate) fn fill_rate(&self) -> f64 {
+        if self.orders == 0 {
+            0.0
+        } else {
+            self.fills as f64 / self.orders as f64
+        }
+    }
+
+    // Function that checks whether a full window of results breaches either limit, 0.0 disables
+    // a limit.
+    pub(crate) fn degraded(&self, window: usize, min_fill_rate: f64, max_slippage: f64) -> bool {
+        if self.orders < window.max(1) {
+            return false;
+        }
+        let poor_fills: bool = min_fill_rate > 0.0 && self.fill_rate() < min_fill_rate;
+        let poor_prices: bool =
+            max_slippage > 0.0 && self.avg_slippage.is_some_and(|slip| slip > max_slippage);
+        poor_fills || poor_prices
+    }
+}
+
+// Function that returns the strategy code used by OPTION and VIX_DISABLED_STRATEGIES.
+pub(crate) fn strategy_code(type_spread: &str) -> Option<&'static str> {
+    match type_spread {
+        "Calendar" => Some("1"),
+        "Butterfly" => Some("2"),
+        "Boxspread" => Some("3"),
+        _ => None,
+    }
+}
+
+// Function that returns a submitted order's modeled edge and combo price at the intended mids.
+fn submitted_edge(record: &Value) -> Option<(f64, f64)> {
+    let modeled_edge: f64 = record["modeled_edge"].as_f64()?;
+    let mid: f64 = record["legs"]
+        .as_array()?
+        .iter()
+        .map(|leg| {
+            leg["ratio"].as_f64().unwrap_or(0.0) * leg["intended_price"].as_f64().unwrap_or(0.0)
+        })
+        .sum();
+    Some((modeled_edge, mid))
+}
+
+// Function that returns the share of the modeled edge kept, None without any modeled edge.
+fn capture(modeled: f64, realized: f64) -> Option<f64> {
+    if modeled == 0.0 {
+        None
+    } else {
+        Some(realized / modeled)
+    }
+}
+
+// Function that checks whether a journal entry was written since the session started.
+fn in_session(record: &Value, since: DateTime<Utc>) -> bool {
+    record["time"]
+        .as_str()
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .is_some_and(|time| time >= since)
+}
+
+// Function that computes per strategy stats over the last `window` orders of each strategy
+// finished since the session started, from journal lines. Earlier sessions don't count, so a
+// strategy disabled for a session is judged afresh on the next one.
+pub(crate) fn strategy_stats<I: Iterator<Item = String>>(
+    lines: I,
+    window: usize,
+    since: DateTime<Utc>,
+) -> HashMap<String, StrategyStats> {
+    let records: Vec<Value> = lines
+        .filter_map(|line| serde_json::from_str::<Value>(&line).ok())
+        .collect();
+
+    // Most recent finished orders first, so the window is the tail of the journal.
+    let mut finished: HashMap<String, Vec<(String, bool)>> = HashMap::new();
+    for record in records.iter().rev() {
+        if record["event"].as_str() != Some("status") || !in_session(record, since) {
+            continue;
+        }
+        let (type_spread, order_id, status) = match (
+            record["type_spread"].as_str(),
+            record["order_id"].as_str(),
+            record["status"].as_str(),
+        ) {
+            (Some(type_spread), Some(order_id), Some(status)) => (type_spread, order_id, status),
+            _ => continue,
+        };
+        let orders: &mut Vec<(String, bool)> = finished.entry(type_spread.to_string()).or_default();
+        if orders.len() < window.max(1) {
+            orders.push((order_id.to_string(), status == "filled"));
+        }
+    }
+
+    let edges: HashMap<&str, (f64, f64)> = records
+        .iter()
+        .filter(|record| record["event"].as_str() == Some("submitted"))
+        .filter_map(|record| Some((record["order_id"].as_str()?, submitted_edge(record)?)))
+        .collect();
+
+    finished
+        .into_iter()
+        .map(|(type_spread, orders)| {
+            let filled: HashSet<&str> = orders
+                .iter()
+                .filter(|(_, filled)| *filled)
+                .map(|(order_id, _)| order_id.as_str())
+                .collect();
+            let slippages: Vec<f64> = records
+                .iter()
+                .filter(|record| {
+                    record["event"].as_str() == Some("execution")
+                        && record["order_id"]
+                            .as_str()
+                            .is_some_and(|order_id| filled.contains(order_id))
+                })
+                .filter_map(|record| record["slippage"].as_f64())
+                .collect();
+            let avg_slippage: Option<f64> = if slippages.is_empty() {
+                None
+            } else {
+                Some(slippages.iter().sum::<f64>() / slippages.len() as f64)
+            };
+            let (modeled, realized): (f64, f64) = records
+                .iter()
+                .filter(|record| record["event"].as_str() == Some("status"))
+                .filter_map(|record| {
+                    let order_id: &str = record["order_id"].as_str()?;
+                    if !filled.contains(order_id) {
+                        return None;
+                    }
+                    let (modeled_edge, mid) = edges.get(order_id)?;
+                    let avg_price: f64 = record["avg_price"].as_f64()?;
+                    Some((*modeled_edge, realized_edge(*modeled_edge, *mid, avg_price)))
+                })
+                .fold(
+                    (0.0, 0.0),
+                    |(modeled, realized), (order_modeled, order_realized)| {
+                        (modeled + order_modeled, realized + order_realized)
+                    },
+                );
+
+            (
+                type_spread,
+                StrategyStats {
+                    orders: orders.len(),
+                    fills: filled.len(),
+                    avg_slippage,
+                    edge_capture: capture(modeled, realized),
+                },
+            )
+        })
+        .collect()
+}
+
+// Function that loads the session's per strategy stats from the journal file, empty without a
+// journal.
+pub(crate) fn load_strategy_stats(
+    path: &str,
+    window: usize,
+    since: DateTime<Utc>,
+) -> HashMap<String, StrategyStats> {
+    match read_lines(path) {
+        Ok(lines) => strategy_stats(lines, window, since),
+        Err(_) => HashMap::new(),
+    }
+}
+
+// One strategy's orders over a session, for the end of day report.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct SessionStats {
+    pub(crate) submitted: usize,
+    pub(crate) filled: usize,
+    pub(crate) cancelled: usize,
+    pub(crate) rejected: usize,
+    pub(crate) slippage_total: f64,
+    pub(crate) executions: usize,
+    pub(crate) modeled_edge: f64,
+    pub(crate) realized_edge: f64,
+}
+
+impl SessionStats {
+    // Function that returns the mean leg slippage of the session's executions.
+    pub(crate) fn avg_slippage(&self) -> Option<f64> {
+        if self.executions == 0 {
+            None
+        } else {
+            Some(self.slippage_total / self.executions as f64)
+        }
+    }
+
+    // Function that returns the share of the modeled edge the session's fills kept.
+    pub(crate) fn edge_capture(&self) -> Option<f64> {
+        capture(self.modeled_edge, self.realized_edge)
+    }
+}
+
+// Function that totals each strategy's journal entries written since the session started.
+pub(crate) fn session_stats<I: Iterator<Item = String>>(
+    lines: I,
+    since: DateTime<Utc>,
+) -> BTreeMap<String, SessionStats> {
+    let mut stats: BTreeMap<String, SessionStats> = BTreeMap::new();
+    let mut edges: HashMap<String, (f64, f64)> = HashMap::new();
+    for record in lines.filter_map(|line| serde_json::from_str::<Value>(&line).ok()) {
+        let type_spread: &str = match record["type_spread"].as_str() {
+            Some(type_spread) if in_session(&record, since) => type_spread,
+            _ => continue,
+        };
+        let stat: &mut SessionStats = stats.entry(type_spread.to_string()).or_default();
+        match (record["event"].as_str(), record["status"].as_str()) {
+            (Some("submitted"), _) => {
+                stat.submitted += 1;
+                if let (Some(order_id), Some(edge)) =
+                    (record["order_id"].as_str(), submitted_edge(&record))
+                {
+                    edges.insert(order_id.to_string(), edge);
+                }
+            }
+            (Some("status"), Some("filled")) => {
+                stat.filled += 1;
+                let edge: Option<&(f64, f64)> = record["order_id"]
+                    .as_str()
+                    .and_then(|order_id| edges.get(order_id));
+                if let (Some((modeled_edge, mid)), Some(avg_price)) =
+                    (edge, record["avg_price"].as_f64())
+                {
+                    stat.modeled_edge += modeled_edge;
+                    stat.realized_edge += realized_edge(*modeled_edge, *mid, avg_price);
+                }
+            }
+            (Some("status"), Some("cancelled")) => stat.cancelled += 1,
+            (Some("status"), Some("rejected")) => stat.rejected += 1,
+            (Some("execution"), _) => {
+                if let Some(slippage) = record["slippage"].as_f64() {
+                    stat.slippage_total += slippage;
+                    stat.executions += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    stats.retain(|_, stat| *stat != SessionStats::default());
+    stats
+}
+
+// Function that loads the session's per strategy totals from the journal file, empty without a
+// journal.
+pub(crate) fn load_session_stats(
+    path: &str,
+    since: DateTime<Utc>,
+) -> BTreeMap<String, SessionStats> {
+    match read_lines(path) {
+        Ok(lines) => session_stats(lines, since),
+        Err(_) => BTreeMap::new(),
+    }
+}
@@ -0,0 +1,43 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::encryption::seal_line;
+
+// Intraday P&L curve, one row per cycle.
+pub(crate) const PNL_PATH: &str = "pnl.csv";
+const PNL_HEADER: &str = "time,equity_with_loan,net_liquidation,pnl,positions_value";
+
+// One sample of the account's equity and open position marks.
+#[derive(Debug, PartialEq)]
+pub(crate) struct PnlSample {
+    pub(crate) time: String,
+    pub(crate) equity_with_loan: f64,
+    pub(crate) net_liquidation: f64,
+    // Change in net liquidation since the first sample of the session.
+    pub(crate) pnl: f64,
+    // Market value of the open option positions in the traded underlyings.
+    pub(crate) positions_value: f64,
+}
+
+impl PnlSample {
+    // Function that formats the sample as a CSV row.
+    pub(crate) fn to_csv(&self) -> String {
+        format!(
+            "{},{:.2},{:.2},{:.2},{:.2}",
+            self.time, self.equity_with_loan, self.net_liquidation, self.pnl, self.positions_value
+        )
+    }
+}
+
+// Function that appends a sample to the P&L file, writing the header for a new file.
+pub(crate) fn append_pnl_sample(path: &str, sample: &PnlSample) -> io::Result<()> {
+    let new_file: bool = !Path::new(path).exists();
+    let mut file: std::fs::File = OpenOptions::new().create(true).append(true).open(path)?;
+    if new_file {
+        writeln!(file, "{}", PNL_HEADER)?;
+    }
+    writeln!(file, "{}", seal_line(&sample.to_csv())?)
+}
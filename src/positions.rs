@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use crate::{chain::ChainView, structs::Contender};
+
+// How a profile's contenders are screened against the positions already held: the most
+// contracts it may be short of any one option (0 disables), and whether contenders that close
+// held contracts are ranked ahead of the rest.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct PositionRules {
+    pub(crate) max_short_per_strike: f64,
+    pub(crate) prefer_reducing: bool,
+}
+
+impl PositionRules {
+    // Function that checks whether contenders need the held positions at all.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.max_short_per_strike > 0.0 || self.prefer_reducing
+    }
+}
+
+// Function that returns the contracts a strategy's combo trades of each of its legs per fill,
+// in contract order and positive to buy, as the order builders lay them out.
+fn leg_ratios(type_spread: &str) -> &'static [i64] {
+    match type_spread {
+        "Calendar" => &[-1, 1],
+        "Butterfly" => &[1, -2, 1],
+        "Boxspread" => &[1, -1, 1, -1],
+        _ => &[],
+    }
+}
+
+// Function that returns the contracts a contender trades of each option per fill by conid,
+// positive to buy, leaving out legs missing from the chain.
+pub(crate) fn contender_legs(contender: &Contender, chain: &ChainView) -> Vec<(String, i64)> {
+    contender
+        .contracts
+        .iter()
+        .zip(leg_ratios(&contender.type_spread))
+        .filter_map(|(leg, ratio)| {
+            let conid: &String = chain.conid(&leg.date, &leg.type_contract, leg.strike)?;
+            Some((conid.clone(), *ratio))
+        })
+        .collect()
+}
+
+// Function that returns how many held contracts one fill closes: the legs trading against a
+// held position, up to its size.
+pub(crate) fn reduced_contracts(legs: &[(String, i64)], positions: &HashMap<String, f64>) -> f64 {
+    legs.iter()
+        .map(|(conid, contracts)| {
+            let held: f64 = positions.get(conid).copied().unwrap_or(0.0);
+            if held * (*contracts as f64) < 0.0 {
+                held.abs().min(contracts.abs() as f64)
+            } else {
+                0.0
+            }
+        })
+        .sum()
+}
+
+// Function that returns the most fills that keep every option the contender sells within the
+// short limit given the held positions, None when nothing limits it.
+pub(crate) fn max_fills_within_short_limit(
+    legs: &[(String, i64)],
+    positions: &HashMap<String, f64>,
+    max_short: f64,
+) -> Option<i32> {
+    if max_short <= 0.0 {
+        return None;
+    }
+    legs.iter()
+        .filter(|(_, contracts)| *contracts < 0)
+        .map(|(conid, contracts)| {
+            let held: f64 = positions.get(conid).copied().unwrap_or(0.0);
+            ((held + max_short).max(0.0) / contracts.abs() as f64).floor() as i32
+        })
+        .min()
+}
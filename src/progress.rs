@@ -0,0 +1,98 @@
+use std::time::{Duration, Instant};
+
+use crate::logging::log_message;
+
+// Least time between two progress lines of a step.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+// Progress through a long init step, such as the strike lookups of a wide chain, logged as plain
+// lines at most every PROGRESS_INTERVAL and once the step is done, so a slow init doesn't look
+// hung.
+pub(crate) struct Progress {
+    label: String,
+    unit: &'static str,
+    total: usize,
+    done: usize,
+    started: Instant,
+    reported: Instant,
+}
+
+impl Progress {
+    pub(crate) fn new(label: String, unit: &'static str, total: usize) -> Self {
+        let now: Instant = Instant::now();
+        Progress {
+            label,
+            unit,
+            total,
+            done: 0,
+            started: now,
+            reported: now,
+        }
+    }
+
+    // Function that records finished units with what they added up to so far, logging progress
+    // when a line is due or the step is done.
+    pub(crate) fn advance(&mut self, units: usize, detail: &str) {
+        self.done = (self.done + units).min(self.total);
+        let now: Instant = Instant::now();
+        if self.done < self.total && now.duration_since(self.reported) < PROGRESS_INTERVAL {
+            return;
+        }
+        self.reported = now;
+        log_message(progress_line(
+            &self.label,
+            self.unit,
+            self.done,
+            self.total,
+            detail,
+            now.duration_since(self.started),
+        ));
+    }
+}
+
+// Function that estimates the time left from the pace so far, None before anything is done.
+pub(crate) fn eta(done: usize, total: usize, elapsed: Duration) -> Option<Duration> {
+    if done == 0 {
+        return None;
+    }
+    Some(elapsed.mul_f64(total.saturating_sub(done) as f64 / done as f64))
+}
+
+// Function that formats a duration to the second, as 42s or 3m 05s.
+pub(crate) fn format_eta(duration: Duration) -> String {
+    let seconds: u64 = duration.as_secs_f64().round() as u64;
+    if seconds < 60 {
+        format!("{}s", seconds)
+    } else {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    }
+}
+
+// Function that returns a progress line: the units done out of the total, the detail, and the
+// time left, or the time taken once the step is done.
+pub(crate) fn progress_line(
+    label: &str,
+    unit: &str,
+    done: usize,
+    total: usize,
+    detail: &str,
+    elapsed: Duration,
+) -> String {
+    let timing: String = if done >= total {
+        format!("took {}", format_eta(elapsed))
+    } else {
+        match eta(done, total, elapsed) {
+            Some(left) => format!("ETA {}", format_eta(left)),
+            None => "ETA unknown".to_string(),
+        }
+    };
+    let detail: String = if detail.is_empty() {
+        String::new()
+    } else {
+        format!(", {}", detail)
+    };
+    format!(
+        "{}: {}/{} {}{}, {}.",
+        label, done, total, unit, detail, timing
+    )
+}
@@ -0,0 +1,124 @@
+use std::collections::HashSet;
+
+use crate::{
+    chain::{ChainView, ContractId},
+    structs::{Contender, Opt},
+};
+
+// The contracts whose quote changed between two snapshots, by contract id. A full diff treats
+// every contract as changed.
+pub(crate) struct QuoteDiff {
+    changed: Option<HashSet<ContractId>>,
+}
+
+impl QuoteDiff {
+    // Function that returns a diff treating every contract as changed, for a full rescan.
+    pub(crate) fn full() -> Self {
+        QuoteDiff { changed: None }
+    }
+
+    // Function that compares two snapshots indexed by contract id, a contract quoted in only one
+    // of them counts as changed.
+    pub(crate) fn between(previous: &[Option<Opt>], current: &[Option<Opt>]) -> Self {
+        let changed: HashSet<ContractId> = (0..previous.len().max(current.len()))
+            .filter(|id| {
+                match (
+                    previous.get(*id).and_then(Option::as_ref),
+                    current.get(*id).and_then(Option::as_ref),
+                ) {
+                    (Some(previous), Some(current)) => !same_quote(previous, current),
+                    (None, None) => false,
+                    _ => true,
+                }
+            })
+            .map(|id| id as ContractId)
+            .collect();
+        QuoteDiff {
+            changed: Some(changed),
+        }
+    }
+
+    // Function that returns the contracts whose quote changed, None for a full diff.
+    pub(crate) fn changed(&self) -> Option<&HashSet<ContractId>> {
+        self.changed.as_ref()
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.changed.is_none()
+    }
+
+    // Function that returns how many contracts changed, None for a full diff.
+    pub(crate) fn changed_count(&self) -> Option<usize> {
+        self.changed.as_ref().map(|changed| changed.len())
+    }
+
+    // Function that checks whether a candidate has a leg whose quote changed. Legs missing from
+    // the chain count as changed.
+    pub(crate) fn touches(&self, chain: &ChainView, legs: &[(f64, &str, &String)]) -> bool {
+        match &self.changed {
+            None => true,
+            Some(changed) => legs.iter().any(|(strike, right, date)| {
+                chain
+                    .contract_id(date, right, *strike)
+                    .is_none_or(|id| changed.contains(&id))
+            }),
+        }
+    }
+
+    // Function that checks whether a contender has a leg whose quote changed.
+    pub(crate) fn touches_contender(&self, chain: &ChainView, contender: &Contender) -> bool {
+        let legs: Vec<(f64, &str, &String)> = contender
+            .contracts
+            .iter()
+            .map(|leg| (leg.strike, leg.type_contract.as_str(), &leg.date))
+            .collect();
+        self.touches(chain, &legs)
+    }
+}
+
+// Function that checks whether two quotes of a contract would scan the same.
+fn same_quote(previous: &Opt, current: &Opt) -> bool {
+    previous.bid == current.bid
+        && previous.ask == current.ask
+        && previous.asz == current.asz
+        && previous.mkt == current.mkt
+        && previous.open_interest == current.open_interest
+}
+
+// The inputs besides the quotes a strategy's scan depends on.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ScanKey {
+    pub(crate) scan_date: String,
+    pub(crate) arb_threshold: f64,
+    // Only set for strategies whose checks use the underlying's price.
+    pub(crate) underlying_price: Option<f64>,
+}
+
+// A strategy's last scan: its inputs and the contenders it found, before ranking.
+pub(crate) struct CachedScan {
+    pub(crate) key: ScanKey,
+    pub(crate) contenders: Vec<Contender>,
+}
+
+impl CachedScan {
+    // Function that returns the cached contenders none of whose legs changed, which the scan
+    // would find again as they were. None when the scan inputs differ or the diff is full, and
+    // the strategy needs a full rescan.
+    pub(crate) fn carry_over(
+        &self,
+        key: &ScanKey,
+        chain: &ChainView,
+        diff: &QuoteDiff,
+    ) -> Option<Vec<Contender>> {
+        if diff.is_full() || self.key != *key {
+            return None;
+        }
+        Some(
+            self.contenders
+                .iter()
+                .filter(|contender| !diff.touches_contender(chain, contender))
+                .cloned()
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,170 @@
+use serde_json::Value;
+
+// Why IBKR is asking to confirm an order, parsed from the reply's message ids and text.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ReplyReason {
+    PriceCap,
+    MarginViolation,
+    OrderValueExceeds,
+    SizeLimit,
+    NoMarketData,
+    Other,
+}
+
+impl ReplyReason {
+    // Function that classifies one prompt from its message id and text.
+    pub(crate) fn classify(message_id: &str, message: &str) -> Self {
+        let message: String = message.to_lowercase();
+        if message_id == "o163"
+            || message.contains("price exceeds")
+            || message.contains("price cap")
+        {
+            ReplyReason::PriceCap
+        } else if message.contains("margin") {
+            ReplyReason::MarginViolation
+        } else if message.contains("order value") && message.contains("exceeds") {
+            ReplyReason::OrderValueExceeds
+        } else if message_id == "o383" || message.contains("size limit") {
+            ReplyReason::SizeLimit
+        } else if message_id == "o354" || message.contains("market data") {
+            ReplyReason::NoMarketData
+        } else {
+            ReplyReason::Other
+        }
+    }
+
+    // Function that checks whether the prompt guards against losing money, these are never
+    // confirmed automatically.
+    pub(crate) fn is_destructive(&self) -> bool {
+        matches!(
+            self,
+            ReplyReason::PriceCap
+                | ReplyReason::MarginViolation
+                | ReplyReason::OrderValueExceeds
+                | ReplyReason::SizeLimit
+        )
+    }
+
+    // Function that returns the metrics counter name for the reason.
+    pub(crate) fn counter(&self) -> &'static str {
+        match self {
+            ReplyReason::PriceCap => "order_replies_price_cap",
+            ReplyReason::MarginViolation => "order_replies_margin",
+            ReplyReason::OrderValueExceeds => "order_replies_order_value",
+            ReplyReason::SizeLimit => "order_replies_size_limit",
+            ReplyReason::NoMarketData => "order_replies_no_market_data",
+            ReplyReason::Other => "order_replies_other",
+        }
+    }
+}
+
+// A confirmation prompt returned while placing an order.
+#[derive(Debug)]
+pub(crate) struct OrderReply {
+    pub(crate) id: String,
+    pub(crate) message_ids: Vec<String>,
+    pub(crate) messages: Vec<String>,
+    pub(crate) reasons: Vec<ReplyReason>,
+}
+
+// What the order endpoint answered.
+#[derive(Debug)]
+pub(crate) enum ReplyOutcome {
+    Placed(Vec<String>),
+    Confirm(OrderReply),
+    Rejected(String),
+}
+
+// Function that parses an order or reply response into its outcome.
+pub(crate) fn parse_reply(response: &Value) -> ReplyOutcome {
+    let first: &Value = match response {
+        Value::Array(items) => match items.first() {
+            Some(first) => first,
+            None => return ReplyOutcome::Rejected("Empty order response".to_string()),
+        },
+        other => other,
+    };
+
+    if let Some(error) = first["error"].as_str() {
+        return ReplyOutcome::Rejected(error.to_string());
+    }
+
+    if let Some(id) = first["id"].as_str() {
+        let strings = |key: &str| -> Vec<String> {
+            match &first[key] {
+                Value::Array(items) => items
+                    .iter()
+                    .filter_map(|item| item.as_str().map(|s| s.to_string()))
+                    .collect(),
+                Value::String(item) => vec![item.clone()],
+                _ => Vec::new(),
+            }
+        };
+        let message_ids: Vec<String> = strings("messageIds");
+        let messages: Vec<String> = strings("message");
+        let reasons: Vec<ReplyReason> = (0..messages.len().max(message_ids.len()))
+            .map(|i| {
+                ReplyReason::classify(
+                    message_ids.get(i).map(|s| s.as_str()).unwrap_or(""),
+                    messages.get(i).map(|s| s.as_str()).unwrap_or(""),
+                )
+            })
+            .collect();
+
+        return ReplyOutcome::Confirm(OrderReply {
+            id: id.to_string(),
+            message_ids,
+            messages,
+            reasons,
+        });
+    }
+
+    let order_ids: Vec<String> = match response {
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|item| item["order_id"].as_str().map(|s| s.to_string()))
+            .collect(),
+        other => other["order_id"]
+            .as_str()
+            .map(|s| vec![s.to_string()])
+            .unwrap_or_default(),
+    };
+    if order_ids.is_empty() {
+        ReplyOutcome::Rejected(format!("Unrecognized order response: {}", response))
+    } else {
+        ReplyOutcome::Placed(order_ids)
+    }
+}
+
+// How order confirmation prompts from /iserver/reply/{id} are answered.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ReplyPolicy {
+    // Confirm every prompt, including price cap and size warnings. Only for paper accounts.
+    AutoConfirmAll,
+    // Confirm prompts whose message ids are all allowlisted and that aren't destructive.
+    ConfirmAllowlist(Vec<String>),
+    // Decline every prompt and log it.
+    NeverConfirm,
+}
+
+impl ReplyPolicy {
+    // Function that decides whether to confirm a prompt under the policy.
+    pub(crate) fn confirms(&self, reply: &OrderReply) -> bool {
+        match self {
+            ReplyPolicy::AutoConfirmAll => true,
+            ReplyPolicy::ConfirmAllowlist(allowlist) => should_confirm(reply, allowlist),
+            ReplyPolicy::NeverConfirm => false,
+        }
+    }
+}
+
+// Function that decides whether to confirm a prompt: never when it guards against a loss, and
+// otherwise only when every message id is on the allowlist.
+pub(crate) fn should_confirm(reply: &OrderReply, allowlist: &[String]) -> bool {
+    !reply.reasons.iter().any(|reason| reason.is_destructive())
+        && !reply.message_ids.is_empty()
+        && reply
+            .message_ids
+            .iter()
+            .all(|message_id| allowlist.contains(message_id))
+}
@@ -0,0 +1,65 @@
+use reqwest::blocking::ClientBuilder;
+use serde::Deserialize;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+// Which address family connections to the gateway are made over.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum IpVersion {
+    #[default]
+    #[serde(rename = "auto", alias = "any")]
+    Any,
+    #[serde(rename = "ipv4", alias = "4")]
+    V4,
+    #[serde(rename = "ipv6", alias = "6")]
+    V6,
+}
+
+// How the gateway's hostname is resolved: an address family to stick to and static
+// host-to-IP mappings that bypass DNS.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ResolverConfig {
+    pub(crate) ip_version: IpVersion,
+    pub(crate) static_hosts: Vec<(String, Vec<IpAddr>)>,
+}
+
+// Function that parses static host mappings from "host=ip,ip;host=ip", skipping malformed ones.
+pub(crate) fn parse_static_hosts(val: &str) -> Vec<(String, Vec<IpAddr>)> {
+    val.split(';')
+        .filter_map(|mapping| {
+            let (host, ips) = mapping.split_once('=')?;
+            let host: &str = host.trim();
+            let ips: Vec<IpAddr> = ips
+                .split(',')
+                .filter_map(|ip| {
+                    ip.trim()
+                        .trim_matches(|c| c == '[' || c == ']')
+                        .parse::<IpAddr>()
+                        .ok()
+                })
+                .collect();
+            if host.is_empty() || ips.is_empty() {
+                return None;
+            }
+            Some((host.to_lowercase(), ips))
+        })
+        .collect()
+}
+
+// Function that applies the resolver config to a client builder. Binding to the unspecified
+// address of a family restricts connections to it, static hosts skip DNS entirely.
+pub(crate) fn apply_resolver(builder: ClientBuilder, config: &ResolverConfig) -> ClientBuilder {
+    let builder: ClientBuilder = match config.ip_version {
+        IpVersion::Any => builder,
+        IpVersion::V4 => builder.local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        IpVersion::V6 => builder.local_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+    };
+
+    config
+        .static_hosts
+        .iter()
+        .fold(builder, |builder, (host, ips)| {
+            // The port comes from the URL, the resolver only supplies addresses.
+            let addrs: Vec<SocketAddr> = ips.iter().map(|ip| SocketAddr::new(*ip, 0)).collect();
+            builder.resolve_to_addrs(host, &addrs)
+        })
+}
@@ -0,0 +1,60 @@
+use reqwest::blocking::Response;
+use serde::de::DeserializeOwned;
+use std::{error::Error, fmt};
+
+// Characters of a response body quoted in a schema error.
+const SNIPPET_CHARS: usize = 200;
+
+// A response body that doesn't match the schema expected from its endpoint.
+#[derive(Debug)]
+pub(crate) struct ResponseError {
+    pub(crate) endpoint: String,
+    pub(crate) reason: String,
+    pub(crate) snippet: String,
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unexpected response from {}: {}. Body: {}",
+            self.endpoint, self.reason, self.snippet
+        )
+    }
+}
+
+impl Error for ResponseError {}
+
+// Function that returns the start of a response body for an error message, on one line.
+pub(crate) fn body_snippet(body: &str) -> String {
+    let body: String = body.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if body.is_empty() {
+        return "(empty)".to_string();
+    }
+    match body.char_indices().nth(SNIPPET_CHARS) {
+        Some((end, _)) => format!("{}...", &body[..end]),
+        None => body,
+    }
+}
+
+// Function that deserializes an endpoint's response body, naming the endpoint, the missing or
+// mistyped field and the start of the body when it doesn't match. Unknown fields are ignored.
+pub(crate) fn decode<T: DeserializeOwned>(endpoint: &str, body: &str) -> Result<T, ResponseError> {
+    serde_json::from_str(body).map_err(|e| ResponseError {
+        endpoint: endpoint.to_string(),
+        reason: e.to_string(),
+        snippet: body_snippet(body),
+    })
+}
+
+// Reads a response body into the struct expected from its endpoint.
+pub(crate) trait ReadJson {
+    fn read_json<T: DeserializeOwned>(self, endpoint: &str) -> Result<T, Box<dyn Error>>;
+}
+
+impl ReadJson for Response {
+    fn read_json<T: DeserializeOwned>(self, endpoint: &str) -> Result<T, Box<dyn Error>> {
+        let body: String = self.text()?;
+        Ok(decode(endpoint, &body)?)
+    }
+}
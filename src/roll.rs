@@ -0,0 +1,28 @@
+use crate::structs::{Opt, RollRule};
+
+// Function that checks if a calendar's short near leg is due to roll: it expires within the
+// rule's days, or it has given up enough of the credit it was sold for.
+pub(crate) fn roll_due(front_days: i64, sold_at: f64, mark: f64, rule: &RollRule) -> bool {
+    let dte_hit: bool = rule.dte.is_some_and(|dte| front_days <= dte);
+    let profit_hit: bool =
+        rule.profit_pct > 0.0 && sold_at > 0.0 && (sold_at - mark) / sold_at >= rule.profit_pct;
+    dte_hit || profit_hit
+}
+
+// Function that returns the first expiry after the near leg's that is still before the far
+// leg's, rolling into the far expiry itself would close the calendar.
+pub(crate) fn roll_target<'a>(dates: &'a [String], near: &str, far: &str) -> Option<&'a String> {
+    dates
+        .iter()
+        .filter(|date| date.as_str() > near && date.as_str() < far)
+        .min()
+}
+
+// Function that prices buying back the near leg and selling the next one at their mids, negative
+// for a credit.
+pub(crate) fn roll_price(near: &Opt, next: &Opt) -> Option<f64> {
+    if near.bid <= 0.0 || near.ask < near.bid || next.bid <= 0.0 || next.ask < next.bid {
+        return None;
+    }
+    Some((near.bid + near.ask) / 2.0 - (next.bid + next.ask) / 2.0)
+}
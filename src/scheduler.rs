@@ -0,0 +1,171 @@
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::America::New_York;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// State of the generator behind random_fraction, seeded from the clock on first use.
+static RNG_STATE: AtomicU64 = AtomicU64::new(0);
+
+// A window of the trading day, in New York time, with its own scan cadence.
+pub(crate) struct ScanWindow {
+    pub(crate) start: NaiveTime,
+    pub(crate) end: NaiveTime,
+    pub(crate) seconds: u64,
+}
+
+// Function that parses "HH:MM-HH:MM=SECONDS" windows separated by semicolons.
+pub(crate) fn parse_scan_schedule(value: &str) -> Vec<ScanWindow> {
+    let mut schedule: Vec<ScanWindow> = Vec::new();
+
+    for window in value.split(';').map(|w| w.trim()).filter(|w| !w.is_empty()) {
+        let (times, seconds) = match window.split_once('=') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let (start, end) = match times.split_once('-') {
+            Some(parts) => parts,
+            None => continue,
+        };
+
+        if let (Ok(start), Ok(end), Ok(seconds)) = (
+            NaiveTime::parse_from_str(start.trim(), "%H:%M"),
+            NaiveTime::parse_from_str(end.trim(), "%H:%M"),
+            seconds.trim().parse::<u64>(),
+        ) {
+            if start < end && seconds >= 5 {
+                schedule.push(ScanWindow {
+                    start,
+                    end,
+                    seconds,
+                });
+            }
+        }
+    }
+
+    schedule
+}
+
+// Function that returns the number of seconds to sleep before the next scan.
+// The first matching window wins, otherwise the default cadence applies.
+pub(crate) fn get_scan_interval(
+    current_time: DateTime<Utc>,
+    schedule: &[ScanWindow],
+    default_seconds: u64,
+) -> u64 {
+    let ny_time: NaiveTime = current_time.with_timezone(&New_York).time();
+
+    schedule
+        .iter()
+        .find(|window| ny_time >= window.start && ny_time < window.end)
+        .map(|window| window.seconds)
+        .unwrap_or(default_seconds)
+}
+
+// When a strategy is scanned: whether at all, at most every interval seconds (0 for every
+// cycle) and only inside its windows of the trading day in New York time (any time without).
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct StrategySchedule {
+    pub(crate) enabled: bool,
+    pub(crate) interval: u64,
+    pub(crate) windows: Vec<(NaiveTime, NaiveTime)>,
+}
+
+impl Default for StrategySchedule {
+    fn default() -> Self {
+        StrategySchedule {
+            enabled: true,
+            interval: 0,
+            windows: Vec::new(),
+        }
+    }
+}
+
+impl StrategySchedule {
+    // Function that checks whether the strategy is scanned at the current time, given when it
+    // was last scanned.
+    pub(crate) fn is_due(
+        &self,
+        current_time: DateTime<Utc>,
+        last_scan: Option<DateTime<Utc>>,
+    ) -> bool {
+        let ny_time: NaiveTime = current_time.with_timezone(&New_York).time();
+        self.enabled
+            && (self.windows.is_empty()
+                || self
+                    .windows
+                    .iter()
+                    .any(|(start, end)| ny_time >= *start && ny_time < *end))
+            && last_scan.is_none_or(|last_scan| {
+                (current_time - last_scan).num_seconds() >= self.interval as i64
+            })
+    }
+}
+
+// The scan schedule of each strategy.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct StrategySchedules {
+    pub(crate) calendar: StrategySchedule,
+    pub(crate) butterfly: StrategySchedule,
+    pub(crate) boxspread: StrategySchedule,
+}
+
+// Function that parses "HH:MM-HH:MM" windows separated by semicolons, skipping those that don't
+// parse or end before they start.
+pub(crate) fn parse_time_windows(value: &str) -> Vec<(NaiveTime, NaiveTime)> {
+    let mut windows: Vec<(NaiveTime, NaiveTime)> = Vec::new();
+
+    for window in value.split(';').map(|w| w.trim()).filter(|w| !w.is_empty()) {
+        let (start, end) = match window.split_once('-') {
+            Some(parts) => parts,
+            None => continue,
+        };
+
+        if let (Ok(start), Ok(end)) = (
+            NaiveTime::parse_from_str(start.trim(), "%H:%M"),
+            NaiveTime::parse_from_str(end.trim(), "%H:%M"),
+        ) {
+            if start < end {
+                windows.push((start, end));
+            }
+        }
+    }
+
+    windows
+}
+
+// Function that returns a pseudo-random fraction in [0, 1) from a splitmix64 sequence seeded
+// from the clock. Good enough to spread out request timing, not for anything secret.
+pub(crate) fn random_fraction() -> f64 {
+    let _ = RNG_STATE.compare_exchange(
+        0,
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or(1)
+            | 1,
+        Ordering::SeqCst,
+        Ordering::SeqCst,
+    );
+    let mut z: u64 = RNG_STATE
+        .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::SeqCst)
+        .wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// Function that spreads a sleep of the given seconds by up to jitter_pct either way, with
+// fraction in [0, 1) picking where in that range it lands.
+pub(crate) fn jittered_sleep(seconds: u64, jitter_pct: f64, fraction: f64) -> Duration {
+    let scale: f64 = 1.0 + jitter_pct * (2.0 * fraction - 1.0);
+    Duration::from_secs_f64((seconds as f64 * scale).max(0.0))
+}
+
+// Function that returns a delay of up to max_ms milliseconds, with fraction in [0, 1) picking
+// where in that range it lands.
+pub(crate) fn random_delay(max_ms: u64, fraction: f64) -> Duration {
+    Duration::from_millis((max_ms as f64 * fraction).round() as u64)
+}
@@ -0,0 +1,80 @@
+use keyring::Entry;
+use std::{
+    error::Error,
+    io::{stdin, BufRead},
+};
+
+use crate::helpers::get_dotenv_variable;
+
+const SERVICE: &str = "trading_bot_rust";
+
+// Where secrets kept out of the config file and .env are stored, so tests can swap out the OS
+// keyring.
+pub(crate) trait SecretStore {
+    fn get(&self, key: &str) -> Result<String, Box<dyn Error>>;
+    fn set(&self, key: &str, value: &str) -> Result<(), Box<dyn Error>>;
+    fn delete(&self, key: &str) -> Result<(), Box<dyn Error>>;
+}
+
+// The OS keyring.
+pub(crate) struct Keyring;
+
+impl SecretStore for Keyring {
+    fn get(&self, key: &str) -> Result<String, Box<dyn Error>> {
+        Ok(Entry::new(SERVICE, key)?.get_password()?)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        Entry::new(SERVICE, key)?.set_password(value)?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        Entry::new(SERVICE, key)?.delete_credential()?;
+        Ok(())
+    }
+}
+
+// Function that gets a secret from the environment/.env, falling back to the OS keyring.
+pub(crate) fn get_secret(key: &str) -> Result<String, Box<dyn Error>> {
+    get_secret_from(&Keyring, key)
+}
+
+// Function that gets a secret from the environment/.env, falling back to the given store.
+pub(crate) fn get_secret_from(
+    store: &impl SecretStore,
+    key: &str,
+) -> Result<String, Box<dyn Error>> {
+    match get_dotenv_variable(key) {
+        Ok(val) => Ok(val),
+        Err(_) => store.get(key),
+    }
+}
+
+// Function that runs the `secrets set|delete KEY` subcommand, the value is read from stdin.
+pub(crate) fn run_secrets_command(args: &[String]) -> Result<String, Box<dyn Error>> {
+    run_secrets_command_with(&Keyring, args, &mut stdin().lock())
+}
+
+// Function that runs the `secrets set|delete KEY` subcommand against the given store, the value
+// is read from input.
+pub(crate) fn run_secrets_command_with(
+    store: &impl SecretStore,
+    args: &[String],
+    input: &mut impl BufRead,
+) -> Result<String, Box<dyn Error>> {
+    match (args.first().map(|s| s.as_str()), args.get(1)) {
+        (Some("set"), Some(key)) => {
+            println!("Enter the value for {}:", key);
+            let mut value: String = String::new();
+            input.read_line(&mut value)?;
+            store.set(key, value.trim())?;
+            Ok(format!("Stored {} in the OS keyring", key))
+        }
+        (Some("delete"), Some(key)) => {
+            store.delete(key)?;
+            Ok(format!("Deleted {} from the OS keyring", key))
+        }
+        _ => Err("Usage: trading_bot_rust secrets <set|delete> KEY".into()),
+    }
+}
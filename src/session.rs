@@ -0,0 +1,57 @@
+use reqwest::StatusCode;
+use std::{
+    error::Error,
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+// Set once a request succeeds, cleared when the session is lost.
+static SESSION_UP: AtomicBool = AtomicBool::new(false);
+
+// Set when a request is rejected as unauthorized after the session was up, the sign of a
+// gateway restart. Picked up by the main loop.
+static SESSION_LOST: AtomicBool = AtomicBool::new(false);
+
+// A request the gateway rejected because its session isn't authenticated.
+#[derive(Debug)]
+pub(crate) struct Unauthenticated(pub(crate) StatusCode);
+
+impl fmt::Display for Unauthenticated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Gateway session is not authenticated: {}", self.0)
+    }
+}
+
+impl Error for Unauthenticated {}
+
+// Function that records the status of a gateway response.
+pub(crate) fn record_status(status: StatusCode) {
+    if status.is_success() {
+        SESSION_UP.store(true, Ordering::SeqCst);
+    } else if status == StatusCode::UNAUTHORIZED && SESSION_UP.swap(false, Ordering::SeqCst) {
+        SESSION_LOST.store(true, Ordering::SeqCst);
+    }
+}
+
+// Function that checks whether a request was rejected as unauthorized after the session was up,
+// clearing the flag so each loss is handled once.
+pub(crate) fn take_session_lost() -> bool {
+    SESSION_LOST.swap(false, Ordering::SeqCst)
+}
+
+// Follows the gateway's reported auth status across health checks, to tell a session that
+// dropped from one that was never up.
+#[derive(Default)]
+pub(crate) struct SessionWatch {
+    authenticated: Option<bool>,
+}
+
+impl SessionWatch {
+    // Function that records a health check's auth status, returning true when it flipped from
+    // authenticated to not.
+    pub(crate) fn record(&mut self, authenticated: bool) -> bool {
+        let flipped: bool = self.authenticated == Some(true) && !authenticated;
+        self.authenticated = Some(authenticated);
+        flipped
+    }
+}
@@ -0,0 +1,281 @@
+use reqwest::blocking::Response;
+use std::{env, error::Error, fs, path::Path};
+
+use crate::{
+    config::{get_user_input, Config, ConfigSource, CONFIG_PATH},
+    execution::{parse_execution_profile, ExecutionProfile},
+    structs::AccountResponse,
+};
+
+// One setup question: the config key it answers, its prompt, the answer used when left empty,
+// and the check that turns an answer into a TOML value or explains what is wrong with it.
+pub(crate) struct Question {
+    pub(crate) key: &'static str,
+    pub(crate) prompt: &'static str,
+    pub(crate) default: &'static str,
+    pub(crate) check: fn(&str) -> Result<String, String>,
+}
+
+// Questions asked by the setup wizard, in order.
+pub(crate) const QUESTIONS: &[Question] = &[
+    Question {
+        key: "profiles",
+        prompt: "Tickers to trade, comma separated",
+        default: "SPX",
+        check: check_tickers,
+    },
+    Question {
+        key: "option",
+        prompt: "Strategy: 1 Calendar, 2 Butterfly, 3 Boxspread, all for every strategy",
+        default: "all",
+        check: check_choice,
+    },
+    Question {
+        key: "execution_profile",
+        prompt: "Execution profile: conservative for single order, single fill; concentrated \
+                 for single order, multiple fills; spread-across-orders for multiple orders, \
+                 single fill; balanced for multiple orders, multiple fills",
+        default: "balanced",
+        check: check_execution_profile,
+    },
+    Question {
+        key: "arb_value",
+        prompt: "Minimum arb value, at least 0.10",
+        default: "0.10",
+        check: |answer| check_number(answer, 0.10, f64::MAX),
+    },
+    Question {
+        key: "strike_dif_value",
+        prompt: "Strike spacing of butterfly wings and boxes",
+        default: "5.0",
+        check: |answer| check_number(answer, 0.0, f64::MAX),
+    },
+    Question {
+        key: "discount_value",
+        prompt: "Discount Value between -0.15 and 0.15",
+        default: "0.0",
+        check: |answer| check_number(answer, -0.15, 0.15),
+    },
+    Question {
+        key: "num_days",
+        prompt: "Number of days worth of data to receive, 1-9",
+        default: "5",
+        check: |answer| check_integer(answer, 1, 9),
+    },
+    Question {
+        key: "num_days_offset",
+        prompt: "Number of days offset, 0-21",
+        default: "0",
+        check: |answer| check_integer(answer, 0, 21),
+    },
+    Question {
+        key: "seconds_to_sleep",
+        prompt: "Number of seconds to sleep between scans, at least 5",
+        default: "60",
+        check: |answer| check_integer(answer, 5, i64::MAX),
+    },
+    Question {
+        key: "domain",
+        prompt: "Gateway host",
+        default: "localhost",
+        check: check_host,
+    },
+    Question {
+        key: "port",
+        prompt: "Gateway port",
+        default: "5000",
+        check: |answer| check_integer(answer, 1, 65535).map(|port| format!("\"{}\"", port)),
+    },
+    Question {
+        key: "gateway_scheme",
+        prompt: "Gateway scheme, http or https",
+        default: "https",
+        check: check_scheme,
+    },
+    Question {
+        key: "test_mode",
+        prompt: "Run the bot in testing mode, logging orders without submitting them? (Y / N)",
+        default: "Y",
+        check: check_yes_no,
+    },
+];
+
+// Function that checks a comma separated list of tickers.
+fn check_tickers(answer: &str) -> Result<String, String> {
+    let tickers: Vec<String> = answer
+        .split(',')
+        .map(|ticker| ticker.trim().to_uppercase())
+        .filter(|ticker| !ticker.is_empty())
+        .collect();
+    if tickers.is_empty() {
+        return Err("Enter at least one ticker".to_string());
+    }
+    if let Some(ticker) = tickers.iter().find(|ticker| {
+        !ticker
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.')
+    }) {
+        return Err(format!("{} is not a valid ticker", ticker));
+    }
+    let tickers: Vec<String> = tickers
+        .iter()
+        .map(|ticker| format!("\"{}\"", ticker))
+        .collect();
+    Ok(format!("[{}]", tickers.join(", ")))
+}
+
+// Function that checks a strategy or fill type choice.
+fn check_choice(answer: &str) -> Result<String, String> {
+    match answer.trim().to_lowercase().as_str() {
+        choice @ ("1" | "2" | "3") => Ok(format!("\"{}\"", choice)),
+        "all" | "default" => Ok("\"all\"".to_string()),
+        _ => Err("Enter 1, 2, 3 or all".to_string()),
+    }
+}
+
+// Function that checks an execution profile spec, a preset with optional overrides.
+fn check_execution_profile(answer: &str) -> Result<String, String> {
+    let answer: &str = answer.trim();
+    match parse_execution_profile(answer, &ExecutionProfile::default()) {
+        Some(_) => Ok(format!("{:?}", answer)),
+        None => Err(
+            "Enter conservative, concentrated, spread-across-orders or balanced, optionally \
+             followed by overrides such as ;max_orders=4"
+                .to_string(),
+        ),
+    }
+}
+
+// Function that checks a number is within a range.
+fn check_number(answer: &str, min: f64, max: f64) -> Result<String, String> {
+    match answer.trim().parse::<f64>() {
+        Ok(val) if val >= min && val <= max => Ok(format!("{:?}", val)),
+        Ok(_) if max == f64::MAX => Err(format!("Enter a number of at least {}", min)),
+        Ok(_) => Err(format!("Enter a number between {} and {}", min, max)),
+        Err(_) => Err("Enter a number".to_string()),
+    }
+}
+
+// Function that checks an integer is within a range.
+fn check_integer(answer: &str, min: i64, max: i64) -> Result<String, String> {
+    match answer.trim().parse::<i64>() {
+        Ok(val) if (min..=max).contains(&val) => Ok(val.to_string()),
+        Ok(_) if max == i64::MAX => Err(format!("Enter a whole number of at least {}", min)),
+        Ok(_) => Err(format!("Enter a whole number between {} and {}", min, max)),
+        Err(_) => Err("Enter a whole number".to_string()),
+    }
+}
+
+// Function that checks a gateway host name or address, without a scheme or port.
+fn check_host(answer: &str) -> Result<String, String> {
+    let host: &str = answer.trim();
+    if host.is_empty()
+        || host.contains("://")
+        || host.contains('/')
+        || host.chars().any(|c| c.is_whitespace() || c == '"')
+    {
+        return Err("Enter a host name or IP address, without a scheme or path".to_string());
+    }
+    Ok(format!("\"{}\"", host))
+}
+
+// Function that checks a gateway scheme.
+fn check_scheme(answer: &str) -> Result<String, String> {
+    match answer.trim().to_lowercase().as_str() {
+        scheme @ ("http" | "https") => Ok(format!("\"{}\"", scheme)),
+        _ => Err("Enter http or https".to_string()),
+    }
+}
+
+// Function that checks a yes or no answer.
+fn check_yes_no(answer: &str) -> Result<String, String> {
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok("true".to_string()),
+        "n" | "no" => Ok("false".to_string()),
+        _ => Err("Enter Y or N".to_string()),
+    }
+}
+
+// Function that asks a question until the answer passes its check.
+fn ask(question: &Question) -> String {
+    loop {
+        let input: String = get_user_input(&format!("{} [{}]:", question.prompt, question.default));
+        let answer: &str = if input.is_empty() {
+            question.default
+        } else {
+            &input
+        };
+        match (question.check)(answer) {
+            Ok(val) => return val,
+            Err(e) => println!("{}, try again.", e),
+        }
+    }
+}
+
+// Function that renders checked answers as a config file.
+pub(crate) fn render_config(answers: &[(&str, String)]) -> String {
+    let mut text: String =
+        String::from("# Written by trading_bot_rust init, see the README for every setting.\n");
+    for (key, val) in answers {
+        text.push_str(&format!("{} = {}\n", key, val));
+    }
+    text
+}
+
+// Function that checks the gateway answers with the account the bot would trade.
+pub(crate) fn check_gateway(config: &Config) -> Result<String, Box<dyn Error>> {
+    let response: Response = config
+        .client()?
+        .get(format!("{}/v1/api/portfolio/accounts", config.base_url()))
+        .send()?;
+    if !response.status().is_success() {
+        return Err(format!("gateway answered {}", response.status()).into());
+    }
+
+    let accounts: Vec<AccountResponse> = response.json()?;
+    match accounts.first() {
+        Some(account) => Ok(account.id.clone()),
+        None => Err("no account found, is the gateway logged in?".into()),
+    }
+}
+
+// Function that walks through creating the config file, then tests the gateway with it.
+pub(crate) fn run_setup_wizard(
+    lookup_secret: impl Fn(&str) -> Option<String>,
+) -> Result<String, Box<dyn Error>> {
+    let path: String = env::var("CONFIG_FILE").unwrap_or(CONFIG_PATH.to_string());
+    if Path::new(&path).exists() {
+        let input: String =
+            get_user_input(&format!("{} already exists, overwrite it? (Y / N):", path));
+        if check_yes_no(&input).as_deref() != Ok("true") {
+            return Ok(format!("Kept the existing {}", path));
+        }
+    }
+
+    let answers: Vec<(&str, String)> = QUESTIONS
+        .iter()
+        .map(|question| (question.key, ask(question)))
+        .collect();
+    let text: String = render_config(&answers);
+    let mut config: Config = Config::from_source(&ConfigSource::from_toml(&text)?, false)?;
+    fs::write(&path, &text)?;
+
+    println!(
+        "Wrote {}, testing the gateway at {}...",
+        path,
+        config.base_url()
+    );
+    config.fill_secrets(lookup_secret);
+    match check_gateway(&config) {
+        Ok(account) => Ok(format!(
+            "Connected to the gateway, trading account {}",
+            account
+        )),
+        Err(e) => Err(format!(
+            "Couldn't reach the gateway at {}: {}. Log in to the gateway, then run the bot",
+            config.base_url(),
+            e
+        )
+        .into()),
+    }
+}
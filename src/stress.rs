@@ -0,0 +1,228 @@
+use chrono::NaiveDate;
+
+use crate::{
+    greeks::{bs_delta, bs_gamma, bs_price, bs_theta, bs_vega, implied_vol, intrinsic_value},
+    helpers::value_to_f64,
+    structs::PositionResponse,
+};
+
+// A short front / long back calendar, priced per share.
+pub(crate) struct CalendarPosition {
+    pub(crate) strike: f64,
+    pub(crate) right: String,
+    // Days until the front and back legs expire.
+    pub(crate) front_days: i64,
+    pub(crate) back_days: i64,
+    // Credit collected per share, front price minus back price.
+    pub(crate) credit: f64,
+    // Current price of the back leg, used to back out its implied volatility.
+    pub(crate) back_price: f64,
+    // Cost per share of getting out of the back leg, e.g. half its bid/ask spread.
+    pub(crate) exit_cost: f64,
+}
+
+// Knobs for the simulation.
+pub(crate) struct StressConfig {
+    pub(crate) paths: usize,
+    // Standard deviation of the parallel implied volatility shift at front expiry.
+    pub(crate) iv_shift: f64,
+    pub(crate) seed: u64,
+}
+
+impl Default for StressConfig {
+    fn default() -> Self {
+        StressConfig {
+            paths: 500,
+            iv_shift: 0.05,
+            seed: 42,
+        }
+    }
+}
+
+// Distribution of the position's P&L per share at front expiry.
+#[derive(Debug)]
+pub(crate) struct StressResult {
+    pub(crate) mean: f64,
+    pub(crate) p5: f64,
+    pub(crate) p1: f64,
+    pub(crate) worst: f64,
+    pub(crate) loss_probability: f64,
+    // Net greeks of the position today, long back minus short front.
+    pub(crate) delta: f64,
+    pub(crate) gamma: f64,
+    pub(crate) vega: f64,
+    pub(crate) theta: f64,
+}
+
+// Small deterministic generator, so repeated scans of the same chain rank identically.
+struct XorShift(u64);
+
+impl XorShift {
+    // Function that returns a uniform number in (0, 1).
+    fn next_uniform(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        ((self.0 >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    }
+
+    // Function that returns a standard normal number (Box-Muller).
+    fn next_normal(&mut self) -> f64 {
+        let (u1, u2): (f64, f64) = (self.next_uniform(), self.next_uniform());
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
+
+// Function that returns the value at a percentile of sorted samples.
+fn percentile(sorted: &[f64], pct: f64) -> f64 {
+    let index: usize = ((sorted.len() - 1) as f64 * pct).round() as usize;
+    sorted[index]
+}
+
+// Function that simulates the underlying and a volatility shift through front expiry, when the
+// short leg settles at intrinsic value and the long leg is sold at its model price.
+pub(crate) fn simulate_calendar(
+    position: &CalendarPosition,
+    spot: f64,
+    config: &StressConfig,
+) -> Option<StressResult> {
+    let back_years: f64 = position.back_days.max(1) as f64 / 365.0;
+    let front_years: f64 = position.front_days.max(0) as f64 / 365.0;
+    let remaining_years: f64 = back_years - front_years;
+    let vol: f64 = implied_vol(
+        position.back_price,
+        spot,
+        position.strike,
+        back_years,
+        &position.right,
+    )?;
+
+    // Same-day expiries still move over the session, so simulate at least a day.
+    let horizon: f64 = front_years.max(1.0 / 365.0);
+    let mut rng: XorShift = XorShift(config.seed.max(1));
+    let mut outcomes: Vec<f64> = Vec::with_capacity(config.paths);
+    for _ in 0..config.paths.max(1) {
+        let move_z: f64 = rng.next_normal();
+        let shift_z: f64 = rng.next_normal();
+        let end_spot: f64 =
+            spot * (-0.5 * vol * vol * horizon + vol * horizon.sqrt() * move_z).exp();
+        let end_vol: f64 = (vol + config.iv_shift * shift_z).max(0.01);
+
+        let front_value: f64 = intrinsic_value(end_spot, position.strike, &position.right);
+        let back_value: f64 = bs_price(
+            end_spot,
+            position.strike,
+            remaining_years,
+            end_vol,
+            &position.right,
+        );
+        outcomes.push(position.credit - front_value + back_value - position.exit_cost);
+    }
+    outcomes.sort_by(|a, b| a.total_cmp(b));
+
+    let (strike, right): (f64, &str) = (position.strike, &position.right);
+    let net = |greek: &dyn Fn(f64) -> f64| -> f64 { greek(back_years) - greek(front_years) };
+
+    Some(StressResult {
+        mean: outcomes.iter().sum::<f64>() / outcomes.len() as f64,
+        p5: percentile(&outcomes, 0.05),
+        p1: percentile(&outcomes, 0.01),
+        worst: outcomes[0],
+        loss_probability: outcomes.iter().filter(|pnl| **pnl < 0.0).count() as f64
+            / outcomes.len() as f64,
+        delta: net(&|years| bs_delta(spot, strike, years, vol, right)),
+        gamma: net(&|years| bs_gamma(spot, strike, years, vol)),
+        vega: net(&|years| bs_vega(spot, strike, years, vol)),
+        theta: net(&|years| bs_theta(spot, strike, years, vol)),
+    })
+}
+
+// A held calendar: a short and a long contract with the same strike and right, where the short
+// leg expires first.
+pub(crate) struct HeldCalendar<'a> {
+    pub(crate) short: &'a PositionResponse,
+    pub(crate) long: &'a PositionResponse,
+    pub(crate) strike: f64,
+    pub(crate) right: &'static str,
+    // Days until the front and back legs expire.
+    pub(crate) front_days: i64,
+    pub(crate) back_days: i64,
+    pub(crate) quantity: f64,
+}
+
+// Function that pairs held option legs into calendars, each short leg with the nearest later
+// long leg at the same strike.
+pub(crate) fn held_calendars(
+    positions: &[PositionResponse],
+    today: NaiveDate,
+) -> Vec<HeldCalendar<'_>> {
+    let legs: Vec<(&PositionResponse, f64, &'static str, i64)> = positions
+        .iter()
+        .filter_map(|position| {
+            let strike: f64 = value_to_f64(position.strike.as_ref()?)?;
+            let right: &'static str = match position.put_or_call.as_deref()? {
+                "C" | "CALL" => "C",
+                "P" | "PUT" => "P",
+                _ => return None,
+            };
+            let expiry: NaiveDate =
+                NaiveDate::parse_from_str(position.expiry.as_ref()?, "%Y%m%d").ok()?;
+            Some((position, strike, right, (expiry - today).num_days()))
+        })
+        .collect();
+
+    let mut calendars: Vec<HeldCalendar> = Vec::new();
+    for (short, strike, right, front_days) in &legs {
+        if short.position >= 0.0 {
+            continue;
+        }
+
+        let long = legs
+            .iter()
+            .filter(|(long, long_strike, long_right, back_days)| {
+                long.position > 0.0
+                    && long_strike == strike
+                    && long_right == right
+                    && back_days > front_days
+            })
+            .min_by_key(|(_, _, _, back_days)| *back_days);
+
+        if let Some((long, _, _, back_days)) = long {
+            calendars.push(HeldCalendar {
+                short,
+                long,
+                strike: *strike,
+                right,
+                front_days: *front_days,
+                back_days: *back_days,
+                quantity: short.position.abs().min(long.position),
+            });
+        }
+    }
+
+    calendars
+}
+
+// Function that prices held calendars for the simulation. Returns each calendar with its size.
+pub(crate) fn calendars_from_positions(
+    positions: &[PositionResponse],
+    today: NaiveDate,
+) -> Vec<(CalendarPosition, f64)> {
+    held_calendars(positions, today)
+        .into_iter()
+        .map(|calendar| {
+            (
+                CalendarPosition {
+                    strike: calendar.strike,
+                    right: calendar.right.to_string(),
+                    front_days: calendar.front_days,
+                    back_days: calendar.back_days,
+                    credit: calendar.short.avg_price - calendar.long.avg_price,
+                    back_price: calendar.long.mkt_price,
+                    exit_cost: 0.0,
+                },
+                calendar.quantity,
+            )
+        })
+        .collect()
+}
@@ -1,4 +1,28 @@
-use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Instant,
+};
+
+use chrono::{DateTime, NaiveTime, Utc};
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    blacklist::BlacklistedQuote,
+    execution::ExecutionProfile,
+    expiry::ExpiryAction,
+    exposure::{ExpiryCaps, ExpiryExposure},
+    metrics::EndpointStats,
+    positions::PositionRules,
+    scheduler::StrategySchedules,
+};
+
+// Map of expiry date -> contract type -> strike -> conid, as loaded. ChainView compacts it into
+// indexed vectors.
+pub(crate) type ConidsMap = HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>;
+
+// Map of expiry date -> contract type -> sorted strikes.
+pub(crate) type StrikeSlice = HashMap<String, HashMap<String, Vec<f64>>>;
 
 #[derive(Serialize, Deserialize)]
 pub(crate) struct Confirmation {
@@ -18,6 +42,14 @@ pub(crate) struct SecDefResponse {
     pub(crate) sections: Option<Vec<Section>>,
 }
 
+// A contract's details from the contract info endpoint.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ContractInfoResponse {
+    pub(crate) symbol: String,
+    // IND for an index, FUT, STK and so on.
+    pub(crate) instrument_type: String,
+}
+
 #[derive(Serialize, Deserialize)]
 pub(crate) struct Section {
     #[serde(rename = "secType")]
@@ -25,6 +57,14 @@ pub(crate) struct Section {
     pub(crate) months: Option<String>,
 }
 
+// Strikes listed in a month's option chain, per right.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub(crate) struct SecDefStrikesResponse {
+    pub(crate) call: Vec<f64>,
+    pub(crate) put: Vec<f64>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub(crate) struct SecDefInfoResponse {
     pub(crate) conid: f64,
@@ -34,22 +74,148 @@ pub(crate) struct SecDefInfoResponse {
     pub(crate) strike: f64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// Snapshot of a conid's market data fields. Fields the gateway hasn't filled in yet are missing,
+// and depending on its version values arrive as strings or numbers.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
 pub(crate) struct MarketDataResponse {
-    #[serde(rename = "conidEx")]
-    pub(crate) conid_ex: String,
-    #[serde(rename = "84")]
+    #[serde(rename = "conidEx", deserialize_with = "deserialize_field")]
+    pub(crate) conid_ex: Option<String>,
+    #[serde(deserialize_with = "deserialize_field")]
+    pub(crate) conid: Option<String>,
+    // Last price.
+    #[serde(rename = "31", deserialize_with = "deserialize_field")]
+    pub(crate) field_31: Option<String>,
+    // Bid.
+    #[serde(rename = "84", deserialize_with = "deserialize_field")]
     pub(crate) field_84: Option<String>,
-    #[serde(rename = "85")]
+    // Ask size.
+    #[serde(rename = "85", deserialize_with = "deserialize_field")]
     pub(crate) field_85: Option<String>,
-    #[serde(rename = "86")]
+    // Ask.
+    #[serde(rename = "86", deserialize_with = "deserialize_field")]
     pub(crate) field_86: Option<String>,
+    // Market data availability.
+    #[serde(rename = "6509", deserialize_with = "deserialize_field")]
+    pub(crate) field_6509: Option<String>,
+    // Prior close.
+    #[serde(rename = "7741", deserialize_with = "deserialize_field")]
+    pub(crate) field_7741: Option<String>,
+    // Option open interest.
+    #[serde(rename = "7638", deserialize_with = "deserialize_field")]
+    pub(crate) field_7638: Option<String>,
+}
+
+// Function that reads a snapshot field sent as either a string or a number.
+fn deserialize_field<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(val) => Some(val),
+        serde_json::Value::Number(val) => Some(val.to_string()),
+        _ => None,
+    })
+}
+
+// Function that reads a required id sent as either a string or a number.
+fn deserialize_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(val) => Ok(val),
+        serde_json::Value::Number(val) => Ok(val.to_string()),
+        other => Err(serde::de::Error::custom(format!(
+            "expected an id, found {}",
+            other
+        ))),
+    }
+}
+
+// A conid's quote parsed from a snapshot, None for fields without a usable value.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Quote {
+    pub(crate) conid: String,
+    pub(crate) bid: Option<f64>,
+    pub(crate) ask: Option<f64>,
+    pub(crate) ask_size: Option<f64>,
+    pub(crate) last: Option<f64>,
+    pub(crate) prior_close: Option<f64>,
+    pub(crate) open_interest: Option<f64>,
+}
+
+impl Quote {
+    // Function that returns the quote the scanner prices with, None unless the bid, ask and ask
+    // size are all known.
+    pub(crate) fn to_opt(&self, provenance: Provenance) -> Option<Opt> {
+        match (self.bid, self.ask, self.ask_size) {
+            (Some(bid), Some(ask), Some(asz)) => Some(Opt {
+                asz,
+                mkt: ((bid + ask) / 2.0 * 100.0).round() / 100.0,
+                bid,
+                ask,
+                open_interest: self.open_interest,
+                provenance,
+            }),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub(crate) struct PortfolioResponse {
     #[serde(rename = "equitywithloanvalue")]
     pub(crate) equity_with_loan_value: PortfolioAmount,
+    #[serde(rename = "availablefunds")]
+    pub(crate) available_funds: Option<PortfolioAmount>,
+    #[serde(rename = "buyingpower")]
+    pub(crate) buying_power: Option<PortfolioAmount>,
+    #[serde(rename = "maintmarginreq")]
+    pub(crate) maintenance_margin: Option<PortfolioAmount>,
+    #[serde(rename = "netliquidation")]
+    pub(crate) net_liquidation: Option<PortfolioAmount>,
+}
+
+// Account balances from the portfolio summary, fields the gateway omits are 0.0.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct AccountSummary {
+    pub(crate) equity_with_loan: f64,
+    pub(crate) available_funds: f64,
+    pub(crate) buying_power: f64,
+    pub(crate) maintenance_margin: f64,
+    pub(crate) net_liquidation: f64,
+}
+
+impl From<PortfolioResponse> for AccountSummary {
+    fn from(response: PortfolioResponse) -> Self {
+        let amount = |amount: Option<PortfolioAmount>| -> f64 {
+            amount.map(|amount| amount.amount).unwrap_or(0.0)
+        };
+        AccountSummary {
+            equity_with_loan: response.equity_with_loan_value.amount,
+            available_funds: amount(response.available_funds),
+            buying_power: amount(response.buying_power),
+            maintenance_margin: amount(response.maintenance_margin),
+            net_liquidation: amount(response.net_liquidation),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PositionResponse {
+    pub(crate) conid: i64,
+    pub(crate) position: f64,
+    #[serde(rename = "mktPrice", default)]
+    pub(crate) mkt_price: f64,
+    #[serde(rename = "avgPrice", default)]
+    pub(crate) avg_price: f64,
+    #[serde(rename = "mktValue", default)]
+    pub(crate) mkt_value: f64,
+    #[serde(rename = "putOrCall")]
+    pub(crate) put_or_call: Option<String>,
+    // Sent as either a number or a string depending on the gateway version.
+    pub(crate) strike: Option<serde_json::Value>,
+    // Expiry as YYYYMMDD.
+    pub(crate) expiry: Option<String>,
+    #[serde(rename = "undConid")]
+    pub(crate) und_conid: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -57,7 +223,7 @@ pub(crate) struct PortfolioAmount {
     pub(crate) amount: f64,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct OrderBody {
     #[serde(rename = "acctId")]
     pub(crate) acct_id: String,
@@ -77,46 +243,409 @@ pub(crate) struct OrderBody {
     pub(crate) quantity: i32,
     #[serde(rename = "useAdaptive")]
     pub(crate) use_adaptive: bool,
+    #[serde(rename = "cOID", skip_serializing_if = "Option::is_none")]
+    pub(crate) c_oid: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub(crate) struct RequestDataStruct {
     pub(crate) orders: Vec<OrderBody>,
+    // Index of the contender each order was built from, not sent to IBKR.
+    #[serde(skip)]
+    pub(crate) contender_index: Vec<usize>,
+    // The contenders and orders each order nets, empty for an order that wasn't netted.
+    #[serde(skip)]
+    pub(crate) netted: Vec<Vec<(usize, OrderBody)>>,
 }
 
+// An order listed by the live orders endpoint.
 #[derive(Serialize, Deserialize)]
 pub(crate) struct Order {
+    #[serde(rename = "orderId", deserialize_with = "deserialize_id")]
+    pub(crate) order_id: String,
     pub(crate) status: String,
-    pub(crate) order_id: f64,
+    // Client order id of the order, only set for orders the bot placed.
+    pub(crate) order_ref: Option<String>,
+    #[serde(rename = "avgPrice", default, deserialize_with = "deserialize_field")]
+    pub(crate) avg_price: Option<String>,
 }
 
+// Live orders, the gateway leaves the list out while it has none to report.
 #[derive(Serialize, Deserialize)]
 pub(crate) struct OrdersResponse {
+    #[serde(default)]
     pub(crate) orders: Vec<Order>,
 }
 
+// Acknowledgement of a cancel request, the gateway answers an error with a 200 too.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct CancelResponse {
+    pub(crate) msg: String,
+    #[serde(deserialize_with = "deserialize_id")]
+    pub(crate) order_id: String,
+}
+
+// An execution listed by the trades endpoint.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct TradeResponse {
+    pub(crate) execution_id: String,
+    #[serde(deserialize_with = "deserialize_id")]
+    pub(crate) conid: String,
+    pub(crate) side: String,
+    #[serde(deserialize_with = "deserialize_field")]
+    pub(crate) size: Option<String>,
+    #[serde(deserialize_with = "deserialize_field")]
+    pub(crate) price: Option<String>,
+    // Client order id of the order, only set for orders the bot placed.
+    pub(crate) order_ref: Option<String>,
+    #[serde(default)]
+    pub(crate) trade_time: String,
+}
+
+// Per-ticker trading parameters.
+pub(crate) struct Profile {
+    pub(crate) ticker: String,
+    pub(crate) execution: ExecutionProfile,
+    pub(crate) arb_val: f64,
+    pub(crate) strike_dif_value: f64,
+    pub(crate) butterfly_wings: ButterflyWings,
+    pub(crate) discount_value: f64,
+    pub(crate) tick_rule: TickRule,
+    pub(crate) profit_gate: ProfitGate,
+    pub(crate) hedge_rule: HedgeRule,
+    pub(crate) expiry_rule: ExpiryRule,
+    pub(crate) roll_rule: RollRule,
+    pub(crate) order_tags: OrderTags,
+    pub(crate) strategies: StrategySchedules,
+    pub(crate) price_limits: PriceLimits,
+    pub(crate) expiry_caps: ExpiryCaps,
+    pub(crate) position_rules: PositionRules,
+}
+
+// Per strategy minimum worst case profit per share a contender must clear after fees, the
+// butterfly and box checks are riskless payoffs and the calendar check is stress tested.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ProfitGate {
+    pub(crate) calendar_min_profit: f64,
+    pub(crate) butterfly_min_profit: f64,
+    pub(crate) boxspread_min_profit: f64,
+    pub(crate) fee_per_contract: f64,
+    // Annual rate the cash from a short box is carried at until expiry.
+    pub(crate) risk_free_rate: f64,
+}
+
+impl Default for ProfitGate {
+    fn default() -> Self {
+        ProfitGate {
+            calendar_min_profit: 0.25,
+            butterfly_min_profit: 0.0,
+            boxspread_min_profit: 0.0,
+            fee_per_contract: 0.0,
+            risk_free_rate: 0.0,
+        }
+    }
+}
+
+impl ProfitGate {
+    // Fees for a combo of the given number of contracts, per share of the 100 multiplier.
+    pub(crate) fn fees_per_share(&self, contracts: f64) -> f64 {
+        self.fee_per_contract * contracts / 100.0
+    }
+}
+
+// Minimum price increment for an underlying's combo orders, a wider tick applies at and above
+// the threshold when it is set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct TickRule {
+    pub(crate) tick: f64,
+    pub(crate) threshold: f64,
+    pub(crate) tick_above: f64,
+}
+
+impl Default for TickRule {
+    fn default() -> Self {
+        TickRule {
+            tick: 0.01,
+            threshold: 0.0,
+            tick_above: 0.01,
+        }
+    }
+}
+
+impl TickRule {
+    pub(crate) fn tick_for(&self, price: f64) -> f64 {
+        if self.threshold > 0.0 && price.abs() >= self.threshold {
+            self.tick_above
+        } else {
+            self.tick
+        }
+    }
+}
+
+// When and with what the net delta of an underlying's positions is hedged. The hedge instrument
+// must be set and tradable, as index underlyings like SPX can't be traded, and each unit of it
+// carries delta_per_unit of delta, e.g. 5 for an MES future hedging SPX options. A threshold of
+// 0.0 disables hedging.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HedgeRule {
+    pub(crate) threshold: f64,
+    pub(crate) conid: Option<String>,
+    pub(crate) delta_per_unit: f64,
+}
+
+impl Default for HedgeRule {
+    fn default() -> Self {
+        HedgeRule {
+            threshold: 0.0,
+            conid: None,
+            delta_per_unit: 1.0,
+        }
+    }
+}
+
+// How option positions are handled on their expiration day: the action taken once the cutoff,
+// in New York time, passes, and whether short legs left in the money turn into shares.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ExpiryRule {
+    pub(crate) action: ExpiryAction,
+    pub(crate) cutoff: NaiveTime,
+    pub(crate) physical_settlement: bool,
+}
+
+impl Default for ExpiryRule {
+    fn default() -> Self {
+        ExpiryRule {
+            action: ExpiryAction::Off,
+            cutoff: NaiveTime::from_hms_opt(15, 30, 0).unwrap(),
+            physical_settlement: true,
+        }
+    }
+}
+
+// When the short near leg of a held calendar is rolled to the next expiry: at or under dte days
+// to expiry, or once profit_pct of the credit it was sold for is captured. None and 0.0 disable
+// each trigger.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct RollRule {
+    pub(crate) dte: Option<i64>,
+    pub(crate) profit_pct: f64,
+}
+
+// How the bot's orders show up in IBKR's reports: the referrer sent with every order, and a tag
+// per strategy put in the client order id (order reference) after the bot's "tbr-" prefix.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct OrderTags {
+    pub(crate) referrer: String,
+    pub(crate) calendar: Option<String>,
+    pub(crate) butterfly: Option<String>,
+    pub(crate) boxspread: Option<String>,
+}
+
+impl Default for OrderTags {
+    fn default() -> Self {
+        OrderTags {
+            referrer: "NO_REFERRER_PROVIDED".to_string(),
+            calendar: None,
+            butterfly: None,
+            boxspread: None,
+        }
+    }
+}
+
+impl OrderTags {
+    // Function that returns the tag of a spread type's orders, if one is set.
+    pub(crate) fn tag(&self, type_spread: &str) -> Option<&str> {
+        match type_spread {
+            "Calendar" => self.calendar.as_deref(),
+            "Butterfly" => self.butterfly.as_deref(),
+            "Boxspread" => self.boxspread.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+// Bounds on the limit price of a strategy's orders, credits being negative as IBKR prices combos
+// bought for a credit. A last guard against pricing bugs, None leaves a side open.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct PriceBounds {
+    pub(crate) min: Option<f64>,
+    pub(crate) max: Option<f64>,
+}
+
+impl PriceBounds {
+    // Function that checks whether a limit price is a number within the bounds.
+    pub(crate) fn allows(&self, price: f64) -> bool {
+        price.is_finite()
+            && self.min.is_none_or(|min| price >= min)
+            && self.max.is_none_or(|max| price <= max)
+    }
+}
+
+// The limit price bounds of each strategy's orders.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct PriceLimits {
+    pub(crate) calendar: PriceBounds,
+    pub(crate) butterfly: PriceBounds,
+    pub(crate) boxspread: PriceBounds,
+}
+
+impl PriceLimits {
+    // Function that returns the bounds of a spread type's orders.
+    pub(crate) fn bounds(&self, type_spread: &str) -> PriceBounds {
+        match type_spread {
+            "Calendar" => self.calendar,
+            "Butterfly" => self.butterfly,
+            "Boxspread" => self.boxspread,
+            _ => PriceBounds::default(),
+        }
+    }
+}
+
+// The butterfly wing widths scanned, each multiple of the strike difference from 1 up to
+// widths, and the arb value each multiple needs from 1 on. Widths without one need the ticker's
+// arb value times the multiple.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ButterflyWings {
+    pub(crate) widths: usize,
+    pub(crate) arb_values: Vec<f64>,
+}
+
+impl ButterflyWings {
+    // Function that returns the multiple of the strike difference a wing width is, None when it
+    // isn't one of the widths scanned.
+    pub(crate) fn multiple(&self, width: f64, strike_dif: f64) -> Option<usize> {
+        let width: f64 = (width * 10.0).round() / 10.0;
+        (1..=self.widths.max(1))
+            .find(|multiple| ((strike_dif * *multiple as f64) * 10.0).round() / 10.0 == width)
+    }
+
+    // Function that returns the arb value a butterfly needs at a multiple of the strike
+    // difference, from the threshold the ticker's arb value scans at, so a regime scaling of it
+    // carries over.
+    pub(crate) fn arb_threshold(&self, multiple: usize, threshold: f64, arb_val: f64) -> f64 {
+        match self.arb_values.get(multiple.saturating_sub(1)) {
+            Some(arb_value) if arb_val > 0.0 => threshold * arb_value / arb_val,
+            _ => threshold * multiple as f64,
+        }
+    }
+}
+
+// Heartbeat written every cycle for external watchdogs.
+#[derive(Serialize, Deserialize, Default)]
+pub(crate) struct Health {
+    pub(crate) state: String,
+    pub(crate) updated_at: String,
+    pub(crate) last_scan_time: Option<String>,
+    pub(crate) last_api_success: Option<String>,
+    pub(crate) live_order_count: usize,
+    pub(crate) metrics: BTreeMap<String, u64>,
+    pub(crate) account: Option<AccountSummary>,
+    // The gateway requests go to, "primary" or "secondary", None without a secondary set.
+    #[serde(default)]
+    pub(crate) active_gateway: Option<String>,
+    // Contracts kept out of scans for producing arbs that never fill.
+    #[serde(default)]
+    pub(crate) blacklist: Vec<BlacklistedQuote>,
+    // The bot's open spreads by ticker and expiration.
+    #[serde(default)]
+    pub(crate) expiry_exposure: Vec<ExpiryExposure>,
+    // Gateway request counts, latencies and errors by endpoint.
+    #[serde(default)]
+    pub(crate) endpoints: BTreeMap<String, EndpointStats>,
+}
+
+// An order submitted by the bot that has not been cancelled yet.
+pub(crate) struct LiveOrder {
+    pub(crate) order_id: String,
+    pub(crate) submitted_at: Instant,
+    // The order as last sent and the most it may be repriced to, for escalating joined orders.
+    pub(crate) order: Option<OrderBody>,
+    pub(crate) limit_price: f64,
+    pub(crate) priced_at: Instant,
+    // Rungs of a laddered combo share a group, the rest are cancelled once one fills.
+    pub(crate) ladder_group: Option<String>,
+}
+
+// One bar of price history, prices in the contract's currency and time in epoch milliseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Bar {
+    pub(crate) time: i64,
+    pub(crate) open: f64,
+    pub(crate) high: f64,
+    pub(crate) low: f64,
+    pub(crate) close: f64,
+    pub(crate) volume: f64,
+}
+
+// Feed a quote came from. Every quote is an IBKR snapshot today, quotes built by hand in
+// replays and tests are unknown.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum QuoteSource {
+    #[default]
+    Unknown,
+    Ibkr,
+}
+
+// Where and when a quote was fetched, so a signal can be traced back to the data behind it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Provenance {
+    pub(crate) source: QuoteSource,
+    pub(crate) fetched_at: Option<DateTime<Utc>>,
+}
+
+impl Provenance {
+    // Function that returns the fetch time as written to the journal.
+    pub(crate) fn fetched_at_rfc3339(&self) -> Option<String> {
+        self.fetched_at.map(|fetched_at| fetched_at.to_rfc3339())
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub(crate) struct Opt {
     pub(crate) asz: f64,
     pub(crate) mkt: f64,
     pub(crate) bid: f64,
+    pub(crate) ask: f64,
+    #[serde(default)]
+    pub(crate) open_interest: Option<f64>,
+    #[serde(skip)]
+    pub(crate) provenance: Provenance,
 }
 
-#[derive(Serialize, Deserialize)]
+impl Opt {
+    // Function that returns a quote priced at zero, what a missing quote is read as with
+    // MISSING_QUOTES=zero.
+    pub(crate) fn zeroed(open_interest: Option<f64>, provenance: Provenance) -> Self {
+        Opt {
+            asz: 0.0,
+            mkt: 0.0,
+            bid: 0.0,
+            ask: 0.0,
+            open_interest,
+            provenance,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct Contract {
     pub(crate) strike: f64,
     pub(crate) mkt_price: f64,
     pub(crate) date: String,
     pub(crate) type_contract: String,
+    #[serde(skip)]
+    pub(crate) provenance: Provenance,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct Contender {
     pub(crate) arb_val: f64,
     pub(crate) avg_ask: f64,
     pub(crate) type_spread: String,
     pub(crate) exp_date: String,
     pub(crate) rank_value: f64,
+    pub(crate) regime_value: f64,
     pub(crate) contracts: Vec<Contract>,
 }
 
@@ -148,6 +677,40 @@ impl Contender {
         }
     }
 
+    // Function that returns the edge the scan saw at mid prices. A box's credit is only edge
+    // beyond the width of its strikes.
+    pub(crate) fn modeled_edge(&self) -> f64 {
+        if self.type_spread != "Boxspread" {
+            return self.arb_val;
+        }
+        let strikes = self.contracts.iter().map(|contract| contract.strike);
+        let width: f64 =
+            strikes.clone().fold(f64::MIN, f64::max) - strikes.fold(f64::MAX, f64::min);
+        self.arb_val - width
+    }
+
+    // Function that returns the capital one fill ties up per share: the premium of the legs it
+    // buys, or for a box the strike width it owes at expiry.
+    pub(crate) fn margin(&self) -> f64 {
+        if self.type_spread == "Boxspread" {
+            return self.arb_val - self.modeled_edge();
+        }
+        self.contracts
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.action(*i).starts_with("BUY"))
+            .map(|(i, contract)| self.multiplier(1, i) as f64 * contract.mkt_price)
+            .sum()
+    }
+
+    // Function that returns how many combo orders one fill of the contender is split into.
+    pub(crate) fn orders_per_fill(&self) -> usize {
+        match self.type_spread.as_str() {
+            "Butterfly" | "Boxspread" => 2,
+            _ => 1,
+        }
+    }
+
     pub(crate) fn multiplier(&self, num_fills: i32, index: usize) -> i32 {
         if self.type_spread == "Butterfly" && index == 1 {
             num_fills * 2
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use crate::{
+    chain::ChainView, greeks::implied_vol, helpers::calc_time_difference, structs::Contender,
+};
+
+// Implied volatility surface for one scan: a quadratic smile in log-moneyness per expiry, fitted
+// to the out of the money quotes, which are the liquid side of the chain.
+pub(crate) struct VolSurface {
+    underlying_price: f64,
+    current_date: String,
+    // Expiry -> smile coefficients (a, b, c) for iv = a + b * k + c * k^2, with k = ln(K / S).
+    fits: HashMap<String, [f64; 3]>,
+}
+
+impl VolSurface {
+    // Function that fits the surface from the chain's latest quotes, None without a spot price.
+    pub(crate) fn build(chain: &ChainView, current_date: &str) -> Option<Self> {
+        let underlying_price: f64 = chain.underlying_price()?;
+        let mut fits: HashMap<String, [f64; 3]> = HashMap::new();
+
+        for date in chain.dates() {
+            let years: f64 = years_to_expiry(current_date, date);
+            let mut points: Vec<(f64, f64)> = Vec::new();
+            for right in ["C", "P"] {
+                for strike in chain.strikes(date, right) {
+                    let out_of_the_money: bool = if right == "C" {
+                        *strike >= underlying_price
+                    } else {
+                        *strike <= underlying_price
+                    };
+                    if !out_of_the_money {
+                        continue;
+                    }
+                    let mkt: f64 = match chain.quote(date, right, *strike) {
+                        Some(opt) if opt.mkt > 0.0 => opt.mkt,
+                        _ => continue,
+                    };
+                    if let Some(iv) = implied_vol(mkt, underlying_price, *strike, years, right) {
+                        points.push(((strike / underlying_price).ln(), iv));
+                    }
+                }
+            }
+
+            if let Some(fit) = fit_quadratic(&points) {
+                fits.insert(date.clone(), fit);
+            }
+        }
+
+        Some(VolSurface {
+            underlying_price,
+            current_date: current_date.to_string(),
+            fits,
+        })
+    }
+
+    // Function that returns the fitted implied volatility, if the expiry had enough quotes.
+    pub(crate) fn fitted_vol(&self, date: &str, strike: f64) -> Option<f64> {
+        let [a, b, c]: [f64; 3] = *self.fits.get(date)?;
+        let k: f64 = (strike / self.underlying_price).ln();
+        Some(a + b * k + c * k * k)
+    }
+
+    // Function that returns how far a price's implied volatility sits above the fitted surface.
+    pub(crate) fn residual(&self, date: &str, right: &str, strike: f64, price: f64) -> Option<f64> {
+        let years: f64 = years_to_expiry(&self.current_date, date);
+        let iv: f64 = implied_vol(price, self.underlying_price, strike, years, right)?;
+        Some(iv - self.fitted_vol(date, strike)?)
+    }
+
+    // Function that checks whether a contender's edge comes from legs off the surface in its
+    // favour, selling above or buying below by more than the threshold, which is usually a stale
+    // quote rather than a true mispricing.
+    pub(crate) fn explains_arb(&self, contender: &Contender, max_residual: f64) -> bool {
+        contender.contracts.iter().enumerate().any(|(index, leg)| {
+            match self.residual(&leg.date, &leg.type_contract, leg.strike, leg.mkt_price) {
+                Some(residual) => {
+                    if contender.action(index) == "SELL" {
+                        residual > max_residual
+                    } else {
+                        residual < -max_residual
+                    }
+                }
+                None => false,
+            }
+        })
+    }
+}
+
+// Function that returns the implied volatility of the call nearest spot on the first expiry that
+// has one quoted, None without a spot price or such a call.
+pub(crate) fn atm_vol(chain: &ChainView, current_date: &str) -> Option<f64> {
+    let underlying_price: f64 = chain.underlying_price()?;
+    chain.dates().iter().find_map(|date| {
+        let strike: f64 = chain.strikes(date, "C").iter().copied().min_by(|a, b| {
+            (a - underlying_price)
+                .abs()
+                .total_cmp(&(b - underlying_price).abs())
+        })?;
+        let mkt: f64 = match chain.quote(date, "C", strike) {
+            Some(opt) if opt.mkt > 0.0 => opt.mkt,
+            _ => return None,
+        };
+        let years: f64 = years_to_expiry(current_date, date);
+        implied_vol(mkt, underlying_price, strike, years, "C")
+    })
+}
+
+// Function that returns the time to expiry in years, counting same-day expiries as one day.
+fn years_to_expiry(current_date: &str, date: &str) -> f64 {
+    calc_time_difference(current_date, date).max(1) as f64 / 365.0
+}
+
+// Function that fits iv = a + b * k + c * k^2 by least squares, None with fewer than three points.
+pub(crate) fn fit_quadratic(points: &[(f64, f64)]) -> Option<[f64; 3]> {
+    if points.len() < 3 {
+        return None;
+    }
+
+    // Normal equations: sums of k^0..k^4 and iv * k^0..k^2.
+    let mut sums: [f64; 5] = [0.0; 5];
+    let mut rhs: [f64; 3] = [0.0; 3];
+    for (k, iv) in points {
+        for (power, sum) in sums.iter_mut().enumerate() {
+            *sum += k.powi(power as i32);
+        }
+        for (power, value) in rhs.iter_mut().enumerate() {
+            *value += iv * k.powi(power as i32);
+        }
+    }
+    let matrix: [[f64; 3]; 3] = [
+        [sums[0], sums[1], sums[2]],
+        [sums[1], sums[2], sums[3]],
+        [sums[2], sums[3], sums[4]],
+    ];
+
+    // Cramer's rule is plenty for a 3x3 system.
+    let det = |m: &[[f64; 3]; 3]| -> f64 {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let denominator: f64 = det(&matrix);
+    if denominator.abs() <= 1e-12 * (matrix[0][0] * matrix[1][1] * matrix[2][2]).abs() {
+        return None;
+    }
+
+    let mut coefficients: [f64; 3] = [0.0; 3];
+    for (column, coefficient) in coefficients.iter_mut().enumerate() {
+        let mut replaced: [[f64; 3]; 3] = matrix;
+        for (row, value) in rhs.iter().enumerate() {
+            replaced[row][column] = *value;
+        }
+        *coefficient = det(&replaced) / denominator;
+    }
+
+    Some(coefficients)
+}
@@ -0,0 +1,95 @@
+use std::{env, io, thread::sleep, time::Duration};
+
+// Exit codes a unit's restart policy can tell apart, e.g. RestartPreventExitStatus=77 78 keeps a
+// bot with bad settings or credentials down instead of restarting it in a loop.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ExitCode {
+    // Anything unexpected, usually worth a restart.
+    Error = 1,
+    // The bot stopped itself to protect the account, e.g. too little equity to trade.
+    RiskShutdown = 3,
+    // The gateway rejected the session.
+    AuthFailure = 77,
+    // Invalid settings, or a host the bot refuses to trade from.
+    ConfigError = 78,
+}
+
+// Function that sends a state like "READY=1" to the service manager, returning whether there was
+// one listening. A no-op unless the bot runs under a Type=notify unit.
+pub(crate) fn notify(state: &str) -> io::Result<bool> {
+    match env::var("NOTIFY_SOCKET") {
+        Ok(socket) if !socket.is_empty() => notify_socket(&socket, state).map(|_| true),
+        _ => Ok(false),
+    }
+}
+
+// Function that sends a state to a notify socket, a path or an abstract name starting with "@".
+#[cfg(unix)]
+pub(crate) fn notify_socket(socket: &str, state: &str) -> io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let sender: UnixDatagram = UnixDatagram::unbound()?;
+    match socket.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+            sender.send_to_addr(state.as_bytes(), &SocketAddr::from_abstract_name(name)?)?;
+        }
+        _ => {
+            sender.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn notify_socket(_socket: &str, _state: &str) -> io::Result<()> {
+    Ok(())
+}
+
+// Function that returns how often to ping a watchdog of WATCHDOG_USEC microseconds, half its
+// timeout, or None when it isn't set or WATCHDOG_PID names another process.
+pub(crate) fn parse_watchdog(
+    usec: Option<&str>,
+    pid: Option<&str>,
+    own_pid: u32,
+) -> Option<Duration> {
+    if pid.is_some_and(|pid| pid.trim().parse::<u32>().ok() != Some(own_pid)) {
+        return None;
+    }
+    match usec?.trim().parse::<u64>() {
+        Ok(usec) if usec > 0 => Some(Duration::from_micros(usec / 2)),
+        _ => None,
+    }
+}
+
+// Function that returns how often the unit's watchdog has to be pinged, None without one.
+pub(crate) fn watchdog_interval() -> Option<Duration> {
+    parse_watchdog(
+        env::var("WATCHDOG_USEC").ok().as_deref(),
+        env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+// Function that tells the watchdog the bot is still making progress.
+pub(crate) fn ping_watchdog() {
+    if watchdog_interval().is_some() {
+        let _ = notify("WATCHDOG=1");
+    }
+}
+
+// Function that sleeps, pinging the watchdog often enough along the way that it doesn't fire.
+pub(crate) fn sleep_watched(duration: Duration) {
+    let interval: Duration = match watchdog_interval() {
+        Some(interval) => interval,
+        None => return sleep(duration),
+    };
+    let mut remaining: Duration = duration;
+    while !remaining.is_zero() {
+        let chunk: Duration = remaining.min(interval);
+        sleep(chunk);
+        remaining -= chunk;
+        let _ = notify("WATCHDOG=1");
+    }
+}
@@ -0,0 +1,144 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, VecDeque};
+
+use crate::structs::Contender;
+
+// Shares per option contract, to turn a fill's per share margin into dollars.
+const CONTRACT_MULTIPLIER: f64 = 100.0;
+
+// Caps the notional the bot submits per rolling minute and hour across every profile, so a burst
+// of arbs, spurious or not, can't fire a stack of large combo orders in one cycle. Contenders
+// over the cap are queued: when a later scan within the hour finds them again they are admitted, at
+// that scan's prices, ahead of the contenders seen for the first time.
+pub(crate) struct NotionalThrottle {
+    max_per_minute: f64,
+    max_per_hour: f64,
+    // Notional placed and when, for the last hour.
+    submitted: VecDeque<(DateTime<Utc>, f64)>,
+    // Contenders waiting for room and when they were queued, forgotten after an hour.
+    queued: HashMap<String, DateTime<Utc>>,
+}
+
+impl NotionalThrottle {
+    pub(crate) fn new(max_per_minute: f64, max_per_hour: f64) -> Self {
+        NotionalThrottle {
+            max_per_minute,
+            max_per_hour,
+            submitted: VecDeque::new(),
+            queued: HashMap::new(),
+        }
+    }
+
+    // Function that checks whether either cap is set (0 disables a cap).
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.max_per_minute > 0.0 || self.max_per_hour > 0.0
+    }
+
+    // Function that returns how many contenders are waiting for room under the caps.
+    pub(crate) fn queued_count(&self) -> usize {
+        self.queued.len()
+    }
+
+    // Function that returns the notional that can still be submitted now under both caps.
+    fn available(&mut self, now: DateTime<Utc>) -> f64 {
+        while self
+            .submitted
+            .front()
+            .is_some_and(|(at, _)| now - *at >= Duration::hours(1))
+        {
+            self.submitted.pop_front();
+        }
+        self.queued
+            .retain(|_, queued_at| now - *queued_at < Duration::hours(1));
+        let used = |window: Duration| -> f64 {
+            self.submitted
+                .iter()
+                .filter(|(at, _)| now - *at < window)
+                .map(|(_, notional)| notional)
+                .sum()
+        };
+        let mut available: f64 = f64::INFINITY;
+        if self.max_per_minute > 0.0 {
+            available = available.min(self.max_per_minute - used(Duration::minutes(1)));
+        }
+        if self.max_per_hour > 0.0 {
+            available = available.min(self.max_per_hour - used(Duration::hours(1)));
+        }
+        available.max(0.0)
+    }
+
+    // Function that trims a scan's quantities to the room left under the caps, queued contenders
+    // first and then by rank, cutting a contender's fills down to what fits. Contenders left
+    // with fewer fills than allocated are queued for the next cycles and their count returned
+    // with the trimmed quantities. Nothing counts against the caps until charged.
+    pub(crate) fn admit(
+        &mut self,
+        ticker: &str,
+        contenders: &[Contender],
+        quantities: &[i32],
+        now: DateTime<Utc>,
+    ) -> (Vec<i32>, usize) {
+        if !self.is_enabled() {
+            return (quantities.to_vec(), 0);
+        }
+        let keys: Vec<String> = contenders
+            .iter()
+            .map(|contender| contender_key(ticker, contender))
+            .collect();
+        let mut available: f64 = self.available(now);
+        let mut order: Vec<usize> = (0..quantities.len().min(contenders.len())).collect();
+        order.sort_by_key(|i| !self.queued.contains_key(&keys[*i]));
+        let mut admitted: Vec<i32> = vec![0; quantities.len()];
+        let mut throttled: usize = 0;
+        for i in order {
+            let wanted: i32 = quantities[i].max(0);
+            let per_fill: f64 = contender_notional(&contenders[i], 1);
+            let fills: i32 = if per_fill > 0.0 {
+                wanted.min((available / per_fill).floor() as i32)
+            } else {
+                wanted
+            };
+            admitted[i] = fills;
+            available -= per_fill * fills as f64;
+            if fills < wanted {
+                self.queued.entry(keys[i].clone()).or_insert(now);
+                throttled += 1;
+            } else {
+                self.queued.remove(&keys[i]);
+            }
+        }
+        (admitted, throttled)
+    }
+
+    // Function that counts the fills actually placed against the caps, after the exposure and
+    // position caps and price bounds have had their say and the gateway has taken the orders.
+    pub(crate) fn charge(&mut self, contenders: &[Contender], placed: &[f64], now: DateTime<Utc>) {
+        if !self.is_enabled() {
+            return;
+        }
+        let notional: f64 = contenders
+            .iter()
+            .zip(placed)
+            .map(|(contender, fills)| contender_notional(contender, 1) * fills)
+            .sum();
+        if notional > 0.0 {
+            self.submitted.push_back((now, notional));
+        }
+    }
+}
+
+// Function that returns the notional of a contender's fills: the capital they tie up, the
+// premium of the legs bought or a box's strike width, in dollars.
+pub(crate) fn contender_notional(contender: &Contender, num_fills: i32) -> f64 {
+    contender.margin().abs() * CONTRACT_MULTIPLIER * num_fills as f64
+}
+
+// Function that identifies a contender across scans by its ticker, spread type and legs.
+fn contender_key(ticker: &str, contender: &Contender) -> String {
+    let legs: Vec<String> = contender
+        .contracts
+        .iter()
+        .map(|leg| format!("{}{}{}", leg.date, leg.type_contract, leg.strike))
+        .collect();
+    format!("{} {} {}", ticker, contender.type_spread, legs.join(" "))
+}
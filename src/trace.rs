@@ -0,0 +1,143 @@
+use chrono::{DateTime, Utc};
+use reqwest::blocking::{Request, RequestBuilder, Response};
+use std::{fs::OpenOptions, io::Write, path::Path, time::Instant};
+
+use crate::{
+    logging::next_request_id,
+    metrics::{endpoint_key, record_request},
+    session::record_status,
+};
+
+// Tracing is on while this file exists, so it can be toggled on a running bot with touch and rm.
+pub(crate) const TRACE_TOGGLE_PATH: &str = "trace.on";
+const TRACE_PATH: &str = "trace.txt";
+
+// Function that checks whether HTTP tracing is currently enabled.
+pub(crate) fn trace_enabled() -> bool {
+    !cfg!(test) && Path::new(TRACE_TOGGLE_PATH).exists()
+}
+
+// Function that masks IBKR account ids (e.g. U1234567, DU1234567) in traced text.
+pub(crate) fn redact(text: &str) -> String {
+    redact_as(text, "[ACCOUNT]")
+}
+
+// Function that replaces IBKR account ids in text with an alias.
+pub(crate) fn redact_as(text: &str, alias: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut redacted: String = String::with_capacity(text.len());
+    let mut i: usize = 0;
+
+    while i < chars.len() {
+        let boundary: bool = i == 0 || !chars[i - 1].is_ascii_alphanumeric();
+        let prefix: usize = if boundary && chars[i] == 'U' {
+            1
+        } else if boundary && chars[i] == 'D' && chars.get(i + 1) == Some(&'U') {
+            2
+        } else {
+            0
+        };
+
+        if prefix > 0 {
+            let digits: usize = chars[i + prefix..]
+                .iter()
+                .take_while(|c| c.is_ascii_digit())
+                .count();
+            let end: usize = i + prefix + digits;
+            if digits >= 5 && chars.get(end).is_none_or(|c| !c.is_ascii_alphanumeric()) {
+                redacted.push_str(alias);
+                i = end;
+                continue;
+            }
+        }
+
+        redacted.push(chars[i]);
+        i += 1;
+    }
+
+    redacted
+}
+
+// Function that appends a request/response pair to the trace file.
+fn log_trace(request_id: &str, request: Option<&Request>, status: u16, payload: &[u8]) {
+    let (method, url, body): (String, String, String) = match request {
+        Some(request) => (
+            request.method().to_string(),
+            request.url().to_string(),
+            request
+                .body()
+                .and_then(|body| body.as_bytes())
+                .map(|body| String::from_utf8_lossy(body).to_string())
+                .unwrap_or_default(),
+        ),
+        None => ("?".to_string(), "?".to_string(), String::new()),
+    };
+
+    let now: DateTime<Utc> = Utc::now();
+    let entry: String = format!(
+        "{}   [{}] {} {}\n  Request: {}\n  Status: {}\n  Response: {}",
+        now.format("%Y-%m-%d %H:%M:%S%.9f UTC"),
+        request_id,
+        method,
+        redact(&url),
+        redact(&body),
+        status,
+        redact(&String::from_utf8_lossy(payload))
+    );
+
+    if let Ok(mut file) = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(TRACE_PATH)
+    {
+        let _ = writeln!(file, "{}", entry);
+    }
+}
+
+// Function that sends a request, recording it under its endpoint's stats.
+fn send_recorded(builder: RequestBuilder, request: Option<&Request>) -> reqwest::Result<Response> {
+    let endpoint: String = request
+        .map(|request| endpoint_key(request.method().as_str(), request.url().path()))
+        .unwrap_or("unknown".to_string());
+    let started: Instant = Instant::now();
+    let response: reqwest::Result<Response> = builder.send();
+    record_request(
+        &endpoint,
+        response
+            .as_ref()
+            .ok()
+            .map(|response| response.status().as_u16()),
+        started.elapsed(),
+    );
+    let response: Response = response?;
+    record_status(response.status());
+    Ok(response)
+}
+
+// Sends a request, tracing it to the trace file when enabled.
+pub(crate) trait TraceSend {
+    fn send_traced(self) -> reqwest::Result<Response>;
+}
+
+impl TraceSend for RequestBuilder {
+    // Function that sends the request, and when tracing reads the payload and hands back an
+    // equivalent response so callers can still parse it.
+    fn send_traced(self) -> reqwest::Result<Response> {
+        let request_id: String = next_request_id();
+        let request: Option<Request> = self.try_clone().and_then(|builder| builder.build().ok());
+        if !trace_enabled() {
+            return send_recorded(self, request.as_ref());
+        }
+
+        let response: Response = send_recorded(self, request.as_ref())?;
+        let status: reqwest::StatusCode = response.status();
+        let headers: reqwest::header::HeaderMap = response.headers().clone();
+        let payload = response.bytes()?;
+        log_trace(&request_id, request.as_ref(), status.as_u16(), &payload);
+
+        let mut traced: http::Response<_> = http::Response::new(payload);
+        *traced.status_mut() = status;
+        *traced.headers_mut() = headers;
+        Ok(Response::from(traced))
+    }
+}
@@ -0,0 +1,125 @@
+use crate::structs::Opt;
+
+// Reasons a fetched quote is rejected before it reaches the scanners.
+#[derive(Debug, PartialEq)]
+pub(crate) enum QuoteRejection {
+    Crossed,
+    NegativePrice,
+    AbsurdSize,
+    ZeroPricedInTheMoney,
+}
+
+impl QuoteRejection {
+    // Function that returns the metrics counter name for the rejection.
+    pub(crate) fn counter(&self) -> &'static str {
+        match self {
+            QuoteRejection::Crossed => "quotes_rejected_crossed",
+            QuoteRejection::NegativePrice => "quotes_rejected_negative_price",
+            QuoteRejection::AbsurdSize => "quotes_rejected_absurd_size",
+            QuoteRejection::ZeroPricedInTheMoney => "quotes_rejected_zero_priced_itm",
+        }
+    }
+}
+
+// Function that checks a quote for bad gateway data. Empty quotes (all zero) pass, since they
+// never meet the scanners' minimum bid anyway.
+pub(crate) fn validate_quote(
+    opt: &Opt,
+    strike: f64,
+    right: &str,
+    underlying_price: Option<f64>,
+    max_size: f64,
+) -> Result<(), QuoteRejection> {
+    if opt.bid == 0.0 && opt.ask == 0.0 && opt.asz == 0.0 {
+        return Ok(());
+    }
+
+    if opt.bid < 0.0 || opt.ask < 0.0 || opt.mkt < 0.0 || opt.asz < 0.0 {
+        return Err(QuoteRejection::NegativePrice);
+    }
+
+    if opt.ask > 0.0 && opt.bid > opt.ask {
+        return Err(QuoteRejection::Crossed);
+    }
+
+    if max_size > 0.0 && opt.asz > max_size {
+        return Err(QuoteRejection::AbsurdSize);
+    }
+
+    // A contract that is deep in the money can't be worth less than half its intrinsic value.
+    if let Some(underlying_price) = underlying_price {
+        let intrinsic: f64 = if right == "C" {
+            underlying_price - strike
+        } else {
+            strike - underlying_price
+        };
+        if intrinsic > 0.0 && opt.ask < intrinsic * 0.5 {
+            return Err(QuoteRejection::ZeroPricedInTheMoney);
+        }
+    }
+
+    Ok(())
+}
+
+// Market data availability reported by the snapshot endpoint in field 6509.
+#[derive(Debug, PartialEq)]
+pub(crate) enum DataAvailability {
+    RealTime,
+    Delayed,
+    Frozen,
+    NotSubscribed,
+    Unknown,
+}
+
+// Function that classifies a 6509 code, e.g. "RpB" is real time, consolidated, with book data.
+pub(crate) fn classify_availability(status: &str) -> DataAvailability {
+    match status.chars().next() {
+        Some('R') => DataAvailability::RealTime,
+        Some('D') | Some('Y') => DataAvailability::Delayed,
+        Some('Z') => DataAvailability::Frozen,
+        Some('N') | Some('O') => DataAvailability::NotSubscribed,
+        _ => DataAvailability::Unknown,
+    }
+}
+
+// Function that turns a scan's 6509 codes into an actionable error when the quotes can't be used.
+pub(crate) fn check_availability(ticker: &str, statuses: &[String]) -> Result<(), String> {
+    let classes: Vec<DataAvailability> = statuses
+        .iter()
+        .map(|status| classify_availability(status))
+        .collect();
+    let known: usize = classes
+        .iter()
+        .filter(|class| **class != DataAvailability::Unknown)
+        .count();
+    if known == 0 {
+        return Ok(());
+    }
+
+    let not_subscribed: usize = classes
+        .iter()
+        .filter(|class| **class == DataAvailability::NotSubscribed)
+        .count();
+    let delayed: usize = classes
+        .iter()
+        .filter(|class| **class == DataAvailability::Delayed)
+        .count();
+
+    if not_subscribed == known {
+        return Err(format!(
+            "No market data subscription for {} options (6509 code N). Subscribe to the exchange's \
+             option data in Client Portal under Settings > Market Data Subscriptions",
+            ticker
+        ));
+    }
+    if delayed + not_subscribed == known {
+        return Err(format!(
+            "Only delayed market data is available for {} options (6509 code D). Delayed quotes \
+             can't be traded, check the account's market data subscriptions and that no other \
+             session is holding them",
+            ticker
+        ));
+    }
+
+    Ok(())
+}